@@ -0,0 +1,29 @@
+//! Lex arbitrary text with the lexer of parser grammars, which has keywords, ignored
+//! terminals and regexes with groups.
+
+#![no_main]
+
+use beans::builder::Buildable;
+use beans::lexer::Lexer;
+use beans::stream::StringStream;
+use libfuzzer_sys::fuzz_target;
+use std::path::Path;
+
+thread_local! {
+    static LEXER: Lexer = Lexer::build_from_plain(StringStream::new(
+        Path::new("parser.lx"),
+        include_str!("../../src/parser/parser.lx"),
+    ))
+    .expect("the lexer grammar of parser grammars is valid");
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    LEXER.with(|lexer| {
+        let mut stream = StringStream::new(Path::new("<fuzz>"), text);
+        let mut input = lexer.lex(&mut stream);
+        while let Ok(Some(_)) = input.next_any() {}
+    });
+});
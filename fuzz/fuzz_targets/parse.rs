@@ -0,0 +1,43 @@
+//! Parse arbitrary text as a parser grammar, both failing on the first error and
+//! recovering from errors.
+
+#![no_main]
+
+use beans::builder::Buildable;
+use beans::lexer::Lexer;
+use beans::parser::earley::{EarleyGrammar, EarleyParser};
+use beans::parser::Parser;
+use beans::stream::StringStream;
+use libfuzzer_sys::fuzz_target;
+use std::path::Path;
+
+thread_local! {
+    static GRAMMARS: (Lexer, EarleyParser) = {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("parser.lx"),
+            include_str!("../../src/parser/parser.lx"),
+        ))
+        .expect("the lexer grammar of parser grammars is valid");
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("parser.gr"),
+                include_str!("../../src/parser/parser.gr"),
+            ),
+            lexer.grammar(),
+        )
+        .expect("the parser grammar of parser grammars is valid");
+        (lexer, EarleyParser::new(grammar))
+    };
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    GRAMMARS.with(|(lexer, parser)| {
+        let mut stream = StringStream::new(Path::new("<fuzz>"), text);
+        let _ = parser.parse(&mut lexer.lex(&mut stream));
+        let mut stream = StringStream::new(Path::new("<fuzz>"), text);
+        let _ = parser.parse_all_errors(&mut lexer.lex(&mut stream), 16);
+    });
+});
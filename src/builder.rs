@@ -1,12 +1,110 @@
 use std::ffi::OsString;
+use std::fmt;
 use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
 
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
 use crate::error::{Error, ErrorKind, Result as BResult};
 use crate::parser::AST;
 use crate::stream::StringStream;
 
+/// The bytes every compiled grammar starts with.
+const MAGIC: [u8; 4] = *b"BEAN";
+
+/// The version of the layout of compiled grammars. It is bumped whenever the layout
+/// of a compiled grammar changes, so that older blobs are rejected rather than
+/// misread.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// The version of Beans, which compiled grammars record.
+pub const BEANS_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// What a compiled grammar tells of how it was written, before the grammar itself.
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    magic: [u8; 4],
+    format: u32,
+    beans_version: String,
+}
+
+/// The kinds of compiled grammars. Compiled lexer grammars and compiled parser
+/// grammars have different extensions, but files can be renamed, and compiled
+/// LALR grammars share theirs with Earley ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompiledKind {
+    LexerGrammar,
+    EarleyGrammar,
+    LalrGrammar,
+}
+
+impl fmt::Display for CompiledKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LexerGrammar => write!(f, "lexer grammar"),
+            Self::EarleyGrammar => write!(f, "parser grammar"),
+            Self::LalrGrammar => write!(f, "LALR parser grammar"),
+        }
+    }
+}
+
+/// A grammar that can be compiled, with the kind of compiled grammar it is written
+/// as, which is checked when it is read back.
+///
+/// This trait is sealed: only the types of this crate implement it.
+pub trait Compiled: Serialize + DeserializeOwned + crate::sealed::Sealed {
+    const KIND: CompiledKind;
+}
+
+/// Serialize `grammar` as a compiled grammar, after a header with the layout and the
+/// version of Beans it was written with, and the kind of grammar it is.
+pub fn serialize_compiled<T: Compiled>(grammar: &T) -> bincode::Result<Vec<u8>> {
+    let header = Header {
+        magic: MAGIC,
+        format: FORMAT_VERSION,
+        beans_version: BEANS_VERSION.to_string(),
+    };
+    let mut blob = bincode::serialize(&header)?;
+    bincode::serialize_into(&mut blob, &T::KIND)?;
+    bincode::serialize_into(&mut blob, grammar)?;
+    Ok(blob)
+}
+
+/// Deserialize the compiled grammar `blob`, read from `path`, if it was written with
+/// the layout, and the version of Beans, of this one, as a grammar of the kind of
+/// `T`.
+pub fn deserialize_compiled<T: Compiled>(blob: &[u8], path: &Path) -> BResult<T> {
+    if !blob.starts_with(&MAGIC) {
+        return ErrorKind::IncompatibleBinaryVersion {
+            path: path.to_owned(),
+            found: None,
+        }
+        .err();
+    }
+    let mut reader = blob;
+    let header: Header =
+        bincode::deserialize_from(&mut reader).map_err(|error| Error::with_file(error, path))?;
+    if header.format != FORMAT_VERSION || header.beans_version != BEANS_VERSION {
+        return ErrorKind::IncompatibleBinaryVersion {
+            path: path.to_owned(),
+            found: Some((header.format, header.beans_version)),
+        }
+        .err();
+    }
+    let kind: CompiledKind =
+        bincode::deserialize_from(&mut reader).map_err(|error| Error::with_file(error, path))?;
+    if kind != T::KIND {
+        return ErrorKind::CompiledKindMismatch {
+            path: path.to_owned(),
+            expected: T::KIND,
+            found: kind,
+        }
+        .err();
+    }
+    bincode::deserialize(reader).map_err(|error| Error::with_file(error, path))
+}
+
 #[derive(Debug)]
 pub enum Format {
     Plain,
@@ -60,7 +158,10 @@ pub fn select_format<'a, T>(
     }
 }
 
-pub trait Buildable: Sized {
+/// Something that can be built from a grammar, in any of its formats.
+///
+/// This trait is sealed: only the types of this crate implement it.
+pub trait Buildable: Sized + crate::sealed::Sealed {
     const RAW_EXTENSION: &'static str;
     const AST_EXTENSION: &'static str;
     const COMPILED_EXTENSION: &'static str;
@@ -138,7 +239,7 @@ pub trait Buildable: Sized {
                 })?
             }
             FileResult::Valid((actual_path, Format::Plain)) => {
-                let stream = StringStream::from_file(actual_path)?;
+                let stream = StringStream::from_file_with_includes(actual_path)?;
                 let result = Self::build_from_plain(stream)?;
                 return Ok(result);
             }
@@ -2,7 +2,11 @@
 //!
 //! This module contains error related primitives.
 
-use crate::span::Span;
+use crate::builder::{CompiledKind, BEANS_VERSION, FORMAT_VERSION};
+use crate::lexer::{Grammar as LexerGrammar, Processor, TerminalId, Token};
+use crate::parser::lalr::Conflict;
+use crate::parser::version::LanguageVersion;
+use crate::span::{Location, Span};
 use either::Either;
 use fragile::Fragile;
 use std::ffi::OsString;
@@ -48,6 +52,7 @@ impl<T: Into<ErrorKind>> From<T> for Error {
 }
 
 #[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
 pub enum ErrorKind {
     InternalError {
         message: String,
@@ -75,9 +80,36 @@ pub enum ErrorKind {
         path: PathBuf,
         error: Either<Utf8Error, FromUtf8Error>,
     },
+    /// The input has a byte that text does not have near its start.
+    BinaryInput {
+        path: PathBuf,
+        byte: usize,
+    },
     GrammarNotFound {
         path: PathBuf,
     },
+    IncludeCycle {
+        path: PathBuf,
+    },
+    InvalidOffset {
+        path: PathBuf,
+        offset: usize,
+    },
+    LexerMismatch {
+        path: PathBuf,
+    },
+    /// The compiled grammar was written with another layout, or another version of
+    /// Beans, given as `found` if the grammar tells them.
+    IncompatibleBinaryVersion {
+        path: PathBuf,
+        found: Option<(u32, String)>,
+    },
+    /// The compiled grammar is of another kind than the one it is read as.
+    CompiledKindMismatch {
+        path: PathBuf,
+        expected: CompiledKind,
+        found: CompiledKind,
+    },
     LexerGrammarSyntax {
         message: String,
         span: Fragile<Span>,
@@ -91,6 +123,25 @@ pub enum ErrorKind {
         span: Fragile<Span>,
     },
     LexerGrammarEofString,
+    LexerGrammarUnknownFamily {
+        name: String,
+        family: String,
+        span: Fragile<Span>,
+    },
+    LexerGrammarUnknownProcessor {
+        name: String,
+        span: Fragile<Span>,
+    },
+    UnknownTerminal {
+        name: String,
+    },
+    UnknownNonTerminal {
+        name: String,
+    },
+    /// A rule of the grammar has a predicate that was not given to the parser.
+    UndefinedPredicate {
+        name: String,
+    },
     /// `LexingError(message: String)`: error while transforming a string stream into a token stream.
     LexingError {
         /// The `Span` that made the error occur. It's a hint a what should
@@ -101,6 +152,38 @@ pub enum ErrorKind {
         span: Fragile<Span>,
         message: String,
     },
+    /// In layout mode, a line is indented less than the block it is in, but not as
+    /// much as any enclosing block.
+    InconsistentDedent {
+        span: Fragile<Span>,
+    },
+    /// The next token is longer than the lexer allows.
+    TokenTooLong {
+        span: Fragile<Span>,
+        max_length: usize,
+    },
+    /// A post-processor of the attributes of a token failed.
+    TokenProcessing {
+        terminal: String,
+        message: String,
+        span: Fragile<Span>,
+    },
+    /// A balanced terminal is not defined as `OPEN ... CLOSE`.
+    LexerGrammarBalancedDelimiters {
+        name: String,
+        span: Fragile<Span>,
+    },
+    /// The priority of a terminal is not a number.
+    LexerGrammarInvalidPriority {
+        name: String,
+        priority: String,
+        span: Fragile<Span>,
+    },
+    /// The input ends before a token of a balanced terminal is closed.
+    UnclosedBalanced {
+        terminal: String,
+        span: Fragile<Span>,
+    },
     GrammarDuplicateDefinition {
         name: String,
         span: Fragile<Span>,
@@ -122,6 +205,50 @@ pub enum ErrorKind {
         name: String,
         span: Fragile<Span>,
     },
+    GrammarUndefinedTerminal {
+        name: String,
+        span: Fragile<Span>,
+    },
+    /// A parser grammar refers to several undefined terminals, non-terminals or
+    /// literals, each of which is one of the `errors`.
+    GrammarUndefinedReferences {
+        errors: Vec<Error>,
+    },
+    /// A literal of a parser grammar, as in `'('`, is not lexed as a single token.
+    GrammarUnknownLiteral {
+        literal: String,
+        span: Fragile<Span>,
+    },
+    /// A terminal is given two levels of the precedence table.
+    GrammarDuplicatePrecedence {
+        name: String,
+        span: Fragile<Span>,
+        old_span: Fragile<Span>,
+    },
+    /// The language version a grammar declares is not `MAJOR.MINOR.PATCH`.
+    InvalidLanguageVersion {
+        version: String,
+        message: String,
+        span: Fragile<Span>,
+    },
+    /// A serialized AST was built by a grammar of a language version that tools
+    /// built for `expected` cannot read, or of no version at all.
+    IncompatibleLanguageVersion {
+        expected: LanguageVersion,
+        found: Option<LanguageVersion>,
+    },
+    GrammarAmbiguousName {
+        name: String,
+        span: Fragile<Span>,
+        nonterminal_span: Fragile<Span>,
+        terminal_span: Fragile<Span>,
+    },
+    GrammarDefaultNotOptional {
+        span: Fragile<Span>,
+    },
+    GrammarDefaultWithoutKey {
+        span: Fragile<Span>,
+    },
     GrammarUndefinedMacro {
         name: String,
         span: Fragile<Span>,
@@ -146,12 +273,47 @@ pub enum ErrorKind {
         old_span: Fragile<Span>,
         name: String,
     },
-    SyntaxError {
+    GrammarDuplicateLexerMode {
+        span: Fragile<Span>,
+        old_span: Fragile<Span>,
         name: String,
+    },
+    GrammarUndefinedLexerMode {
+        name: String,
+        span: Fragile<Span>,
+    },
+    GrammarUnknownAnnotation {
+        name: String,
+        span: Fragile<Span>,
+    },
+    GrammarUnknownGroup {
+        terminal: String,
+        name: String,
+        span: Fragile<Span>,
+    },
+    /// A pass run on the AST of a grammar rejected it.
+    GrammarPass {
+        pass: String,
+        message: String,
+    },
+    /// A token, or the end of the input, that the parser did not expect.
+    ParseError {
+        /// The unexpected token, or `None` if the input ended too early.
+        found: Option<Fragile<Token>>,
+        /// The terminals that could have come instead, sorted.
+        expected: Vec<TerminalId>,
+        /// Where the unexpected token starts, or where the input ends.
+        location: Location,
+        /// The description of the unexpected token, or its name.
+        name: Option<String>,
+        /// What could have come instead, as shown to the user.
         alternatives: Vec<String>,
         span: Fragile<Span>,
     },
-    SyntaxErrorValidPrefix {
+    LalrConflicts {
+        conflicts: Vec<Conflict>,
+    },
+    LalrInheritedAttributes {
         span: Fragile<Span>,
     },
     IOError {
@@ -171,6 +333,36 @@ impl ErrorKind {
             kind: Box::new(self),
         })
     }
+
+    /// Build a [`ErrorKind::ParseError`] on `found`, or on the end of the input,
+    /// right after `last_span`, if it is `None`.
+    pub(crate) fn parse_error(
+        lexer_grammar: &LexerGrammar,
+        found: Option<&Token>,
+        mut expected: Vec<TerminalId>,
+        alternatives: Vec<String>,
+        last_span: &Span,
+    ) -> Self {
+        expected.sort_unstable();
+        expected.dedup();
+        let (span, location) = match found {
+            Some(token) => (token.span().clone(), token.span().start()),
+            None => (last_span.clone(), last_span.end()),
+        };
+        Self::ParseError {
+            name: found.map(|token| {
+                lexer_grammar
+                    .description_of(token.id())
+                    .unwrap_or_else(|| token.name())
+                    .to_string()
+            }),
+            found: found.cloned().map(Fragile::new),
+            expected,
+            location,
+            alternatives,
+            span: Fragile::new(span),
+        }
+    }
 }
 
 impl From<(PathBuf, std::io::Error)> for ErrorKind {
@@ -237,6 +429,54 @@ impl Display for ErrorKind {
             Self::GrammarNotFound { path } => {
                 writeln!(f, "Grammar not found at {}", path.display(),)
             }
+            Self::IncludeCycle { path } => {
+                writeln!(f, "The file {} includes itself", path.display())
+            }
+            Self::InvalidOffset { path, offset } => {
+                writeln!(
+                    f,
+                    "No character of the stream of {} starts at byte {offset}.",
+                    path.display()
+                )
+            }
+            Self::LexerMismatch { path } => {
+                writeln!(
+                    f,
+                    "The parser grammar {} was compiled against another lexer grammar. \
+                     Recompile it with the lexer grammar it is used with.",
+                    path.display(),
+                )
+            }
+            Self::IncompatibleBinaryVersion { path, found } => {
+                match found {
+                    Some((format, version)) => write!(
+                        f,
+                        "The compiled grammar {} was written by Beans {version}, in format \
+                         {format}, but this is Beans {BEANS_VERSION}, which reads format \
+                         {FORMAT_VERSION}. ",
+                        path.display(),
+                    )?,
+                    None => write!(
+                        f,
+                        "The file {} is not a compiled grammar, or was written by a version \
+                         of Beans that did not record its own. ",
+                        path.display(),
+                    )?,
+                }
+                writeln!(f, "Recompile it from its source.")
+            }
+            Self::CompiledKindMismatch {
+                path,
+                expected,
+                found,
+            } => {
+                writeln!(
+                    f,
+                    "The file {} is a compiled {found}, but a compiled {expected} was \
+                     expected.",
+                    path.display(),
+                )
+            }
             Self::LexerGrammarSyntax { message, span } => {
                 writeln!(f, "Syntax error {span}.\n{message}")
             }
@@ -249,15 +489,72 @@ impl Display for ErrorKind {
                     "{token} is tagged `unwanted` but has no description {span}."
                 )
             }
+            Self::LexerGrammarUnknownFamily { name, family, span } => {
+                writeln!(
+                    f,
+                    "{name} is split from {family}, which is not a terminal defined by a regex {span}."
+                )
+            }
+            Self::LexerGrammarUnknownProcessor { name, span } => {
+                writeln!(
+                    f,
+                    "Unknown post-processor {name} {span}, expected one of {}.",
+                    Processor::names().collect::<Vec<_>>().join(", ")
+                )
+            }
             Self::LexerGrammarEofString => {
                 writeln!(f, "Found EOF while reading a string.")
             }
+            Self::UnknownTerminal { name } => {
+                writeln!(f, "There is no terminal called {name}.")
+            }
+            Self::UnknownNonTerminal { name } => {
+                writeln!(f, "There is no non-terminal called {name}.")
+            }
+            Self::UndefinedPredicate { name } => {
+                writeln!(f, "The predicate {name} of the grammar was not given to the parser.")
+            }
             Self::LexingError { span } => {
                 writeln!(f, "Could not lex anything {span}.")
             }
             Self::UnwantedToken { span, message } => {
                 writeln!(f, "Lexing error {span}.\n{message}")
             }
+            Self::InconsistentDedent { span } => {
+                writeln!(
+                    f,
+                    "Inconsistent dedent {span}: no enclosing block is indented as much."
+                )
+            }
+            Self::LexerGrammarBalancedDelimiters { name, span } => {
+                writeln!(
+                    f,
+                    "The balanced terminal {name} {span} must be defined as `OPEN ... CLOSE`."
+                )
+            }
+            Self::LexerGrammarInvalidPriority {
+                name,
+                priority,
+                span,
+            } => {
+                writeln!(
+                    f,
+                    "The priority {priority:?} of the terminal {name} {span} is not a number."
+                )
+            }
+            Self::UnclosedBalanced { terminal, span } => {
+                writeln!(f, "The {terminal} opened {span} is never closed.")
+            }
+            Self::TokenTooLong { span, max_length } => {
+                writeln!(f, "The token {span} is longer than {max_length} chars.")
+            }
+            Self::TokenProcessing {
+                terminal,
+                message,
+                span,
+            } => {
+                writeln!(f, "Could not process the token {terminal} {span}: {message}.")
+            }
             Self::GrammarDuplicateDefinition {
                 name: message,
                 span,
@@ -285,6 +582,13 @@ impl Display for ErrorKind {
             } => {
                 writeln!(f, "Macro {name} {span} was already defined {old_span}.")
             }
+            Self::GrammarDuplicateLexerMode {
+                span,
+                old_span,
+                name,
+            } => {
+                writeln!(f, "Lexer mode {name} {span} was already defined {old_span}.")
+            }
             Self::GrammarArityMismatch {
                 macro_name,
                 definition_arity,
@@ -312,32 +616,142 @@ impl Display for ErrorKind {
             Self::GrammarVariantKey { span } => {
                 writeln!(f, "The `variant` key is reserved {span}.")
             }
-            Self::SyntaxError {
-                name,
+            Self::ParseError {
+                name: Some(name),
                 alternatives,
                 span,
+                ..
             } => {
                 writeln!(
                     f,
                     "Syntax error {name} {span}. You could have tried {alternatives:?}."
                 )
             }
+            Self::ParseError {
+                name: None, span, ..
+            } => {
+                writeln!(
+                    f,
+                    "Syntax error {span}: reached EOF while parsing a valid file."
+                )
+            }
             Self::IntegerTooBig { string, span } => {
                 writeln!(
 		    f,
 		    "Integer {string} does not fit on a 64 bit integer, {span}.\nWhy do you even need such a number?"
 		)
             }
+            Self::GrammarDefaultNotOptional { span } => {
+                writeln!(f, "Only optional elements can have a default value {span}.")
+            }
+            Self::GrammarDefaultWithoutKey { span } => {
+                writeln!(
+                    f,
+                    "The default value of an element without a key is never used {span}."
+                )
+            }
+            Self::GrammarUndefinedTerminal { name, span } => {
+                writeln!(f, "Terminal {name} is undefined {span}.")
+            }
+            Self::GrammarUndefinedReferences { errors } => {
+                writeln!(f, "The grammar refers to {} undefined names.", errors.len())?;
+                for error in errors {
+                    write!(f, " - {error}")?;
+                }
+                Ok(())
+            }
+            Self::GrammarUnknownLiteral { literal, span } => {
+                writeln!(
+                    f,
+                    "No terminal lexes {literal:?} as a single token {span}. It should be \
+                     declared in the lexer grammar."
+                )
+            }
+            Self::GrammarDuplicatePrecedence {
+                name,
+                span,
+                old_span,
+            } => {
+                writeln!(
+                    f,
+                    "The terminal {name} is given a precedence {span}, and already {old_span}."
+                )
+            }
+            Self::InvalidLanguageVersion {
+                version,
+                message,
+                span,
+            } => {
+                writeln!(f, "Invalid language version {version:?} {span}: {message}.")
+            }
+            Self::IncompatibleLanguageVersion {
+                expected,
+                found: Some(found),
+            } => {
+                writeln!(
+                    f,
+                    "The AST is of version {found} of the language, which tools built \
+                     for version {expected} cannot read."
+                )
+            }
+            Self::IncompatibleLanguageVersion {
+                expected,
+                found: None,
+            } => {
+                writeln!(
+                    f,
+                    "The AST has no language version, while tools are built for version \
+                     {expected}."
+                )
+            }
+            Self::GrammarAmbiguousName {
+                name,
+                span,
+                nonterminal_span,
+                terminal_span,
+            } => {
+                writeln!(
+                    f,
+                    "{name} could be the non-terminal defined {nonterminal_span}, or the terminal defined {terminal_span}, {span}. Write `!n {name}` or `!t {name}` instead."
+                )
+            }
             Self::GrammarUndefinedMacro { name, span } => {
                 writeln!(f, "Macro {name} is undefined {span}.")
             }
+            Self::GrammarUndefinedLexerMode { name, span } => {
+                writeln!(f, "Lexer mode {name} is undefined {span}.")
+            }
+            Self::GrammarUnknownAnnotation { name, span } => {
+                writeln!(f, "Unknown rule annotation {name} {span}, expected `weight`.")
+            }
+            Self::GrammarUnknownGroup {
+                terminal,
+                name,
+                span,
+            } => {
+                writeln!(f, "Terminal {terminal} has no group named {name} {span}.")
+            }
+            Self::GrammarPass { pass, message } => {
+                writeln!(f, "The grammar pass {pass} failed: {message}.")
+            }
             Self::GrammarUndefinedNonTerminal { name, span } => {
                 writeln!(f, "Non-terminal {name} is undefined {span}.")
             }
-            Self::SyntaxErrorValidPrefix { span } => {
+            Self::LalrConflicts { conflicts } => {
                 writeln!(
                     f,
-                    "Syntax error {span}: reached EOF while parsing a valid file."
+                    "The grammar is not LALR(1), found {} conflicts.",
+                    conflicts.len()
+                )?;
+                for conflict in conflicts {
+                    writeln!(f, " - {conflict}")?;
+                }
+                Ok(())
+            }
+            Self::LalrInheritedAttributes { span } => {
+                writeln!(
+                    f,
+                    "Inherited attributes are not supported by the LALR(1) backend {span}."
                 )
             }
             Self::IOError { error, path } => {
@@ -360,6 +774,14 @@ impl Display for ErrorKind {
                     path.display()
                 )
             }
+            Self::BinaryInput { path, byte } => {
+                writeln!(
+                    f,
+                    "The input {} looks binary, as its byte {byte} is not text. Only text \
+                     can be lexed.",
+                    path.display()
+                )
+            }
         }
     }
 }
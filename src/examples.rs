@@ -0,0 +1,8 @@
+//! # Examples
+//!
+//! Complete languages written with Beans, to be read as documentation and reused
+//! as starting points. They are only available with the `examples` feature.
+//!
+//! - [`petitc`] is a small subset of C, with a typed AST and a semantic checker.
+
+pub mod petitc;
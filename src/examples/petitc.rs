@@ -0,0 +1,936 @@
+//! # Petit C
+//!
+//! A small subset of C: functions, which may be nested, `int`, `bool`, `void` and
+//! pointer types, the usual operators, and the `if`, `while`, `for`, `return`,
+//! `break` and `continue` statements. `#include`s are accepted, and ignored.
+//!
+//! [`PetitC`] builds the lexer and the parser from [`LEXER`] and [`GRAMMAR`], and
+//! reads the sources into the typed AST rooted at [`File`], which [`check`] then
+//! looks for semantic errors.
+//!
+//! ```rust
+//! use beans::examples::petitc::{check, PetitC};
+//! use std::path::Path;
+//!
+//! let petitc = PetitC::new().unwrap();
+//! let file = petitc
+//!     .parse(Path::new("main.c"), "int main() { return x; }")
+//!     .unwrap();
+//! let errors = check(&file);
+//! assert_eq!(errors[0].message, "unknown variable `x`");
+//! ```
+
+use std::{collections::HashMap, fmt, path::Path, rc::Rc};
+
+use crate::{
+    builder::Buildable,
+    error::Result,
+    lexer::Lexer,
+    parser::{
+        earley::{EarleyGrammar, EarleyParser},
+        Parser, AST,
+    },
+    span::Span,
+    stream::StringStream,
+    typed::{get, match_variant, node, span, spanned_value, value, Spanned, Tree},
+};
+
+/// The lexer grammar of Petit C.
+pub const LEXER: &str = include_str!("../parser/gmrs/petitc.lx");
+/// The parser grammar of Petit C.
+pub const GRAMMAR: &str = include_str!("../parser/gmrs/petitc.gr");
+
+/// # Summary
+///
+/// [`PetitC`] holds the lexer and the parser of Petit C.
+#[derive(Debug)]
+pub struct PetitC {
+    lexer: Lexer,
+    parser: EarleyParser,
+}
+
+impl PetitC {
+    /// Build the lexer and the parser from [`LEXER`] and [`GRAMMAR`].
+    pub fn new() -> Result<Self> {
+        let lexer = Lexer::build_from_plain(StringStream::new(Path::new("petitc.lx"), LEXER))?;
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("petitc.gr"), GRAMMAR),
+            lexer.grammar(),
+        )?;
+        Ok(Self {
+            lexer,
+            parser: EarleyParser::new(grammar),
+        })
+    }
+
+    pub fn lexer(&self) -> &Lexer {
+        &self.lexer
+    }
+
+    pub fn parser(&self) -> &EarleyParser {
+        &self.parser
+    }
+
+    /// Parse `source`, read from `path`, into a typed AST.
+    pub fn parse(&self, path: &Path, source: &str) -> Result<File> {
+        let mut stream = StringStream::new(path, source);
+        let mut input = self.lexer.lex(&mut stream);
+        self.parser.parse(&mut input)?.tree.to_tree()
+    }
+}
+
+/// A source file: its toplevel functions.
+#[derive(Debug, Clone)]
+pub struct File {
+    pub functions: Vec<Function>,
+    pub span: Span,
+}
+
+impl Tree for File {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self {
+            functions: get!(node => decls).to_tree::<Spanned<_>>()?.inner,
+            span: span!(node),
+        })
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub return_type: Spanned<Type>,
+    pub name: Spanned<Rc<str>>,
+    pub parameters: Vec<Parameter>,
+    pub body: Block,
+    pub span: Span,
+}
+
+impl Tree for Function {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self {
+            return_type: get!(node => rettype).to_tree()?,
+            name: spanned_value!(node => name),
+            parameters: get!(node => args).to_tree::<Spanned<_>>()?.inner,
+            body: get!(node => block).to_tree()?,
+            span: span!(node),
+        })
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Void,
+    Int,
+    Bool,
+    Pointer(Box<Type>),
+}
+
+impl Tree for Spanned<Type> {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(match_variant! {(node) {
+            Void => Type::Void,
+            Int => Type::Int,
+            Bool => Type::Bool,
+            Pointer => Type::Pointer(Box::new(
+                get!(node => pointed).to_tree::<Spanned<Type>>()?.inner,
+            )),
+        }})
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub ty: Spanned<Type>,
+    pub name: Spanned<Rc<str>>,
+    pub span: Span,
+}
+
+impl Tree for Parameter {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self {
+            ty: get!(node => "type").to_tree()?,
+            name: spanned_value!(node => name),
+            span: span!(node),
+        })
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub statements: Vec<DeclStatement>,
+    pub span: Span,
+}
+
+impl Tree for Block {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self {
+            statements: get!(node => stmts).to_tree::<Spanned<_>>()?.inner,
+            span: span!(node),
+        })
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+/// An item of a block.
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum DeclStatement {
+    Function(Function),
+    Declaration(VariableDeclaration),
+    Statement(Spanned<Statement>),
+}
+
+impl Tree for DeclStatement {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(match_variant! {(node) {
+            Function => Self::Function(get!(node => declaration).to_tree()?),
+            Declaration => Self::Declaration(get!(node => declaration).to_tree()?),
+            Statement => Self::Statement(get!(node => stmt).to_tree()?),
+        }}
+        .inner)
+    }
+
+    fn span(&self) -> &Span {
+        match self {
+            Self::Function(function) => &function.span,
+            Self::Declaration(declaration) => &declaration.span,
+            Self::Statement(statement) => &statement.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VariableDeclaration {
+    pub ty: Spanned<Type>,
+    pub name: Spanned<Rc<str>>,
+    pub value: Option<Spanned<Expr>>,
+    pub span: Span,
+}
+
+impl Tree for VariableDeclaration {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self {
+            ty: get!(node => "type").to_tree()?,
+            name: spanned_value!(node => name),
+            value: get!(node => value)
+                .to_tree::<Spanned<Option<Definition>>>()?
+                .inner
+                .map(|definition| definition.value),
+            span: span!(node),
+        })
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+/// The `= value` part of a variable declaration.
+#[derive(Debug)]
+struct Definition {
+    value: Spanned<Expr>,
+    span: Span,
+}
+
+impl Tree for Definition {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self {
+            value: get!(node => value).to_tree()?,
+            span: span!(node),
+        })
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum Statement {
+    /// A lone `;`.
+    Empty,
+    Expr(Spanned<Expr>),
+    If {
+        condition: Spanned<Expr>,
+        then: Box<Spanned<Statement>>,
+        otherwise: Option<Box<Spanned<Statement>>>,
+    },
+    While {
+        condition: Spanned<Expr>,
+        body: Box<Spanned<Statement>>,
+    },
+    For {
+        init: Option<VariableDeclaration>,
+        test: Option<Spanned<Expr>>,
+        step: Vec<Spanned<Expr>>,
+        body: Box<Spanned<Statement>>,
+    },
+    Block(Block),
+    Return(Option<Spanned<Expr>>),
+    Break,
+    Continue,
+}
+
+impl Tree for Spanned<Statement> {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(match_variant! {(node) {
+            None => Statement::Empty,
+            Regular => Statement::Expr(get!(node => stmt).to_tree()?),
+            If => Statement::If {
+                condition: get!(node => condition).to_tree()?,
+                then: Box::new(get!(node => then).to_tree()?),
+                otherwise: get!(node => "else")
+                    .to_tree::<Spanned<Option<Else>>>()?
+                    .inner
+                    .map(|otherwise| otherwise.statement),
+            },
+            While => Statement::While {
+                condition: get!(node => condition).to_tree()?,
+                body: Box::new(get!(node => body).to_tree()?),
+            },
+            For => Statement::For {
+                init: get!(node => init).to_tree::<Spanned<_>>()?.inner,
+                test: get!(node => test).to_tree::<Spanned<_>>()?.inner,
+                step: get!(node => step).to_tree::<Spanned<_>>()?.inner,
+                body: Box::new(get!(node => body).to_tree()?),
+            },
+            Block => Statement::Block(get!(node => stmts).to_tree()?),
+            Return => Statement::Return(get!(node => value).to_tree::<Spanned<_>>()?.inner),
+            Break => Statement::Break,
+            Continue => Statement::Continue,
+        }})
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+/// The `else` branch of an `if` statement.
+#[derive(Debug)]
+struct Else {
+    statement: Box<Spanned<Statement>>,
+    span: Span,
+}
+
+impl Tree for Else {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self {
+            statement: Box::new(get!(node => "else").to_tree()?),
+            span: span!(node),
+        })
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+/// An expression. Parenthesized expressions are read as the expression they
+/// contain, and `a[i]` as `*(a + i)`.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Int(Rc<str>),
+    /// A character literal, quotes and escape sequences included.
+    Char(Rc<str>),
+    Bool(bool),
+    Null,
+    Ident(Rc<str>),
+    Sizeof(Spanned<Type>),
+    Call {
+        name: Spanned<Rc<str>>,
+        args: Vec<Spanned<Expr>>,
+    },
+    Unary {
+        op: UnaryOp,
+        value: Box<Spanned<Expr>>,
+    },
+    Binary {
+        op: Spanned<BinaryOp>,
+        left: Box<Spanned<Expr>>,
+        right: Box<Spanned<Expr>>,
+    },
+    Assign {
+        key: Box<Spanned<Expr>>,
+        value: Box<Spanned<Expr>>,
+    },
+}
+
+impl Expr {
+    /// Whether the expression designates a location, which can be assigned to.
+    pub fn is_lvalue(&self) -> bool {
+        matches!(
+            self,
+            Self::Ident(_)
+                | Self::Unary {
+                    op: UnaryOp::Deref,
+                    ..
+                }
+        )
+    }
+}
+
+fn unary(op: UnaryOp, value: AST) -> Result<Expr> {
+    Ok(Expr::Unary {
+        op,
+        value: Box::new(value.to_tree()?),
+    })
+}
+
+impl Tree for Spanned<Expr> {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(match_variant! {(node) {
+            Int => {
+                let value = get!(node => value);
+                let mut int = node!(value);
+                match_variant! {(int) {
+                    Int => Expr::Int(value!(int => value)),
+                    Char => Expr::Char(value!(int => value)),
+                }}
+                .inner
+            },
+            Bool => {
+                let value = get!(node => value);
+                let mut boolean = node!(value);
+                match_variant! {(boolean) {
+                    True => Expr::Bool(true),
+                    False => Expr::Bool(false),
+                }}
+                .inner
+            },
+            Null => Expr::Null,
+            Through => get!(node => this).to_tree::<Spanned<Expr>>()?.inner,
+            Not => unary(UnaryOp::Not, get!(node => value))?,
+            Incrl => unary(UnaryOp::PreIncrement, get!(node => value))?,
+            Incrr => unary(UnaryOp::PostIncrement, get!(node => value))?,
+            Decrl => unary(UnaryOp::PreDecrement, get!(node => value))?,
+            Decrr => unary(UnaryOp::PostDecrement, get!(node => value))?,
+            Borrow => unary(UnaryOp::Borrow, get!(node => value))?,
+            Deref => unary(UnaryOp::Deref, get!(node => value))?,
+            Plus => unary(UnaryOp::Plus, get!(node => value))?,
+            Minus => unary(UnaryOp::Minus, get!(node => value))?,
+            Ident => Expr::Ident(value!(node => value)),
+            Sizeof => Expr::Sizeof(get!(node => "type").to_tree()?),
+            Call => Expr::Call {
+                name: spanned_value!(node => name),
+                args: get!(node => args).to_tree::<Spanned<_>>()?.inner,
+            },
+            BinOp => Expr::Binary {
+                op: get!(node => op).to_tree()?,
+                left: Box::new(get!(node => left).to_tree()?),
+                right: Box::new(get!(node => right).to_tree()?),
+            },
+            Assign => Expr::Assign {
+                key: Box::new(get!(node => key).to_tree()?),
+                value: Box::new(get!(node => value).to_tree()?),
+            },
+        }})
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Not,
+    PreIncrement,
+    PostIncrement,
+    PreDecrement,
+    PostDecrement,
+    /// `&value`
+    Borrow,
+    /// `*value`
+    Deref,
+    Plus,
+    Minus,
+}
+
+impl UnaryOp {
+    /// Whether the operator writes to its operand, which must then be an lvalue.
+    pub fn is_mutating(self) -> bool {
+        matches!(
+            self,
+            Self::PreIncrement | Self::PostIncrement | Self::PreDecrement | Self::PostDecrement
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Mod,
+    Add,
+    Sub,
+    Lt,
+    Gt,
+    Geq,
+    Leq,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+}
+
+impl Tree for Spanned<BinaryOp> {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(match_variant! {(node) {
+            Mul => BinaryOp::Mul,
+            Div => BinaryOp::Div,
+            Mod => BinaryOp::Mod,
+            Add => BinaryOp::Add,
+            Sub => BinaryOp::Sub,
+            Lt => BinaryOp::Lt,
+            Gt => BinaryOp::Gt,
+            Geq => BinaryOp::Geq,
+            Leq => BinaryOp::Leq,
+            Equal => BinaryOp::Equal,
+            NotEqual => BinaryOp::NotEqual,
+            And => BinaryOp::And,
+            Or => BinaryOp::Or,
+        }})
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+/// An error found by [`check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Check that
+/// - there is a `main` function;
+/// - every name is declared once per scope, and before it is used;
+/// - functions are called with as many arguments as they have parameters;
+/// - only lvalues are assigned, incremented or decremented;
+/// - `break` and `continue` only appear in loops.
+///
+/// Types are not checked.
+pub fn check(file: &File) -> Vec<SemanticError> {
+    let mut checker = Checker {
+        scopes: vec![HashMap::new()],
+        loops: 0,
+        errors: Vec::new(),
+    };
+    // Toplevel functions can call each other, whatever their order.
+    for function in &file.functions {
+        checker.declare_function(function);
+    }
+    if !file.functions.iter().any(|f| &*f.name.inner == "main") {
+        checker.error("there is no `main` function", &file.span);
+    }
+    for function in &file.functions {
+        checker.function(function);
+    }
+    checker.errors
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Binding {
+    Variable,
+    Function { arity: usize },
+}
+
+struct Checker {
+    scopes: Vec<HashMap<Rc<str>, Binding>>,
+    /// The number of loops around the current statement, in the current function.
+    loops: usize,
+    errors: Vec<SemanticError>,
+}
+
+impl Checker {
+    fn error(&mut self, message: impl Into<String>, span: &Span) {
+        self.errors.push(SemanticError {
+            message: message.into(),
+            span: span.clone(),
+        });
+    }
+
+    fn lookup(&self, name: &str) -> Option<Binding> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    fn declare(&mut self, name: &Spanned<Rc<str>>, binding: Binding) {
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.insert(name.inner.clone(), binding).is_some() {
+            let message = format!("`{}` is already declared in this scope", name.inner);
+            self.error(message, &name.span);
+        }
+    }
+
+    fn declare_function(&mut self, function: &Function) {
+        let arity = function.parameters.len();
+        self.declare(&function.name, Binding::Function { arity });
+    }
+
+    fn function(&mut self, function: &Function) {
+        let loops = std::mem::take(&mut self.loops);
+        // Parameters live in the same scope as the toplevel of the body.
+        self.scopes.push(HashMap::new());
+        for parameter in &function.parameters {
+            self.declare(&parameter.name, Binding::Variable);
+        }
+        self.items(&function.body.statements);
+        self.scopes.pop();
+        self.loops = loops;
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.scopes.push(HashMap::new());
+        self.items(&block.statements);
+        self.scopes.pop();
+    }
+
+    fn items(&mut self, items: &[DeclStatement]) {
+        for item in items {
+            match item {
+                DeclStatement::Function(function) => {
+                    self.declare_function(function);
+                    self.function(function);
+                }
+                DeclStatement::Declaration(declaration) => self.declaration(declaration),
+                DeclStatement::Statement(statement) => self.statement(statement),
+            }
+        }
+    }
+
+    fn declaration(&mut self, declaration: &VariableDeclaration) {
+        if let Some(value) = &declaration.value {
+            self.expr(value);
+        }
+        self.declare(&declaration.name, Binding::Variable);
+    }
+
+    fn statement(&mut self, statement: &Spanned<Statement>) {
+        match &statement.inner {
+            Statement::Empty => {}
+            Statement::Expr(expr) => self.expr(expr),
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.expr(condition);
+                self.statement(then);
+                if let Some(otherwise) = otherwise {
+                    self.statement(otherwise);
+                }
+            }
+            Statement::While { condition, body } => {
+                self.expr(condition);
+                self.loop_body(body);
+            }
+            Statement::For {
+                init,
+                test,
+                step,
+                body,
+            } => {
+                self.scopes.push(HashMap::new());
+                if let Some(init) = init {
+                    self.declaration(init);
+                }
+                if let Some(test) = test {
+                    self.expr(test);
+                }
+                for expr in step {
+                    self.expr(expr);
+                }
+                self.loop_body(body);
+                self.scopes.pop();
+            }
+            Statement::Block(block) => self.block(block),
+            Statement::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            Statement::Break if self.loops == 0 => {
+                self.error("`break` outside of a loop", &statement.span);
+            }
+            Statement::Continue if self.loops == 0 => {
+                self.error("`continue` outside of a loop", &statement.span);
+            }
+            Statement::Break | Statement::Continue => {}
+        }
+    }
+
+    fn loop_body(&mut self, body: &Spanned<Statement>) {
+        self.loops += 1;
+        self.statement(body);
+        self.loops -= 1;
+    }
+
+    fn expr(&mut self, expr: &Spanned<Expr>) {
+        match &expr.inner {
+            Expr::Int(_) | Expr::Char(_) | Expr::Bool(_) | Expr::Null | Expr::Sizeof(_) => {}
+            Expr::Ident(name) => match self.lookup(name) {
+                Some(Binding::Variable) => {}
+                Some(Binding::Function { .. }) => {
+                    self.error(format!("`{name}` is a function, not a variable"), &expr.span);
+                }
+                None => self.error(format!("unknown variable `{name}`"), &expr.span),
+            },
+            Expr::Call { name, args } => {
+                match self.lookup(&name.inner) {
+                    Some(Binding::Function { arity }) if arity != args.len() => {
+                        let message = format!(
+                            "`{}` takes {arity} arguments, but {} were given",
+                            name.inner,
+                            args.len(),
+                        );
+                        self.error(message, &expr.span);
+                    }
+                    Some(Binding::Function { .. }) => {}
+                    Some(Binding::Variable) => {
+                        self.error(format!("`{}` is not a function", name.inner), &name.span);
+                    }
+                    None => {
+                        self.error(format!("unknown function `{}`", name.inner), &name.span);
+                    }
+                }
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::Unary { op, value } => {
+                if op.is_mutating() && !value.inner.is_lvalue() {
+                    self.error("only lvalues can be incremented or decremented", &value.span);
+                }
+                self.expr(value);
+            }
+            Expr::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Assign { key, value } => {
+                if !key.inner.is_lvalue() {
+                    self.error("only lvalues can be assigned", &key.span);
+                }
+                self.expr(key);
+                self.expr(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> File {
+        PetitC::new()
+            .unwrap()
+            .parse(Path::new("<input>"), source)
+            .unwrap()
+    }
+
+    fn messages(source: &str) -> Vec<String> {
+        check(&parse(source))
+            .into_iter()
+            .map(|error| error.message)
+            .collect()
+    }
+
+    #[test]
+    fn typed_ast() {
+        let file = parse(
+            r#"
+#include <stdio.h>
+
+int *get(int *array, int i) {
+  return &(array[i]);
+}
+
+int main() {
+  int a = 3 + 3 * 2;
+  while (a) a--;
+}
+"#,
+        );
+        assert_eq!(file.functions.len(), 2);
+
+        let get = &file.functions[0];
+        assert_eq!(&*get.name.inner, "get");
+        assert_eq!(get.return_type.inner, Type::Pointer(Box::new(Type::Int)));
+        assert_eq!(get.parameters.len(), 2);
+        assert_eq!(&*get.parameters[1].name.inner, "i");
+        let DeclStatement::Statement(Spanned {
+            inner: Statement::Return(Some(value)),
+            ..
+        }) = &get.body.statements[0]
+        else {
+            panic!("expected a return, found {:?}", get.body.statements[0]);
+        };
+        // `&(array[i])` is `&*(array + i)`; the grammar binds `&` tighter than `[]`.
+        let Expr::Unary {
+            op: UnaryOp::Borrow,
+            value,
+        } = &value.inner
+        else {
+            panic!("expected a borrow, found {value:?}");
+        };
+        let Expr::Unary {
+            op: UnaryOp::Deref,
+            value,
+        } = &value.inner
+        else {
+            panic!("expected a dereference, found {value:?}");
+        };
+        assert!(matches!(
+            &value.inner,
+            Expr::Binary {
+                op: Spanned {
+                    inner: BinaryOp::Add,
+                    ..
+                },
+                ..
+            }
+        ));
+
+        let main = &file.functions[1];
+        let DeclStatement::Declaration(declaration) = &main.body.statements[0] else {
+            panic!("expected a declaration, found {:?}", main.body.statements[0]);
+        };
+        assert_eq!(&*declaration.name.inner, "a");
+        // `*` binds tighter than `+`.
+        let Some(Spanned {
+            inner: Expr::Binary { op, left, right },
+            ..
+        }) = &declaration.value
+        else {
+            panic!("expected a sum, found {:?}", declaration.value);
+        };
+        assert_eq!(op.inner, BinaryOp::Add);
+        assert!(matches!(&left.inner, Expr::Int(value) if &**value == "3"));
+        assert!(matches!(
+            &right.inner,
+            Expr::Binary {
+                op: Spanned {
+                    inner: BinaryOp::Mul,
+                    ..
+                },
+                ..
+            }
+        ));
+        assert!(matches!(
+            &main.body.statements[1],
+            DeclStatement::Statement(Spanned {
+                inner: Statement::While { .. },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn check_valid() {
+        let source = r#"
+int fact(int n) {
+  int result = 1;
+  for (int i = 1; i <= n; i++) {
+    if (i == 0) continue;
+    result = result * i;
+  }
+  return result;
+}
+
+int main() {
+  int twice(int x) { return 2 * x; }
+  int *p = NULL;
+  *p = twice(fact(3));
+  while (true) break;
+  return even(4);
+}
+
+bool even(int n) { return n % 2 == 0; }
+"#;
+        assert_eq!(messages(source), Vec::<String>::new());
+    }
+
+    #[test]
+    fn check_invalid() {
+        let source = r#"
+int f(int a, int a) {
+  break;
+  return g(a) + f(a);
+}
+
+int main() {
+  int x;
+  int x;
+  for (int i = 0; i < 10; i++) {}
+  3 = i;
+  x();
+  (x + 1)++;
+  while (true) {
+    void inner() { continue; }
+  }
+  return f;
+}
+"#;
+        assert_eq!(
+            messages(source),
+            vec![
+                "`a` is already declared in this scope",
+                "`break` outside of a loop",
+                "unknown function `g`",
+                "`f` takes 2 arguments, but 1 were given",
+                "`x` is already declared in this scope",
+                "only lvalues can be assigned",
+                "unknown variable `i`",
+                "`x` is not a function",
+                "only lvalues can be incremented or decremented",
+                "`continue` outside of a loop",
+                "`f` is a function, not a variable",
+            ]
+        );
+        assert_eq!(messages("int f() {}"), vec!["there is no `main` function"]);
+    }
+}
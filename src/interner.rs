@@ -0,0 +1,191 @@
+//! # Interner
+//!
+//! Names of terminals, non-terminals and attributes are interned once for the whole
+//! program, so that they are copied and compared as cheaply as integers, rather than
+//! cloned and compared byte by byte.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::rc::Rc;
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+/// Keeps a single copy of every string it is given. Copies are never freed, which
+/// is fine for names, as there are only as many as the grammars a program loads.
+#[derive(Debug, Default)]
+struct Interner {
+    strings: HashSet<&'static str>,
+}
+
+impl Interner {
+    fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(&interned) = self.strings.get(string) {
+            return Symbol(interned);
+        }
+        let interned: &'static str = Box::leak(Box::from(string));
+        self.strings.insert(interned);
+        Symbol(interned)
+    }
+}
+
+fn interner() -> &'static Mutex<Interner> {
+    static INTERNER: OnceLock<Mutex<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(Mutex::default)
+}
+
+/// # Summary
+///
+/// [`Symbol`] is an interned string. Two symbols are equal if and only if their
+/// strings are, which is checked in constant time. Symbols hash like their strings,
+/// so maps keyed by symbols can be looked up with a `&str`, and they are ordered
+/// like their strings. They are serialized as their strings.
+#[derive(Clone, Copy)]
+pub struct Symbol(&'static str);
+
+impl Symbol {
+    /// The symbol of `string`, interning it if it is new.
+    pub fn intern(string: &str) -> Self {
+        interner()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .intern(string)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self == other {
+            Ordering::Equal
+        } else {
+            self.0.cmp(other.0)
+        }
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        self.0
+    }
+}
+
+impl Borrow<str> for Symbol {
+    fn borrow(&self) -> &str {
+        self.0
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(string: &str) -> Self {
+        Self::intern(string)
+    }
+}
+
+impl From<Symbol> for Rc<str> {
+    fn from(symbol: Symbol) -> Self {
+        Rc::from(symbol.0)
+    }
+}
+
+impl From<Symbol> for String {
+    fn from(symbol: Symbol) -> Self {
+        symbol.0.to_string()
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.0, f)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string = String::deserialize(deserializer)?;
+        Ok(Self::intern(&string))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn intern() {
+        let expr = Symbol::intern("Expr");
+        assert_eq!(expr, Symbol::intern(&String::from("Expr")));
+        assert!(std::ptr::eq(expr.as_str(), Symbol::from("Expr").as_str()));
+        assert_ne!(expr, Symbol::intern("Term"));
+        assert_eq!(expr, "Expr");
+        assert!(Symbol::intern("Atom") < expr);
+
+        let map = HashMap::from([(expr, 1)]);
+        assert_eq!(map.get("Expr"), Some(&1));
+        assert_eq!(map.get("Term"), None);
+
+        let json = serde_json::to_string(&map).unwrap();
+        assert_eq!(json, r#"{"Expr":1}"#);
+        let back: HashMap<Symbol, i32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, map);
+    }
+}
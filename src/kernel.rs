@@ -0,0 +1,286 @@
+//! # Kernel
+//!
+//! The language-specific part of a Jupyter kernel, driven by a Beans grammar
+//! and an evaluator callback. It is only available with the `kernel` feature.
+//!
+//! [`Kernel`] handles the content of the requests of the Jupyter messaging
+//! protocol, and returns the content of the messages to send back, along with
+//! the channel to send them on. The transport (ZeroMQ sockets, signing, and
+//! message headers) is left to the embedding application.
+
+use crate::{
+    error::ErrorKind,
+    lexer::Lexer,
+    parser::{earley::EarleyParser, Parser, AST},
+    stream::StringStream,
+};
+use serde_json::{json, Value};
+use std::borrow::Borrow;
+use std::path::Path;
+
+/// The version of the messaging protocol implemented.
+pub const PROTOCOL_VERSION: &str = "5.3";
+
+/// The channel on which a message is sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    /// The reply to the request, on the socket it was received on.
+    Shell,
+    /// Side effects, broadcasted to every frontend.
+    IoPub,
+}
+
+/// A message to send back to the frontends.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reply {
+    pub channel: Channel,
+    pub msg_type: &'static str,
+    pub content: Value,
+}
+
+impl Reply {
+    fn new(channel: Channel, msg_type: &'static str, content: Value) -> Self {
+        Self {
+            channel,
+            msg_type,
+            content,
+        }
+    }
+}
+
+/// Information about the language, sent to the frontends.
+#[derive(Debug, Clone)]
+pub struct LanguageInfo {
+    pub name: String,
+    pub file_extension: String,
+}
+
+/// # Summary
+///
+/// [`Kernel`] parses the cells it is asked to execute with a pre-loaded
+/// lexer and parser, and hands the ASTs over to `evaluator`. The evaluator
+/// returns the text representation of the result, if there is any, or an
+/// error message. The lexer and the parser can be owned, borrowed, or shared.
+pub struct Kernel<E, L = Lexer, P = EarleyParser> {
+    lexer: L,
+    parser: P,
+    evaluator: E,
+    language: LanguageInfo,
+    execution_count: usize,
+}
+
+impl<E, L, P> std::fmt::Debug for Kernel<E, L, P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Kernel")
+            .field("language", &self.language)
+            .field("execution_count", &self.execution_count)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E, L, P> Kernel<E, L, P>
+where
+    E: FnMut(AST) -> Result<Option<String>, String>,
+    L: Borrow<Lexer>,
+    P: Borrow<EarleyParser>,
+{
+    pub fn new(lexer: L, parser: P, language: LanguageInfo, evaluator: E) -> Self {
+        Self {
+            lexer,
+            parser,
+            evaluator,
+            language,
+            execution_count: 0,
+        }
+    }
+
+    /// Handle a request of type `msg_type`. The busy and idle status
+    /// messages are included in the replies. Unknown requests are ignored.
+    pub fn handle(&mut self, msg_type: &str, content: &Value) -> Vec<Reply> {
+        let mut replies = vec![Reply::new(
+            Channel::IoPub,
+            "status",
+            json!({ "execution_state": "busy" }),
+        )];
+        match msg_type {
+            "kernel_info_request" => replies.push(Reply::new(
+                Channel::Shell,
+                "kernel_info_reply",
+                self.kernel_info(),
+            )),
+            "execute_request" => self.execute(content, &mut replies),
+            "is_complete_request" => replies.push(Reply::new(
+                Channel::Shell,
+                "is_complete_reply",
+                self.is_complete(content["code"].as_str().unwrap_or_default()),
+            )),
+            "shutdown_request" => replies.push(Reply::new(
+                Channel::Shell,
+                "shutdown_reply",
+                json!({ "status": "ok", "restart": content["restart"] }),
+            )),
+            _ => {}
+        }
+        replies.push(Reply::new(
+            Channel::IoPub,
+            "status",
+            json!({ "execution_state": "idle" }),
+        ));
+        replies
+    }
+
+    fn kernel_info(&self) -> Value {
+        json!({
+            "status": "ok",
+            "protocol_version": PROTOCOL_VERSION,
+            "implementation": "beans",
+            "implementation_version": env!("CARGO_PKG_VERSION"),
+            "language_info": {
+                "name": self.language.name,
+                "version": "",
+                "mimetype": "text/plain",
+                "file_extension": self.language.file_extension,
+            },
+            "banner": format!("{} (beans)", self.language.name),
+        })
+    }
+
+    fn execute(&mut self, content: &Value, replies: &mut Vec<Reply>) {
+        let code = content["code"].as_str().unwrap_or_default();
+        let silent = content["silent"].as_bool().unwrap_or(false);
+        if !silent {
+            self.execution_count += 1;
+        }
+        let execution_count = self.execution_count;
+        replies.push(Reply::new(
+            Channel::IoPub,
+            "execute_input",
+            json!({ "code": code, "execution_count": execution_count }),
+        ));
+        let mut stream = StringStream::new(Path::new("<cell>"), code);
+        let mut input = self.lexer.borrow().lex(&mut stream);
+        let result = match self.parser.borrow().parse(&mut input) {
+            Ok(result) => (self.evaluator)(result.tree).map_err(|message| ("Error", message)),
+            Err(error) => Err(("SyntaxError", error.to_string().trim_end().to_string())),
+        };
+        match result {
+            Ok(output) => {
+                if let (Some(output), false) = (output, silent) {
+                    replies.push(Reply::new(
+                        Channel::IoPub,
+                        "execute_result",
+                        json!({
+                            "execution_count": execution_count,
+                            "data": { "text/plain": output },
+                            "metadata": {},
+                        }),
+                    ));
+                }
+                replies.push(Reply::new(
+                    Channel::Shell,
+                    "execute_reply",
+                    json!({
+                        "status": "ok",
+                        "execution_count": execution_count,
+                        "payload": [],
+                        "user_expressions": {},
+                    }),
+                ));
+            }
+            Err((name, message)) => {
+                let error = json!({
+                    "ename": name,
+                    "evalue": message,
+                    "traceback": [message],
+                });
+                replies.push(Reply::new(Channel::IoPub, "error", error.clone()));
+                let mut reply = error;
+                reply["status"] = json!("error");
+                reply["execution_count"] = json!(execution_count);
+                replies.push(Reply::new(Channel::Shell, "execute_reply", reply));
+            }
+        }
+    }
+
+    /// A cell is incomplete if it is a valid prefix of the language, so that
+    /// frontends can ask for more lines instead of executing it.
+    fn is_complete(&self, code: &str) -> Value {
+        let mut stream = StringStream::new(Path::new("<cell>"), code);
+        let mut input = self.lexer.borrow().lex(&mut stream);
+        match self.parser.borrow().parse(&mut input) {
+            Ok(_) => json!({ "status": "complete" }),
+            Err(error) => match *error.kind {
+                ErrorKind::ParseError { found: None, .. } => {
+                    json!({ "status": "incomplete", "indent": "" })
+                }
+                _ => json!({ "status": "invalid" }),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Buildable;
+    use crate::parser::earley::EarleyGrammar;
+
+    fn kernel() -> Kernel<impl FnMut(AST) -> Result<Option<String>, String>> {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "NUMBER ::= ([0-9]+)\nPLUS ::= \\+",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@Sum ::= Sum@left PLUS NUMBER.0@right <>\n NUMBER.0@self <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let language = LanguageInfo {
+            name: String::from("sum"),
+            file_extension: String::from(".sum"),
+        };
+        Kernel::new(
+            lexer,
+            EarleyParser::new(grammar),
+            language,
+            |ast: AST| Ok(ast.span().map(|span| format!("{}", span.end_byte()))),
+        )
+    }
+
+    fn find<'a>(replies: &'a [Reply], msg_type: &str) -> &'a Value {
+        &replies
+            .iter()
+            .find(|reply| reply.msg_type == msg_type)
+            .unwrap()
+            .content
+    }
+
+    #[test]
+    fn execute() {
+        let mut kernel = kernel();
+        let replies = kernel.handle("execute_request", &json!({ "code": "1+2" }));
+        assert_eq!(replies.first().unwrap().content["execution_state"], "busy");
+        assert_eq!(replies.last().unwrap().content["execution_state"], "idle");
+        assert_eq!(find(&replies, "execute_reply")["status"], "ok");
+        assert_eq!(find(&replies, "execute_result")["execution_count"], 1);
+
+        let replies = kernel.handle("execute_request", &json!({ "code": "1+" }));
+        let reply = find(&replies, "execute_reply");
+        assert_eq!(reply["status"], "error");
+        assert_eq!(reply["ename"], "SyntaxError");
+        assert_eq!(reply["execution_count"], 2);
+    }
+
+    #[test]
+    fn is_complete() {
+        let mut kernel = kernel();
+        for (code, status) in [("1+2", "complete"), ("1+", "incomplete"), ("+", "invalid")] {
+            let replies = kernel.handle("is_complete_request", &json!({ "code": code }));
+            assert_eq!(find(&replies, "is_complete_reply")["status"], status);
+        }
+    }
+}
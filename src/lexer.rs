@@ -8,13 +8,35 @@
 //!  - [`Token`] is the basic interface to deal with the result of the tokenization. The goal of a lexer is to turn a stream
 //!    characters into a stream of tokens.
 //!  - [`LexedStream`] is the interface for generating a stream of [`Token`] from a [`Lexer`] and a [`StringStream`].
+//!  - [`AllowedSet`] is the set of terminals that the [`LexedStream`] may produce next.
 //!
 //!  [`StringStream`]: crate::stream::StringStream
 
+mod allowed;
 mod ast;
 mod grammar;
+pub mod layout;
+// With `hardened`, lexing does not panic, whatever the input.
+#[cfg_attr(
+    all(feature = "hardened", not(test)),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::unreachable,
+        clippy::todo,
+        clippy::unimplemented
+    )
+)]
 #[allow(clippy::module_inception)]
 mod lexer;
+mod process;
+pub mod editor;
+pub mod format;
+pub mod textmate;
 
+pub use allowed::{AllowedGroups, AllowedSet};
 pub use grammar::{Grammar, Ignores};
-pub use lexer::{LexedStream, Lexer, TerminalId, Token};
+pub use layout::Layout;
+pub use lexer::{Checkpoint, Disambiguation, LexedStream, Lexer, TerminalId, Token};
+pub use process::{Converter, PostProcessor, Processor};
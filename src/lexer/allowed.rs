@@ -0,0 +1,211 @@
+use super::{Grammar, TerminalId};
+use crate::{
+    error::{ErrorKind, Result},
+    regex::Allowed,
+};
+use std::collections::{BTreeSet, HashMap};
+
+/// # Summary
+///
+/// [`AllowedSet`] is a set of terminals the lexer is allowed to produce, which
+/// is turned into an [`Allowed`] when asking for the next token.
+///
+/// The lexer skips the ignored terminals it comes across, but only the allowed
+/// ones: a set that does not contain them makes the lexer fail on whitespace
+/// or comments, for instance. [`AllowedSet::with_ignored`] adds them, which is
+/// what the parsers do. [`AllowedSet::all`] contains every terminal, ignored
+/// ones included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllowedSet {
+    /// The allowed terminals, or `None` if every terminal is.
+    terminals: Option<BTreeSet<TerminalId>>,
+}
+
+impl AllowedSet {
+    /// The set of every terminal.
+    pub fn all() -> Self {
+        Self { terminals: None }
+    }
+
+    /// The empty set.
+    pub fn none() -> Self {
+        Self {
+            terminals: Some(BTreeSet::new()),
+        }
+    }
+
+    pub fn from_terminals(terminals: impl IntoIterator<Item = TerminalId>) -> Self {
+        Self {
+            terminals: Some(terminals.into_iter().collect()),
+        }
+    }
+
+    /// The set of the terminals called `names` in `grammar`.
+    pub fn from_names<'a>(
+        grammar: &Grammar,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self> {
+        let terminals = names
+            .into_iter()
+            .map(|name| {
+                grammar.id(name).ok_or_else(|| {
+                    ErrorKind::UnknownTerminal {
+                        name: name.to_string(),
+                    }
+                    .into()
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            terminals: Some(terminals),
+        })
+    }
+
+    /// The set of the terminals `grammar` ignores.
+    pub fn ignored(grammar: &Grammar) -> Self {
+        Self::from_terminals(grammar.default_allowed())
+    }
+
+    /// Add the terminal `terminal`.
+    pub fn with(mut self, terminal: TerminalId) -> Self {
+        if let Some(terminals) = &mut self.terminals {
+            terminals.insert(terminal);
+        }
+        self
+    }
+
+    /// Add the terminals `grammar` ignores, so that the lexer can skip them.
+    pub fn with_ignored(self, grammar: &Grammar) -> Self {
+        self.union(&Self::ignored(grammar))
+    }
+
+    pub fn union(self, other: &Self) -> Self {
+        match (self.terminals, &other.terminals) {
+            (Some(mut terminals), Some(others)) => {
+                terminals.extend(others);
+                Self {
+                    terminals: Some(terminals),
+                }
+            }
+            _ => Self::all(),
+        }
+    }
+
+    pub fn intersection(self, other: &Self) -> Self {
+        match (self.terminals, &other.terminals) {
+            (Some(terminals), Some(others)) => Self {
+                terminals: Some(terminals.intersection(others).copied().collect()),
+            },
+            (None, others) => Self {
+                terminals: others.clone(),
+            },
+            (terminals, None) => Self { terminals },
+        }
+    }
+
+    pub fn contains(&self, terminal: TerminalId) -> bool {
+        self.terminals
+            .as_ref()
+            .is_none_or(|terminals| terminals.contains(&terminal))
+    }
+
+    /// Iterate over the terminals of the set, or return `None` if every terminal
+    /// is in it.
+    pub fn terminals(&self) -> Option<impl Iterator<Item = TerminalId> + '_> {
+        self.terminals
+            .as_ref()
+            .map(|terminals| terminals.iter().copied())
+    }
+}
+
+impl From<&AllowedSet> for Allowed {
+    fn from(set: &AllowedSet) -> Self {
+        match set.terminals() {
+            Some(terminals) => Allowed::Some(terminals.collect()),
+            None => Allowed::All,
+        }
+    }
+}
+
+impl From<AllowedSet> for Allowed {
+    fn from(set: AllowedSet) -> Self {
+        Self::from(&set)
+    }
+}
+
+/// # Summary
+///
+/// [`AllowedGroups`] names sets of terminals, such as the terminals allowed
+/// in a given lexing mode, so that they are built once and looked up by name.
+#[derive(Debug, Clone, Default)]
+pub struct AllowedGroups {
+    groups: HashMap<String, AllowedSet>,
+}
+
+impl AllowedGroups {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Name `set` as `name`, replacing the group that had the same name, if any.
+    pub fn with_group(mut self, name: impl Into<String>, set: AllowedSet) -> Self {
+        self.groups.insert(name.into(), set);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AllowedSet> {
+        self.groups.get(name)
+    }
+
+    /// The union of the groups called `names`, or `None` if one of them does not
+    /// exist.
+    pub fn union<'a>(&self, names: impl IntoIterator<Item = &'a str>) -> Option<AllowedSet> {
+        names
+            .into_iter()
+            .try_fold(AllowedSet::none(), |set, name| Some(set.union(self.get(name)?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Buildable;
+    use crate::lexer::Lexer;
+    use crate::stream::StringStream;
+    use std::path::Path;
+
+    #[test]
+    fn allowed_set() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "ignore SPACE ::= \\s+\nNUMBER ::= [0-9]+\nID ::= [a-z]+\nPLUS ::= \\+",
+        ))
+        .unwrap();
+        let grammar = lexer.grammar();
+        let id = |name: &str| grammar.id(name).unwrap();
+        let operands = AllowedSet::from_names(grammar, ["NUMBER", "ID"]).unwrap();
+        assert!(AllowedSet::from_names(grammar, ["MINUS"]).is_err());
+        assert!(operands.contains(id("ID")) && !operands.contains(id("PLUS")));
+        let numbers = operands.clone().intersection(&AllowedSet::none().with(id("NUMBER")));
+        assert_eq!(numbers.terminals().unwrap().collect::<Vec<_>>(), [id("NUMBER")]);
+        assert_eq!(operands.clone().union(&AllowedSet::all()), AllowedSet::all());
+        assert_eq!(AllowedSet::all().intersection(&operands), operands);
+
+        let groups = AllowedGroups::new()
+            .with_group("operands", operands.clone())
+            .with_group("operators", AllowedSet::from_terminals([id("PLUS")]));
+        let expression = groups.union(["operands", "operators"]).unwrap();
+        assert!(expression.contains(id("PLUS")) && expression.contains(id("NUMBER")));
+        assert!(groups.union(["operands", "keywords"]).is_none());
+
+        let mut stream = StringStream::new(Path::new("<input>"), "x 1");
+        let mut input = lexer.lex(&mut stream);
+        assert_eq!(input.next(&operands).unwrap().unwrap().name(), "ID");
+        assert!(input.next(&operands).is_err());
+        let mut stream = StringStream::new(Path::new("<input>"), "x 1");
+        let mut input = lexer.lex(&mut stream);
+        let operands = operands.with_ignored(grammar);
+        assert_eq!(input.next(&operands).unwrap().unwrap().name(), "ID");
+        assert_eq!(input.next(&operands).unwrap().unwrap().name(), "NUMBER");
+    }
+}
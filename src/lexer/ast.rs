@@ -24,11 +24,26 @@ impl Tree for Ast {
 #[derive(Debug)]
 pub(crate) struct Terminal {
     pub ignore: Spanned<bool>,
+    /// The channel its tokens are kept on, rather than given to the parser, if any.
+    pub channel: Option<Spanned<Rc<str>>>,
+    /// Whether its tokens are only produced where the parser expects them.
+    pub soft: Spanned<bool>,
+    /// Whether its regex is made of an opening and a closing delimiter, which its
+    /// tokens may nest.
+    pub balanced: Spanned<bool>,
     pub keyword: Spanned<bool>,
+    pub insensitive: Spanned<bool>,
     pub unwanted: Spanned<bool>,
     pub name: Spanned<Rc<str>>,
     pub regex: Spanned<Rc<str>>,
     pub comment: Option<Spanned<Rc<str>>>,
+    /// The terminal this one is split from, if any. Then `regex` is the text
+    /// of the matches of the family that stand for this terminal.
+    pub family: Option<Spanned<Rc<str>>>,
+    /// The names of the post-processors of the attributes of its tokens.
+    pub processors: Vec<Spanned<Rc<str>>>,
+    /// Its priority over the terminals that match the same text, if it has one.
+    pub priority: Option<Spanned<Rc<str>>>,
     pub span: Span,
 }
 
@@ -37,12 +52,31 @@ impl Tree for Terminal {
         let mut node = node!(ast);
         Ok(Self {
             ignore: get!(node => ignore).to_tree()?,
+            channel: get!(node => channel)
+                .to_tree::<Spanned<Option<Channel>>>()?
+                .transpose()
+                .map(|x| x.map(|y| y.0).merge()),
+            soft: get!(node => soft).to_tree()?,
+            balanced: get!(node => balanced).to_tree()?,
             keyword: get!(node => keyword).to_tree()?,
+            insensitive: get!(node => insensitive).to_tree()?,
             unwanted: get!(node => unwanted).to_tree()?,
             comment: get!(node => comment)
                 .to_tree::<Spanned<Option<Comment>>>()?
                 .transpose()
                 .map(|x| x.map(|y| y.0).merge()),
+            family: get!(node => family)
+                .to_tree::<Spanned<Option<Family>>>()?
+                .transpose()
+                .map(|x| x.map(|y| y.0).merge()),
+            processors: get!(node => processors)
+                .to_tree::<Spanned<Option<Processors>>>()?
+                .inner
+                .map_or_else(Vec::new, |processors| processors.processors),
+            priority: get!(node => priority)
+                .to_tree::<Spanned<Option<Priority>>>()?
+                .transpose()
+                .map(|x| x.map(|y| y.0).merge()),
             name: spanned_value!(node => name),
             regex: spanned_value!(node => value),
             span: span!(node),
@@ -66,3 +100,79 @@ impl Tree for Comment {
         &self.0.span
     }
 }
+
+struct Channel(Spanned<Rc<str>>);
+
+impl Tree for Channel {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self(spanned_value!(node => value)))
+    }
+
+    fn span(&self) -> &Span {
+        &self.0.span
+    }
+}
+
+struct Processors {
+    processors: Vec<Spanned<Rc<str>>>,
+    span: Span,
+}
+
+impl Tree for Processors {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self {
+            processors: get!(node => processors)
+                .to_tree::<Spanned<Vec<_>>>()?
+                .inner
+                .into_iter()
+                .map(|processor: Processor| processor.0)
+                .collect(),
+            span: span!(node),
+        })
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+struct Processor(Spanned<Rc<str>>);
+
+impl Tree for Processor {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self(spanned_value!(node => name)))
+    }
+
+    fn span(&self) -> &Span {
+        &self.0.span
+    }
+}
+
+struct Priority(Spanned<Rc<str>>);
+
+impl Tree for Priority {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self(spanned_value!(node => value)))
+    }
+
+    fn span(&self) -> &Span {
+        &self.0.span
+    }
+}
+
+struct Family(Spanned<Rc<str>>);
+
+impl Tree for Family {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self(spanned_value!(node => value)))
+    }
+
+    fn span(&self) -> &Span {
+        &self.0.span
+    }
+}
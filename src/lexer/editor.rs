@@ -0,0 +1,220 @@
+//! # Editor
+//!
+//! Export of the files an editor extension needs to support a language: the
+//! `language-configuration.json` of VSCode, whose comments and brackets are
+//! guessed from the terminals of a lexer grammar, and the skeleton of the
+//! manifest of the extension.
+
+use super::textmate::read_terminals;
+use crate::{error::Result, stream::StringStream};
+use serde::Serialize;
+use serde_json::json;
+
+/// The comments of a language, as a `language-configuration.json` declares them.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Comments {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_comment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_comment: Option<[String; 2]>,
+}
+
+/// # Summary
+///
+/// The configuration of a language in VSCode, as serialized in
+/// `language-configuration.json` files.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LanguageConfiguration {
+    pub comments: Comments,
+    pub brackets: Vec<[String; 2]>,
+    pub auto_closing_pairs: Vec<[String; 2]>,
+    pub surrounding_pairs: Vec<[String; 2]>,
+}
+
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+impl LanguageConfiguration {
+    /// Build the configuration from the source of a lexer grammar. Comments are
+    /// the ignored terminals whose name contains `COMMENT`, and brackets the pairs
+    /// of terminals that match exactly a pair of parentheses, square brackets or
+    /// braces.
+    pub fn build_from_plain(source: StringStream) -> Result<Self> {
+        let terminals = read_terminals(source)?;
+        let mut comments = Comments::default();
+        for terminal in terminals.iter().filter(|terminal| {
+            terminal.ignore.inner && terminal.name.inner.to_uppercase().contains("COMMENT")
+        }) {
+            let (prefix, suffix) = literal_ends(&terminal.regex.inner);
+            if prefix.is_empty() {
+                continue;
+            }
+            if suffix.is_empty() {
+                comments.line_comment.get_or_insert(prefix);
+            } else {
+                comments.block_comment.get_or_insert([prefix, suffix]);
+            }
+        }
+        let matches = |c: char| {
+            terminals
+                .iter()
+                .any(|terminal| literal(&terminal.regex.inner) == Some(c))
+        };
+        let brackets = BRACKETS
+            .into_iter()
+            .filter(|&(open, close)| matches(open) && matches(close))
+            .map(|(open, close)| [open.to_string(), close.to_string()])
+            .collect::<Vec<_>>();
+        let mut auto_closing_pairs = brackets.clone();
+        let strings = terminals.iter().filter(|terminal| {
+            let name = terminal.name.inner.to_uppercase();
+            name.contains("STRING") || name.contains("CHAR")
+        });
+        for terminal in strings {
+            let (prefix, suffix) = literal_ends(&terminal.regex.inner);
+            if (prefix == "\"" || prefix == "'") && prefix == suffix {
+                let pair = [prefix, suffix];
+                if !auto_closing_pairs.contains(&pair) {
+                    auto_closing_pairs.push(pair);
+                }
+            }
+        }
+        Ok(Self {
+            comments,
+            brackets,
+            surrounding_pairs: auto_closing_pairs.clone(),
+            auto_closing_pairs,
+        })
+    }
+}
+
+/// The skeleton of the `package.json` of a VSCode extension for the language
+/// `name`, whose files have the extensions `file_types`. It refers to
+/// `language-configuration.json`, and to the TextMate grammar
+/// `syntaxes/{name}.tmLanguage.json`.
+pub fn extension_manifest(name: &str, file_types: &[String]) -> serde_json::Value {
+    json!({
+        "name": name,
+        "displayName": name,
+        "version": "0.0.1",
+        "engines": { "vscode": "^1.60.0" },
+        "categories": ["Programming Languages"],
+        "contributes": {
+            "languages": [{
+                "id": name,
+                "aliases": [name],
+                "extensions": file_types
+                    .iter()
+                    .map(|file_type| format!(".{file_type}"))
+                    .collect::<Vec<_>>(),
+                "configuration": "./language-configuration.json",
+            }],
+            "grammars": [{
+                "language": name,
+                "scopeName": format!("source.{name}"),
+                "path": format!("./syntaxes/{name}.tmLanguage.json"),
+            }],
+        },
+    })
+}
+
+/// Split a Beans regex into pieces, each one being either a literal character, or
+/// `None` for anything else. Quantified characters are not literal.
+fn pieces(regex: &str) -> Vec<Option<char>> {
+    let mut pieces = Vec::new();
+    let mut chars = regex.chars();
+    while let Some(chr) = chars.next() {
+        match chr {
+            '\\' => match chars.next() {
+                Some(escaped) if !escaped.is_alphanumeric() => pieces.push(Some(escaped)),
+                _ => pieces.push(None),
+            },
+            '[' => {
+                while let Some(chr) = chars.next() {
+                    match chr {
+                        '\\' => {
+                            chars.next();
+                        }
+                        ']' => break,
+                        _ => {}
+                    }
+                }
+                pieces.push(None);
+            }
+            '*' | '+' | '?' => {
+                if let Some(last) = pieces.last_mut() {
+                    *last = None;
+                }
+                pieces.push(None);
+            }
+            '(' | ')' | '|' | '.' | '^' | '$' => pieces.push(None),
+            _ => pieces.push(Some(chr)),
+        }
+    }
+    pieces
+}
+
+/// The literal text `regex` starts with, and the one it ends with, which are empty
+/// if the regex is a literal as a whole.
+fn literal_ends(regex: &str) -> (String, String) {
+    let pieces = pieces(regex);
+    let prefix = pieces.iter().map_while(|&piece| piece).collect::<String>();
+    if prefix.chars().count() == pieces.len() {
+        return (String::new(), String::new());
+    }
+    let mut suffix = pieces
+        .iter()
+        .rev()
+        .map_while(|&piece| piece)
+        .collect::<Vec<_>>();
+    suffix.reverse();
+    (prefix, suffix.into_iter().collect())
+}
+
+/// The character `regex` matches, if it only matches a single one.
+fn literal(regex: &str) -> Option<char> {
+    match pieces(regex)[..] {
+        [Some(chr)] => Some(chr),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn language_configuration() {
+        let configuration = LanguageConfiguration::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            r#"ignore SPACE ::= \s+
+ignore LINECOMMENT ::= //[^\n]*
+ignore BLOCKCOMMENT ::= /\*([^*]|\*[^/])*\*/
+STRING ::= "([^"\\]|\\.)*"
+LPAR ::= \(
+RPAR ::= \)
+LBRACE ::= {
+RBRACE ::= }
+LBRACKET ::= \[
+TIMES ::= \*"#,
+        ))
+        .unwrap();
+        assert_eq!(
+            configuration.comments,
+            Comments {
+                line_comment: Some(String::from("//")),
+                block_comment: Some([String::from("/*"), String::from("*/")]),
+            }
+        );
+        let pair = |open: &str, close: &str| [open.to_string(), close.to_string()];
+        assert_eq!(configuration.brackets, [pair("(", ")"), pair("{", "}")]);
+        assert_eq!(
+            configuration.auto_closing_pairs,
+            [pair("(", ")"), pair("{", "}"), pair("\"", "\"")]
+        );
+        let manifest = extension_manifest("toy", &[String::from("toy")]);
+        assert_eq!(manifest["contributes"]["languages"][0]["extensions"][0], ".toy");
+    }
+}
@@ -0,0 +1,162 @@
+//! # Format
+//!
+//! The canonical layout of lexer grammars, which `beans fmt` writes: each terminal
+//! is on its own line, after its comment, and the `::=` of the terminals of a
+//! paragraph are aligned. Paragraphs are kept, separated by a single blank line,
+//! as are the `include` and `import` lines, and the regexes, which are left as they
+//! are written.
+
+use std::path::Path;
+
+use super::{ast::Terminal, textmate::read_terminals};
+use crate::{
+    error::Result,
+    stream::{blank_inclusions, StringStream},
+};
+
+/// Format the lexer grammar `text`, read from the file `path`, in the canonical
+/// layout.
+pub fn format_lexer_grammar(path: &Path, text: &str) -> Result<String> {
+    let (source, inclusions) = blank_inclusions(text);
+    let terminals = read_terminals(StringStream::new(path, source.as_str()))?;
+    // Each line, or terminal, along with the bytes of the source it comes from.
+    let mut blocks = inclusions
+        .into_iter()
+        .map(|(offset, line)| (offset..offset + line.len(), Err(line)))
+        .collect::<Vec<_>>();
+    blocks.extend(
+        terminals
+            .iter()
+            .map(|terminal| (terminal.span.byte_range(), Ok(terminal))),
+    );
+    blocks.sort_by_key(|(range, _)| range.start);
+
+    let mut paragraphs = Vec::new();
+    let mut paragraph: Vec<std::result::Result<&Terminal, &str>> = Vec::new();
+    let mut last_end = None;
+    for (range, block) in blocks {
+        let blank_line = |end: usize| source[end..range.start].matches('\n').count() > 1;
+        if last_end.is_some_and(blank_line) {
+            paragraphs.push(std::mem::take(&mut paragraph));
+        }
+        paragraph.push(block);
+        last_end = Some(range.end);
+    }
+    paragraphs.push(paragraph);
+
+    let mut formatted = Vec::new();
+    for paragraph in paragraphs.iter().filter(|paragraph| !paragraph.is_empty()) {
+        let width = paragraph
+            .iter()
+            .filter_map(|block| block.ok())
+            .map(|terminal| head(terminal).chars().count())
+            .max()
+            .unwrap_or(0);
+        let mut lines = Vec::new();
+        for block in paragraph {
+            match block {
+                Ok(terminal) => {
+                    if let Some(comment) = &terminal.comment {
+                        lines.push(format!("({})", comment.inner));
+                    }
+                    let head = head(terminal);
+                    let line = format!("{head:width$} ::= {}", terminal.regex.inner);
+                    if terminal.regex.inner.is_empty() {
+                        lines.push(line.trim_end().to_string());
+                    } else {
+                        lines.push(line);
+                    }
+                }
+                Err(line) => lines.push(line.to_string()),
+            }
+        }
+        formatted.push(lines.join("\n"));
+    }
+    let mut formatted = formatted.join("\n\n");
+    if !formatted.is_empty() {
+        formatted.push('\n');
+    }
+    Ok(formatted)
+}
+
+/// What comes before the `::=` of `terminal`: its flags, its name, and its family,
+/// post-processors and priority.
+fn head(terminal: &Terminal) -> String {
+    let mut words = Vec::new();
+    if terminal.ignore.inner {
+        words.push(String::from("ignore"));
+    }
+    if let Some(channel) = &terminal.channel {
+        words.push(format!("channel ({})", channel.inner));
+    }
+    let flags = [
+        (terminal.soft.inner, "soft"),
+        (terminal.balanced.inner, "balanced"),
+        (terminal.keyword.inner, "keyword"),
+        (terminal.insensitive.inner, "insensitive"),
+        (terminal.unwanted.inner, "unwanted"),
+    ];
+    words.extend(
+        flags
+            .into_iter()
+            .filter(|&(set, _)| set)
+            .map(|(_, flag)| flag.to_string()),
+    );
+    words.push(terminal.name.inner.to_string());
+    if let Some(family) = &terminal.family {
+        words.push(format!("in {}", family.inner));
+    }
+    if !terminal.processors.is_empty() {
+        words.push(String::from("with"));
+        words.extend(terminal.processors.iter().map(|name| name.inner.to_string()));
+    }
+    if let Some(priority) = &terminal.priority {
+        words.push(format!("priority {}", priority.inner));
+    }
+    words.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(text: &str) -> String {
+        format_lexer_grammar(Path::new("<lexer input>"), text).unwrap()
+    }
+
+    #[test]
+    fn aligned_terminals() {
+        let formatted = format(
+            "import \"common.lx\"\nignore   SPACE ::=   \\s+\n(a number)\nINT   with  trim \
+             ::= [0-9]+\nkeyword IF::=if\n\n\n\nID ::= [a-z]+\nNUMBER ::= [0-9]+",
+        );
+        assert_eq!(
+            formatted,
+            "import \"common.lx\"\n\
+             ignore SPACE  ::= \\s+\n\
+             (a number)\n\
+             INT with trim ::= [0-9]+\n\
+             keyword IF    ::= if\n\
+             \n\
+             ID     ::= [a-z]+\n\
+             NUMBER ::= [0-9]+\n"
+        );
+        assert_eq!(format(&formatted), formatted);
+    }
+
+    #[test]
+    fn bundled_grammars() {
+        for grammar in [
+            include_str!("lexer.lx"),
+            include_str!("../parser/parser.lx"),
+            include_str!("../parser/gmrs/dummy.lx"),
+            include_str!("../parser/gmrs/earley.lx"),
+            include_str!("../parser/gmrs/petitc.lx"),
+            include_str!("../../benches/grammars/arithmetic.lx"),
+            include_str!("../../benches/grammars/keywords.lx"),
+            include_str!("../../benches/grammars/lists.lx"),
+        ] {
+            assert_eq!(format(grammar), grammar);
+        }
+    }
+}
@@ -1,17 +1,25 @@
-use super::{ast::Ast, TerminalId};
+use super::{ast::Ast, Processor, TerminalId};
 use crate::{
     build_system,
-    builder::Buildable,
+    builder::{deserialize_compiled, Buildable, Compiled, CompiledKind},
     error::{Error, ErrorKind, Result},
+    interner::Symbol,
     parser::{Parser, AST},
-    regex::{CompiledRegex, RegexBuilder},
+    regex::{Allowed, CompiledRegex, RegexBuilder, RegexError},
+    span::Span,
+    stable::{stable_hash, StableHasher},
     stream::StringStream,
     typed::Tree,
 };
-use bincode::deserialize;
+use itertools::Itertools;
 use newty::newty;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, path::PathBuf, rc::Rc};
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    rc::Rc,
+};
 
 newty! {
     pub id TokenId
@@ -31,40 +39,107 @@ newty! {
     pub map Descriptions(Rc<str>)[TerminalId]
 }
 
+newty! {
+    #[derive(Serialize, Deserialize)]
+    pub map Processors(Vec<Processor>)[TerminalId]
+}
+
+/// How the regex of a terminal was written, which its compiled form no longer tells.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Source {
+    regex: Rc<str>,
+    keyword: bool,
+    insensitive: bool,
+}
+
 /// A grammar for a Beans lexer.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Grammar {
     pattern: CompiledRegex,
-    names: Vec<String>,
+    names: Vec<Symbol>,
+    /// The span of the definition of each terminal.
+    spans: Vec<Span>,
     ignores: Ignores,
+    /// The terminals whose tokens are only produced where they are allowed, and
+    /// skipped elsewhere.
+    soft_terminals: Vec<TerminalId>,
     errors: Errors,
     descriptions: Descriptions,
+    /// The post-processors of the attributes of the tokens of each terminal.
+    processors: Processors,
     default_allowed: Vec<TerminalId>,
-    name_map: HashMap<String, TerminalId>,
+    name_map: HashMap<Symbol, TerminalId>,
+    /// For each family, the terminal each text it is split into stands for.
+    families: HashMap<TerminalId, HashMap<String, TerminalId>>,
+    member_of: HashMap<TerminalId, TerminalId>,
+    /// The named groups of the regex of each terminal, with their index.
+    captures: Vec<Vec<(Symbol, usize)>>,
+    /// The opening and the closing delimiters of each balanced terminal, as the
+    /// regexes 0 and 1. The regex of the terminal in `pattern` is the opening one.
+    delimiters: HashMap<TerminalId, CompiledRegex>,
+    /// The priority of the terminals that have one, which is otherwise 0.
+    priorities: HashMap<TerminalId, u32>,
+    /// The channel of the tokens of each terminal that has one.
+    channels: HashMap<TerminalId, Rc<str>>,
+    /// How each terminal was written, or nothing if the grammar was not built from
+    /// a source.
+    sources: Vec<Source>,
 }
 
 impl Grammar {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         pattern: CompiledRegex,
-        names: Vec<String>,
+        names: Vec<Symbol>,
+        spans: Vec<Span>,
         ignores: Ignores,
+        soft_terminals: Vec<TerminalId>,
         errors: Errors,
         descriptions: Descriptions,
+        processors: Processors,
+        families: HashMap<TerminalId, HashMap<String, TerminalId>>,
+        delimiters: HashMap<TerminalId, CompiledRegex>,
+        priorities: HashMap<TerminalId, u32>,
+        channels: HashMap<TerminalId, Rc<str>>,
     ) -> Self {
         let mut name_map = HashMap::new();
-        for (i, name) in names.iter().enumerate() {
-            let id = TerminalId(i);
-            name_map.insert(name.clone(), id);
+        for (i, &name) in names.iter().enumerate() {
+            name_map.insert(name, TerminalId(i));
         }
         let default_allowed = ignores.0.ones().map(TerminalId).collect();
+        let member_of: HashMap<_, _> = families
+            .iter()
+            .flat_map(|(&family, members)| members.values().map(move |&id| (id, family)))
+            .collect();
+        // Members of families have no regex of their own, but that of the family.
+        let captures = (0..names.len())
+            .map(|i| {
+                let id = TerminalId(i);
+                pattern
+                    .group_names(member_of.get(&id).copied().unwrap_or(id))
+                    .iter()
+                    .map(|(name, index)| (Symbol::intern(name), *index))
+                    .collect()
+            })
+            .collect();
         Self {
             pattern,
             names,
+            spans,
             ignores,
+            soft_terminals,
             errors,
             descriptions,
+            processors,
             default_allowed,
             name_map,
+            families,
+            member_of,
+            captures,
+            delimiters,
+            priorities,
+            channels,
+            sources: Vec::new(),
         }
     }
 
@@ -73,7 +148,22 @@ impl Grammar {
     }
 
     pub fn name(&self, idx: TerminalId) -> &str {
-        &self.names[idx.0]
+        self.names[idx.0].as_str()
+    }
+
+    /// Return the interned name of the terminal `idx`.
+    pub fn symbol(&self, idx: TerminalId) -> Symbol {
+        self.names[idx.0]
+    }
+
+    /// Return the span of the definition of the terminal `idx`.
+    pub fn span_of(&self, idx: TerminalId) -> &Span {
+        &self.spans[idx.0]
+    }
+
+    /// Iterate over the identifiers of every terminal, in definition order.
+    pub fn terminals(&self) -> impl Iterator<Item = TerminalId> {
+        (0..self.names.len()).map(TerminalId)
     }
 
     pub fn contains(&self, name: &str) -> bool {
@@ -84,6 +174,63 @@ impl Grammar {
         self.ignores.contains(idx)
     }
 
+    /// Return the channel of the tokens of `idx`, declared as
+    /// `channel(NAME) TERMINAL ::= ...`, if it has one. They are skipped like those of
+    /// ignored terminals, but lexed streams keep them.
+    pub fn channel(&self, idx: TerminalId) -> Option<&str> {
+        self.channels.get(&idx).map(|channel| &**channel)
+    }
+
+    /// Return whether the tokens of `idx` are only produced where the parser allows
+    /// them. Elsewhere, they are skipped like those of ignored terminals.
+    pub fn soft(&self, idx: TerminalId) -> bool {
+        self.soft_terminals.contains(&idx)
+    }
+
+    /// Return whether the tokens of `idx` go from an opening delimiter to the closing
+    /// delimiter that balances it, nested delimiters included.
+    pub fn balanced(&self, idx: TerminalId) -> bool {
+        self.delimiters.contains_key(&idx)
+    }
+
+    /// Return the regex of the opening and the closing delimiters of `idx`, if it is
+    /// balanced.
+    pub(crate) fn delimiters(&self, idx: TerminalId) -> Option<&CompiledRegex> {
+        self.delimiters.get(&idx)
+    }
+
+    /// Return the priority of `idx`, declared as `NAME priority N ::= ...`, over the
+    /// terminals that match the same text.
+    pub fn priority(&self, idx: TerminalId) -> u32 {
+        self.priorities.get(&idx).copied().unwrap_or(0)
+    }
+
+    /// Return the highest priority of the terminals.
+    pub(crate) fn max_priority(&self) -> u32 {
+        self.priorities.values().copied().max().unwrap_or(0)
+    }
+
+    /// Return the priorities of the terminals, each once, the highest first.
+    pub(crate) fn priority_levels(&self) -> Vec<u32> {
+        let mut levels: Vec<_> = self.priorities.values().copied().chain([0]).collect();
+        levels.sort_unstable_by(|left, right| right.cmp(left));
+        levels.dedup();
+        levels
+    }
+
+    /// Return the pairs of terminals of the same priority that both match a same
+    /// text, which lexers that pick the longest match settle by picking the first
+    /// terminal of each pair, or `None` if the regex of the grammar has no DFA to
+    /// tell.
+    pub fn ties(&self) -> Option<Vec<(TerminalId, TerminalId)>> {
+        let ties = self.pattern.ties()?;
+        Some(
+            ties.into_iter()
+                .filter(|&(first, second)| self.priority(first) == self.priority(second))
+                .collect(),
+        )
+    }
+
     pub fn err_message(&self, idx: TerminalId) -> Option<&str> {
         self.errors.get(&idx).map(|x| &**x)
     }
@@ -92,6 +239,125 @@ impl Grammar {
         self.descriptions.get(&idx).map(|x| &**x)
     }
 
+    /// Return the post-processors of the attributes of the tokens of `idx`, in the
+    /// order they are applied.
+    pub fn processors_of(&self, idx: TerminalId) -> &[Processor] {
+        self.processors.get(&idx).map_or(&[], Vec::as_slice)
+    }
+
+    /// Return the terminal `idx` is split from, if it is the member of a family.
+    pub fn family_of(&self, idx: TerminalId) -> Option<TerminalId> {
+        self.member_of.get(&idx).copied()
+    }
+
+    /// Return the named groups of the terminal `idx`, with their index among the
+    /// attributes of its tokens.
+    pub fn group_names(&self, idx: TerminalId) -> &[(Symbol, usize)] {
+        &self.captures[idx.0]
+    }
+
+    /// Return the index of the group `name` of the terminal `idx`, if it has one.
+    pub fn group_index(&self, idx: TerminalId, name: &str) -> Option<usize> {
+        self.group_names(idx)
+            .iter()
+            .find(|(group, _)| group.as_str() == name)
+            .map(|&(_, index)| index)
+    }
+
+    /// Return the terminal a match of the regex `idx` on `text` stands for, among
+    /// the `allowed` ones: the member of the family `idx` split for `text`, if
+    /// there is one, or else `idx` itself.
+    pub(crate) fn resolve(
+        &self,
+        idx: TerminalId,
+        text: &str,
+        allowed: &Allowed,
+    ) -> Option<TerminalId> {
+        let Some(members) = self.families.get(&idx) else {
+            return Some(idx);
+        };
+        members
+            .get(text)
+            .copied()
+            .into_iter()
+            .chain([idx])
+            .find(|&id| allowed.contains(id))
+    }
+
+    /// Return the terminal that the lexer lexes the whole of `text` as, if it lexes it
+    /// as a single token that is not ignored. Parser grammars refer to it as `'text'`.
+    pub fn literal(&self, text: &str) -> Option<TerminalId> {
+        let length = text.chars().count();
+        if length == 0 {
+            return None;
+        }
+        // A match of the whole text is the longest, so the highest priority wins.
+        self.priority_levels().into_iter().find_map(|level| {
+            let terminals = self
+                .terminals()
+                .filter(|&id| self.priority(id) == level && !self.ignored(id))
+                .collect();
+            let found = self.pattern.find_after(text, None, &Allowed::Some(terminals))?;
+            if found.chars_length() != length {
+                return None;
+            }
+            self.resolve(found.id(), text, &Allowed::All)
+        })
+    }
+
+    /// Build a text that the lexer lexes as a single token of the terminal `idx`,
+    /// following the branches of its regex that `choose` picks: it is given the number
+    /// of branches, and returns the one to follow. Members of families get one of
+    /// their texts. Return `None` if no text was found in a few attempts.
+    pub fn sample(
+        &self,
+        idx: TerminalId,
+        choose: &mut dyn FnMut(usize) -> usize,
+    ) -> Option<String> {
+        if let Some(family) = self.family_of(idx) {
+            let texts: Vec<_> = self.families[&family]
+                .iter()
+                .filter(|&(_, &member)| member == idx)
+                .map(|(text, _)| text)
+                .sorted()
+                .collect();
+            return texts.get(choose(texts.len().max(1))).map(|text| text.to_string());
+        }
+        (0..16)
+            .filter_map(|_| self.pattern.sample(idx, choose))
+            .find(|text| self.literal(text) == Some(idx))
+    }
+
+    /// Return whether the lexer skips the whole of `text`, as a single ignored token.
+    pub fn skips(&self, text: &str) -> bool {
+        let ignored = self.terminals().filter(|&id| self.ignored(id)).collect();
+        self.pattern
+            .find_after(text, None, &Allowed::Some(ignored))
+            .is_some_and(|found| found.chars_length() == text.chars().count())
+    }
+
+    /// Members of families have no regex of their own, and soft terminals are lexed
+    /// even where they are not allowed, to be skipped. Return the regex to allow to
+    /// lex the `allowed` terminals, if it is not `allowed` itself.
+    pub(crate) fn pattern_allowed(&self, allowed: &Allowed) -> Option<Allowed> {
+        let Allowed::Some(ids) = allowed else {
+            return None;
+        };
+        if !ids.iter().any(|id| self.member_of.contains_key(id))
+            && self.soft_terminals.iter().all(|id| ids.contains(id))
+        {
+            return None;
+        }
+        let mut ids: Vec<_> = ids
+            .iter()
+            .map(|&id| self.family_of(id).unwrap_or(id))
+            .chain(self.soft_terminals.iter().copied())
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        Some(Allowed::Some(ids))
+    }
+
     pub fn pattern(&self) -> &CompiledRegex {
         &self.pattern
     }
@@ -103,6 +369,135 @@ impl Grammar {
     pub fn id(&self, name: &str) -> Option<TerminalId> {
         self.name_map.get(name).copied()
     }
+
+    /// A fingerprint of the terminals of the grammar. Parser grammars compiled
+    /// against lexer grammars with the same fingerprint agree on the identifiers
+    /// of the terminals. It is stable across builds.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = StableHasher::new();
+        for name in self.names.iter() {
+            // A null byte after each name, so that they cannot run into each other.
+            hasher.write(name.as_bytes());
+            hasher.write(&[0]);
+        }
+        hasher.finish()
+    }
+
+    /// The canonical form of the grammar: a text that only depends on what the
+    /// grammar lexes, and not on where it was defined. Two grammars are equal if
+    /// and only if their canonical forms are.
+    pub fn canonical(&self) -> String {
+        let pattern = stable_hash(&format!("{:?}", self.pattern));
+        let mut canonical = format!("pattern {pattern:016x}\n");
+        for id in self.terminals() {
+            if let Some(channel) = self.channel(id) {
+                canonical.push_str(&format!("channel({channel}) "));
+            } else if self.ignored(id) {
+                canonical.push_str("ignore ");
+            }
+            if self.soft(id) {
+                canonical.push_str("soft ");
+            }
+            canonical.push_str(self.name(id));
+            if let Some(delimiters) = self.delimiters(id) {
+                let delimiters = stable_hash(&format!("{delimiters:?}"));
+                canonical.push_str(&format!(" balanced {delimiters:016x}"));
+            }
+            if let Some(description) = self.description_of(id) {
+                canonical.push_str(&format!(" {description:?}"));
+            }
+            if let Some(message) = self.err_message(id) {
+                canonical.push_str(&format!(" error {message:?}"));
+            }
+            for processor in self.processors_of(id) {
+                canonical.push_str(&format!(" with {}", processor.name()));
+            }
+            if self.priority(id) != 0 {
+                canonical.push_str(&format!(" priority {}", self.priority(id)));
+            }
+            canonical.push('\n');
+        }
+        for (&family, members) in self.families.iter().sorted_by_key(|&(&family, _)| family) {
+            for (text, &member) in members.iter().sorted() {
+                canonical.push_str(&format!(
+                    "{} {text:?} {}\n",
+                    self.name(family),
+                    self.name(member)
+                ));
+            }
+        }
+        canonical
+    }
+
+    /// A lexer grammar that builds this grammar, as it was written: a terminal per
+    /// line, after its description, the members of families last. Grammars that were
+    /// not built from a source, with [`Grammar::new`], have no regexes to write.
+    pub fn decompile(&self) -> String {
+        let mut decompiled = String::new();
+        for id in self.terminals() {
+            if let Some(description) = self.description_of(id) {
+                decompiled.push_str(&format!("({description})\n"));
+            }
+            let source = self.sources.get(id.0);
+            let unwanted = self.err_message(id).is_some();
+            let mut words = Vec::new();
+            if let Some(channel) = self.channel(id) {
+                words.push(format!("channel ({channel})"));
+            } else if self.ignored(id) && !unwanted {
+                words.push(String::from("ignore"));
+            }
+            let flags = [
+                (self.soft(id), "soft"),
+                (self.balanced(id), "balanced"),
+                (source.is_some_and(|source| source.keyword), "keyword"),
+                (source.is_some_and(|source| source.insensitive), "insensitive"),
+                (unwanted, "unwanted"),
+            ];
+            words.extend(
+                flags
+                    .into_iter()
+                    .filter(|&(set, _)| set)
+                    .map(|(_, flag)| flag.to_string()),
+            );
+            words.push(self.name(id).to_string());
+            if let Some(family) = self.family_of(id) {
+                words.push(format!("in {}", self.name(family)));
+            }
+            let processors = self.processors_of(id);
+            if !processors.is_empty() {
+                words.push(String::from("with"));
+                words.extend(processors.iter().map(|processor| processor.name().to_string()));
+            }
+            if self.priority(id) != 0 {
+                words.push(format!("priority {}", self.priority(id)));
+            }
+            let regex = source.map_or("", |source| &source.regex);
+            decompiled.push_str(&format!("{} ::= {regex}\n", words.join(" ")));
+        }
+        decompiled
+    }
+}
+
+impl PartialEq for Grammar {
+    fn eq(&self, other: &Self) -> bool {
+        self.canonical() == other.canonical()
+    }
+}
+
+impl Eq for Grammar {}
+
+/// Grammars hash like their canonical form, so with a [`StableHasher`], their hash
+/// is stable across builds.
+impl Hash for Grammar {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.canonical().hash(state)
+    }
+}
+
+impl crate::sealed::Sealed for Grammar {}
+
+impl Compiled for Grammar {
+    const KIND: CompiledKind = CompiledKind::LexerGrammar;
 }
 
 impl Buildable for Grammar {
@@ -113,17 +508,39 @@ impl Buildable for Grammar {
     fn build_from_ast(ast: AST) -> Result<Self> {
         let typed_ast = Ast::read(ast)?;
         let mut ignores = Ignores::with_raw_capacity(typed_ast.terminals.len());
+        let mut soft_terminals = Vec::new();
         let mut errors = Errors::new();
         let mut descriptions = Descriptions::new();
+        let mut processors = Processors::new();
         let mut names = Vec::new();
+        let mut spans = Vec::new();
+        let mut sources = Vec::new();
         let mut regex_builder = RegexBuilder::new();
         let mut found_identifiers = HashMap::new();
+        let mut families: HashMap<_, HashMap<_, _>> = HashMap::new();
+        let mut delimiters = HashMap::new();
+        let mut priorities = HashMap::new();
+        let mut channels = HashMap::new();
+
+        // Members of families come after the terminals defined by a regex, so
+        // that the identifiers of the latter are the ones of their regex.
+        let (members, terminals): (Vec<_>, Vec<_>) = typed_ast
+            .terminals
+            .into_iter()
+            .partition(|terminal| terminal.family.is_some());
+        let regex_count = terminals.len();
 
-        for terminal in typed_ast.terminals {
+        for terminal in terminals.into_iter().chain(members) {
             let id = TerminalId(names.len());
-            if terminal.ignore.inner || terminal.unwanted.inner {
+            if terminal.ignore.inner || terminal.unwanted.inner || terminal.channel.is_some() {
                 ignores.put(id);
             }
+            if let Some(channel) = terminal.channel {
+                channels.insert(id, channel.inner);
+            }
+            if terminal.soft.inner {
+                soft_terminals.push(id);
+            }
             if terminal.unwanted.inner {
                 if let Some(ref message) = terminal.comment {
                     errors.insert(id, message.inner.clone());
@@ -138,7 +555,39 @@ impl Buildable for Grammar {
             if let Some(comment) = terminal.comment {
                 descriptions.insert(id, comment.inner);
             }
-            names.push(terminal.name.inner.to_string());
+            if !terminal.processors.is_empty() {
+                let terminal_processors = terminal
+                    .processors
+                    .iter()
+                    .map(|name| {
+                        Processor::from_name(&name.inner).ok_or_else(|| {
+                            Error::new(ErrorKind::LexerGrammarUnknownProcessor {
+                                name: name.inner.to_string(),
+                                span: name.span.clone().into(),
+                            })
+                        })
+                    })
+                    .collect::<Result<_>>()?;
+                processors.insert(id, terminal_processors);
+            }
+            if let Some(priority) = terminal.priority {
+                let Ok(value) = priority.inner.parse() else {
+                    return ErrorKind::LexerGrammarInvalidPriority {
+                        name: terminal.name.inner.to_string(),
+                        priority: priority.inner.to_string(),
+                        span: priority.span.into(),
+                    }
+                    .err();
+                };
+                priorities.insert(id, value);
+            }
+            names.push(Symbol::intern(&terminal.name.inner));
+            spans.push(terminal.name.span.clone());
+            sources.push(Source {
+                regex: Rc::from(&*terminal.regex.inner),
+                keyword: terminal.keyword.inner,
+                insensitive: terminal.insensitive.inner,
+            });
 
             if let Some(span) =
                 found_identifiers.insert(terminal.name.inner.clone(), terminal.name.span.clone())
@@ -151,25 +600,81 @@ impl Buildable for Grammar {
                 .err();
             }
 
+            if let Some(family) = terminal.family {
+                let Some(family_id) = names[..regex_count]
+                    .iter()
+                    .position(|name| **name == *family.inner)
+                else {
+                    return ErrorKind::LexerGrammarUnknownFamily {
+                        name: terminal.name.inner.to_string(),
+                        family: family.inner.to_string(),
+                        span: family.span.into(),
+                    }
+                    .err();
+                };
+                families
+                    .entry(TerminalId(family_id))
+                    .or_default()
+                    .insert(terminal.regex.inner.to_string(), id);
+                continue;
+            }
+
+            let regex_error = |error: RegexError| {
+                Error::new(ErrorKind::RegexError {
+                    message: error.message,
+                    span: terminal.regex.span.clone().into(),
+                })
+            };
+            let mut regex = &*terminal.regex.inner;
+            if terminal.balanced.inner {
+                let Some((open, close)) = regex.split_once(" ... ") else {
+                    return ErrorKind::LexerGrammarBalancedDelimiters {
+                        name: terminal.name.inner.to_string(),
+                        span: (&terminal.regex.span).into(),
+                    }
+                    .err();
+                };
+                let insensitive = terminal.insensitive.inner;
+                let terminal_delimiters = RegexBuilder::new()
+                    .with_named_regex(open, "OPEN".into(), false, insensitive)
+                    .and_then(|builder| {
+                        builder.with_named_regex(close, "CLOSE".into(), false, insensitive)
+                    })
+                    .map_err(regex_error)?
+                    .build();
+                delimiters.insert(id, terminal_delimiters);
+                regex = open;
+            }
             regex_builder = regex_builder
                 .with_named_regex(
-                    &terminal.regex.inner,
+                    regex,
                     terminal.name.inner.to_string(),
                     terminal.keyword.inner,
+                    terminal.insensitive.inner,
                 )
-                .map_err(|error| {
-                    Error::new(ErrorKind::RegexError {
-                        message: error.message,
-                        span: terminal.regex.span.into(),
-                    })
-                })?;
+                .map_err(regex_error)?;
         }
         let re = regex_builder.build();
-        Ok(Self::new(re, names, ignores, errors, descriptions))
+        let mut grammar = Self::new(
+            re,
+            names,
+            spans,
+            ignores,
+            soft_terminals,
+            errors,
+            descriptions,
+            processors,
+            families,
+            delimiters,
+            priorities,
+            channels,
+        );
+        grammar.sources = sources;
+        Ok(grammar)
     }
 
     fn build_from_compiled(blob: &[u8], path: impl ToOwned<Owned = PathBuf>) -> Result<Self> {
-        deserialize(blob).map_err(|error| Error::with_file(error, path.to_owned()))
+        deserialize_compiled(blob, &path.to_owned())
     }
 
     fn build_from_plain(mut source: StringStream) -> Result<Self> {
@@ -196,7 +701,7 @@ mod tests {
                 .unwrap()
                 .pattern(),
             RegexBuilder::new()
-                .with_named_regex("wot!", String::from("A"), false)
+                .with_named_regex("wot!", String::from("A"), false, false)
                 .unwrap()
                 .build(),
         );
@@ -208,7 +713,7 @@ mod tests {
             .unwrap()
             .pattern(),
             RegexBuilder::new()
-                .with_named_regex("wot!  ", String::from("B"), false)
+                .with_named_regex("wot!  ", String::from("B"), false, false)
                 .unwrap()
                 .build()
         );
@@ -220,9 +725,9 @@ mod tests {
             .unwrap()
             .pattern(),
             RegexBuilder::new()
-                .with_named_regex("wot!", String::from("A"), false)
+                .with_named_regex("wot!", String::from("A"), false, false)
                 .unwrap()
-                .with_named_regex("wheel", String::from("B"), false)
+                .with_named_regex("wheel", String::from("B"), false, false)
                 .unwrap()
                 .build()
         );
@@ -233,6 +738,27 @@ mod tests {
             RegexBuilder::new().build()
         );
     }
+    #[test]
+    fn decompile() {
+        let source = "(a number)\nINT with trim priority 2 ::= [0-9]+\n\
+                      ignore SPACE ::= \\s+\n\
+                      keyword insensitive IF ::= if\n\
+                      channel (doc) DOC ::= ///.*\n\
+                      balanced STRING ::= \" ... \"\n\
+                      ID ::= [a-z]+\n\
+                      ELSE in ID ::= else\n";
+        let grammar =
+            Grammar::build_from_plain(StringStream::new(Path::new("<lexer input>"), source))
+                .unwrap();
+        assert_eq!(grammar.decompile(), source);
+        let decompiled = Grammar::build_from_plain(StringStream::new(
+            Path::new("<decompiled>"),
+            grammar.decompile(),
+        ))
+        .unwrap();
+        assert_eq!(decompiled, grammar);
+    }
+
     #[test]
     fn lexer_grammar() {
         let grammar = Grammar::build_from_plain(StringStream::new(
@@ -262,4 +788,44 @@ mod tests {
             &**grammar.errors.get(&TerminalId(1)).unwrap()
         );
     }
+
+    #[test]
+    fn canonical() {
+        let build = |path: &str, source: &str| {
+            Grammar::build_from_plain(StringStream::new(Path::new(path), source)).unwrap()
+        };
+        let grammar = build("a.lx", "ignore A ::= [ ]\n(a b) B ::= b");
+        let same = build("b.lx", "\nignore A ::= [ ]\n\n(a b)  B ::= b\n");
+        assert!(grammar.canonical().ends_with("\nignore A\nB \"a b\"\n"));
+        assert_eq!(grammar, same);
+        assert_eq!(stable_hash(&grammar), stable_hash(&same));
+        assert_ne!(grammar, build("a.lx", "ignore A ::= [ ]\n(a b) B ::= c"));
+        assert_ne!(grammar, build("a.lx", "A ::= [ ]\n(a b) B ::= b"));
+        assert_ne!(grammar, build("a.lx", "ignore A ::= [ ]\n(a b) B with trim ::= b"));
+    }
+
+    #[test]
+    fn processors() {
+        let grammar = Grammar::build_from_plain(StringStream::new(
+            Path::new("<processors>"),
+            "A with trim uppercase ::= a\nB ::= b",
+        ))
+        .unwrap();
+        assert_eq!(
+            grammar.processors_of(TerminalId(0)),
+            [Processor::Trim, Processor::Uppercase]
+        );
+        assert!(grammar.processors_of(TerminalId(1)).is_empty());
+        assert!(grammar.canonical().contains("A with trim with uppercase\n"));
+
+        let error = Grammar::build_from_plain(StringStream::new(
+            Path::new("<processors>"),
+            "A with nfc ::= a",
+        ))
+        .unwrap_err();
+        assert!(matches!(
+            *error.kind,
+            ErrorKind::LexerGrammarUnknownProcessor { ref name, .. } if name == "nfc"
+        ));
+    }
 }
@@ -0,0 +1,110 @@
+//! # Layout
+//!
+//! Indentation-sensitive languages, such as Python or YAML, delimit blocks by the
+//! indentation of their lines rather than by brackets. In layout mode, set with
+//! [`Lexer::with_layout`], the lexer produces synthetic tokens that parser grammars
+//! use in place of those brackets:
+//!  - `NEWLINE` ends each line that has a token, before the first token of the next
+//!    line, and at the end of the input;
+//!  - `INDENT` comes before the first token of a line indented deeper than the
+//!    block it is in, and opens a block;
+//!  - `DEDENT` comes before the first token of a line indented less than the block
+//!    it is in, once for each block it closes, and at the end of the input, once for
+//!    each block still open.
+//!
+//! Lines without tokens, such as blank lines or lines with only comments, do not
+//! count, and the indentation of a line is the column of its first token, so tabs
+//! and spaces should not be mixed.
+//!
+//! [`Lexer::with_layout`]: super::Lexer::with_layout
+
+use super::{Grammar, TerminalId};
+use crate::error::{Error, ErrorKind, Result};
+
+/// The terminals of the synthetic tokens of layout mode. They are declared in the
+/// lexer grammar like any other terminal, but their regexes never match, so that
+/// `NEWLINE ::= \n` declares the terminal of newlines well enough; newlines must
+/// then be ignored by another terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub indent: TerminalId,
+    pub dedent: TerminalId,
+    pub newline: TerminalId,
+}
+
+impl Layout {
+    pub fn new(indent: TerminalId, dedent: TerminalId, newline: TerminalId) -> Self {
+        Self {
+            indent,
+            dedent,
+            newline,
+        }
+    }
+
+    /// The layout of the terminals called `indent`, `dedent` and `newline` in
+    /// `grammar`.
+    pub fn from_names(
+        grammar: &Grammar,
+        indent: &str,
+        dedent: &str,
+        newline: &str,
+    ) -> Result<Self> {
+        let id = |name: &str| {
+            grammar.id(name).ok_or_else(|| {
+                Error::from(ErrorKind::UnknownTerminal {
+                    name: name.to_string(),
+                })
+            })
+        };
+        Ok(Self::new(id(indent)?, id(dedent)?, id(newline)?))
+    }
+
+    /// Return whether `id` is the terminal of synthetic tokens.
+    pub fn contains(&self, id: TerminalId) -> bool {
+        id == self.indent || id == self.dedent || id == self.newline
+    }
+}
+
+/// How far a [`LexedStream`] went, for the synthetic tokens of layout mode.
+///
+/// [`LexedStream`]: super::LexedStream
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Indentation {
+    /// The columns of the open blocks, the innermost last.
+    levels: Vec<usize>,
+    /// The line on which the last token ends, and the char right after it, unless
+    /// no token was lexed since the start or the end of the input.
+    pub(crate) last: Option<(usize, usize)>,
+}
+
+impl Indentation {
+    /// Return the synthetic tokens that come before a token at `column`, closing
+    /// blocks first, and opening a block last, or `None` if `column` closes blocks
+    /// but is not the column of a block that is still open.
+    pub(crate) fn before(&mut self, layout: &Layout, column: usize) -> Option<Vec<TerminalId>> {
+        let mut tokens = Vec::new();
+        if self.last.is_some() {
+            tokens.push(layout.newline);
+        }
+        while self.levels.last().is_some_and(|&level| level > column) {
+            self.levels.pop();
+            tokens.push(layout.dedent);
+        }
+        let current = self.levels.last().copied().unwrap_or(0);
+        if column > current {
+            if tokens.contains(&layout.dedent) {
+                return None;
+            }
+            self.levels.push(column);
+            tokens.push(layout.indent);
+        }
+        Some(tokens)
+    }
+
+    /// Return the synthetic tokens at the end of the input, and forget the blocks.
+    pub(crate) fn end(&mut self, layout: &Layout) -> Vec<TerminalId> {
+        let newline = self.last.take().map(|_| layout.newline);
+        let dedents = self.levels.drain(..).map(|_| layout.dedent);
+        newline.into_iter().chain(dedents).collect()
+    }
+}
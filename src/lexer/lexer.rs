@@ -1,16 +1,19 @@
 use super::grammar::Grammar;
+use super::layout::{Indentation, Layout};
+use super::{Converter, PostProcessor};
 use crate::builder::Buildable;
 use crate::error::ErrorKind;
 use crate::error::Result;
-use crate::parser::AST;
-use crate::regex::Allowed;
+use crate::interner::Symbol;
+use crate::parser::{Value, AST};
+use crate::regex::{Allowed, CompiledRegex, Failures, Match};
 use crate::span::Span;
 use crate::stream::StringStream;
 
 use fragile::Fragile;
 use newty::newty;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
 use std::ops::Index;
 use std::path::Path;
@@ -26,13 +29,22 @@ newty! {
 /// `Token` contains information about a token, thus it contains
 ///  - `name`: the identifier of the token;
 ///  - `attributes`: the attributes of the token;
-///  - `location`: the location of the substring that generated this token.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+///  - `names`: the named groups of the token, with the key of their attribute;
+///  - `values`: the values of its attributes, if the lexer converts them;
+///  - `location`: the location of the substring that generated this token;
+///  - `trivia`: the ignored tokens right before this one, if they were kept.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Token {
-    name: String,
+    name: Symbol,
     id: TerminalId,
     attributes: HashMap<usize, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    names: Vec<(Symbol, usize)>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    values: HashMap<usize, Value>,
     span: Span,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    trivia: Vec<Token>,
 }
 
 impl fmt::Display for Token {
@@ -52,7 +64,7 @@ impl Index<&usize> for Token {
 impl Token {
     /// Build a new token.
     pub fn new(
-        name: String,
+        name: Symbol,
         id: TerminalId,
         attributes: HashMap<usize, String>,
         span: Span,
@@ -61,10 +73,32 @@ impl Token {
             name,
             id,
             attributes,
+            names: Vec::new(),
+            values: HashMap::new(),
             span,
+            trivia: Vec::new(),
         }
     }
 
+    /// Give the attributes of the token the values they were converted to.
+    pub(crate) fn with_values(mut self, values: HashMap<usize, Value>) -> Self {
+        self.values = values;
+        self
+    }
+
+    /// Return the value the attribute `key` was converted to, if the lexer converts
+    /// the attributes of the tokens of the terminal.
+    pub fn value(&self, key: usize) -> Option<&Value> {
+        self.values.get(&key)
+    }
+
+    /// Name the attributes of the token: the attribute of key `index` is also
+    /// called `name`, for each of `names`.
+    pub fn with_names(mut self, names: Vec<(Symbol, usize)>) -> Self {
+        self.names = names;
+        self
+    }
+
     /// Return whether the token has a given attribute.
     pub fn contains(&self, key: usize) -> bool {
         self.attributes.contains_key(&key)
@@ -76,6 +110,27 @@ impl Token {
         self.attributes.get(&key).map(|x| x.as_str())
     }
 
+    /// Return the value captured by the group called `name` if any,
+    /// or `None` otherwise.
+    pub fn get_named(&self, name: &str) -> Option<&str> {
+        self.names
+            .iter()
+            .find(|(group, _)| group.as_str() == name)
+            .and_then(|&(_, key)| self.get(key))
+    }
+
+    /// Return the named groups of the token, with the key of their attribute.
+    pub fn group_names(&self) -> &[(Symbol, usize)] {
+        &self.names
+    }
+
+    /// Iterate over the attributes of the named groups, with their name.
+    pub fn named_attributes(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.names
+            .iter()
+            .filter_map(|&(name, key)| Some((name.as_str(), self.get(key)?)))
+    }
+
     /// Borrows the tokens attributes, as a HashMap.
     pub fn attributes(&self) -> &HashMap<usize, String> {
         &self.attributes
@@ -92,6 +147,7 @@ impl Token {
     ///
     /// Since this method panics instead of returning an error,
     /// it is recommended to use [`get`] instead.
+    #[allow(clippy::unwrap_used)]
     pub fn force_get(&self, key: usize) -> &str {
         self.get(key).unwrap()
     }
@@ -101,6 +157,11 @@ impl Token {
         self.name.as_str()
     }
 
+    /// Return the interned `name` of the token.
+    pub fn symbol(&self) -> Symbol {
+        self.name
+    }
+
     /// Return the `id` of the token.
     pub fn id(&self) -> TerminalId {
         self.id
@@ -110,6 +171,31 @@ impl Token {
     pub fn span(&self) -> &Span {
         &self.span
     }
+
+    /// Return the ignored tokens, such as comments, that come right before this one.
+    /// They are only kept by a [`LexedStream`] built with [`LexedStream::with_trivia`].
+    pub fn trivia(&self) -> &[Token] {
+        &self.trivia
+    }
+}
+
+/// # Summary
+///
+/// [`Checkpoint`] is where a [`LexedStream`] stands right after a token: the char
+/// that follows the token, and in layout mode, the blocks that are open there.
+/// [`Lexer::resume`] goes on lexing from it as if the tokens before had just been
+/// lexed, on any version of the input that is the same up to there.
+#[derive(Debug, Clone, Default)]
+pub struct Checkpoint {
+    pos: usize,
+    indentation: Indentation,
+}
+
+impl Checkpoint {
+    /// The char right after the token.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
 }
 
 /// # Summary
@@ -131,64 +217,447 @@ pub struct LexedStream<'lexer, 'stream> {
     pos: usize,
     tokens: Vec<(usize, Token)>,
     last_span: Span,
+    /// Whether the stream might still be fed.
+    incremental: bool,
+    starved: bool,
+    /// Whether ignored tokens are kept as the trivia of the next token.
+    keep_trivia: bool,
+    /// The ignored tokens lexed since the last token.
+    trivia: Vec<Token>,
+    /// The tokens of the terminals that have a channel, with the char they start at.
+    channeled: Vec<(usize, Token)>,
+    /// What the previous scans of the stream found, which keeps lexing linear.
+    failures: Failures,
+    /// What the previous scans found for each set of terminals that a match is
+    /// compared with to settle which terminal it is, which keeps them linear too.
+    restricted: HashMap<Allowed, Failures>,
+    /// The blocks that are open, in layout mode.
+    indentation: Indentation,
+    /// The synthetic tokens of layout mode that come before the next token.
+    pending: VecDeque<Token>,
+    /// The terminal of the tokens of input that cannot be lexed, if they are produced
+    /// rather than failing.
+    error_terminal: Option<TerminalId>,
 }
 
 impl<'lexer, 'stream> LexedStream<'lexer, 'stream> {
     /// Create a new [`LexedStream`] instance.
     pub fn new(lexer: &'lexer Lexer, stream: &'stream mut StringStream) -> Self {
         Self {
-            last_span: Span::new(
-                stream.origin(),
-                (0, 0),
-                (0, 0),
-                0,
-                0,
-                stream.text(),
-                stream.lines(),
-            ),
+            last_span: stream.curr_span(),
             lexer,
             stream,
             pos: 0,
             tokens: Vec::new(),
+            incremental: false,
+            starved: false,
+            keep_trivia: false,
+            trivia: Vec::new(),
+            channeled: Vec::new(),
+            failures: Failures::new(),
+            restricted: HashMap::new(),
+            indentation: Indentation::default(),
+            pending: VecDeque::new(),
+            error_terminal: None,
+        }
+    }
+
+    /// Keep the ignored tokens, rather than dropping them, and attach them to the
+    /// token that follows as its [`Token::trivia`]. Those after the last token are
+    /// in [`LexedStream::trailing_trivia`].
+    pub fn with_trivia(self) -> Self {
+        Self {
+            keep_trivia: true,
+            ..self
+        }
+    }
+
+    /// Rather than failing where no terminal matches, produce a token of the terminal
+    /// `error` that spans the run of chars from there to the next place where one
+    /// does, and go on from there. The token has the text of the run as its
+    /// attribute `0`. It is only produced where `error` is allowed, which lexing with
+    /// [`Allowed::All`] always does, so that the parser reports it as an unexpected
+    /// token, and skips it when it recovers from errors. `error` is declared in the
+    /// lexer grammar like any other terminal, but its regex never matches.
+    pub fn with_error_tokens(self, error: TerminalId) -> Self {
+        Self {
+            error_terminal: Some(error),
+            ..self
+        }
+    }
+
+    /// Find the next token, among the matches of the `allowed` terminals, as the
+    /// disambiguation policy of the lexer picks it.
+    fn find(&mut self, allowed: &Allowed, waiting: bool) -> Option<Match<'lexer>> {
+        let lexer: &'lexer Lexer = self.lexer;
+        let grammar = lexer.grammar();
+        let mut found = self.find_longest(allowed, waiting)?;
+        match lexer.disambiguation {
+            Disambiguation::LongestMatch => loop {
+                // Among the longest matches, the one of the highest priority wins.
+                let priority = grammar.priority(found.id());
+                if priority >= grammar.max_priority() {
+                    break Some(found);
+                }
+                let higher = self.restrict(allowed, |id| grammar.priority(id) > priority);
+                match self.find_restricted(higher, waiting) {
+                    Some(tie) if tie.chars_length() == found.chars_length() => found = tie,
+                    _ => break Some(found),
+                }
+            },
+            Disambiguation::DeclarationOrder => loop {
+                let first = found.id();
+                match self.find_restricted(self.restrict(allowed, |id| id < first), waiting) {
+                    Some(other) => found = other,
+                    None => break Some(found),
+                }
+            },
+            Disambiguation::Priority => {
+                // The longest match is the one of its own priority.
+                let priority = grammar.priority(found.id());
+                if priority >= grammar.max_priority() {
+                    return Some(found);
+                }
+                for level in grammar.priority_levels() {
+                    if level <= priority {
+                        break;
+                    }
+                    let terminals = self.restrict(allowed, |id| grammar.priority(id) == level);
+                    if let Some(other) = self.find_restricted(terminals, waiting) {
+                        return Some(other);
+                    }
+                }
+                Some(found)
+            }
+        }
+    }
+
+    /// Find the longest match of the `allowed` terminals, remembering the scans of
+    /// the stream unless its text may still change.
+    fn find_longest(&mut self, allowed: &Allowed, waiting: bool) -> Option<Match<'lexer>> {
+        if waiting || self.cut().is_some() {
+            return self.find_among(allowed);
+        }
+        let pattern = self.lexer.grammar().pattern();
+        let (input, last) = (self.stream.peek(), self.stream.previous());
+        pattern.find_at(input, self.stream.pos(), last, allowed, &mut self.failures)
+    }
+
+    /// Find the longest match of the `allowed` terminals, which the disambiguation
+    /// policy restricted, remembering the scans of the stream for each restriction.
+    fn find_restricted(&mut self, allowed: Allowed, waiting: bool) -> Option<Match<'lexer>> {
+        if waiting || self.cut().is_some() {
+            return self.find_among(&allowed);
         }
+        let pattern = self.lexer.grammar().pattern();
+        let (input, last) = (self.stream.peek(), self.stream.previous());
+        let failures = self.restricted.entry(allowed.clone()).or_default();
+        pattern.find_at(input, self.stream.pos(), last, &allowed, failures)
+    }
+
+    /// Find the longest match of the `allowed` terminals, without remembering the
+    /// scans of the stream.
+    fn find_among(&self, allowed: &Allowed) -> Option<Match<'lexer>> {
+        let lexer: &'lexer Lexer = self.lexer;
+        let (input, last) = (self.stream.peek(), self.stream.previous());
+        let input = self.cut().map_or(input, |end| &input[..end]);
+        lexer.grammar().pattern().find_after(input, last, allowed)
+    }
+
+    /// Return where to cut the rest of the stream, in bytes, if tokens are bounded
+    /// and it is longer. Scanning one more char than a token may have is enough to
+    /// tell that the next one is too long, and keeps each scan short on
+    /// pathological inputs.
+    fn cut(&self) -> Option<usize> {
+        let max_length = self.lexer.max_token_length?;
+        let (end, _) = self.stream.peek().char_indices().nth(max_length + 1)?;
+        Some(end)
+    }
+
+    /// Return whether the next chunks of the stream may make the rest of it start
+    /// with a token of the `allowed` terminals.
+    fn may_extend(&self, allowed: &Allowed) -> bool {
+        let (input, last) = (self.stream.peek(), self.stream.previous());
+        self.lexer.grammar().pattern().may_extend(input, last, allowed)
+    }
+
+    /// The `allowed` terminals that `keep` keeps.
+    fn restrict(&self, allowed: &Allowed, keep: impl Fn(TerminalId) -> bool) -> Allowed {
+        let terminals: Vec<_> = match allowed {
+            Allowed::All => self.lexer.grammar().terminals().collect(),
+            Allowed::Some(terminals) => terminals.clone(),
+        };
+        Allowed::Some(terminals.into_iter().filter(|&id| keep(id)).collect())
     }
 
     fn lex_next(&mut self, allowed: Allowed) -> Result<bool> {
+        match self.lexer.layout {
+            Some(layout) => self.lex_layout(&layout, allowed),
+            None => self.lex_token(allowed),
+        }
+    }
+
+    /// Lex the next token in layout mode, or the next synthetic token before it.
+    fn lex_layout(&mut self, layout: &Layout, allowed: Allowed) -> Result<bool> {
+        if let Some(token) = self.pending.pop_front() {
+            self.push_synthetic(token);
+            return Ok(true);
+        }
+        let allowed = self.lexer.lexable(layout, allowed);
+        let (pos, trivia) = (self.stream.pos(), self.trivia.len());
+        let lexed = self.lex_token(allowed.clone());
+        let next = match lexed {
+            Ok(true) => self
+                .tokens
+                .last()
+                .map(|(start, token)| (*start, token.span().start())),
+            Ok(false) => None,
+            Err(_) => {
+                // The next token may be unexpected only because synthetic tokens must
+                // come before it, which the line it starts on tells.
+                self.rewind(pos, trivia);
+                let any = self.lexer.lexable(layout, Allowed::All);
+                let next = match self.lex_token(any) {
+                    Ok(true) => self
+                        .tokens
+                        .pop()
+                        .map(|(start, token)| (start, token.span().start())),
+                    _ => None,
+                };
+                self.rewind(pos, trivia);
+                next
+            }
+        };
+        let last = self.indentation.last;
+        let synthetic = match (&lexed, next) {
+            (Ok(false), _) if self.starved => Vec::new(),
+            (Ok(false), _) => self.indentation.end(layout),
+            (_, Some((start, (line, column))))
+                if last.is_none_or(|(last_line, _)| line > last_line) =>
+            {
+                let Some(synthetic) = self.indentation.before(layout, column) else {
+                    return ErrorKind::InconsistentDedent {
+                        span: Fragile::new(self.point(start)),
+                    }
+                    .err();
+                };
+                self.indentation.last = Some((line, start));
+                synthetic
+            }
+            _ => Vec::new(),
+        };
+        if synthetic.is_empty() {
+            return match lexed {
+                Ok(true) => {
+                    let Some((_, token)) = self.tokens.last() else {
+                        return ErrorKind::InternalError {
+                            message: String::from("a token was lexed, but none was produced"),
+                        }
+                        .err();
+                    };
+                    self.indentation.last = Some((token.span().end().0, self.stream.pos()));
+                    Ok(true)
+                }
+                Ok(false) => Ok(false),
+                // The next token may be allowed once it is fed.
+                Err(_) if self.starved => Ok(false),
+                // Lex again, to fail as the first scan did.
+                Err(_) => self.lex_token(allowed),
+            };
+        }
+        if let Ok(true) = lexed {
+            self.tokens.pop();
+            self.rewind(pos, trivia);
+        }
+        let start = next.map_or(self.stream.pos(), |(start, _)| start);
+        for id in synthetic {
+            let span = match last {
+                // Newlines span what separates the tokens around them.
+                Some((_, end)) if id == layout.newline && start > end => {
+                    self.stream.span_between(end, start - 1)
+                }
+                _ => self.point(start),
+            };
+            let name = self.lexer.grammar().symbol(id);
+            self.pending.push_back(Token::new(name, id, HashMap::new(), span));
+        }
+        let Some(token) = self.pending.pop_front() else {
+            return ErrorKind::InternalError {
+                message: String::from("no synthetic token to produce"),
+            }
+            .err();
+        };
+        self.push_synthetic(token);
+        Ok(true)
+    }
+
+    /// Produce the synthetic `token`, which is at the current char.
+    fn push_synthetic(&mut self, token: Token) {
+        self.last_span = token.span().clone();
+        self.tokens.push((self.stream.pos(), token));
+    }
+
+    /// Return whether `token` is a synthetic token of layout mode.
+    fn is_synthetic(&self, token: &Token) -> bool {
+        self.lexer
+            .layout
+            .is_some_and(|layout| layout.contains(token.id()))
+    }
+
+    /// The empty span at the char `pos`.
+    fn point(&self, pos: usize) -> Span {
+        if pos < self.stream.len() {
+            self.stream.span_between(pos, pos).start_point()
+        } else {
+            self.stream.curr_span()
+        }
+    }
+
+    /// Go back to the char `pos`, and forget the trivia lexed since there were
+    /// `trivia` of them.
+    fn rewind(&mut self, pos: usize, trivia: usize) {
+        self.stream.seek(pos);
+        self.trivia.truncate(trivia);
+    }
+
+    /// Lex the next token of the input.
+    fn lex_token(&mut self, allowed: Allowed) -> Result<bool> {
+        let recover = self.error_terminal.filter(|&error| allowed.contains(error));
+        let allowed = match self.error_terminal {
+            Some(error) => self.restrict(&allowed, |id| id != error),
+            None => allowed,
+        };
+        let pattern_allowed = self.lexer.grammar().pattern_allowed(&allowed);
+        let pattern_allowed = pattern_allowed.as_ref().unwrap_or(&allowed);
+        // A match that reaches the end of an open stream could go on in the next
+        // chunk, and a failure could be an unfinished token.
+        let waiting = self.incremental && self.stream.is_open();
+        self.starved = false;
         'lex: loop {
             if self.stream.is_empty() {
+                self.starved = waiting;
                 break 'lex Ok(false);
-            } else if let Some(result) = self
-                .lexer
-                .grammar()
-                .pattern()
-                .find(self.stream.peek(), &allowed)
-            {
-                let name = result.name().to_string();
+            } else if let Some(result) = self.find(pattern_allowed, waiting) {
+                if let Some(max_length) = self.lexer.max_token_length {
+                    if result.chars_length() > max_length {
+                        break 'lex ErrorKind::TokenTooLong {
+                            span: Fragile::new(self.stream.curr_span()),
+                            max_length,
+                        }
+                        .err();
+                    }
+                }
+                let peek = self.stream.peek();
+                let length = peek
+                    .char_indices()
+                    .nth(result.chars_length())
+                    .map_or(peek.len(), |(i, _)| i);
+                if waiting && length == peek.len() {
+                    self.starved = true;
+                    break 'lex Ok(false);
+                }
+                let Some(id) = self
+                    .lexer
+                    .grammar()
+                    .resolve(result.id(), &peek[..length], &allowed)
+                else {
+                    break 'lex ErrorKind::LexingError {
+                        span: Fragile::new(self.stream.curr_span()),
+                    }
+                    .err();
+                };
+                let name = self.lexer.grammar().symbol(id);
+                let names = self.lexer.grammar().group_names(id).to_vec();
                 let mut attributes = HashMap::new();
                 for (i, attr) in result.groups().iter().enumerate() {
                     if let Some(a) = attr {
                         attributes.insert(i, a.text(self.stream.peek()).to_string());
                     }
                 }
+                let mut chars_length = result.chars_length();
+                if let Some(delimiters) = self.lexer.grammar().delimiters(id) {
+                    // The regex of a balanced terminal only matches its opening delimiter.
+                    match balanced_length(delimiters, self.stream.peek(), chars_length) {
+                        Some(length) => chars_length = length,
+                        None if waiting => {
+                            self.starved = true;
+                            break 'lex Ok(false);
+                        }
+                        None => {
+                            break 'lex ErrorKind::UnclosedBalanced {
+                                terminal: name.to_string(),
+                                span: Fragile::new(self.stream.curr_span()),
+                            }
+                            .err();
+                        }
+                    }
+                    if let Some(max_length) = self.lexer.max_token_length {
+                        if chars_length > max_length {
+                            break 'lex ErrorKind::TokenTooLong {
+                                span: Fragile::new(self.stream.curr_span()),
+                                max_length,
+                            }
+                            .err();
+                        }
+                    }
+                }
                 let start = self.stream.pos();
-                self.stream.shift(result.chars_length());
+                self.stream.shift(chars_length);
                 let end = self.stream.pos();
                 let span = self.stream.span_between(start, end - 1);
-                if let Some(err_message) = self.lexer.grammar().err_message(result.id()) {
+                if let Some(err_message) = self.lexer.grammar().err_message(id) {
                     break 'lex ErrorKind::UnwantedToken {
                         span: Fragile::new(span),
                         message: err_message.to_string(),
                     }
                     .err();
                 }
-                if self.lexer.grammar().ignored(result.id()) {
+                // Soft tokens the parser does not expect are skipped too.
+                let ignored = self.lexer.grammar().ignored(id)
+                    || (self.lexer.grammar().soft(id) && !allowed.contains(id));
+                let channel = self.lexer.grammar().channel(id).is_some();
+                let mut values = HashMap::new();
+                // Ignored tokens that are dropped are not worth processing.
+                if !ignored || self.keep_trivia || channel {
+                    match self.lexer.process(id, &mut attributes) {
+                        Ok(converted) => values = converted,
+                        Err(message) => {
+                            break 'lex ErrorKind::TokenProcessing {
+                                terminal: name.to_string(),
+                                message,
+                                span: Fragile::new(span),
+                            }
+                            .err();
+                        }
+                    }
+                }
+                if ignored {
+                    let token = Token::new(name, id, attributes, span)
+                        .with_names(names)
+                        .with_values(values);
+                    // The same token may be lexed again after going back.
+                    let lexed =
+                        matches!(self.channeled.last(), Some(&(last, _)) if last >= start);
+                    if channel && !lexed {
+                        self.channeled.push((start, token.clone()));
+                    }
+                    if self.keep_trivia {
+                        self.trivia.push(token);
+                    }
                     continue;
                 }
-                let id = self.lexer.grammar.id(&name).unwrap();
-                let token = Token::new(name, id, attributes, span.clone());
+                let mut token = Token::new(name, id, attributes, span.clone())
+                    .with_names(names)
+                    .with_values(values);
+                token.trivia = std::mem::take(&mut self.trivia);
                 self.last_span = span;
                 self.tokens.push((start, token));
                 break 'lex Ok(true);
+            } else if waiting && self.may_extend(pattern_allowed) {
+                self.starved = true;
+                break 'lex Ok(false);
+            } else if let Some(error) = recover {
+                break 'lex self.lex_error(error);
             } else {
                 break 'lex ErrorKind::LexingError {
                     span: Fragile::new(self.stream.curr_span()),
@@ -198,10 +667,125 @@ impl<'lexer, 'stream> LexedStream<'lexer, 'stream> {
         }
     }
 
+    /// Produce a token of the terminal `error` for the run of chars, from the current
+    /// one, at which no terminal matches, or fail if one matches at the current char.
+    fn lex_error(&mut self, error: TerminalId) -> Result<bool> {
+        let lexer: &'lexer Lexer = self.lexer;
+        let any = self.restrict(&Allowed::All, |id| {
+            id != error && !lexer.layout.is_some_and(|layout| layout.contains(id))
+        });
+        let start = self.stream.pos();
+        let mut text = String::new();
+        while !self.stream.is_empty() && self.find_among(&any).is_none() {
+            text.extend(self.stream.peek().chars().next());
+            self.stream.shift(1);
+        }
+        if text.is_empty() {
+            return ErrorKind::LexingError {
+                span: Fragile::new(self.stream.curr_span()),
+            }
+            .err();
+        }
+        let span = self.stream.span_between(start, self.stream.pos() - 1);
+        let name = lexer.grammar().symbol(error);
+        let mut token = Token::new(name, error, HashMap::from([(0, text)]), span.clone());
+        token.trivia = std::mem::take(&mut self.trivia);
+        self.last_span = span;
+        self.tokens.push((start, token));
+        Ok(true)
+    }
+
     /// Get the last span lexed. Useful if you want to know where you failed to find a token.
     pub fn last_span(&self) -> &Span {
         &self.last_span
     }
+
+    pub(crate) fn set_last_span(&mut self, span: Span) {
+        self.last_span = span;
+    }
+
+    /// The ignored tokens lexed after the last token, when they are kept.
+    pub fn trailing_trivia(&self) -> &[Token] {
+        &self.trivia
+    }
+
+    /// The tokens of the terminals of `channel` lexed so far, in the order of the
+    /// input. The parser does not see them, as if they were ignored.
+    pub fn channel<'a>(&'a self, channel: &'a str) -> impl Iterator<Item = &'a Token> + 'a {
+        let grammar = self.lexer.grammar();
+        self.channeled
+            .iter()
+            .filter(move |(_, token)| grammar.channel(token.id()) == Some(channel))
+            .map(|(_, token)| token)
+    }
+
+    /// Return whether lexing stopped because the stream needs more input, rather
+    /// than because it is over.
+    pub fn starved(&self) -> bool {
+        self.starved
+    }
+
+    /// Get the range of characters of the most recently lexed token.
+    pub(crate) fn last_range(&self) -> Option<(usize, usize)> {
+        self.tokens.last().map(|&(start, _)| (start, self.stream.pos()))
+    }
+
+    /// Get the [`Checkpoint`] right after the most recently lexed token, or before
+    /// the first one. There is none right after a synthetic token of layout mode,
+    /// since the synthetic tokens depend on the token that follows them.
+    pub fn checkpoint(&self) -> Option<Checkpoint> {
+        let last = self.tokens.last();
+        (!last.is_some_and(|(_, token)| self.is_synthetic(token))).then(|| self.suspend())
+    }
+
+    /// Get where the stream stands, even right after a synthetic token, which is
+    /// only right to resume from if the input is not edited, but fed further.
+    pub(crate) fn suspend(&self) -> Checkpoint {
+        Checkpoint {
+            pos: self.stream.pos(),
+            indentation: self.indentation.clone(),
+        }
+    }
+
+    /// Resume lexing from `checkpoint`, after the tokens that led to it.
+    pub(crate) fn restore(&mut self, checkpoint: &Checkpoint) {
+        self.stream.seek(checkpoint.pos);
+        self.last_span = self.stream.curr_span();
+        self.indentation = checkpoint.indentation.clone();
+    }
+}
+
+/// Return the length, in chars, of the token of a balanced terminal that starts
+/// `input` with an opening delimiter of `open` chars, up to the closing delimiter
+/// that balances it, or `None` if there is none.
+fn balanced_length(delimiters: &CompiledRegex, input: &str, open: usize) -> Option<usize> {
+    let mut depth = 1;
+    let mut length = open;
+    let mut chars = input.char_indices().skip(open).peekable();
+    let mut last = input[..chars.peek()?.0].chars().next_back();
+    while let Some(&(byte, chr)) = chars.peek() {
+        let delimiter = delimiters
+            .find_after(&input[byte..], last, &Allowed::All)
+            .filter(|delimiter| delimiter.chars_length() > 0);
+        let Some(delimiter) = delimiter else {
+            chars.next();
+            length += 1;
+            last = Some(chr);
+            continue;
+        };
+        // The opening delimiter is the regex 0, and the closing one the regex 1.
+        if delimiter.id() == TerminalId(0) {
+            depth += 1;
+        } else {
+            depth -= 1;
+        }
+        length += delimiter.chars_length();
+        if depth == 0 {
+            return Some(length);
+        }
+        last = chars.nth(delimiter.chars_length() - 1).map(|(_, chr)| chr);
+    }
+    None
 }
 
 impl LexedStream<'_, '_> {
@@ -210,16 +794,51 @@ impl LexedStream<'_, '_> {
         self.next(Allowed::All)
     }
 
-    /// Lex any allowed token.
-    pub fn next(&mut self, allowed: Allowed) -> Result<Option<&Token>> {
+    /// Lex any allowed token. Ignored tokens are skipped, as long as they are
+    /// allowed too.
+    pub fn next(&mut self, allowed: impl Into<Allowed>) -> Result<Option<&Token>> {
         self.pos += 1;
-        if self.lex_next(allowed)? {
+        if self.lex_next(allowed.into())? {
             Ok(self.tokens.last().map(|(_, token)| token))
         } else {
             Ok(None)
         }
     }
 
+    /// Skip the input until one of `terminals` comes next, which is left to be lexed,
+    /// or until the end of the input. Characters that cannot be lexed, and unwanted
+    /// tokens, are skipped too. Return the span of what was skipped, if anything was.
+    pub fn skip_until(&mut self, terminals: &[TerminalId]) -> Option<Span> {
+        let start = self.stream.pos();
+        loop {
+            let before = self.stream.pos();
+            match self.lex_next(Allowed::All) {
+                Ok(true) => {
+                    let Some((token_start, token)) = self.tokens.pop() else {
+                        break;
+                    };
+                    if terminals.contains(&token.id()) {
+                        if self.is_synthetic(&token) {
+                            self.pending.push_front(token);
+                        } else {
+                            self.stream.seek(token_start);
+                            self.trivia = token.trivia;
+                        }
+                        break;
+                    }
+                }
+                Ok(false) => break,
+                Err(_) => {
+                    if self.stream.pos() == before {
+                        self.stream.incr_pos();
+                    }
+                }
+            }
+        }
+        let end = self.stream.pos();
+        (end > start).then(|| self.stream.span_between(start, end - 1))
+    }
+
     /// Peek for the most recently lexed token, which has not been droped.
     pub fn peek(&self) -> Option<&Token> {
         self.tokens.last().map(|(_, token)| token)
@@ -227,8 +846,13 @@ impl LexedStream<'_, '_> {
 
     /// Drop the last token.
     pub fn drop_last(&mut self) {
-        if let Some((pos, _)) = self.tokens.pop() {
+        if let Some((pos, token)) = self.tokens.pop() {
             self.pos -= 1;
+            if self.is_synthetic(&token) {
+                self.pending.push_front(token);
+                return;
+            }
+            self.trivia = token.trivia;
             while self.stream.pos() > pos {
                 self.stream.decr_pos();
             }
@@ -244,6 +868,23 @@ impl LexedStream<'_, '_> {
         self.stream.is_empty()
     }
 }
+/// # Summary
+///
+/// [`Disambiguation`] is how the lexer picks the token to produce when several
+/// terminals match at the same place.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Disambiguation {
+    /// The longest match wins, then the terminal of the highest priority, and then
+    /// the one declared first.
+    #[default]
+    LongestMatch,
+    /// The terminal declared first wins, however long its match.
+    DeclarationOrder,
+    /// The terminal of the highest priority wins, then the longest match, and then
+    /// the terminal declared first.
+    Priority,
+}
+
 /// # Summary
 ///
 /// `Lexer` is the main object that is used for lexing.
@@ -256,16 +897,139 @@ impl LexedStream<'_, '_> {
 /// # Methods
 ///
 /// `new`: build a new `Lexer`.
+/// `with_post_processor`: transform the attributes of the tokens of a terminal.
+/// `with_converter`: convert the attributes of the tokens of a terminal to values.
+/// `with_max_token_length`: bound the length of tokens.
+/// `with_layout`: produce synthetic tokens from the indentation of lines.
+/// `with_disambiguation`: choose which terminal wins when several match.
 /// `lex`: consume the `StringStream` until a valid `Token` is generated
 ///      or raise an error.
-#[derive(Debug)]
 pub struct Lexer {
     grammar: Grammar,
+    /// The post-processors added to those of the grammar, for each terminal.
+    processors: HashMap<TerminalId, Vec<PostProcessor>>,
+    /// What the attributes of the tokens of each terminal are converted to values
+    /// with, if they are.
+    converters: HashMap<TerminalId, Converter>,
+    /// The number of chars tokens may have at most.
+    max_token_length: Option<usize>,
+    /// The terminals of the synthetic tokens, in layout mode.
+    layout: Option<Layout>,
+    disambiguation: Disambiguation,
+}
+
+impl fmt::Debug for Lexer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lexer")
+            .field("grammar", &self.grammar)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Lexer {
     pub fn new(grammar: Grammar) -> Self {
-        Self { grammar }
+        Self {
+            grammar,
+            processors: HashMap::new(),
+            converters: HashMap::new(),
+            max_token_length: None,
+            layout: None,
+            disambiguation: Disambiguation::default(),
+        }
+    }
+
+    /// Lex in layout mode, producing the synthetic tokens of `layout` from the
+    /// indentation of lines, as described in [`super::layout`].
+    pub fn with_layout(mut self, layout: Layout) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    /// Pick the token to produce, when several terminals match at the same place,
+    /// following `disambiguation` rather than the longest match.
+    pub fn with_disambiguation(mut self, disambiguation: Disambiguation) -> Self {
+        self.disambiguation = disambiguation;
+        self
+    }
+
+    /// Remove the terminals of the synthetic tokens of `layout` from `allowed`,
+    /// since the input has none.
+    fn lexable(&self, layout: &Layout, allowed: Allowed) -> Allowed {
+        let terminals = match allowed {
+            Allowed::All => self.grammar.terminals().collect(),
+            Allowed::Some(terminals) => terminals,
+        };
+        Allowed::Some(
+            terminals
+                .into_iter()
+                .filter(|&id| !layout.contains(id))
+                .collect(),
+        )
+    }
+
+    /// Fail to lex tokens of more than `max_length` chars, rather than matching
+    /// regexes over the whole rest of the input, so that lexing stays fast on inputs
+    /// that are not what the grammar expects. Tokens that are too long are reported
+    /// as such, unless their regex only matches the whole token.
+    pub fn with_max_token_length(mut self, max_length: usize) -> Self {
+        self.max_token_length = Some(max_length);
+        self
+    }
+
+    /// Transform each attribute of the tokens of the terminal `id` with
+    /// `processor`, after the post-processors the grammar declares for it, and
+    /// those added before. Lexing fails with the message of `processor`, if it
+    /// returns an error.
+    pub fn with_post_processor(
+        mut self,
+        id: TerminalId,
+        processor: impl Fn(&str) -> std::result::Result<String, String> + 'static,
+    ) -> Self {
+        self.processors
+            .entry(id)
+            .or_default()
+            .push(Box::new(processor));
+        self
+    }
+
+    /// Convert each attribute of the tokens of the terminal `id`, once processed,
+    /// with `converter`, which replaces the one it may have had, such as parsing
+    /// integers into [`Value::Int`]. Parsers then put the values in ASTs, rather
+    /// than the text of the attributes. Lexing fails with the message of
+    /// `converter`, if it fails.
+    pub fn with_converter(
+        mut self,
+        id: TerminalId,
+        converter: impl Fn(&str) -> std::result::Result<Value, String> + 'static,
+    ) -> Self {
+        self.converters.insert(id, Box::new(converter));
+        self
+    }
+
+    /// Apply the post-processors of the terminal `id` to the `attributes` of one
+    /// of its tokens, and return the values they are converted to, if they are.
+    fn process(
+        &self,
+        id: TerminalId,
+        attributes: &mut HashMap<usize, String>,
+    ) -> std::result::Result<HashMap<usize, Value>, String> {
+        for processor in self.grammar.processors_of(id) {
+            for value in attributes.values_mut() {
+                *value = processor.apply(value)?;
+            }
+        }
+        for processor in self.processors.get(&id).into_iter().flatten() {
+            for value in attributes.values_mut() {
+                *value = processor(value)?;
+            }
+        }
+        let Some(converter) = self.converters.get(&id) else {
+            return Ok(HashMap::new());
+        };
+        attributes
+            .iter()
+            .map(|(&key, text)| Ok((key, converter(text)?)))
+            .collect()
     }
 
     /// Get a [`LexedStream`] on the stream.
@@ -276,6 +1040,47 @@ impl Lexer {
         LexedStream::new(self, stream)
     }
 
+    /// Get a [`LexedStream`] on the stream, from the character that starts at the
+    /// byte `offset` of the file on. The tokens before are not lexed, but the spans
+    /// of the next ones are the same as if they were. In layout mode, no block is
+    /// open at `offset`; [`Lexer::resume`] goes on with the blocks that are open at
+    /// a [`Checkpoint`].
+    pub fn lex_at<'lexer, 'stream>(
+        &'lexer self,
+        stream: &'stream mut StringStream,
+        offset: usize,
+    ) -> Result<LexedStream<'lexer, 'stream>> {
+        stream.seek_byte(offset)?;
+        Ok(LexedStream::new(self, stream))
+    }
+
+    /// Get a [`LexedStream`] on the stream, from `checkpoint` on, as if the tokens
+    /// before it had just been lexed.
+    pub fn resume<'lexer, 'stream>(
+        &'lexer self,
+        stream: &'stream mut StringStream,
+        checkpoint: &Checkpoint,
+    ) -> LexedStream<'lexer, 'stream> {
+        let mut lexed = LexedStream::new(self, stream);
+        lexed.restore(checkpoint);
+        lexed
+    }
+
+    /// Get a [`LexedStream`] on a stream that is still being fed. While the stream
+    /// is open, a token is only produced once what follows it has been fed, and
+    /// lexing stops, instead of failing, when it needs more input. Regexes that
+    /// look further than one character past their match may then be cut between
+    /// two chunks.
+    pub fn lex_incremental<'lexer, 'stream>(
+        &'lexer self,
+        stream: &'stream mut StringStream,
+    ) -> LexedStream<'lexer, 'stream> {
+        LexedStream {
+            incremental: true,
+            ..LexedStream::new(self, stream)
+        }
+    }
+
     /// Get the [`Grammar`] bound to the lexer.
     pub fn grammar(&self) -> &Grammar {
         &self.grammar
@@ -283,10 +1088,12 @@ impl Lexer {
 
     pub fn from_path(path: &Path) -> Result<Self> {
         let grammar = Grammar::build_from_path(path)?;
-        Ok(Self { grammar })
+        Ok(Self::new(grammar))
     }
 }
 
+impl crate::sealed::Sealed for Lexer {}
+
 impl Buildable for Lexer {
     const RAW_EXTENSION: &'static str = Grammar::RAW_EXTENSION;
     const COMPILED_EXTENSION: &'static str = Grammar::COMPILED_EXTENSION;
@@ -294,17 +1101,17 @@ impl Buildable for Lexer {
 
     fn build_from_ast(ast: AST) -> Result<Self> {
         let grammar = Grammar::build_from_ast(ast)?;
-        Ok(Self { grammar })
+        Ok(Self::new(grammar))
     }
 
     fn build_from_compiled(blob: &[u8], path: impl ToOwned<Owned = PathBuf>) -> Result<Self> {
         let grammar = Grammar::build_from_compiled(blob, path)?;
-        Ok(Self { grammar })
+        Ok(Self::new(grammar))
     }
 
     fn build_from_plain(raw: StringStream) -> Result<Self> {
         let grammar = Grammar::build_from_plain(raw)?;
-        Ok(Self { grammar })
+        Ok(Self::new(grammar))
     }
 }
 
@@ -342,7 +1149,7 @@ mod tests {
 
     impl PartialEq<Token> for TestToken {
         fn eq(&self, right: &Token) -> bool {
-            self.name == right.name
+            right.name == self.name.as_str()
                 && self.attributes.iter().all(|(key, value)| {
                     right.attributes.get(key).filter(|&v| v == value).is_some()
                 })
@@ -352,7 +1159,7 @@ mod tests {
     #[test]
     fn token() {
         let token = Token::new(
-            String::from("wow"),
+            Symbol::intern("wow"),
             0.into(),
             HashMap::new(),
             Span::new(
@@ -361,6 +1168,8 @@ mod tests {
                 (3, 2),
                 10,
                 12,
+                10,
+                12,
                 "",
                 Vec::new(),
             ),
@@ -398,6 +1207,416 @@ mod tests {
         assert!(lexed_input.next(Allowed::All).unwrap().is_none());
     }
 
+    #[test]
+    fn lex_named_groups() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<named groups>"),
+            r"FLOAT ::= (?P<int>[0-9]+)\.(?P<frac>[0-9]+)(e[0-9]+)?",
+        ))
+        .unwrap();
+        let mut input = StringStream::new(Path::new("<input>"), "12.5");
+        let mut lexed_input = lexer.lex(&mut input);
+
+        let token = lexed_input.next(Allowed::All).unwrap().unwrap();
+        assert_eq!(token.get_named("int"), Some("12"));
+        assert_eq!(token.get_named("frac"), Some("5"));
+        assert_eq!(token.get(1), Some("5"));
+        assert_eq!(token.get_named("exp"), None);
+        assert_eq!(
+            token.named_attributes().collect::<Vec<_>>(),
+            [("int", "12"), ("frac", "5")]
+        );
+    }
+
+    #[test]
+    fn lex_insensitive() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<insensitive>"),
+            "keyword insensitive SELECT ::= select\nID ::= [a-zA-Z]+\nignore SPACE ::= \\s+",
+        ))
+        .unwrap();
+        let mut input = StringStream::new(Path::new("<input>"), "SeLeCt selected");
+        let mut lexed_input = lexer.lex(&mut input);
+        assert_eq!(lexed_input.next_any().unwrap().unwrap().name(), "SELECT");
+        assert_eq!(lexed_input.next_any().unwrap().unwrap().name(), "ID");
+    }
+
+    #[test]
+    fn lex_anchors() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<anchors>"),
+            r"IF ::= \bif\b
+DIRECTIVE ::= ^#([a-z]+)
+HASH ::= #
+ID ::= [a-z]
+ignore SPACE ::= [ \n]+",
+        ))
+        .unwrap();
+        let mut input = StringStream::new(Path::new("<input>"), "if aif #a\n#b");
+        let mut lexed_input = lexer.lex(&mut input);
+        let mut names = Vec::new();
+        while let Some(token) = lexed_input.next_any().unwrap() {
+            names.push(token.name().to_string());
+        }
+        assert_eq!(names, ["IF", "ID", "ID", "ID", "HASH", "ID", "DIRECTIVE"]);
+    }
+
+    #[test]
+    fn lex_soft() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<soft>"),
+            "soft NEWLINE ::= \\n\nID ::= [a-z]+\nignore SPACE ::= [ ]+",
+        ))
+        .unwrap();
+        let grammar = lexer.grammar();
+        let [newline, id, space] =
+            ["NEWLINE", "ID", "SPACE"].map(|name| grammar.id(name).unwrap());
+        assert!(grammar.soft(newline));
+        let names = |allowed: Allowed| {
+            let mut input = StringStream::new(Path::new("<input>"), "a\n b\n\nc");
+            let mut lexed_input = lexer.lex(&mut input);
+            let mut names = Vec::new();
+            while let Some(token) = lexed_input.next(allowed.clone()).unwrap() {
+                names.push(token.name().to_string());
+            }
+            names
+        };
+        assert_eq!(names(Allowed::Some(vec![id, space])), ["ID", "ID", "ID"]);
+        assert_eq!(
+            names(Allowed::Some(vec![id, newline, space])),
+            ["ID", "NEWLINE", "ID", "NEWLINE", "NEWLINE", "ID"]
+        );
+    }
+
+    #[test]
+    fn lex_layout() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<layout>"),
+            "ID ::= [a-z]+\nCOLON ::= :\nINDENT ::= INDENT\nDEDENT ::= DEDENT\n\
+             NEWLINE ::= \\n\nignore SPACE ::= [ \\t\\n]+\nignore COMMENT ::= #[^\\n]*",
+        ))
+        .unwrap();
+        let layout =
+            Layout::from_names(lexer.grammar(), "INDENT", "DEDENT", "NEWLINE").unwrap();
+        let [id, colon, space, comment] =
+            ["ID", "COLON", "SPACE", "COMMENT"].map(|name| lexer.grammar().id(name).unwrap());
+        let lexer = lexer.with_layout(layout);
+        let mut input = StringStream::new(
+            Path::new("<input>"),
+            "a:\n  b\n  # comment\n\n  c:\n    d\ne",
+        );
+        let mut lexed_input = lexer.lex(&mut input);
+        let mut names = Vec::new();
+        let mut spans = Vec::new();
+        while let Some(token) = lexed_input.next_any().unwrap() {
+            names.push(token.name().to_string());
+            spans.push((token.span().start(), token.span().end()));
+        }
+        assert_eq!(
+            names,
+            [
+                "ID", "COLON", "NEWLINE", "INDENT", "ID", "NEWLINE", "ID", "COLON", "NEWLINE",
+                "INDENT", "ID", "NEWLINE", "DEDENT", "DEDENT", "ID", "NEWLINE",
+            ]
+        );
+        assert_eq!(spans[2], ((0, 2), (1, 1)));
+        assert_eq!(spans[3], ((1, 2), (1, 2)));
+        assert_eq!(spans[12], ((6, 0), (6, 0)));
+
+        // Synthetic tokens come before tokens that are only allowed after them.
+        let mut input = StringStream::new(Path::new("<input>"), "a:\n  b");
+        let mut lexed_input = lexer.lex(&mut input);
+        let names = [
+            vec![id, space, comment],
+            vec![colon, space, comment],
+            vec![layout.newline, space, comment],
+            vec![layout.indent, space, comment],
+            vec![id, space, comment],
+        ]
+        .map(|allowed| {
+            let token = lexed_input.next(Allowed::Some(allowed)).unwrap().unwrap();
+            token.name().to_string()
+        });
+        assert_eq!(names, ["ID", "COLON", "NEWLINE", "INDENT", "ID"]);
+
+        let mut input = StringStream::new(Path::new("<input>"), "a\n    b\n  c");
+        let mut lexed_input = lexer.lex(&mut input);
+        for _ in 0..4 {
+            lexed_input.next_any().unwrap();
+        }
+        let error = lexed_input.next_any().unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::InconsistentDedent { .. }));
+    }
+
+    #[test]
+    fn lex_balanced() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<balanced>"),
+            "ID ::= ([a-z]+)\nignore SPACE ::= \\s+\nignore balanced COMMENT ::= /\\* ... \\*/",
+        ))
+        .unwrap();
+        let comment = lexer.grammar().id("COMMENT").unwrap();
+        assert!(lexer.grammar().balanced(comment));
+        let mut input = StringStream::new(Path::new("<input>"), "a /* b /* c */ d */ e");
+        let mut lexed_input = lexer.lex(&mut input).with_trivia();
+        assert_eq!(lexed_input.next_any().unwrap().unwrap().content(), "a");
+        let token = lexed_input.next_any().unwrap().unwrap();
+        assert_eq!(token.content(), "e");
+        let trivia = &token.trivia()[1];
+        assert_eq!(trivia.name(), "COMMENT");
+        assert_eq!((trivia.span().start(), trivia.span().end()), ((0, 2), (0, 18)));
+        assert!(lexed_input.next_any().unwrap().is_none());
+
+        let mut input = StringStream::new(Path::new("<input>"), "a /* b /* c */");
+        let mut lexed_input = lexer.lex(&mut input);
+        lexed_input.next_any().unwrap();
+        let error = lexed_input.next_any().unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::UnclosedBalanced { .. }));
+
+        let error = Lexer::build_from_plain(StringStream::new(
+            Path::new("<balanced>"),
+            "balanced COMMENT ::= /\\*",
+        ))
+        .unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::LexerGrammarBalancedDelimiters { .. }));
+    }
+
+    #[test]
+    fn lex_disambiguation() {
+        let build = |source: &str| {
+            Lexer::build_from_plain(StringStream::new(Path::new("<disambiguation>"), source))
+                .unwrap()
+        };
+        let names = |lexer: &Lexer| {
+            let mut input = StringStream::new(Path::new("<input>"), "if iffy");
+            let mut lexed_input = lexer.lex(&mut input);
+            let mut names = Vec::new();
+            while let Some(token) = lexed_input.next_any().unwrap() {
+                names.push(token.name().to_string());
+            }
+            names
+        };
+        let lexer = build("ID ::= [a-z]+\nIF ::= if\nignore SPACE ::= [ ]+");
+        assert_eq!(names(&lexer), ["ID", "ID"]);
+        let grammar = lexer.grammar();
+        let (ident, keyword) = (grammar.id("ID").unwrap(), grammar.id("IF").unwrap());
+        assert_eq!(grammar.ties().unwrap(), [(ident, keyword)]);
+
+        let lexer = build("ID ::= [a-z]+\nIF priority 1 ::= if\nignore SPACE ::= [ ]+");
+        assert_eq!(lexer.grammar().priority(keyword), 1);
+        assert!(lexer.grammar().ties().unwrap().is_empty());
+        assert!(lexer.grammar().canonical().contains("IF priority 1\n"));
+        assert_eq!(names(&lexer), ["IF", "ID"]);
+        let lexer = lexer.with_disambiguation(Disambiguation::Priority);
+        assert_eq!(names(&lexer), ["IF", "IF", "ID"]);
+        let lexer = lexer.with_disambiguation(Disambiguation::DeclarationOrder);
+        assert_eq!(names(&lexer), ["ID", "ID"]);
+
+        let error = Lexer::build_from_plain(StringStream::new(
+            Path::new("<disambiguation>"),
+            "IF priority high ::= if",
+        ))
+        .unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::LexerGrammarInvalidPriority { .. }));
+    }
+
+    #[test]
+    fn lex_error_tokens() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<error tokens>"),
+            "ID ::= ([a-z]+)\nERROR ::= ERROR\nignore SPACE ::= [ ]+",
+        ))
+        .unwrap();
+        let [id, error] = ["ID", "ERROR"].map(|name| lexer.grammar().id(name).unwrap());
+        let mut input = StringStream::new(Path::new("<input>"), "ab $%& cd#");
+        let mut lexed_input = lexer.lex(&mut input).with_error_tokens(error);
+        let mut tokens = Vec::new();
+        while let Some(token) = lexed_input.next_any().unwrap() {
+            let span = (token.span().start(), token.span().end());
+            tokens.push((token.name().to_string(), token.content().to_string(), span));
+        }
+        assert_eq!(
+            tokens,
+            [
+                ("ID".to_string(), "ab".to_string(), ((0, 0), (0, 1))),
+                ("ERROR".to_string(), "$%&".to_string(), ((0, 3), (0, 5))),
+                ("ID".to_string(), "cd".to_string(), ((0, 7), (0, 8))),
+                ("ERROR".to_string(), "#".to_string(), ((0, 9), (0, 9))),
+            ]
+        );
+
+        // Where the error terminal is not allowed, lexing fails as usual.
+        let mut input = StringStream::new(Path::new("<input>"), "$ab");
+        let mut lexed_input = lexer.lex(&mut input).with_error_tokens(error);
+        let error = lexed_input.next(Allowed::Some(vec![id])).unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::LexingError { .. }));
+        assert_eq!(lexed_input.next_any().unwrap().unwrap().content(), "$");
+        assert_eq!(lexed_input.next(Allowed::Some(vec![id])).unwrap().unwrap().content(), "ab");
+    }
+
+    #[test]
+    fn lex_channels() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<channels>"),
+            "ID ::= ([a-z]+)\nchannel(comments) COMMENT ::= #([^\\n]*)\n\
+             ignore SPACE ::= [ \\n]+",
+        ))
+        .unwrap();
+        let comment = lexer.grammar().id("COMMENT").unwrap();
+        assert_eq!(lexer.grammar().channel(comment), Some("comments"));
+        assert!(lexer.grammar().ignored(comment));
+        assert!(lexer.grammar().canonical().contains("\nchannel(comments) COMMENT\n"));
+        let mut input = StringStream::new(Path::new("<input>"), "a #one\nb\n#two");
+        let mut lexed_input = lexer.lex(&mut input);
+        let mut names = Vec::new();
+        while let Some(token) = lexed_input.next_any().unwrap() {
+            names.push(token.content().to_string());
+        }
+        assert_eq!(names, ["a", "b"]);
+        let comments: Vec<_> = lexed_input
+            .channel("comments")
+            .map(|token| token.content())
+            .collect();
+        assert_eq!(comments, ["one", "two"]);
+        assert_eq!(lexed_input.channel("other").count(), 0);
+    }
+
+    #[test]
+    fn lex_converters() {
+        use crate::parser::{earley::{EarleyGrammar, EarleyParser}, Parser};
+
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<converters>"),
+            "INT with integer ::= ([0-9a-fx]+)\nignore SPACE ::= [ ]+",
+        ))
+        .unwrap();
+        let int = lexer.grammar().id("INT").unwrap();
+        let lexer = lexer.with_converter(int, |text| {
+            text.parse()
+                .map(Value::Int)
+                .map_err(|error| format!("{text}: {error}"))
+        });
+        let mut input = StringStream::new(Path::new("<input>"), "0x1f 7");
+        let mut lexed_input = lexer.lex(&mut input);
+        let token = lexed_input.next_any().unwrap().unwrap();
+        assert_eq!(token.content(), "31");
+        assert_eq!(token.value(0), Some(&Value::Int(31)));
+
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar>"), "@Number ::= INT.0@value <>;"),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let mut input = StringStream::new(Path::new("<input>"), "0x1f");
+        let ast = parser.parse(&mut lexer.lex(&mut input)).unwrap().tree;
+        let AST::Node { attributes, .. } = ast else {
+            panic!("expected a node");
+        };
+        assert!(matches!(attributes["value"], AST::Literal { value: Value::Int(31), .. }));
+
+        let mut input = StringStream::new(Path::new("<input>"), "0xffffffffff");
+        let error = lexer.lex(&mut input).next_any().unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::TokenProcessing { .. }));
+    }
+
+    #[test]
+    fn lex_max_token_length() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<max token length>"),
+            "ID ::= [a-z]+\nignore SPACE ::= [ ]+",
+        ))
+        .unwrap()
+        .with_max_token_length(4);
+        let mut input = StringStream::new(Path::new("<input>"), "abcd ab  abcde");
+        let mut lexed_input = lexer.lex(&mut input);
+        assert_eq!(lexed_input.next_any().unwrap().unwrap().name(), "ID");
+        assert_eq!(lexed_input.next_any().unwrap().unwrap().name(), "ID");
+        let error = lexed_input.next_any().unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::TokenTooLong { max_length: 4, .. }));
+    }
+
+    #[test]
+    fn lex_post_processors() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<post-processors>"),
+            r#"STRING with unescape ::= "(([^"\\]|\\.)*)"
+INT with integer ::= ([0-9a-fx_]+)
+ID with trim lowercase ::= ([A-Za-z]+)
+ignore SPACE ::= \s+"#,
+        ))
+        .unwrap();
+        let id = lexer.grammar().id("ID").unwrap();
+        let lexer = lexer.with_post_processor(id, |text| Ok(format!("{text}!")));
+        let lex = |input: &str| {
+            let mut input = StringStream::new(Path::new("<input>"), input);
+            let mut lexed_input = lexer.lex(&mut input);
+            let mut attributes = Vec::new();
+            while let Some(token) = lexed_input.next_any()? {
+                attributes.push(token.content().to_string());
+            }
+            Ok::<_, crate::error::Error>(attributes)
+        };
+        assert_eq!(
+            lex(r#""a\"\n" 0x_1f Hello"#).unwrap(),
+            ["a\"\n", "31", "hello!"]
+        );
+        let error = lex(r#""\q""#).unwrap_err();
+        let ErrorKind::TokenProcessing { terminal, .. } = *error.kind else {
+            panic!("wrong error");
+        };
+        assert_eq!(terminal, "STRING");
+        assert!(lex("0x").is_err());
+    }
+
+    #[test]
+    fn lex_linear() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<linear lexing>"),
+            "A ::= a\nB ::= a*b",
+        ))
+        .unwrap();
+        let length = 3000;
+        let mut input = StringStream::new(Path::new("<input>"), "a".repeat(length));
+        let mut lexed_input = lexer.lex(&mut input);
+        let mut tokens = 0;
+        while lexed_input.next_any().unwrap().is_some() {
+            tokens += 1;
+        }
+        assert_eq!(tokens, length);
+        // Each token would otherwise be found by a scan to the end of the input.
+        assert!(lexed_input.failures.scanned() <= 4 * length);
+    }
+
+    #[test]
+    fn lex_linear_priority() {
+        let length = 3000;
+        for disambiguation in [
+            Disambiguation::LongestMatch,
+            Disambiguation::Priority,
+            Disambiguation::DeclarationOrder,
+        ] {
+            let lexer = Lexer::build_from_plain(StringStream::new(
+                Path::new("<linear lexing>"),
+                "B priority 1 ::= a*b\nA ::= a",
+            ))
+            .unwrap()
+            .with_disambiguation(disambiguation);
+            let mut input = StringStream::new(Path::new("<input>"), "a".repeat(length));
+            let mut lexed_input = lexer.lex(&mut input);
+            let mut tokens = 0;
+            while lexed_input.next_any().unwrap().is_some() {
+                tokens += 1;
+            }
+            assert_eq!(tokens, length);
+            // Each token would otherwise be compared with a scan of `B` to the end.
+            assert!(!lexed_input.restricted.is_empty());
+            let scanned = lexed_input.failures.scanned()
+                + lexed_input.restricted.values().map(Failures::scanned).sum::<usize>();
+            assert!(scanned <= 8 * length, "{disambiguation:?} scanned {scanned}");
+        }
+    }
+
     #[test]
     fn unwantend_token() {
         let lexer = Lexer::build_from_plain(StringStream::new(
@@ -417,6 +1636,124 @@ mod tests {
         assert_eq!("unclosed comment", message);
     }
 
+    #[test]
+    fn skip_until() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<skip until>"),
+            "ignore SPACE ::= \\s+\nID ::= [a-z]+\nSEMICOLON ::= ;",
+        ))
+        .unwrap();
+        let semicolon = lexer.grammar().id("SEMICOLON").unwrap();
+        let mut input = StringStream::new(Path::new("<skip until input>"), "a b $ c; d");
+        let mut lexed_input = lexer.lex(&mut input);
+
+        let span = lexed_input.skip_until(&[semicolon]).unwrap();
+        assert_eq!(span.start(), (0, 0));
+        assert_eq!(span.end(), (0, 6));
+        let token = lexed_input.next(Allowed::All).unwrap().unwrap();
+        assert_eq!(token.name(), "SEMICOLON");
+        assert!(lexed_input.skip_until(&[semicolon]).is_some());
+        assert!(lexed_input.next(Allowed::All).unwrap().is_none());
+        assert!(lexed_input.skip_until(&[semicolon]).is_none());
+    }
+
+    #[test]
+    fn lex_at() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lex at>"),
+            "ignore SPACE ::= \\s+\nID ::= [a-z]+",
+        ))
+        .unwrap();
+        let mut input = StringStream::new(Path::new("<lex at input>"), "ab\ncd ef");
+        let mut lexed_input = lexer.lex_at(&mut input, 6).unwrap();
+        let token = lexed_input.next(Allowed::All).unwrap().unwrap();
+        assert_eq!(token.span().start(), (1, 3));
+        assert_eq!(token.span().byte_range(), 6..8);
+        assert!(lexer.lex_at(&mut input, 42).is_err());
+
+        let mut window =
+            StringStream::starting_at(Path::new("<lex at input>"), "cd ef", 3, 3, (1, 0));
+        let mut lexed_window = lexer.lex(&mut window);
+        assert_eq!(lexed_window.last_span().start(), (1, 0));
+        let token = lexed_window.next(Allowed::All).unwrap().unwrap();
+        assert_eq!(token.span().start(), (1, 0));
+        assert_eq!(token.span().byte_range(), 3..5);
+        let token = lexed_window.next(Allowed::All).unwrap().unwrap();
+        assert_eq!(token.span().start(), (1, 3));
+        assert_eq!(token.span().byte_range(), 6..8);
+    }
+
+    #[test]
+    fn trivia() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<trivia>"),
+            "ignore COMMENT ::= #[^\\n]*\nignore SPACE ::= [ \\t\\n]+\nID ::= [a-z]+",
+        ))
+        .unwrap();
+        let mut input =
+            StringStream::new(Path::new("<trivia input>"), "# doc\na # b\n  b # end");
+        let mut lexed_input = lexer.lex(&mut input).with_trivia();
+        let names = |tokens: &[Token]| {
+            tokens
+                .iter()
+                .map(|token| token.name().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let token = lexed_input.next(Allowed::All).unwrap().unwrap();
+        assert_eq!(names(token.trivia()), ["COMMENT", "SPACE"]);
+        let token = lexed_input.next(Allowed::All).unwrap().unwrap();
+        assert_eq!(names(token.trivia()), ["SPACE", "COMMENT", "SPACE"]);
+        lexed_input.drop_last();
+        let token = lexed_input.next(Allowed::All).unwrap().unwrap();
+        assert_eq!(names(token.trivia()), ["SPACE", "COMMENT", "SPACE"]);
+        assert!(lexed_input.next(Allowed::All).unwrap().is_none());
+        assert_eq!(names(lexed_input.trailing_trivia()), ["SPACE", "COMMENT"]);
+
+        let mut input = StringStream::new(Path::new("<trivia input>"), "# doc\na");
+        let mut lexed_input = lexer.lex(&mut input);
+        let token = lexed_input.next(Allowed::All).unwrap().unwrap();
+        assert!(token.trivia().is_empty());
+    }
+
+    #[test]
+    fn families() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<families>"),
+            r#"ignore SPACE ::= \s+
+OPERATOR ::= [-+*/]
+PLUS in OPERATOR ::= +
+STAR in OPERATOR ::= *"#,
+        ))
+        .unwrap();
+        let grammar = lexer.grammar();
+        let id = |name: &str| grammar.id(name).unwrap();
+        assert_eq!(grammar.family_of(id("PLUS")), Some(id("OPERATOR")));
+        assert_eq!(grammar.family_of(id("OPERATOR")), None);
+
+        let mut input = StringStream::new(Path::new("<families input>"), "+ - *");
+        verify_input(
+            lexer.lex(&mut input),
+            &[
+                ((0, 0), (0, 0), "PLUS"),
+                ((0, 2), (0, 2), "OPERATOR"),
+                ((0, 4), (0, 4), "STAR"),
+            ],
+        );
+
+        let mut input = StringStream::new(Path::new("<families input>"), "*+");
+        let mut lexed_input = lexer.lex(&mut input);
+        let stars = Allowed::Some(vec![id("STAR")]);
+        assert_eq!(lexed_input.next(stars).unwrap().unwrap().name(), "STAR");
+        assert!(lexed_input.next(Allowed::Some(vec![id("STAR")])).is_err());
+
+        assert!(Lexer::build_from_plain(StringStream::new(
+            Path::new("<families>"),
+            "PLUS in OPERATOR ::= +",
+        ))
+        .is_err());
+    }
+
     fn verify_input(
         mut lexed_input: LexedStream<'_, '_>,
         result: &[(Location, Location, &str)],
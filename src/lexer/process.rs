@@ -0,0 +1,162 @@
+//! # Process
+//!
+//! Post-processors transform the attributes of the tokens of a terminal once they
+//! are lexed, before parsers see them. The builtin [`Processor`]s are declared in
+//! lexer grammars, as in `STRING with unescape ::= "(([^"\\]|\\.)*)"`, and any
+//! [`PostProcessor`] can be added to a lexer with [`Lexer::with_post_processor`].
+//! A [`Converter`], added with [`Lexer::with_converter`], then turns them into the
+//! values parsers put in ASTs, such as integers, rather than their text.
+//!
+//! [`Lexer::with_post_processor`]: super::Lexer::with_post_processor
+//! [`Lexer::with_converter`]: super::Lexer::with_converter
+
+use crate::parser::Value;
+use serde::{Deserialize, Serialize};
+
+/// A transformation of the attributes of tokens, which fails with a message.
+pub type PostProcessor = Box<dyn Fn(&str) -> Result<String, String>>;
+
+/// A conversion of the attributes of tokens to the values parsers put in ASTs,
+/// which fails with a message.
+pub type Converter = Box<dyn Fn(&str) -> Result<Value, String>>;
+
+/// The post-processors that lexer grammars can declare.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Processor {
+    /// Replace the escape sequences `\n`, `\r`, `\t`, `\0`, `\\`, `\"`, `\'` and
+    /// `\u{...}` with the char they stand for.
+    Unescape,
+    /// Remove the whitespace around the attribute.
+    Trim,
+    Lowercase,
+    Uppercase,
+    /// Write an integer literal, decimal or prefixed by `0x`, `0o` or `0b`, and
+    /// whose digits may be separated by `_`, in decimal.
+    Integer,
+}
+
+impl Processor {
+    const ALL: [Self; 5] = [
+        Self::Unescape,
+        Self::Trim,
+        Self::Lowercase,
+        Self::Uppercase,
+        Self::Integer,
+    ];
+
+    /// Return the processor called `name` in lexer grammars, if there is one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|processor| processor.name() == name)
+    }
+
+    /// Return the name of the processor in lexer grammars.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Unescape => "unescape",
+            Self::Trim => "trim",
+            Self::Lowercase => "lowercase",
+            Self::Uppercase => "uppercase",
+            Self::Integer => "integer",
+        }
+    }
+
+    /// Return the names of every processor, for error messages.
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        Self::ALL.into_iter().map(Self::name)
+    }
+
+    /// Transform the attribute `text`.
+    pub fn apply(self, text: &str) -> Result<String, String> {
+        match self {
+            Self::Unescape => unescape(text),
+            Self::Trim => Ok(text.trim().to_string()),
+            Self::Lowercase => Ok(text.to_lowercase()),
+            Self::Uppercase => Ok(text.to_uppercase()),
+            Self::Integer => integer(text),
+        }
+    }
+}
+
+fn unescape(text: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(chr) = chars.next() {
+        if chr != '\\' {
+            result.push(chr);
+            continue;
+        }
+        let escaped = match chars.next() {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('0') => '\0',
+            Some(c @ ('\\' | '"' | '\'')) => c,
+            Some('u') => {
+                let rest = chars.as_str();
+                let code = rest
+                    .strip_prefix('{')
+                    .and_then(|rest| rest.split_once('}'))
+                    .map(|(code, _)| code)
+                    .ok_or_else(|| String::from("expected \\u{...}"))?;
+                let escaped = u32::from_str_radix(code, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("\\u{{{code}}} is not a char"))?;
+                // Skip the braces and the code.
+                chars.nth(code.chars().count() + 1);
+                escaped
+            }
+            Some(c) => return Err(format!("unknown escape sequence \\{c}")),
+            None => return Err(String::from("unfinished escape sequence")),
+        };
+        result.push(escaped);
+    }
+    Ok(result)
+}
+
+fn integer(text: &str) -> Result<String, String> {
+    let (digits, radix) = match text.get(..2) {
+        Some("0x" | "0X") => (&text[2..], 16),
+        Some("0o" | "0O") => (&text[2..], 8),
+        Some("0b" | "0B") => (&text[2..], 2),
+        _ => (text, 10),
+    };
+    let digits = digits.replace('_', "");
+    if digits.is_empty() {
+        return Err(format!("{text} has no digits"));
+    }
+    u128::from_str_radix(&digits, radix)
+        .map(|value| value.to_string())
+        .map_err(|error| format!("{text} is not an integer: {error}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn processors() {
+        for name in Processor::names() {
+            assert_eq!(Processor::from_name(name).unwrap().name(), name);
+        }
+        assert_eq!(Processor::from_name("nfc"), None);
+
+        let unescape = |text: &str| Processor::Unescape.apply(text);
+        assert_eq!(unescape(r#"a\n\t\"\\\u{e9}b"#).unwrap(), "a\n\t\"\\\u{e9}b");
+        assert!(unescape(r"\q").is_err());
+        assert!(unescape("a\\").is_err());
+        assert!(unescape(r"\u{110000}").is_err());
+        assert!(unescape(r"\u{e9").is_err());
+
+        let integer = |text: &str| Processor::Integer.apply(text);
+        assert_eq!(integer("0x_ff").unwrap(), "255");
+        assert_eq!(integer("0b101").unwrap(), "5");
+        assert_eq!(integer("1_000").unwrap(), "1000");
+        assert!(integer("0x").is_err());
+        assert!(integer("12a").is_err());
+
+        assert_eq!(Processor::Trim.apply(" a ").unwrap(), "a");
+        assert_eq!(Processor::Lowercase.apply("aB").unwrap(), "ab");
+        assert_eq!(Processor::Uppercase.apply("aB").unwrap(), "AB");
+    }
+}
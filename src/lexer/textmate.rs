@@ -0,0 +1,215 @@
+//! # TextMate
+//!
+//! Export of a lexer grammar to a TextMate grammar (`.tmLanguage.json`), which
+//! most editors understand. The mapping is best-effort: each terminal becomes
+//! a `match` pattern, whose scope is guessed from the terminal's flags and
+//! name.
+
+use super::ast::{Ast, Terminal};
+use crate::{
+    build_system, builder::Buildable, error::Result, parser::Parser, stream::StringStream,
+    typed::Tree,
+};
+use serde::Serialize;
+
+/// A single TextMate pattern, matching one terminal.
+#[derive(Debug, Serialize)]
+pub struct Pattern {
+    pub name: String,
+    #[serde(rename = "match")]
+    pub regex: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+/// # Summary
+///
+/// A TextMate grammar, as serialized in `.tmLanguage.json` files.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextMateGrammar {
+    pub name: String,
+    pub scope_name: String,
+    pub file_types: Vec<String>,
+    pub patterns: Vec<Pattern>,
+}
+
+impl TextMateGrammar {
+    /// Build a TextMate grammar named `name` from the source of a lexer
+    /// grammar. Ignored terminals that are not comments are left out, since
+    /// there is nothing to highlight.
+    pub fn build_from_plain(
+        source: StringStream,
+        name: &str,
+        file_types: Vec<String>,
+    ) -> Result<Self> {
+        let patterns = read_terminals(source)?
+            .iter()
+            // Members of families are highlighted by the regex of their family.
+            .filter(|terminal| terminal.family.is_none())
+            .filter_map(|terminal| {
+                let mut regex = translate(&terminal.regex.inner, terminal.keyword.inner);
+                if terminal.insensitive.inner {
+                    regex.insert_str(0, "(?i)");
+                }
+                scope_of(terminal).map(|scope| Pattern {
+                    name: format!("{scope}.{name}"),
+                    regex,
+                    comment: Some(terminal.name.inner.to_string()),
+                })
+            })
+            .collect();
+        Ok(Self {
+            name: name.to_string(),
+            scope_name: format!("source.{name}"),
+            file_types,
+            patterns,
+        })
+    }
+}
+
+/// Parse the source of a lexer grammar, and return its terminals.
+pub(super) fn read_terminals(mut source: StringStream) -> Result<Vec<Terminal>> {
+    let (lexer, parser) = build_system!(
+        lexer => "lexer.clx",
+        parser => "lexer.cgr",
+    )?;
+    let mut input = lexer.lex(&mut source);
+    let result = parser.parse(&mut input)?;
+    Ok(Ast::read(result.tree)?.terminals)
+}
+
+/// Guess the scope of a terminal, or `None` if it should not be highlighted.
+fn scope_of(terminal: &Terminal) -> Option<&'static str> {
+    let name = terminal.name.inner.to_uppercase();
+    let is = |words: &[&str]| words.iter().any(|word| name.contains(word));
+    if terminal.unwanted.inner {
+        Some("invalid.illegal")
+    } else if is(&["COMMENT"]) {
+        Some("comment.block")
+    } else if terminal.ignore.inner {
+        None
+    } else if is(&["STRING", "CHAR"]) {
+        Some("string.quoted")
+    } else if is(&["TRUE", "FALSE", "BOOL", "NULL", "NIL"]) {
+        Some("constant.language")
+    } else if terminal.keyword.inner
+        || terminal
+            .regex
+            .inner
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_')
+    {
+        Some("keyword.control")
+    } else if is(&["NUMBER", "INT", "FLOAT", "DIGIT"]) {
+        Some("constant.numeric")
+    } else if is(&["ID", "NAME"]) {
+        Some("variable.other")
+    } else if terminal.regex.inner.chars().any(char::is_alphanumeric) {
+        Some("entity.name")
+    } else {
+        Some("keyword.operator")
+    }
+}
+
+/// Translate a Beans regex into an Oniguruma one. Both syntaxes mostly agree,
+/// except that braces are literal characters in Beans, unless they bound a
+/// repetition. Keywords must end on a word boundary, and are made to start on one
+/// too.
+fn translate(regex: &str, keyword: bool) -> String {
+    let mut result = String::with_capacity(regex.len());
+    if keyword {
+        result.push_str("\\b");
+    }
+    let mut chars = regex.chars();
+    let mut in_class = false;
+    let mut in_bounds = false;
+    while let Some(chr) = chars.next() {
+        match chr {
+            '\\' => {
+                result.push(chr);
+                if let Some(escaped) = chars.next() {
+                    result.push(escaped);
+                }
+            }
+            '[' if !in_class => {
+                in_class = true;
+                result.push(chr);
+            }
+            ']' if in_class => {
+                in_class = false;
+                result.push(chr);
+            }
+            // TextMate grammars name groups the way of Oniguruma, `(?<name>...)`.
+            '(' if !in_class && chars.as_str().starts_with("?P<") => {
+                chars.nth(1);
+                result.push_str("(?");
+            }
+            '{' if !in_class
+                && matches!(chars.clone().next(), Some(c) if c.is_ascii_digit()) =>
+            {
+                in_bounds = true;
+                result.push(chr);
+            }
+            '}' if in_bounds => {
+                in_bounds = false;
+                result.push(chr);
+            }
+            '{' | '}' if !in_class => {
+                result.push('\\');
+                result.push(chr);
+            }
+            _ => result.push(chr),
+        }
+    }
+    if keyword {
+        result.push_str("\\b");
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn textmate() {
+        let grammar = TextMateGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<lexer input>"),
+                r#"ignore SPACE ::= \s+
+ignore COMMENT ::= //[^\n]*
+keyword IF ::= if
+keyword insensitive THEN ::= then
+ID ::= [a-z]+
+NUMBER ::= [0-9]+
+LBRACE ::= {
+PLUS ::= \+"#,
+            ),
+            "toy",
+            vec![String::from("toy")],
+        )
+        .unwrap();
+        assert_eq!(grammar.scope_name, "source.toy");
+        let patterns = grammar
+            .patterns
+            .iter()
+            .map(|pattern| (pattern.name.as_str(), pattern.regex.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            patterns,
+            [
+                ("comment.block.toy", r"//[^\n]*"),
+                ("keyword.control.toy", r"\bif\b"),
+                ("keyword.control.toy", r"(?i)\bthen\b"),
+                ("variable.other.toy", "[a-z]+"),
+                ("constant.numeric.toy", "[0-9]+"),
+                ("keyword.operator.toy", r"\{"),
+                ("keyword.operator.toy", r"\+"),
+            ]
+        );
+        assert_eq!(translate("[{]a{2,3}{x}", false), r"[{]a{2,3}\{x\}");
+        assert_eq!(translate("(?P<int>[0-9]+)[(?P<]", false), "(?<int>[0-9]+)[(?P<]");
+    }
+}
@@ -9,17 +9,30 @@
 pub mod builder;
 mod case;
 pub mod error;
+#[cfg(feature = "examples")]
+pub mod examples;
+pub mod interner;
+#[cfg(feature = "kernel")]
+pub mod kernel;
 pub mod lexer;
 mod list;
 pub mod parser;
+pub mod prelude;
 pub mod printer;
+pub mod progress;
 pub mod regex;
+#[cfg(feature = "serve")]
+pub mod server;
 pub mod span;
+pub mod stable;
 pub mod stream;
 pub mod typed;
+
+/// Traits that only this crate implements, so that the traits they bound can gain
+/// methods without breaking anyone.
+mod sealed {
+    pub trait Sealed {}
+}
 // Macros
 #[macro_use]
 mod utilities;
-#[cfg(test)]
-#[macro_use]
-mod test_utilities;
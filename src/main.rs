@@ -1,17 +1,45 @@
 use anyhow::Context;
-use beans::builder::Buildable;
+use beans::builder::{serialize_compiled, Buildable};
 use beans::error::ErrorKind;
-use beans::lexer::{Grammar as LexerGrammar, Lexer};
-use beans::parser::earley::{print_final_sets, print_sets, EarleyGrammar, EarleyParser};
-use beans::parser::Parser;
-use beans::printer::print_ast;
-use beans::regex::Allowed;
+use beans::lexer::editor::{extension_manifest, LanguageConfiguration};
+use beans::lexer::format::format_lexer_grammar;
+use beans::lexer::textmate::TextMateGrammar;
+use beans::lexer::{Disambiguation, Grammar as LexerGrammar, Layout, Lexer, TerminalId};
+use beans::parser::analysis::{ambiguity_hints, lint, Completions, Lint};
+use beans::parser::codegen::{generate_types, generate_visitor};
+use beans::parser::coverage::Coverage;
+use beans::parser::dot::{ast_to_dot, forest_to_dot};
+use beans::parser::earley::{
+    print_final_sets, print_profile, print_sets, EarleyGrammar, EarleyParser, RulePreference,
+};
+use beans::parser::format::{format_parser_grammar, DEFAULT_WIDTH};
+use beans::parser::lalr::{LalrGrammar, LalrParser};
+use beans::parser::resolve::{
+    ambiguity_questions, conflict_questions, prioritize, range_in, set_associativity,
+    strip_associativity, Question,
+};
+use beans::parser::version::VersionedAst;
+use beans::parser::{Event, Parser, AST};
+use beans::printer::{print_ast, to_sexpr};
+use beans::progress::{Phase, Progress, ProgressBar, ProgressReporter};
+use beans::regex::{Allowed, RegexBuilder};
+#[cfg(feature = "serve")]
+use beans::server::Server;
+use beans::span::Span;
+use beans::stable::StableHasher;
 use beans::stream::StringStream;
-use bincode::{deserialize, serialize};
-use clap::{Parser as CliParser, Subcommand};
+use clap::{Args, Parser as CliParser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 use std::fs::File;
-use std::io::{prelude::*, stdout, BufWriter};
-use std::path::PathBuf;
+use std::hash::Hasher;
+use std::io::{prelude::*, stderr, stdin, stdout, BufReader, BufWriter};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 #[derive(CliParser)]
 #[command(author, version, about, long_about=None)]
@@ -19,16 +47,153 @@ use std::path::PathBuf;
 struct Cli {
     #[command(subcommand)]
     action: Action,
+    /// Draw the progress of long compilations and parses on the standard error
+    #[arg(long, global = true)]
+    progress: bool,
 }
 
-#[derive(Subcommand)]
+#[derive(Clone, Subcommand)]
 enum Action {
+    /// Time how long the cases of a benchmark suite, such as `benches/grammars`, take
+    /// to build, lex and parse, to tell the impact of a change of the engine
+    Bench {
+        /// The directory of the suite, whose `suite.toml` lists its cases
+        #[arg(long)]
+        suite: PathBuf,
+        /// How many times to run each case, the median run being reported
+        #[arg(long, value_name = "N", default_value_t = 5)]
+        runs: usize,
+        /// Multiply the size of the input of each case
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        scale: usize,
+        /// Only run the cases whose name contains this
+        filter: Option<String>,
+    },
+    /// Build grammars in memory, without writing them, and warn about their likely
+    /// mistakes: terminals that match a same text, non-terminals that no axiom
+    /// derives, rules that derive no sequence of tokens, rules repeated within a
+    /// non-terminal, and terminals neither ignored nor used
+    Check {
+        /// Specify the lexer's grammar
+        #[arg(short, long = "lexer")]
+        lexer_grammar: PathBuf,
+        /// Specify the parser's grammar. Without it, only the lexer's grammar is checked
+        #[arg(short, long = "parser")]
+        parser_grammar: Option<PathBuf>,
+        /// Also warn about what usually makes the parser's grammar ambiguous
+        #[arg(long, requires = "parser_grammar")]
+        check_conflicts: bool,
+        /// Fail if there is any warning
+        #[arg(long)]
+        deny_warnings: bool,
+    },
+    /// Generate a Rust visitor trait for the ASTs of a parser grammar
+    Codegen {
+        /// Specify the lexer's grammar
+        #[arg(short, long = "lexer")]
+        lexer_grammar: PathBuf,
+        /// Specify the parser's grammar
+        #[arg(short, long = "parser")]
+        parser_grammar: PathBuf,
+        /// The path of the resulting Rust module. Defaults to the standard output
+        #[arg(short = 'o', long = "output")]
+        output_path: Option<PathBuf>,
+    },
+    Compile(CompileArgs),
+    /// Parse a test corpus, and print the rules of a parser grammar that no file of
+    /// the corpus exercises
+    Coverage {
+        /// Specify the lexer's grammar
+        #[arg(short, long = "lexer")]
+        lexer_grammar: PathBuf,
+        /// Specify the parser's grammar
+        #[arg(short, long = "parser", visible_alias = "grammar")]
+        parser_grammar: PathBuf,
+        /// The files of the corpus, `-` standing for the standard input
+        #[arg(required = true)]
+        sources: Vec<PathBuf>,
+    },
+    /// Print a grammar that builds a compiled lexer (`.clx`) or parser (`.cgr`)
+    /// grammar: its terminals with their regexes, or its rules with their proxies
+    Decompile {
+        /// The compiled grammar
+        blob: PathBuf,
+        /// The lexer's grammar of a compiled parser grammar, to name its terminals.
+        /// Without it, terminals are written `#id`
+        #[arg(short, long = "lexer")]
+        lexer_grammar: Option<PathBuf>,
+    },
     #[command(subcommand)]
-    Compile(CompileAction),
-    Lex {
-        #[arg(short = 'l', long = "lexer")]
+    Export(ExportAction),
+    /// Rewrite lexer (`.lx`) and parser (`.gr`) grammars in the canonical layout, with
+    /// aligned `::=`, one rule per line and sorted proxies
+    Fmt {
+        /// The grammars to format
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Only list the grammars that are not formatted, and fail if there is any
+        #[arg(long)]
+        check: bool,
+        /// Wrap the rules of parser grammars longer than this many chars
+        #[arg(long, value_name = "N", default_value_t = DEFAULT_WIDTH)]
+        width: usize,
+    },
+    /// Print random sentences of a parser grammar, one per line, to test the lexer and
+    /// the parser, or to seed a fuzzer
+    Generate {
+        /// Specify the lexer's grammar
+        #[arg(short, long = "lexer")]
         lexer_grammar: PathBuf,
-        source: PathBuf,
+        /// Specify the parser's grammar
+        #[arg(short, long = "parser")]
+        parser_grammar: PathBuf,
+        /// How many sentences to print
+        #[arg(short = 'n', long, value_name = "N", default_value_t = 1)]
+        count: usize,
+        /// Past this many nested non-terminals, derive them as soon as possible
+        #[arg(long, value_name = "N", default_value_t = 8)]
+        depth: usize,
+        /// Where the random choices start from, to print the same sentences again.
+        /// Defaults to the current time
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Create a starter project: a lexer and a parser grammar, a `beans.toml`
+    /// manifest, a sample input and a Rust program that parses it
+    Init {
+        /// The directory of the project, created if needed
+        #[arg(default_value = ".")]
+        directory: PathBuf,
+        /// The name of the language. Defaults to the name of the directory
+        #[arg(long)]
+        name: Option<String>,
+        /// Overwrite the files that already exist
+        #[arg(long)]
+        force: bool,
+    },
+    Lex {
+        #[arg(short = 'l', long = "lexer", required_unless_present = "manifest")]
+        lexer_grammar: Option<PathBuf>,
+        /// Pick the lexer's grammar of each file from the registry of a manifest
+        #[arg(long, conflicts_with = "lexer_grammar")]
+        manifest: Option<PathBuf>,
+        /// Fail on tokens of more than this many chars
+        #[arg(long, value_name = "N")]
+        max_token_length: Option<usize>,
+        /// Produce INDENT, DEDENT and NEWLINE tokens from the indentation of lines, the
+        /// lexer's grammar declaring those terminals
+        #[arg(long)]
+        layout: bool,
+        /// Which terminal wins when several match at the same place
+        #[arg(long, value_enum, default_value_t = TokenPolicy::LongestMatch)]
+        disambiguation: TokenPolicy,
+        /// Produce ERROR tokens for the input that cannot be lexed, rather than failing,
+        /// the lexer's grammar declaring that terminal
+        #[arg(long)]
+        error_tokens: bool,
+        /// The files to lex, `-` standing for the standard input
+        #[arg(required = true)]
+        sources: Vec<PathBuf>,
     },
     Parse {
         /// Show the intermediate table used by the Earley parser
@@ -37,24 +202,277 @@ enum Action {
         /// Show the final table used by the Earley parser
         #[arg(short, long)]
         final_table: bool,
+        /// Use the LALR(1) backend instead of the Earley one. The grammar must then
+        /// either be compiled with `--lalr`, or be free of conflicts
+        #[arg(long)]
+        lalr: bool,
+        /// Skip the unexpected tokens, and report up to this many syntax errors
+        #[arg(long, value_name = "N", conflicts_with = "lalr")]
+        max_errors: Option<usize>,
+        /// Report where the input is ambiguous, and which rules picked the derivation
+        #[arg(long, conflicts_with_all = ["lalr", "max_errors"])]
+        warn_ambiguity: bool,
+        /// Which of two rules to prefer when associativity does not decide, whatever
+        /// the grammar prefers
+        #[arg(long, value_enum, conflicts_with = "lalr")]
+        prefer: Option<Prefer>,
+        /// How to print the ASTs
+        #[arg(long, value_enum, default_value_t = OutputFormat::Tree)]
+        format: OutputFormat,
+        /// Print the shared packed parse forest, as a Graphviz graph, instead of the AST
+        #[arg(long, conflicts_with_all = ["lalr", "max_errors", "format"])]
+        forest: bool,
+        /// Print the rules entered and exited, and the tokens read, while walking the
+        /// derivation, instead of building the AST, so that huge inputs can be streamed
+        #[arg(
+            long,
+            conflicts_with_all = [
+                "table",
+                "final_table",
+                "max_errors",
+                "warn_ambiguity",
+                "format",
+                "forest",
+            ]
+        )]
+        events: bool,
+        /// Report, on the standard error, what each rule cost to parse each input: the
+        /// items it added to the table, how many were predicted and completed, and the
+        /// nodes built with it
+        #[arg(long, conflicts_with_all = ["lalr", "max_errors", "events"])]
+        profile_rules: bool,
+        /// Fail on tokens of more than this many chars
+        #[arg(long, value_name = "N")]
+        max_token_length: Option<usize>,
+        /// Produce INDENT, DEDENT and NEWLINE tokens from the indentation of lines, the
+        /// lexer's grammar declaring those terminals
+        #[arg(long)]
+        layout: bool,
+        /// Which terminal wins when several match at the same place
+        #[arg(long, value_enum, default_value_t = TokenPolicy::LongestMatch)]
+        disambiguation: TokenPolicy,
+        /// Skip the input that cannot be lexed as ERROR tokens, the lexer's grammar
+        /// declaring that terminal
+        #[arg(long, requires = "max_errors")]
+        error_tokens: bool,
+        /// Specify the lexer's grammar
+        #[arg(short, long = "lexer", required_unless_present = "manifest")]
+        lexer_grammar: Option<PathBuf>,
+        /// Specify the parser's grammar
+        #[arg(short, long = "parser", required_unless_present = "manifest")]
+        parser_grammar: Option<PathBuf>,
+        /// Pick the grammars of each file from the registry of a manifest
+        #[arg(long, conflicts_with_all = ["lexer_grammar", "parser_grammar"])]
+        manifest: Option<PathBuf>,
+        /// Parse the files again whenever they, or the grammars, change
+        #[arg(long)]
+        watch: bool,
+        /// The files to parse, `-` standing for the standard input. With a manifest,
+        /// files are parsed grouped by grammar
+        #[arg(required = true)]
+        sources: Vec<PathBuf>,
+    },
+    /// Check that sources are accepted by a grammar, without building their ASTs
+    Validate {
+        /// Use the LALR(1) backend instead of the Earley one
+        #[arg(long)]
+        lalr: bool,
+        /// Specify the lexer's grammar
+        #[arg(short, long = "lexer")]
+        lexer_grammar: PathBuf,
+        /// Specify the parser's grammar
+        #[arg(short, long = "parser")]
+        parser_grammar: PathBuf,
+        /// The files to check, `-` standing for the standard input
+        #[arg(required = true)]
+        sources: Vec<PathBuf>,
+    },
+    #[command(subcommand)]
+    Regex(RegexAction),
+    /// Settle the ambiguities of a parser grammar interactively, by annotating the
+    /// associativity of its rules or by ordering them
+    Resolve {
+        /// Specify the lexer's grammar
+        #[arg(short, long = "lexer")]
+        lexer_grammar: PathBuf,
+        /// Specify the parser's grammar, whose files are edited
+        #[arg(short, long = "parser")]
+        parser_grammar: PathBuf,
+        /// Sources to look for ambiguities in, besides the LALR(1) conflicts
+        samples: Vec<PathBuf>,
+    },
+    /// Serve a JSON API to lex and parse sources, for playgrounds
+    #[cfg(feature = "serve")]
+    Serve {
+        /// Specify the lexer's grammar
+        #[arg(short, long = "lexer")]
+        lexer_grammar: PathBuf,
+        /// Specify the parser's grammar
+        #[arg(short, long = "parser")]
+        parser_grammar: PathBuf,
+        /// The address to listen on
+        #[arg(short, long, default_value = "127.0.0.1:8080")]
+        address: String,
+    },
+    /// Print the size of a parser grammar: how many terminals, non-terminals and rules
+    /// it has, how long its rules are, and how many rules each non-terminal has
+    Stats {
         /// Specify the lexer's grammar
         #[arg(short, long = "lexer")]
         lexer_grammar: PathBuf,
         /// Specify the parser's grammar
         #[arg(short, long = "parser")]
         parser_grammar: PathBuf,
-        /// The file to parse
-        source: PathBuf,
     },
+    /// Run the tests of a grammar, listed in a `tests.toml` manifest: parse each input,
+    /// and compare the result with the expected AST or error
+    Test {
+        /// The manifest of the tests, or its directory
+        #[arg(default_value = ".")]
+        manifest: PathBuf,
+        /// Only run the tests whose input contains this
+        filter: Option<String>,
+        /// Write the ASTs the parser builds as the expected ones, rather than
+        /// comparing them
+        #[arg(long)]
+        bless: bool,
+    },
+}
+
+/// How `beans parse` prints ASTs.
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    /// A tree, for humans
+    Tree,
+    /// A JSON document per source, on a line, with the spans
+    Json,
+    /// An S-expression per source, on a line, without the spans
+    Sexpr,
+    /// A Graphviz graph per source
+    Dot,
+}
+
+/// Which rule `beans parse` prefers.
+#[derive(Clone, Copy, ValueEnum)]
+enum Prefer {
+    /// The rule that comes first in the grammar
+    First,
+    /// The rule that comes last in the grammar
+    Last,
+}
+
+/// Which terminal `beans lex` and `beans parse` pick when several match.
+#[derive(Clone, Copy, ValueEnum)]
+enum TokenPolicy {
+    /// The longest match, then the terminal of the highest priority, and then the one
+    /// declared first
+    LongestMatch,
+    /// The terminal declared first
+    DeclarationOrder,
+    /// The terminal of the highest priority, then the longest match, and then the
+    /// one declared first
+    Priority,
+}
+
+impl From<TokenPolicy> for Disambiguation {
+    fn from(policy: TokenPolicy) -> Self {
+        match policy {
+            TokenPolicy::LongestMatch => Self::LongestMatch,
+            TokenPolicy::DeclarationOrder => Self::DeclarationOrder,
+            TokenPolicy::Priority => Self::Priority,
+        }
+    }
+}
+
+impl From<Prefer> for RulePreference {
+    fn from(prefer: Prefer) -> Self {
+        match prefer {
+            Prefer::First => Self::First,
+            Prefer::Last => Self::Last,
+        }
+    }
+}
+
+/// Print `ast` to the standard output, in `format`. JSON documents hold the language
+/// version of `grammar` too, if it declares one.
+fn print_output(ast: AST, grammar: &EarleyGrammar, format: OutputFormat) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Tree => print_ast(&ast)?,
+        OutputFormat::Json => {
+            let mut output = stdout().lock();
+            match grammar.language_version() {
+                Some(version) => {
+                    serde_json::to_writer(&mut output, &VersionedAst::new(Some(version), ast))?
+                }
+                None => serde_json::to_writer(&mut output, &ast)?,
+            }
+            writeln!(output)?;
+        }
+        OutputFormat::Sexpr => println!("{}", to_sexpr(&ast)),
+        OutputFormat::Dot => print!("{}", ast_to_dot(&ast)),
+    }
+    Ok(())
+}
+
+/// Return a sink printing each event of a walk on its own line, indented by the
+/// number of rules it is in. Tokens are printed as by `beans lex`.
+fn event_printer(grammar: &EarleyGrammar) -> impl FnMut(Event<'_>) + '_ {
+    let mut depth = 0;
+    move |event: Event<'_>| match event {
+        Event::Enter { nonterminal, .. } => {
+            let name = grammar.name_of(nonterminal);
+            println!("{:indent$}enter {name}", "", indent = 2 * depth);
+            depth += 1;
+        }
+        Event::Token(token) => {
+            print!("{:indent$}{} {{ ", "", token.name(), indent = 2 * depth);
+            for (key, value) in token.attributes().iter() {
+                print!("{}: {}, ", key, value);
+            }
+            println!("}}");
+        }
+        Event::Exit { nonterminal, .. } => {
+            depth -= 1;
+            let name = grammar.name_of(nonterminal);
+            println!("{:indent$}exit {name}", "", indent = 2 * depth);
+        }
+    }
 }
 
-#[derive(Subcommand)]
+#[derive(Clone, Args)]
+#[command(args_conflicts_with_subcommands = true)]
+struct CompileArgs {
+    /// Compile every grammar of a directory (recursively), or listed in a manifest, lexers
+    /// first. A parser grammar relies on the lexer grammar with the same name, unless the
+    /// manifest line gives it after the parser grammar. Directories with a `beans.toml`
+    /// are compiled as the project it describes
+    #[arg(long, value_name = "DIRECTORY_OR_MANIFEST")]
+    all: Option<PathBuf>,
+    /// The number of grammars compiled in parallel. Defaults to the number of CPUs
+    #[arg(short, long, requires = "all")]
+    jobs: Option<usize>,
+    /// Fail if the grammars of a `beans.toml` project differ from the ones its
+    /// `beans.lock` was written for
+    #[arg(long, requires = "all")]
+    locked: bool,
+    #[command(subcommand)]
+    action: Option<CompileAction>,
+}
+
+#[derive(Clone, Subcommand)]
 enum CompileAction {
     /// Compile a lexer grammar
     Lexer {
         lexer_grammar: PathBuf,
         #[arg(short = 'o', long = "output")]
         output_path: Option<PathBuf>,
+        /// Fail if the grammar is too large for a DFA, without which lexing may take
+        /// more than linear time
+        #[arg(long)]
+        linear: bool,
+        /// Compile the grammar again whenever it changes
+        #[arg(long)]
+        watch: bool,
     },
     /// Compile a parser grammar
     Parser {
@@ -66,17 +484,797 @@ enum CompileAction {
         /// The path to the lexer grammar on top of which the parser relies
         #[arg(short = 'l', long = "lexer")]
         lexer_path: PathBuf,
+        /// Compile the grammar to LALR(1) tables, and fail if it has conflicts
+        #[arg(long)]
+        lalr: bool,
+        /// Leave out the proxies and the attributes, for a grammar only used by
+        /// `beans validate`
+        #[arg(long)]
+        recognizer: bool,
+        /// Warn about the likely sources of ambiguity of the grammar: cycles of rules,
+        /// operators without precedence, rules that start alike, and optional
+        /// non-terminals that can start with what follows them
+        #[arg(long)]
+        check_conflicts: bool,
+        /// Compile the grammar again whenever it, or the lexer grammar, changes
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Generate Rust types for the ASTs of a parser grammar, with conversions from
+    /// the ASTs
+    Ast {
+        /// The path to the grammar
+        parser_grammar: PathBuf,
+        /// The path of the resulting Rust module. Defaults to the standard output
+        #[arg(short = 'o', long = "output")]
+        output_path: Option<PathBuf>,
+        /// The path to the lexer grammar on top of which the parser relies
+        #[arg(short = 'l', long = "lexer")]
+        lexer_path: PathBuf,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+enum ExportAction {
+    /// Export a table of the terminals that can start each non-terminal, and of the
+    /// terminals that can follow each terminal, as JSON
+    Completions {
+        /// Specify the lexer's grammar
+        #[arg(short, long = "lexer")]
+        lexer_grammar: PathBuf,
+        /// Specify the parser's grammar
+        #[arg(short, long = "parser")]
+        parser_grammar: PathBuf,
+        /// The path of the resulting table. Defaults to the standard output
+        #[arg(short = 'o', long = "output")]
+        output_path: Option<PathBuf>,
     },
+    /// Export a TextMate grammar (`.tmLanguage.json`) from a lexer grammar, for
+    /// baseline highlighting in editors
+    Textmate {
+        /// The path to the (uncompiled) lexer grammar
+        lexer_grammar: PathBuf,
+        /// The name of the language. Defaults to the name of the lexer grammar
+        #[arg(short, long)]
+        name: Option<String>,
+        /// The file extensions of the language. Can be repeated
+        #[arg(short = 'e', long = "extension")]
+        file_types: Vec<String>,
+        /// The path of the resulting grammar. Defaults to the standard output
+        #[arg(short = 'o', long = "output")]
+        output_path: Option<PathBuf>,
+    },
+    /// Write the skeleton of a VSCode extension for a language: its manifest, its
+    /// language configuration, with comments and brackets guessed from the lexer
+    /// grammar, and its TextMate grammar
+    LspConfig {
+        /// The path to the (uncompiled) lexer grammar
+        lexer_grammar: PathBuf,
+        /// The name of the language. Defaults to the name of the lexer grammar
+        #[arg(short, long)]
+        name: Option<String>,
+        /// The file extensions of the language. Can be repeated
+        #[arg(short = 'e', long = "extension")]
+        file_types: Vec<String>,
+        /// The directory to write the extension to
+        #[arg(short = 'o', long = "output")]
+        output_dir: PathBuf,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+enum RegexAction {
+    /// Print the program a regex compiles to, and the states of its DFA, to tell why
+    /// a terminal matches more or less than expected
+    Explain {
+        /// The regex, written as in lexer grammars
+        pattern: String,
+        /// Match regardless of case, as `insensitive` terminals do
+        #[arg(long)]
+        insensitive: bool,
+    },
+}
+
+/// The name of the language of `lexer_grammar_path`, unless it is given.
+fn language_name(name: Option<String>, lexer_grammar_path: &Path) -> anyhow::Result<String> {
+    Ok(match name {
+        Some(name) => name,
+        None => lexer_grammar_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .context("Could not guess the name of the language, try `--name`")?
+            .to_string(),
+    })
+}
+
+/// Open `output_path` for writing, or the standard output if there is none.
+fn open_output(output_path: Option<PathBuf>) -> anyhow::Result<Box<dyn Write>> {
+    Ok(match output_path {
+        Some(output) => Box::new(BufWriter::new(
+            File::create(&output)
+                .context(format!("Could not write to file {}", output.display()))?,
+        )),
+        None => Box::new(BufWriter::new(stdout())),
+    })
+}
+
+/// Read the source at `path`, or the standard input if `path` is `-`.
+fn open_source(path: &Path) -> beans::error::Result<StringStream> {
+    if path == Path::new("-") {
+        StringStream::from_reader(stdin().lock(), Path::new("<stdin>"))
+    } else {
+        StringStream::from_file(path)
+    }
+}
+
+/// The `ERROR` terminal of the grammar of `lexer`, if `error_tokens` asks for it.
+fn error_terminal(
+    lexer: &Lexer,
+    error_tokens: bool,
+) -> beans::error::Result<Option<TerminalId>> {
+    if !error_tokens {
+        return Ok(None);
+    }
+    match lexer.grammar().id("ERROR") {
+        Some(id) => Ok(Some(id)),
+        None => ErrorKind::UnknownTerminal {
+            name: String::from("ERROR"),
+        }
+        .err(),
+    }
+}
+
+fn export(export_action: ExportAction) -> anyhow::Result<()> {
+    match export_action {
+        ExportAction::Completions {
+            lexer_grammar: lexer_grammar_path,
+            parser_grammar: parser_grammar_path,
+            output_path,
+        } => {
+            let lexer = Lexer::build_from_path(&lexer_grammar_path)?;
+            let parser_grammar =
+                EarleyGrammar::build_from_path(&parser_grammar_path, lexer.grammar())?;
+            let completions = Completions::new(&parser_grammar, lexer.grammar());
+            let mut output = open_output(output_path)?;
+            serde_json::to_writer_pretty(&mut output, &completions)?;
+            writeln!(output)?;
+            output.flush()?;
+        }
+        ExportAction::Textmate {
+            lexer_grammar: lexer_grammar_path,
+            name,
+            file_types,
+            output_path,
+        } => {
+            let name = language_name(name, &lexer_grammar_path)?;
+            let stream = StringStream::from_file_with_includes(lexer_grammar_path)?;
+            let grammar = TextMateGrammar::build_from_plain(stream, &name, file_types)?;
+            let mut output = open_output(output_path)?;
+            serde_json::to_writer_pretty(&mut output, &grammar)?;
+            writeln!(output)?;
+            output.flush()?;
+        }
+        ExportAction::LspConfig {
+            lexer_grammar: lexer_grammar_path,
+            name,
+            file_types,
+            output_dir,
+        } => {
+            let name = language_name(name, &lexer_grammar_path)?;
+            let configuration = LanguageConfiguration::build_from_plain(
+                StringStream::from_file_with_includes(lexer_grammar_path.as_path())?,
+            )?;
+            let grammar = TextMateGrammar::build_from_plain(
+                StringStream::from_file_with_includes(lexer_grammar_path.as_path())?,
+                &name,
+                file_types.clone(),
+            )?;
+            let manifest = extension_manifest(&name, &file_types);
+            std::fs::create_dir_all(output_dir.join("syntaxes"))
+                .context(format!("Could not create {}", output_dir.display()))?;
+            let files: [(PathBuf, serde_json::Value); 3] = [
+                (output_dir.join("package.json"), manifest),
+                (
+                    output_dir.join("language-configuration.json"),
+                    serde_json::to_value(&configuration)?,
+                ),
+                (
+                    output_dir
+                        .join("syntaxes")
+                        .join(format!("{name}.tmLanguage.json")),
+                    serde_json::to_value(&grammar)?,
+                ),
+            ];
+            for (path, content) in files {
+                let mut output = open_output(Some(path))?;
+                serde_json::to_writer_pretty(&mut output, &content)?;
+                writeln!(output)?;
+                output.flush()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The grammars a source is handled with: a lexer grammar, and maybe a parser
+/// grammar relying on it.
+#[derive(Clone, PartialEq, Eq)]
+struct Grammars {
+    lexer: PathBuf,
+    parser: Option<PathBuf>,
+}
+
+/// The sources that the grammars of a manifest line handle.
+enum Selector {
+    /// The files with this extension.
+    Extension(String),
+    /// The files whose shebang line contains this.
+    Shebang(String),
+}
+
+/// The name of the manifests of grammar projects, and of the lock of their grammars.
+const TOML_MANIFEST: &str = "beans.toml";
+const LOCK: &str = "beans.lock";
+
+/// A `beans.toml` manifest. Paths are relative to the manifest.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct TomlManifest {
+    workspace: TomlWorkspace,
+    /// Other grammar projects, whose grammars this one relies on, by name.
+    dependencies: BTreeMap<String, TomlDependency>,
+    lexer: Vec<TomlLexer>,
+    parser: Vec<TomlParser>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct TomlWorkspace {
+    /// The directories of the nested projects, or their manifests.
+    members: Vec<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlDependency {
+    path: PathBuf,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlLexer {
+    path: PathBuf,
+    /// The sources it handles, as in text manifests.
+    #[serde(default, rename = "for")]
+    sources: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlParser {
+    path: PathBuf,
+    /// The lexer grammar it relies on, the one with the same name by default.
+    lexer: Option<PathBuf>,
+    #[serde(default, rename = "for")]
+    sources: Vec<String>,
+}
+
+/// A stable hash of `chunks`, which stays the same from one build of Beans to the
+/// other.
+fn fingerprint(chunks: &[&[u8]]) -> u64 {
+    let mut hasher = StableHasher::new();
+    for chunk in chunks {
+        hasher.write(chunk);
+    }
+    hasher.finish()
+}
+
+/// The grammars found by `beans compile --all`: lexer grammars, and parser
+/// grammars along with the lexer grammar they rely on.
+#[derive(Default)]
+struct Workspace {
+    lexers: Vec<PathBuf>,
+    parsers: Vec<(PathBuf, PathBuf)>,
+    /// The grammars sources are handled with, given by a manifest.
+    registry: Vec<(Selector, Grammars)>,
+}
+
+impl Workspace {
+    fn from_directory(directory: &Path) -> anyhow::Result<Self> {
+        fn walk(directory: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+            for entry in std::fs::read_dir(directory)
+                .context(format!("Could not read directory {}", directory.display()))?
+            {
+                let path = entry?.path();
+                if path.is_dir() {
+                    walk(&path, files)?;
+                } else {
+                    files.push(path);
+                }
+            }
+            Ok(())
+        }
+        let mut files = Vec::new();
+        walk(directory, &mut files)?;
+        files.sort();
+        let mut workspace = Self::default();
+        for file in files {
+            match file.extension().and_then(|extension| extension.to_str()) {
+                Some("lx") => workspace.lexers.push(file),
+                Some("gr") => {
+                    let lexer = file.with_extension("lx");
+                    workspace.parsers.push((file, lexer));
+                }
+                _ => {}
+            }
+        }
+        Ok(workspace)
+    }
+
+    /// Read a manifest, which lists a grammar per line. Parser grammars can be
+    /// followed by the lexer grammar they rely on. A line can end with `for` and
+    /// the sources it handles, given by extensions such as `.c`, or by what their
+    /// shebang line contains, such as `#!python`. Empty lines and lines starting
+    /// with `#` are ignored. Paths are relative to the manifest.
+    fn from_manifest(manifest: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(manifest)
+            .context(format!("Could not read manifest {}", manifest.display()))?;
+        let root = manifest.parent().unwrap_or(Path::new(""));
+        let mut workspace = Self::default();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let (paths, patterns) = match words.iter().position(|&word| word == "for") {
+                Some(index) => (&words[..index], &words[index + 1..]),
+                None => (&words[..], &[][..]),
+            };
+            let mut paths = paths.iter().map(|path| root.join(path));
+            let grammar = paths
+                .next()
+                .context(format!("Missing a grammar in manifest line `{line}`"))?;
+            let grammars = match grammar.extension().and_then(|extension| extension.to_str()) {
+                Some("lx") => {
+                    workspace.lexers.push(grammar.clone());
+                    Grammars {
+                        lexer: grammar,
+                        parser: None,
+                    }
+                }
+                Some("gr") => {
+                    let lexer = paths.next().unwrap_or_else(|| grammar.with_extension("lx"));
+                    workspace.parsers.push((grammar.clone(), lexer.clone()));
+                    Grammars {
+                        lexer,
+                        parser: Some(grammar),
+                    }
+                }
+                _ => anyhow::bail!(
+                    "{} is neither a lexer grammar nor a parser grammar",
+                    grammar.display()
+                ),
+            };
+            workspace.register(patterns, &grammars)?;
+        }
+        Ok(workspace)
+    }
+
+    /// Register `grammars` to handle the sources `patterns` select: extensions such
+    /// as `.c`, or what shebangs contain, such as `#!python`.
+    fn register(
+        &mut self,
+        patterns: &[impl AsRef<str>],
+        grammars: &Grammars,
+    ) -> anyhow::Result<()> {
+        for pattern in patterns.iter().map(AsRef::as_ref) {
+            let selector = if let Some(shebang) = pattern.strip_prefix("#!") {
+                Selector::Shebang(shebang.to_string())
+            } else if let Some(extension) = pattern.strip_prefix('.') {
+                Selector::Extension(extension.to_string())
+            } else {
+                anyhow::bail!("{pattern} is neither an extension nor a shebang")
+            };
+            self.registry.push((selector, grammars.clone()));
+        }
+        Ok(())
+    }
+
+    /// Read a `beans.toml` manifest, along with the manifests of its workspace
+    /// members and of its dependencies, whose grammars come first.
+    fn from_toml(manifest: &Path) -> anyhow::Result<Self> {
+        let mut workspace = Self::default();
+        workspace.load_toml(manifest, &mut Vec::new(), &mut HashSet::new())?;
+        Ok(workspace)
+    }
+
+    /// Add the grammars of the `beans.toml` at `manifest`, after the ones of the
+    /// manifests it depends on. `loading` are the manifests being loaded, to detect
+    /// cycles, and `loaded` the ones already added.
+    fn load_toml(
+        &mut self,
+        manifest: &Path,
+        loading: &mut Vec<PathBuf>,
+        loaded: &mut HashSet<PathBuf>,
+    ) -> anyhow::Result<()> {
+        let manifest = if manifest.is_dir() {
+            manifest.join(TOML_MANIFEST)
+        } else {
+            manifest.to_path_buf()
+        };
+        let canonical = manifest
+            .canonicalize()
+            .context(format!("Could not read manifest {}", manifest.display()))?;
+        if loaded.contains(&canonical) {
+            return Ok(());
+        }
+        if loading.contains(&canonical) {
+            anyhow::bail!("The manifest {} depends on itself", manifest.display());
+        }
+        let content = std::fs::read_to_string(&manifest)
+            .context(format!("Could not read manifest {}", manifest.display()))?;
+        let project: TomlManifest = toml::from_str(&content)
+            .context(format!("Invalid manifest {}", manifest.display()))?;
+        let root = manifest.parent().unwrap_or(Path::new(""));
+        loading.push(canonical.clone());
+        let dependencies = project.dependencies.values().map(|dependency| &dependency.path);
+        for nested in project.workspace.members.iter().chain(dependencies) {
+            self.load_toml(&root.join(nested), loading, loaded)?;
+        }
+        loading.pop();
+        loaded.insert(canonical);
+        for lexer in project.lexer {
+            let path = root.join(lexer.path);
+            if !self.lexers.contains(&path) {
+                self.lexers.push(path.clone());
+            }
+            let grammars = Grammars {
+                lexer: path,
+                parser: None,
+            };
+            self.register(&lexer.sources, &grammars)?;
+        }
+        for parser in project.parser {
+            let path = root.join(parser.path);
+            let lexer = match parser.lexer {
+                Some(lexer) => root.join(lexer),
+                None => path.with_extension("lx"),
+            };
+            self.parsers.push((path.clone(), lexer.clone()));
+            let grammars = Grammars {
+                lexer,
+                parser: Some(path),
+            };
+            self.register(&parser.sources, &grammars)?;
+        }
+        Ok(())
+    }
+
+    /// Read a manifest, either a `beans.toml` one, maybe given by its directory, or a
+    /// list of grammars.
+    fn from_any_manifest(manifest: &Path) -> anyhow::Result<Self> {
+        if manifest.is_dir()
+            || manifest.extension().and_then(|extension| extension.to_str()) == Some("toml")
+        {
+            Self::from_toml(manifest)
+        } else {
+            Self::from_manifest(manifest)
+        }
+    }
+
+    /// The fingerprint of every grammar, as `beans.lock` lists them: a hash of its
+    /// content, along with the one of its lexer grammar for parser grammars. Paths
+    /// are relative to `root`.
+    fn fingerprints(&self, root: &Path) -> anyhow::Result<BTreeMap<String, String>> {
+        let read = |path: &Path| {
+            std::fs::read(path).context(format!("Could not read file {}", path.display()))
+        };
+        let relative = |path: &Path| {
+            path.strip_prefix(root)
+                .unwrap_or(path)
+                .display()
+                .to_string()
+        };
+        let mut fingerprints = BTreeMap::new();
+        for lexer in &self.lexers {
+            let fingerprint = fingerprint(&[&read(lexer)?]);
+            fingerprints.insert(relative(lexer), format!("{fingerprint:016x}"));
+        }
+        for (parser, lexer) in &self.parsers {
+            let fingerprint = fingerprint(&[&read(parser)?, &read(lexer)?]);
+            fingerprints.insert(relative(parser), format!("{fingerprint:016x}"));
+        }
+        Ok(fingerprints)
+    }
+
+    /// The grammars `source` is handled with, according to the registry. Shebangs
+    /// take precedence over extensions.
+    fn grammars_of(&self, source: &Path) -> anyhow::Result<&Grammars> {
+        let mut first_line = String::new();
+        File::open(source)
+            .map(BufReader::new)
+            .and_then(|mut reader| reader.read_line(&mut first_line))
+            .context(format!("Could not read file {}", source.display()))?;
+        let shebang = first_line.strip_prefix("#!");
+        let extension = source.extension().and_then(|extension| extension.to_str());
+        let found = self
+            .registry
+            .iter()
+            .find(|(selector, _)| {
+                matches!((selector, shebang), (Selector::Shebang(pattern), Some(shebang))
+                    if shebang.contains(pattern.as_str()))
+            })
+            .or_else(|| {
+                self.registry.iter().find(|(selector, _)| {
+                    matches!(selector, Selector::Extension(pattern)
+                        if Some(pattern.as_str()) == extension)
+                })
+            });
+        match found {
+            Some((_, grammars)) => Ok(grammars),
+            None => anyhow::bail!("No grammar of the manifest handles {}", source.display()),
+        }
+    }
+}
+
+/// Group `sources` by the grammars they are handled with: the ones given on the
+/// command line, or the ones the registry of `manifest` picks.
+fn group_sources(
+    lexer: Option<PathBuf>,
+    parser: Option<PathBuf>,
+    manifest: Option<PathBuf>,
+    sources: Vec<PathBuf>,
+) -> anyhow::Result<Vec<(Grammars, Vec<PathBuf>)>> {
+    let Some(manifest) = manifest else {
+        let lexer = lexer.context("Missing a lexer grammar, try `--lexer` or `--manifest`")?;
+        return Ok(vec![(Grammars { lexer, parser }, sources)]);
+    };
+    let workspace = Workspace::from_any_manifest(&manifest)?;
+    let mut groups: Vec<(Grammars, Vec<PathBuf>)> = Vec::new();
+    for source in sources {
+        let grammars = workspace.grammars_of(&source)?;
+        match groups.iter_mut().find(|(other, _)| other == grammars) {
+            Some((_, sources)) => sources.push(source),
+            None => groups.push((grammars.clone(), vec![source])),
+        }
+    }
+    Ok(groups)
+}
+
+/// Run `job` on every item of `items`, with at most `jobs` threads. The results are
+/// in the same order as the items.
+fn run_parallel<T: Sync, R: Send>(
+    items: &[T],
+    jobs: usize,
+    job: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..items.len()).map(|_| None).collect::<Vec<_>>());
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, items.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(i) else {
+                    break;
+                };
+                let result = job(item);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(Option::unwrap)
+        .collect()
+}
+
+/// Compile a lexer grammar, next to its source.
+fn compile_lexer_grammar(lexer_path: &Path) -> anyhow::Result<()> {
+    let lexer_grammar = LexerGrammar::build_from_path(lexer_path)?;
+    let output = lexer_path.with_extension("clx");
+    std::fs::write(&output, serialize_compiled(&lexer_grammar)?)
+        .context(format!("Could not write to file {}", output.display()))?;
+    Ok(())
+}
+
+/// Compile a parser grammar against its compiled lexer grammar if there is one, and
+/// against `lexer_path` otherwise.
+fn compile_parser_grammar(parser_path: &Path, lexer_path: &Path) -> anyhow::Result<()> {
+    let compiled_lexer = lexer_path.with_extension("clx");
+    let lexer = if compiled_lexer.exists() {
+        Lexer::build_from_path(&compiled_lexer)?
+    } else {
+        Lexer::build_from_path(lexer_path)?
+    };
+    let parser_grammar = EarleyGrammar::build_from_path(parser_path, lexer.grammar())?;
+    let output = parser_path.with_extension("cgr");
+    std::fs::write(&output, serialize_compiled(&parser_grammar)?)
+        .context(format!("Could not write to file {}", output.display()))?;
+    Ok(())
+}
+
+/// The fingerprints of the grammars of a `beans.toml` project, as `beans.lock`
+/// stores them.
+#[derive(Serialize, Deserialize, PartialEq, Eq)]
+struct Lock {
+    grammars: BTreeMap<String, String>,
 }
 
-fn compile(compile_action: CompileAction) -> anyhow::Result<()> {
+fn compile_all(
+    path: &Path,
+    jobs: Option<usize>,
+    locked: bool,
+    reporter: &mut (dyn ProgressReporter + Send),
+) -> anyhow::Result<()> {
+    let toml_manifest = if path.is_dir() {
+        Some(path.join(TOML_MANIFEST)).filter(|manifest| manifest.exists())
+    } else {
+        Some(path.to_path_buf()).filter(|manifest| {
+            manifest.extension().and_then(|extension| extension.to_str()) == Some("toml")
+        })
+    };
+    let workspace = match &toml_manifest {
+        Some(manifest) => Workspace::from_toml(manifest)?,
+        None if path.is_dir() => Workspace::from_directory(path)?,
+        None => Workspace::from_manifest(path)?,
+    };
+    // Projects with a `beans.toml` get their grammars locked.
+    let lock = match &toml_manifest {
+        Some(manifest) => {
+            let root = manifest.parent().unwrap_or(Path::new(""));
+            let lock = Lock {
+                grammars: workspace.fingerprints(root)?,
+            };
+            Some((root.join(LOCK), lock))
+        }
+        None => None,
+    };
+    if locked {
+        let Some((lock_path, lock)) = &lock else {
+            anyhow::bail!("Only the grammars of a `{TOML_MANIFEST}` project can be locked");
+        };
+        let content = std::fs::read_to_string(lock_path)
+            .context(format!("Could not read lock {}", lock_path.display()))?;
+        let previous: Lock = toml::from_str(&content)
+            .context(format!("Invalid lock {}", lock_path.display()))?;
+        if previous != *lock {
+            anyhow::bail!(
+                "The grammars changed since {} was written, compile them without `--locked`",
+                lock_path.display()
+            );
+        }
+    }
+    let jobs = jobs.unwrap_or_else(|| thread::available_parallelism().map_or(1, usize::from));
+    let start = Instant::now();
+    let reporter = Mutex::new(reporter);
+    let done = AtomicUsize::new(0);
+    // Report that one more grammar of `phase` is compiled, out of `total`.
+    let step = |phase, total| {
+        let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+        reporter
+            .lock()
+            .unwrap()
+            .report(Progress::new(phase, done, total));
+    };
+    let total = workspace.lexers.len();
+    reporter
+        .lock()
+        .unwrap()
+        .report(Progress::new(Phase::LexerGrammar, 0, total));
+    // Errors hold spans that can only be used on the thread that made them, so they
+    // are rendered there.
+    let lexer_results = run_parallel(&workspace.lexers, jobs, |lexer_path| {
+        let result =
+            compile_lexer_grammar(lexer_path).map_err(|error| anyhow::anyhow!("{error:#}"));
+        step(Phase::LexerGrammar, total);
+        result
+    });
+    done.store(0, Ordering::Relaxed);
+    // The compiled lexer grammars that failed, which would be stale if there are any.
+    let failed_lexers: HashSet<PathBuf> = workspace
+        .lexers
+        .iter()
+        .zip(&lexer_results)
+        .filter(|(_, result)| result.is_err())
+        .map(|(path, _)| path.with_extension("clx"))
+        .collect();
+    let total = workspace.parsers.len();
+    reporter
+        .lock()
+        .unwrap()
+        .report(Progress::new(Phase::ParserGrammar, 0, total));
+    // Parser grammars are compiled once every lexer grammar is, against the compiled
+    // lexer grammar if there is one. Those whose lexer grammar failed are skipped.
+    let parser_results = run_parallel(&workspace.parsers, jobs, |(parser_path, lexer_path)| {
+        let result = (!failed_lexers.contains(&lexer_path.with_extension("clx"))).then(|| {
+            compile_parser_grammar(parser_path, lexer_path)
+                .map_err(|error| anyhow::anyhow!("{error:#}"))
+        });
+        step(Phase::ParserGrammar, total);
+        result
+    });
+    let mut failures = 0;
+    let mut skipped = 0;
+    let results = workspace
+        .lexers
+        .iter()
+        .zip(lexer_results.into_iter().map(Some))
+        .chain(workspace.parsers.iter().map(|(path, _)| path).zip(parser_results));
+    for (path, result) in results {
+        match result {
+            Some(Ok(())) => eprintln!("compiled {}", path.display()),
+            Some(Err(error)) => {
+                failures += 1;
+                eprintln!("failed   {}\n{error:#}", path.display());
+            }
+            None => {
+                skipped += 1;
+                eprintln!("skipped  {}, as its lexer grammar failed", path.display());
+            }
+        }
+    }
+    let total = workspace.lexers.len() + workspace.parsers.len();
+    eprintln!(
+        "{} grammars compiled, {failures} failed, {skipped} skipped, in {:.2}s",
+        total - failures - skipped,
+        start.elapsed().as_secs_f64()
+    );
+    if failures > 0 {
+        anyhow::bail!("{failures} grammars could not be compiled");
+    }
+    if let Some((lock_path, lock)) = lock {
+        std::fs::write(&lock_path, toml::to_string(&lock)?)
+            .context(format!("Could not write to file {}", lock_path.display()))?;
+    }
+    Ok(())
+}
+
+/// The warnings about the pairs of terminals of `lexer_grammar` that match a same
+/// text, with the same priority.
+fn tie_warnings(lexer_grammar: &LexerGrammar) -> Vec<String> {
+    let ties = lexer_grammar.ties().unwrap_or_default();
+    ties.into_iter()
+        .map(|(first, second)| {
+            format!(
+                "the terminals {} {} and {} {} match a same text, which the longest match \
+                 lexes as {}, declared first",
+                lexer_grammar.name(first),
+                lexer_grammar.span_of(first),
+                lexer_grammar.name(second),
+                lexer_grammar.span_of(second),
+                lexer_grammar.name(first),
+            )
+        })
+        .collect()
+}
+
+fn compile(
+    compile_action: CompileAction,
+    reporter: &mut dyn ProgressReporter,
+) -> anyhow::Result<()> {
     match compile_action {
         CompileAction::Lexer {
             lexer_grammar: mut lexer_grammar_path,
             output_path,
+            linear,
+            ..
         } => {
+            reporter.report(Progress::new(Phase::LexerGrammar, 0, 1));
             let lexer_grammar = LexerGrammar::build_from_path(lexer_grammar_path.as_path())?;
-            let res = serialize(&lexer_grammar)?;
+            reporter.report(Progress::new(Phase::LexerGrammar, 1, 1));
+            for warning in tie_warnings(&lexer_grammar) {
+                eprintln!("warning: {warning}");
+            }
+            if linear && !lexer_grammar.pattern().has_dfa() {
+                anyhow::bail!(
+                    "The terminals of {} are too many, or too large, for a DFA, so lexing \
+                     may take more than linear time",
+                    lexer_grammar_path.display()
+                );
+            }
+            let res = serialize_compiled(&lexer_grammar)?;
             let output = match output_path {
                 Some(output) => output,
                 None => {
@@ -95,12 +1293,38 @@ fn compile(compile_action: CompileAction) -> anyhow::Result<()> {
             parser_grammar: mut parser_grammar_path,
             output_path,
             lexer_path,
+            lalr,
+            recognizer,
+            check_conflicts,
+            ..
         } => {
             let lexer = Lexer::build_from_path(&lexer_path)?;
-            let parser_grammar = EarleyGrammar::build_from_path(
+            reporter.report(Progress::new(Phase::ParserGrammar, 0, 1));
+            let mut parser_grammar = EarleyGrammar::build_from_path(
                 parser_grammar_path.as_path(),
                 lexer.grammar(),
             )?;
+            reporter.report(Progress::new(Phase::ParserGrammar, 1, 1));
+            for lint in lint(&parser_grammar, lexer.grammar()) {
+                if matches!(lint, Lint::UnusedTerminal { .. } | Lint::Cycle { .. }) {
+                    eprintln!("warning: {lint}");
+                }
+            }
+            if check_conflicts {
+                for hint in ambiguity_hints(&parser_grammar, lexer.grammar()) {
+                    eprintln!("warning: {hint}");
+                }
+            }
+            if recognizer {
+                parser_grammar.strip_attributes();
+            }
+            let blob = if lalr {
+                let grammar =
+                    LalrGrammar::with_progress(parser_grammar, lexer.grammar(), reporter)?;
+                serialize_compiled(&grammar)?
+            } else {
+                serialize_compiled(&parser_grammar)?
+            };
             let output = match output_path {
                 Some(output) => output,
                 None => {
@@ -111,77 +1335,1115 @@ fn compile(compile_action: CompileAction) -> anyhow::Result<()> {
                 }
             };
             let mut output_fd = File::create(output)?;
-            output_fd.write_all(&serialize(&parser_grammar)?)?;
+            output_fd.write_all(&blob)?;
+        }
+        CompileAction::Ast {
+            parser_grammar: parser_grammar_path,
+            output_path,
+            lexer_path,
+        } => {
+            let lexer = Lexer::build_from_path(&lexer_path)?;
+            let parser_grammar =
+                EarleyGrammar::build_from_path(&parser_grammar_path, lexer.grammar())?;
+            let mut output = open_output(output_path)?;
+            write!(output, "{}", generate_types(&parser_grammar))?;
+            output.flush()?;
         }
     }
     Ok(())
 }
 
+/// The ambiguities of the parser grammar at `parser_path`: its LALR(1) conflicts, and
+/// the ambiguities found in `samples`.
+fn find_questions(
+    lexer: &Lexer,
+    parser_path: &Path,
+    samples: &[PathBuf],
+) -> anyhow::Result<Vec<Question>> {
+    let mut questions = Vec::new();
+    let grammar = EarleyGrammar::build_from_path(parser_path, lexer.grammar())?;
+    if let Err(error) = LalrGrammar::new(grammar, lexer.grammar()) {
+        if let ErrorKind::LalrConflicts { conflicts } = &*error.kind {
+            let grammar = EarleyGrammar::build_from_path(parser_path, lexer.grammar())?;
+            questions.extend(conflict_questions(&grammar, conflicts));
+        }
+    }
+    let parser = EarleyParser::new(EarleyGrammar::build_from_path(
+        parser_path,
+        lexer.grammar(),
+    )?);
+    for sample in samples {
+        let mut stream = StringStream::from_file(sample.as_path())?;
+        let text = stream.text();
+        let mut input = lexer.lex(&mut stream);
+        let (table, raw_input) = parser.recognise(&mut input)?;
+        let forest = parser.to_forest(&table, &raw_input)?;
+        let ambiguities = parser.ambiguities(&forest, &raw_input, input.last_span());
+        questions.extend(ambiguity_questions(parser.grammar(), &ambiguities, &text));
+    }
+    Ok(questions)
+}
+
+/// The text of the file `span` points to, and the range of `span` within it.
+fn source_of(span: &Span) -> anyhow::Result<(String, Range<usize>)> {
+    let file = span.file();
+    let text = std::fs::read_to_string(&*file)
+        .context(format!("Could not read {}", file.display()))?;
+    let range = range_in(&text, span);
+    Ok((text, range))
+}
+
+/// The files `beans init` creates, with `{name}` standing for the name of the
+/// language.
+const INIT_FILES: [(&str, &str); 5] = [
+    (
+        "{name}.lx",
+        r"ignore SPACE ::= [ \t\n]+
+(an integer)
+INT ::= ([0-9]+)
+(`+`)
+PLUS ::= \+
+(`-`)
+MINUS ::= -
+(`*`)
+TIMES ::= \*
+(`/`)
+DIVIDE ::= /
+(`(`)
+LPAR ::= \(
+(`\)`)
+RPAR ::= \)
+",
+    ),
+    (
+        "{name}.gr",
+        r#""an expression"
+@Expr ::=
+  Expr@left PLUS Term@right <Add>
+  Expr@left MINUS Term@right <Sub>
+  Term@value <Term>;
+
+"a term"
+Term ::=
+  Term@left TIMES Factor@right <Mul>
+  Term@left DIVIDE Factor@right <Div>
+  Factor@value <Factor>;
+
+"a factor"
+Factor ::=
+  INT.0@value <Int>
+  LPAR Expr@value RPAR <Parenthesized>;
+"#,
+    ),
+    (
+        "beans.toml",
+        r#"[[lexer]]
+path = "{name}.lx"
+
+[[parser]]
+path = "{name}.gr"
+for = [".{name}"]
+"#,
+    ),
+    ("input.{name}", "1 + 2 * (3 - 4)\n"),
+    (
+        "main.rs",
+        r#"//! Compile the grammars with `beans compile --all .` first.
+
+use beans::prelude::*;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let (lexer, parser) = build_system!(
+        lexer => "{name}.clx",
+        parser => "{name}.cgr",
+    )?;
+    let mut input = StringStream::from_file(std::path::Path::new("input.{name}"))?;
+    let ast = parser.parse(&mut lexer.lex(&mut input))?.tree;
+    print_ast(&ast)?;
+    Ok(())
+}
+"#,
+    ),
+];
+
+/// The file of a benchmark suite that lists its cases.
+const SUITE: &str = "suite.toml";
+
+/// The cases of a benchmark suite.
+#[derive(Deserialize)]
+struct Suite {
+    #[serde(rename = "case")]
+    cases: Vec<BenchCase>,
+}
+
+/// A case of a benchmark suite, whose input is `repeat` written `count` times, and
+/// then `suffix`.
+#[derive(Deserialize)]
+struct BenchCase {
+    name: String,
+    lexer: PathBuf,
+    parser: PathBuf,
+    repeat: String,
+    #[serde(default)]
+    suffix: String,
+    count: usize,
+}
+
+/// The median of `durations`, in milliseconds.
+fn median(durations: &mut [Duration]) -> String {
+    durations.sort_unstable();
+    let median = durations[durations.len() / 2];
+    format!("{:.2} ms", median.as_secs_f64() * 1000.0)
+}
+
+/// The size of the stack of the thread benchmarks run on. The trees of the inputs
+/// are built recursively, and the large inputs of the suite, or those of `--scale`,
+/// are too deep for the stack of the main thread in debug builds.
+const BENCH_STACK_SIZE: usize = 1 << 30;
+
+/// Run the cases of the suite in `directory` whose name contains `filter`, `runs`
+/// times each, on inputs `scale` times as large, and print how long building their
+/// grammars, lexing and parsing took, in the median run.
+fn bench(
+    directory: &Path,
+    runs: usize,
+    scale: usize,
+    filter: Option<&str>,
+) -> anyhow::Result<()> {
+    thread::scope(|scope| {
+        // Errors hold spans that can only be used on the thread that made them, so
+        // they are rendered there.
+        let run = || {
+            run_bench(directory, runs, scale, filter)
+                .map_err(|error| anyhow::anyhow!("{error:#}"))
+        };
+        thread::Builder::new()
+            .stack_size(BENCH_STACK_SIZE)
+            .spawn_scoped(scope, run)?
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+fn run_bench(
+    directory: &Path,
+    runs: usize,
+    scale: usize,
+    filter: Option<&str>,
+) -> anyhow::Result<()> {
+    let path = directory.join(SUITE);
+    let content = std::fs::read_to_string(&path)
+        .context(format!("Could not read suite {}", path.display()))?;
+    let suite: Suite =
+        toml::from_str(&content).context(format!("Invalid suite {}", path.display()))?;
+    println!(
+        "{:<24} {:>8} {:>12} {:>12} {:>12}",
+        "case", "tokens", "grammars", "lex", "parse"
+    );
+    for case in suite.cases {
+        if let Some(filter) = filter {
+            if !case.name.contains(filter) {
+                continue;
+            }
+        }
+        let input = case.repeat.repeat(case.count * scale) + &case.suffix;
+        let origin = Path::new("<input>");
+        let (mut building, mut lexing, mut parsing) = (Vec::new(), Vec::new(), Vec::new());
+        let mut tokens = 0;
+        for _ in 0..runs.max(1) {
+            let start = Instant::now();
+            let lexer = Lexer::build_from_path(&directory.join(&case.lexer))?;
+            let parser_grammar =
+                EarleyGrammar::build_from_path(&directory.join(&case.parser), lexer.grammar())?;
+            let parser = EarleyParser::new(parser_grammar);
+            building.push(start.elapsed());
+
+            let start = Instant::now();
+            let mut stream = StringStream::new(origin, input.as_str());
+            let mut lexed_stream = lexer.lex(&mut stream);
+            tokens = 0;
+            while lexed_stream.next(Allowed::All)?.is_some() {
+                tokens += 1;
+            }
+            lexing.push(start.elapsed());
+
+            let start = Instant::now();
+            let mut stream = StringStream::new(origin, input.as_str());
+            parser
+                .parse(&mut lexer.lex(&mut stream))
+                .context(format!("Case {} does not parse", case.name))?;
+            parsing.push(start.elapsed());
+        }
+        println!(
+            "{:<24} {tokens:>8} {:>12} {:>12} {:>12}",
+            case.name,
+            median(&mut building),
+            median(&mut lexing),
+            median(&mut parsing),
+        );
+    }
+    Ok(())
+}
+
+/// The manifest of the tests of a grammar.
+const TESTS: &str = "tests.toml";
+
+/// The tests of a grammar, whose inputs are parsed with the grammars `lexer` and
+/// `parser`.
+#[derive(Deserialize)]
+struct TestSuite {
+    lexer: PathBuf,
+    parser: PathBuf,
+    #[serde(rename = "test")]
+    tests: Vec<GrammarTest>,
+}
+
+/// A test of a grammar: the file `input` must parse to the AST of the file `ast`,
+/// written in JSON if its extension is `json`, and as an S-expression otherwise, or
+/// fail with an error whose message contains `error`. Without either, it must parse.
+#[derive(Deserialize)]
+struct GrammarTest {
+    input: PathBuf,
+    ast: Option<PathBuf>,
+    error: Option<String>,
+}
+
+/// Run the tests of the manifest `manifest`, or of the manifest of the directory
+/// `manifest`, whose input contains `filter`. With `bless`, the expected ASTs are
+/// written rather than compared.
+fn run_tests(manifest: &Path, filter: Option<&str>, bless: bool) -> anyhow::Result<()> {
+    let manifest = if manifest.is_dir() {
+        manifest.join(TESTS)
+    } else {
+        manifest.to_path_buf()
+    };
+    let content = std::fs::read_to_string(&manifest)
+        .context(format!("Could not read manifest {}", manifest.display()))?;
+    let suite: TestSuite =
+        toml::from_str(&content).context(format!("Invalid manifest {}", manifest.display()))?;
+    let directory = manifest.parent().unwrap_or(Path::new("."));
+    let lexer = Lexer::build_from_path(&directory.join(&suite.lexer))?;
+    let parser = EarleyParser::new(EarleyGrammar::build_from_path(
+        &directory.join(&suite.parser),
+        lexer.grammar(),
+    )?);
+    let mut failures = Vec::new();
+    let mut passed = 0;
+    for test in suite.tests {
+        let name = test.input.display().to_string();
+        if filter.is_some_and(|filter| !name.contains(filter)) {
+            continue;
+        }
+        let mut stream = StringStream::from_file(directory.join(&test.input))?;
+        let result = parser.parse(&mut lexer.lex(&mut stream));
+        let failure = match (result, test.ast, test.error) {
+            (_, Some(_), Some(_)) => {
+                anyhow::bail!("The test {name} expects both an AST and an error")
+            }
+            (Ok(_), None, Some(error)) => Some(format!(
+                "expected an error containing {error:?}, but the input parsed"
+            )),
+            (Err(found), _, Some(error)) => {
+                let found = found.to_string();
+                (!found.contains(&error))
+                    .then(|| format!("expected an error containing {error:?}, found: {found}"))
+            }
+            (Err(found), _, None) => Some(found.to_string()),
+            (Ok(_), None, None) => None,
+            (Ok(result), Some(expected), None) => {
+                let path = directory.join(expected);
+                let json = path.extension().is_some_and(|extension| extension == "json");
+                if bless {
+                    // As `beans parse` prints them.
+                    let text = match (json, parser.grammar().language_version()) {
+                        (true, Some(version)) => serde_json::to_string(&VersionedAst::new(
+                            Some(version),
+                            result.tree,
+                        ))?,
+                        (true, None) => serde_json::to_string(&result.tree)?,
+                        (false, _) => to_sexpr(&result.tree),
+                    };
+                    std::fs::write(&path, text + "\n")
+                        .context(format!("Could not write to file {}", path.display()))?;
+                    None
+                } else {
+                    let text = std::fs::read_to_string(&path)
+                        .context(format!("Could not read file {}", path.display()))?;
+                    let expected = if json {
+                        let versioned = serde_json::from_str::<VersionedAst>(&text);
+                        let ast = match versioned {
+                            Ok(versioned) => versioned.tree,
+                            Err(_) => serde_json::from_str::<AST>(&text)
+                                .context(format!("Invalid AST {}", path.display()))?,
+                        };
+                        to_sexpr(&ast)
+                    } else {
+                        text.trim().to_string()
+                    };
+                    ast_difference(&expected, &to_sexpr(&result.tree))
+                }
+            }
+        };
+        match failure {
+            Some(failure) => {
+                println!("test {name} ... FAILED");
+                failures.push((name, failure));
+            }
+            None => {
+                println!("test {name} ... ok");
+                passed += 1;
+            }
+        }
+    }
+    for (name, failure) in &failures {
+        println!("\n---- {name} ----\n{failure}");
+    }
+    println!("\n{passed} passed; {} failed", failures.len());
+    if !failures.is_empty() {
+        anyhow::bail!("{} tests failed", failures.len());
+    }
+    Ok(())
+}
+
+/// Describe where the AST `found` differs from `expected`, both written as
+/// S-expressions, if it does.
+fn ast_difference(expected: &str, found: &str) -> Option<String> {
+    if expected == found {
+        return None;
+    }
+    let common = expected
+        .chars()
+        .zip(found.chars())
+        .take_while(|(expected, found)| expected == found)
+        .count();
+    let start = common.saturating_sub(24);
+    let excerpt = |ast: &str| ast.chars().skip(start).take(72).collect::<String>();
+    Some(format!(
+        "the ASTs differ at char {common}:\n  expected: {}\n     found: {}",
+        excerpt(expected),
+        excerpt(found)
+    ))
+}
+
+/// Create a starter project in `directory`, for the language `name`.
+fn init(directory: &Path, name: Option<String>, force: bool) -> anyhow::Result<()> {
+    std::fs::create_dir_all(directory)
+        .context(format!("Could not create directory {}", directory.display()))?;
+    let name = match name {
+        Some(name) => name,
+        None => directory
+            .canonicalize()
+            .ok()
+            .and_then(|directory| Some(directory.file_name()?.to_str()?.to_string()))
+            .context("Could not guess the name of the language, try `--name`")?,
+    };
+    if name.is_empty() || name.contains(['/', '\\', '.']) {
+        anyhow::bail!("{name:?} is not a valid name for a language");
+    }
+    let files = INIT_FILES.map(|(path, content)| {
+        (
+            directory.join(path.replace("{name}", &name)),
+            content.replace("{name}", &name),
+        )
+    });
+    if !force {
+        if let Some((path, _)) = files.iter().find(|(path, _)| path.exists()) {
+            anyhow::bail!("{} already exists, try `--force`", path.display());
+        }
+    }
+    for (path, content) in files {
+        std::fs::write(&path, content)
+            .context(format!("Could not write {}", path.display()))?;
+        println!("Created {}", path.display());
+    }
+    Ok(())
+}
+
+/// Ask how to settle every ambiguity of the parser grammar at `parser_path`, and write
+/// the answers back into the files its rules are defined in. Every pair of rules is
+/// only asked about once.
+fn resolve(lexer_path: &Path, parser_path: &Path, samples: &[PathBuf]) -> anyhow::Result<()> {
+    let lexer = Lexer::build_from_path(lexer_path)?;
+    let mut answered = HashSet::new();
+    loop {
+        let mut next = None;
+        for question in find_questions(&lexer, parser_path, samples)? {
+            let (text, range) = source_of(&question.rule)?;
+            let rule = strip_associativity(&text[range]).to_string();
+            let (text, range) = source_of(&question.other)?;
+            let other = strip_associativity(&text[range]).to_string();
+            let key = if rule <= other {
+                (rule.clone(), other.clone())
+            } else {
+                (other.clone(), rule.clone())
+            };
+            if !answered.contains(&key) {
+                next = Some((question, key, rule, other));
+                break;
+            }
+        }
+        let Some((question, key, rule, other)) = next else {
+            println!("No ambiguity left to settle");
+            return Ok(());
+        };
+        println!("{}\n  {}", question.title, question.example);
+        let orderable = question.siblings && question.rule.file() == question.other.file();
+        let options = if question.same_rule() {
+            println!("  derives it in two ways with `{rule}`");
+            "[l]eft-assoc, [r]ight-assoc, [s]kip, [q]uit"
+        } else if orderable {
+            println!("  1. `{rule}`\n  2. `{other}`");
+            "[1] or [2] binds tighter, [s]kip, [q]uit"
+        } else {
+            println!("  with `{rule}` and `{other}`, which cannot be ordered");
+            answered.insert(key);
+            continue;
+        };
+        loop {
+            print!("{options}? ");
+            stdout().flush()?;
+            let mut answer = String::new();
+            if stdin().read_line(&mut answer)? == 0 {
+                return Ok(());
+            }
+            let (tighter, looser) = match (answer.trim(), question.same_rule()) {
+                ("q", _) => return Ok(()),
+                ("s", _) => break,
+                (answer @ ("l" | "r"), true) => {
+                    let (text, range) = source_of(&question.rule)?;
+                    let text = set_associativity(&text, range, answer == "l");
+                    std::fs::write(&*question.rule.file(), text)?;
+                    break;
+                }
+                ("1", false) => (&question.rule, &question.other),
+                ("2", false) => (&question.other, &question.rule),
+                _ => continue,
+            };
+            let preference = EarleyGrammar::build_from_path(parser_path, lexer.grammar())?
+                .rule_preference();
+            let (text, tighter) = source_of(tighter)?;
+            let looser = range_in(&text, looser);
+            match prioritize(&text, tighter, looser, preference) {
+                Some(text) => std::fs::write(&*question.rule.file(), text)?,
+                None => println!("It already does"),
+            }
+            break;
+        }
+        answered.insert(key);
+    }
+}
+
+impl Action {
+    /// The files to watch with `--watch`, if the action was given it: the grammars,
+    /// and the sources or the manifest.
+    fn watched(&self) -> anyhow::Result<Option<Vec<PathBuf>>> {
+        let watched = match self {
+            Action::Parse {
+                watch: true,
+                lexer_grammar,
+                parser_grammar,
+                manifest,
+                sources,
+                ..
+            } => {
+                if sources.iter().any(|source| source == Path::new("-")) {
+                    anyhow::bail!("The standard input cannot be watched");
+                }
+                let groups = group_sources(
+                    lexer_grammar.clone(),
+                    parser_grammar.clone(),
+                    manifest.clone(),
+                    sources.clone(),
+                )?;
+                let mut watched: Vec<_> = manifest.iter().chain(sources).cloned().collect();
+                for (grammars, _) in groups {
+                    watched.push(grammars.lexer);
+                    watched.extend(grammars.parser);
+                }
+                watched
+            }
+            Action::Compile(CompileArgs {
+                action:
+                    Some(CompileAction::Lexer {
+                        watch: true,
+                        lexer_grammar,
+                        ..
+                    }),
+                ..
+            }) => vec![lexer_grammar.clone()],
+            Action::Compile(CompileArgs {
+                action:
+                    Some(CompileAction::Parser {
+                        watch: true,
+                        parser_grammar,
+                        lexer_path,
+                        ..
+                    }),
+                ..
+            }) => vec![parser_grammar.clone(), lexer_path.clone()],
+            _ => return Ok(None),
+        };
+        Ok(Some(watched))
+    }
+}
+
+/// How often `--watch` looks for changes of the watched files.
+const WATCH_PERIOD: Duration = Duration::from_millis(250);
+
+/// Run `run`, and run it again whenever one of `paths` changes, until interrupted.
+/// Errors are printed, rather than returned, to be fixed before the next run.
+fn watch(paths: &[PathBuf], mut run: impl FnMut() -> anyhow::Result<()>) -> anyhow::Result<()> {
+    let modified = || {
+        paths
+            .iter()
+            .map(|path| path.metadata().and_then(|metadata| metadata.modified()).ok())
+            .collect::<Vec<_>>()
+    };
+    loop {
+        let seen = modified();
+        if let Err(error) = run() {
+            eprintln!("Error: {error:?}");
+        }
+        eprintln!("Watching {} files for changes, press Ctrl-C to stop", paths.len());
+        while modified() == seen {
+            std::thread::sleep(WATCH_PERIOD);
+        }
+        eprintln!();
+    }
+}
+
 fn main() -> anyhow::Result<()> {
-    let Cli { action } = Cli::parse();
+    let Cli { action, progress } = Cli::parse();
+    let mut reporter: Box<dyn ProgressReporter + Send> = if progress {
+        Box::new(ProgressBar::new(stderr()))
+    } else {
+        Box::new(|_: Progress| {})
+    };
+    match action.watched()? {
+        Some(paths) => watch(&paths, || run(action.clone(), &mut *reporter)),
+        None => run(action, &mut *reporter),
+    }
+}
+
+fn run(action: Action, reporter: &mut (dyn ProgressReporter + Send)) -> anyhow::Result<()> {
     match action {
-        Action::Compile(compile_action) => compile(compile_action)?,
-        Action::Lex {
+        Action::Bench {
+            suite,
+            runs,
+            scale,
+            filter,
+        } => bench(&suite, runs, scale, filter.as_deref())?,
+        Action::Check {
             lexer_grammar: lexer_grammar_path,
-            source,
+            parser_grammar: parser_grammar_path,
+            check_conflicts,
+            deny_warnings,
         } => {
             let lexer = Lexer::build_from_path(&lexer_grammar_path)?;
-            let mut stream = StringStream::from_file(source)?;
-            let mut lexed_stream = lexer.lex(&mut stream);
+            let mut warnings = tie_warnings(lexer.grammar());
+            if let Some(parser_grammar_path) = parser_grammar_path {
+                let parser_grammar =
+                    EarleyGrammar::build_from_path(&parser_grammar_path, lexer.grammar())?;
+                let lints = lint(&parser_grammar, lexer.grammar());
+                warnings.extend(lints.iter().map(ToString::to_string));
+                if check_conflicts {
+                    let hints = ambiguity_hints(&parser_grammar, lexer.grammar());
+                    warnings.extend(hints.iter().map(ToString::to_string));
+                }
+            }
+            for warning in warnings.iter() {
+                eprintln!("warning: {warning}");
+            }
+            if deny_warnings && !warnings.is_empty() {
+                anyhow::bail!("{} warnings were found", warnings.len());
+            }
+        }
+        Action::Codegen {
+            lexer_grammar: lexer_grammar_path,
+            parser_grammar: parser_grammar_path,
+            output_path,
+        } => {
+            let lexer = Lexer::build_from_path(&lexer_grammar_path)?;
+            let parser_grammar =
+                EarleyGrammar::build_from_path(&parser_grammar_path, lexer.grammar())?;
+            let mut output = open_output(output_path)?;
+            write!(output, "{}", generate_visitor(&parser_grammar))?;
+            output.flush()?;
+        }
+        Action::Compile(CompileArgs {
+            all: Some(path),
+            jobs,
+            locked,
+            ..
+        }) => compile_all(&path, jobs, locked, reporter)?,
+        Action::Compile(CompileArgs {
+            action: Some(compile_action),
+            ..
+        }) => compile(compile_action, reporter)?,
+        Action::Compile(_) => {
+            anyhow::bail!("Nothing to compile, try `beans compile --help`")
+        }
+        Action::Coverage {
+            lexer_grammar: lexer_grammar_path,
+            parser_grammar: parser_grammar_path,
+            sources,
+        } => {
+            let lexer = Lexer::build_from_path(&lexer_grammar_path)?;
+            let parser = EarleyParser::new(EarleyGrammar::build_from_path(
+                &parser_grammar_path,
+                lexer.grammar(),
+            )?);
+            let mut coverage = Coverage::new(parser.grammar());
+            let mut rejected = 0;
+            let total = sources.len();
+            reporter.report(Progress::new(Phase::Corpus, 0, total));
+            for (done, source) in sources.into_iter().enumerate() {
+                let mut stream = open_source(&source)?;
+                let recorded = parser.recognise(&mut lexer.lex(&mut stream)).and_then(
+                    |(table, raw_input)| {
+                        let forest = parser.to_forest(&table, &raw_input)?;
+                        coverage.record(&parser, &forest, &raw_input)
+                    },
+                );
+                if let Err(error) = recorded {
+                    eprintln!("{error}");
+                    rejected += 1;
+                }
+                reporter.report(Progress::new(Phase::Corpus, done + 1, total));
+            }
+            let grammar = parser.grammar();
+            for rule in coverage.uncovered() {
+                let rule = &grammar.rules()[rule];
+                let elements = rule
+                    .elements
+                    .iter()
+                    .map(|element| element.name(lexer.grammar(), grammar))
+                    .collect::<Vec<_>>();
+                println!(
+                    "uncovered: {} -> {}, {}",
+                    grammar.name_of(rule.id),
+                    elements.join(" "),
+                    rule.span
+                );
+            }
+            println!(
+                "{} of {} rules covered ({:.1}%)",
+                coverage.covered(),
+                coverage.total(),
+                100. * coverage.covered() as f64 / coverage.total().max(1) as f64
+            );
+            if rejected > 0 {
+                anyhow::bail!("{rejected} files were rejected");
+            }
+        }
+        Action::Decompile {
+            blob,
+            lexer_grammar: lexer_grammar_path,
+        } => {
+            let decompiled = match blob.extension().and_then(|extension| extension.to_str()) {
+                Some("clx") => LexerGrammar::build_from_path(&blob)?.decompile(),
+                Some("cgr") => {
+                    let bytes = std::fs::read(&blob)
+                        .context(format!("Could not read file {}", blob.display()))?;
+                    let grammar = EarleyGrammar::build_from_compiled(&bytes, blob.clone())?;
+                    let lexer_grammar = lexer_grammar_path
+                        .map(|path| LexerGrammar::build_from_path(&path))
+                        .transpose()?;
+                    if let Some(lexer_grammar) = &lexer_grammar {
+                        grammar.check_lexer(lexer_grammar, &blob)?;
+                    }
+                    grammar.decompile(lexer_grammar.as_ref())
+                }
+                _ => anyhow::bail!(
+                    "{} is neither a compiled lexer (.clx) nor parser (.cgr) grammar",
+                    blob.display()
+                ),
+            };
+            print!("{decompiled}");
+        }
+        Action::Export(export_action) => export(export_action)?,
+        Action::Fmt {
+            files,
+            check,
+            width,
+        } => {
+            let mut unformatted = 0;
+            for path in files {
+                let text = std::fs::read_to_string(&path)
+                    .context(format!("Could not read file {}", path.display()))?;
+                let extension = path.extension().and_then(|extension| extension.to_str());
+                let formatted = match extension {
+                    Some("lx") => format_lexer_grammar(&path, &text)?,
+                    Some("gr") => format_parser_grammar(&path, &text, width)?,
+                    _ => anyhow::bail!(
+                        "{} is neither a lexer (.lx) nor a parser (.gr) grammar",
+                        path.display()
+                    ),
+                };
+                if formatted == text {
+                    continue;
+                }
+                if check {
+                    println!("{}", path.display());
+                    unformatted += 1;
+                } else {
+                    std::fs::write(&path, formatted)
+                        .context(format!("Could not write to file {}", path.display()))?;
+                }
+            }
+            if unformatted > 0 {
+                anyhow::bail!("{unformatted} grammars are not formatted");
+            }
+        }
+        Action::Generate {
+            lexer_grammar: lexer_grammar_path,
+            parser_grammar: parser_grammar_path,
+            count,
+            depth,
+            seed,
+        } => {
+            let lexer = Lexer::build_from_path(&lexer_grammar_path)?;
+            let parser_grammar =
+                EarleyGrammar::build_from_path(&parser_grammar_path, lexer.grammar())?;
+            let seed = seed.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+            });
+            eprintln!("seed: {seed}");
+            for i in 0..count as u64 {
+                let Some(sentence) =
+                    parser_grammar.generate(lexer.grammar(), depth, seed.wrapping_add(i))
+                else {
+                    anyhow::bail!("No axiom of the grammar derives anything");
+                };
+                println!("{sentence}");
+            }
+        }
+        Action::Init {
+            directory,
+            name,
+            force,
+        } => init(&directory, name, force)?,
+        Action::Regex(RegexAction::Explain {
+            pattern,
+            insensitive,
+        }) => {
+            let regex = RegexBuilder::new()
+                .with_named_regex(&pattern, String::from("REGEX"), false, insensitive)
+                .map_err(|error| {
+                    let position = error.position;
+                    anyhow::anyhow!("Invalid regex at char {position}: {}", error.message)
+                })?
+                .build();
+            print!("{}", regex.dump());
+        }
+        Action::Resolve {
+            lexer_grammar,
+            parser_grammar,
+            samples,
+        } => resolve(&lexer_grammar, &parser_grammar, &samples)?,
+        #[cfg(feature = "serve")]
+        Action::Serve {
+            lexer_grammar: lexer_grammar_path,
+            parser_grammar: parser_grammar_path,
+            address,
+        } => {
+            let lexer = Lexer::build_from_path(&lexer_grammar_path)?;
+            let parser_grammar =
+                EarleyGrammar::build_from_path(&parser_grammar_path, lexer.grammar())?;
+            let server = Server::new(lexer, EarleyParser::new(parser_grammar));
+            eprintln!("Listening on http://{address}");
+            server.serve(address)?;
+        }
+        Action::Stats {
+            lexer_grammar: lexer_grammar_path,
+            parser_grammar: parser_grammar_path,
+        } => {
+            let lexer = Lexer::build_from_path(&lexer_grammar_path)?;
+            let grammar =
+                EarleyGrammar::build_from_path(&parser_grammar_path, lexer.grammar())?;
+            let nullables = grammar
+                .non_terminals()
+                .filter(|&id| grammar.is_nullable(id))
+                .count();
+            let rules = grammar.rules();
+            let elements: usize = rules.iter().map(|rule| rule.elements.len()).sum();
+            println!("terminals: {}", lexer.grammar().terminals().count());
+            println!(
+                "non-terminals: {} ({nullables} nullable)",
+                grammar.nb_non_terminals()
+            );
+            println!("rules: {}", rules.len());
+            println!(
+                "average rule length: {:.2} elements",
+                elements as f64 / rules.len().max(1) as f64
+            );
+            // The non-terminals with the most rules first, as they cost the most to
+            // predict.
+            let mut fan_out = grammar
+                .non_terminals()
+                .map(|id| (grammar.name_of(id), grammar.rules_of(id).len()))
+                .collect::<Vec<_>>();
+            fan_out.sort_by(|(left, left_rules), (right, right_rules)| {
+                right_rules
+                    .cmp(left_rules)
+                    .then_with(|| left.as_str().cmp(right.as_str()))
+            });
+            let width = fan_out
+                .iter()
+                .map(|(name, _)| name.as_str().chars().count())
+                .max()
+                .unwrap_or(0);
+            println!("rules per non-terminal:");
+            for (name, rules) in fan_out {
+                println!("  {:width$} {rules}", name.as_str());
+            }
+        }
+        Action::Test {
+            manifest,
+            filter,
+            bless,
+        } => run_tests(&manifest, filter.as_deref(), bless)?,
+        Action::Lex {
+            lexer_grammar,
+            manifest,
+            max_token_length,
+            layout,
+            disambiguation,
+            error_tokens,
+            sources,
+        } => {
             let mut output_buffer = BufWriter::new(stdout());
-            while let Some(token) = lexed_stream.next(Allowed::All)? {
-                write!(output_buffer, "{} {{ ", token.name())?;
-                for (key, value) in token.attributes().iter() {
-                    write!(output_buffer, "{}: {}, ", key, value)?;
+            for (grammars, sources) in group_sources(lexer_grammar, None, manifest, sources)? {
+                let mut lexer = Lexer::build_from_path(&grammars.lexer)?;
+                if let Some(max_token_length) = max_token_length {
+                    lexer = lexer.with_max_token_length(max_token_length);
+                }
+                if layout {
+                    let layout =
+                        Layout::from_names(lexer.grammar(), "INDENT", "DEDENT", "NEWLINE")?;
+                    lexer = lexer.with_layout(layout);
+                }
+                lexer = lexer.with_disambiguation(disambiguation.into());
+                let error = error_terminal(&lexer, error_tokens)?;
+                for source in sources {
+                    let mut stream = open_source(&source)?;
+                    let mut lexed_stream = lexer.lex(&mut stream);
+                    if let Some(error) = error {
+                        lexed_stream = lexed_stream.with_error_tokens(error);
+                    }
+                    while let Some(token) = lexed_stream.next(Allowed::All)? {
+                        write!(output_buffer, "{} {{ ", token.name())?;
+                        for (key, value) in token.attributes().iter() {
+                            write!(output_buffer, "{}: {}, ", key, value)?;
+                        }
+                        writeln!(output_buffer, "}}")?;
+                    }
                 }
-                writeln!(output_buffer, "}}")?;
             }
             output_buffer.flush()?;
         }
-        Action::Parse {
-            table: print_table,
-            final_table: print_final_table,
+        Action::Validate {
+            lalr,
             lexer_grammar: lexer_grammar_path,
             parser_grammar: parser_grammar_path,
-            source,
+            sources,
         } => {
             let lexer = Lexer::build_from_path(&lexer_grammar_path)?;
-            let parser_grammar = if let Some("cgr") =
-                parser_grammar_path.extension().and_then(|x| x.to_str())
-            {
-                let mut buffer = Vec::new();
-                let mut fd = File::open(parser_grammar_path.as_path())?;
-                fd.read_to_end(&mut buffer)?;
-                deserialize(&buffer)?
+            let mut rejected = 0;
+            let total = sources.len();
+            reporter.report(Progress::new(Phase::Corpus, 0, total));
+            if lalr {
+                let parser = LalrParser::new(LalrGrammar::build_from_path(
+                    &parser_grammar_path,
+                    lexer.grammar(),
+                )?);
+                for (done, source) in sources.into_iter().enumerate() {
+                    let mut stream = open_source(&source)?;
+                    if let Err(error) = parser.parse(&mut lexer.lex(&mut stream)) {
+                        eprintln!("{error}");
+                        rejected += 1;
+                    }
+                    reporter.report(Progress::new(Phase::Corpus, done + 1, total));
+                }
             } else {
-                EarleyGrammar::build_from_path(parser_grammar_path.as_path(), lexer.grammar())?
-            };
-            let parser = EarleyParser::new(parser_grammar);
-            // let (table, raw_input) =
-            //     EarleyParser::recognise(
-            // 	    &parser,
-            //         &mut lexer.lex(
-            //             &mut StringStream::from_file(source)?
-            //                 ,
-            //         ),
-            //     )?
-            //     ;
-            // println!("{:#?}\n{}", table, raw_input.len());
-            let mut stream = StringStream::from_file(source)?;
-            let mut input = lexer.lex(&mut stream);
-            let (table, raw_input) = parser.recognise(&mut input)?;
-            if print_table {
-                println!(" ### TABLE ###");
-                print_sets(&table, &parser, &lexer);
-            }
-            let forest = parser.to_forest(&table, &raw_input)?;
-            if print_final_table {
-                println!(" ### FINAL TABLE ###");
-                print_final_sets(&forest, &parser, &lexer);
-            }
-            let ast = parser.select_ast(&forest, &raw_input, input.last_span());
-            print_ast(&ast)?;
+                let parser = EarleyParser::new(EarleyGrammar::build_from_path(
+                    &parser_grammar_path,
+                    lexer.grammar(),
+                )?);
+                for (done, source) in sources.into_iter().enumerate() {
+                    let mut stream = open_source(&source)?;
+                    if let Err(error) = parser.validate(&mut lexer.lex(&mut stream)) {
+                        eprintln!("{error}");
+                        rejected += 1;
+                    }
+                    reporter.report(Progress::new(Phase::Corpus, done + 1, total));
+                }
+            }
+            if rejected > 0 {
+                anyhow::bail!("{rejected} files were rejected");
+            }
+        }
+        Action::Parse {
+            table: print_table,
+            final_table: print_final_table,
+            lalr,
+            max_errors,
+            warn_ambiguity,
+            prefer,
+            format,
+            forest: print_forest,
+            events,
+            profile_rules,
+            max_token_length,
+            layout,
+            disambiguation,
+            error_tokens,
+            lexer_grammar,
+            parser_grammar,
+            manifest,
+            sources,
+            ..
+        } => {
+            let total = sources.len();
+            let mut done = 0;
+            for (grammars, sources) in
+                group_sources(lexer_grammar, parser_grammar, manifest, sources)?
+            {
+                let parser_grammar_path = grammars.parser.context(format!(
+                    "The manifest gives no parser grammar for {}",
+                    sources[0].display()
+                ))?;
+                let mut lexer = Lexer::build_from_path(&grammars.lexer)?;
+                if let Some(max_token_length) = max_token_length {
+                    lexer = lexer.with_max_token_length(max_token_length);
+                }
+                if layout {
+                    let layout =
+                        Layout::from_names(lexer.grammar(), "INDENT", "DEDENT", "NEWLINE")?;
+                    lexer = lexer.with_layout(layout);
+                }
+                lexer = lexer.with_disambiguation(disambiguation.into());
+                if lalr {
+                    let parser_grammar =
+                        LalrGrammar::build_from_path(&parser_grammar_path, lexer.grammar())?;
+                    let parser = LalrParser::new(parser_grammar);
+                    for source in sources {
+                        reporter.report(Progress::new(Phase::Corpus, done, total));
+                        done += 1;
+                        let mut stream = open_source(&source)?;
+                        let mut input = lexer.lex(&mut stream);
+                        if events {
+                            let mut printer = event_printer(parser.grammar().grammar());
+                            parser.parse_events(&mut input, &mut printer)?;
+                            continue;
+                        }
+                        let ast = parser.parse(&mut input)?.tree;
+                        print_output(ast, parser.grammar().grammar(), format)?;
+                    }
+                    continue;
+                }
+                let parser_grammar = EarleyGrammar::build_from_path(
+                    parser_grammar_path.as_path(),
+                    lexer.grammar(),
+                )?;
+                let mut parser = EarleyParser::new(parser_grammar);
+                if let Some(prefer) = prefer {
+                    parser = parser.with_rule_preference(prefer.into());
+                }
+                let error = error_terminal(&lexer, error_tokens)?;
+                for source in sources {
+                    reporter.report(Progress::new(Phase::Corpus, done, total));
+                    done += 1;
+                    let mut stream = open_source(&source)?;
+                    if let Some(max_errors) = max_errors {
+                        let mut input = lexer.lex(&mut stream);
+                        if let Some(error) = error {
+                            input = input.with_error_tokens(error);
+                        }
+                        let result = parser.parse_all_errors(&mut input, max_errors);
+                        for error in &result.errors {
+                            eprintln!("{error}");
+                        }
+                        if let Some(ast) = result.tree {
+                            print_output(ast, parser.grammar(), format)?;
+                        }
+                        if !result.errors.is_empty() {
+                            anyhow::bail!("Found {} errors", result.errors.len());
+                        }
+                        continue;
+                    }
+                    let mut input = lexer.lex(&mut stream);
+                    if events {
+                        parser.parse_events(&mut input, &mut event_printer(parser.grammar()))?;
+                        continue;
+                    }
+                    let (table, raw_input) = parser.recognise(&mut input)?;
+                    if print_table {
+                        println!(" ### TABLE ###");
+                        print_sets(&table, &parser, &lexer);
+                    }
+                    let forest = parser.to_forest(&table, &raw_input)?;
+                    if print_final_table {
+                        println!(" ### FINAL TABLE ###");
+                        print_final_sets(&forest, &parser, &lexer);
+                    }
+                    if profile_rules {
+                        let profile = parser.profile(&table, &forest, &raw_input)?;
+                        print_profile(&profile, &parser, &lexer);
+                    }
+                    if warn_ambiguity {
+                        for ambiguity in
+                            parser.ambiguities(&forest, &raw_input, input.last_span())
+                        {
+                            eprint!("{ambiguity}");
+                        }
+                    }
+                    if print_forest {
+                        let sppf = parser.to_sppf(&forest, &raw_input);
+                        print!("{}", forest_to_dot(&sppf, parser.grammar(), &raw_input));
+                        continue;
+                    }
+                    let ast = parser.select_ast(&forest, &raw_input, input.last_span())?;
+                    print_output(ast, parser.grammar(), format)?;
+                }
+            }
+            reporter.report(Progress::new(Phase::Corpus, total, total));
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn init_parses_its_input() {
+        let dir = std::env::temp_dir().join(format!("beans-init-{}", std::process::id()));
+        init(&dir, Some(String::from("calc")), true).unwrap();
+        compile_all(&dir, Some(1), false, &mut |_: Progress| {}).unwrap();
+        let lexer = Lexer::build_from_path(&dir.join("calc.clx")).unwrap();
+        let parser = EarleyParser::new(
+            EarleyGrammar::build_from_path(&dir.join("calc.cgr"), lexer.grammar()).unwrap(),
+        );
+        let mut input = StringStream::from_file(dir.join("input.calc")).unwrap();
+        let ast = parser.parse(&mut lexer.lex(&mut input)).unwrap().tree;
+        assert!(matches!(ast, AST::Node { .. }));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn compile_all_skips_parsers_of_failed_lexers() {
+        let dir = std::env::temp_dir().join(format!("beans-skip-{}", std::process::id()));
+        init(&dir, Some(String::from("calc")), true).unwrap();
+        compile_all(&dir, Some(1), false, &mut |_: Progress| {}).unwrap();
+        // The parser grammar is not compiled against the stale compiled lexer grammar.
+        std::fs::write(dir.join("calc.lx"), "NUMBER ::= [0-9").unwrap();
+        std::fs::remove_file(dir.join("calc.cgr")).unwrap();
+        assert!(compile_all(&dir, Some(1), false, &mut |_: Progress| {}).is_err());
+        assert!(!dir.join("calc.cgr").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,9 +1,48 @@
+pub mod analysis;
+pub mod arbitrary;
+pub mod arena;
 mod ast;
+pub mod codegen;
+pub mod coverage;
+pub mod cst;
+pub mod disambiguation;
+pub mod dot;
+// With `hardened`, parsing does not panic, whatever the input.
+#[cfg_attr(
+    all(feature = "hardened", not(test)),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::unreachable,
+        clippy::todo,
+        clippy::unimplemented
+    )
+)]
 pub mod earley;
+pub mod forest;
+pub mod format;
+// The rules build the ASTs of inputs, which must not panic either.
+#[cfg_attr(
+    all(feature = "hardened", not(test)),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::unreachable,
+        clippy::todo,
+        clippy::unimplemented
+    )
+)]
 mod grammar;
+pub mod incremental;
+pub mod lalr;
+pub mod passes;
+pub mod resolve;
+pub mod version;
 // mod grammarparser;
 #[allow(clippy::module_inception)]
 mod parser;
 
 // pub use grammarparser::Grammar;
-pub use parser::{Parser, Value, AST};
+pub use parser::{Event, External, ParseResult, ParseSink, Parser, Value, AST};
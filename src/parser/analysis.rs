@@ -0,0 +1,746 @@
+//! # Analysis
+//!
+//! Static analyses of a parser grammar, which do not require any input.
+//! The most useful is [`FirstFollow`], which computes the usual FIRST and
+//! FOLLOW sets of a grammar, and [`lint`] finds the likely mistakes of a
+//! grammar, as [`ambiguity_hints`] finds its likely sources of ambiguity.
+
+use super::earley::EarleyGrammar;
+use super::grammar::{Element, ElementType, Rule, RuleId};
+use super::parser::NonTerminalId;
+use crate::interner::Symbol;
+use crate::lexer::{Grammar as LexerGrammar, TerminalId};
+use crate::span::Span;
+use itertools::Itertools;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+/// # Summary
+///
+/// The FIRST and FOLLOW sets of an [`EarleyGrammar`].
+///
+/// The FIRST set of a non-terminal is the set of terminals that can start
+/// a sentence it derives. The FOLLOW set of a non-terminal (or of a terminal)
+/// is the set of terminals that can appear right after it in a sentence
+/// derived from an axiom. Whether the end of the input can follow is
+/// tracked separately.
+#[derive(Debug)]
+pub struct FirstFollow {
+    first: Vec<BTreeSet<TerminalId>>,
+    follow: Vec<BTreeSet<TerminalId>>,
+    can_end: Vec<bool>,
+    terminal_follow: BTreeMap<TerminalId, BTreeSet<TerminalId>>,
+    terminal_can_end: BTreeSet<TerminalId>,
+}
+
+impl FirstFollow {
+    /// Compute the FIRST and FOLLOW sets of `grammar`.
+    pub fn new(grammar: &EarleyGrammar) -> Self {
+        let nb_non_terminals = grammar.nb_non_terminals();
+        let mut result = Self {
+            first: vec![BTreeSet::new(); nb_non_terminals],
+            follow: vec![BTreeSet::new(); nb_non_terminals],
+            can_end: (0..nb_non_terminals)
+                .map(|id| grammar.is_axiom(NonTerminalId(id)))
+                .collect(),
+            terminal_follow: BTreeMap::new(),
+            terminal_can_end: BTreeSet::new(),
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for rule in grammar.rules().iter() {
+                let (first, _) = result.first_of(&rule.elements, grammar);
+                let before = result.first[rule.id.0].len();
+                result.first[rule.id.0].extend(first);
+                changed |= before != result.first[rule.id.0].len();
+            }
+        }
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for rule in grammar.rules().iter() {
+                for (i, element) in rule.elements.iter().enumerate() {
+                    let ElementType::NonTerminal(id) = element.element_type else {
+                        continue;
+                    };
+                    let (mut follow, nullable) =
+                        result.first_of(&rule.elements[i + 1..], grammar);
+                    if nullable {
+                        follow.extend(result.follow[rule.id.0].iter().copied());
+                        if result.can_end[rule.id.0] && !result.can_end[id.0] {
+                            result.can_end[id.0] = true;
+                            changed = true;
+                        }
+                    }
+                    let before = result.follow[id.0].len();
+                    result.follow[id.0].extend(follow);
+                    changed |= before != result.follow[id.0].len();
+                }
+            }
+        }
+
+        for rule in grammar.rules().iter() {
+            for (i, element) in rule.elements.iter().enumerate() {
+                let ElementType::Terminal(id) = element.element_type else {
+                    continue;
+                };
+                let (mut follow, nullable) = result.first_of(&rule.elements[i + 1..], grammar);
+                if nullable {
+                    follow.extend(result.follow[rule.id.0].iter().copied());
+                    if result.can_end[rule.id.0] {
+                        result.terminal_can_end.insert(id);
+                    }
+                }
+                result.terminal_follow.entry(id).or_default().extend(follow);
+            }
+        }
+        result
+    }
+
+    /// Return the FIRST set of a sequence of elements, along with whether
+    /// that sequence can match the empty string.
+    pub(crate) fn first_of(
+        &self,
+        elements: &[Element],
+        grammar: &EarleyGrammar,
+    ) -> (BTreeSet<TerminalId>, bool) {
+        let mut first = BTreeSet::new();
+        for element in elements {
+            match element.element_type {
+                ElementType::Terminal(id) => {
+                    first.insert(id);
+                    return (first, false);
+                }
+                ElementType::NonTerminal(id) => {
+                    first.extend(self.first[id.0].iter().copied());
+                    if !grammar.is_nullable(id) {
+                        return (first, false);
+                    }
+                }
+            }
+        }
+        (first, true)
+    }
+
+    /// Return the terminals that can start a sentence derived from `id`.
+    pub fn first(&self, id: NonTerminalId) -> &BTreeSet<TerminalId> {
+        &self.first[id.0]
+    }
+
+    /// Return the terminals that can appear right after the non-terminal `id`.
+    pub fn follow(&self, id: NonTerminalId) -> &BTreeSet<TerminalId> {
+        &self.follow[id.0]
+    }
+
+    /// Return whether the input can end right after the non-terminal `id`.
+    pub fn can_end(&self, id: NonTerminalId) -> bool {
+        self.can_end[id.0]
+    }
+
+    /// Return the terminals that can appear right after the terminal `id`.
+    /// This is empty for terminals that are never used by the grammar.
+    pub fn follow_terminal(&self, id: TerminalId) -> impl Iterator<Item = TerminalId> + '_ {
+        self.terminal_follow
+            .get(&id)
+            .into_iter()
+            .flat_map(|follow| follow.iter().copied())
+    }
+
+    /// Return whether the input can end right after the terminal `id`.
+    pub fn terminal_can_end(&self, id: TerminalId) -> bool {
+        self.terminal_can_end.contains(&id)
+    }
+}
+
+/// # Summary
+///
+/// A static completion table, meant to be consumed by editor plugins that
+/// cannot run a parser. Every terminal and non-terminal is referred to by
+/// its name.
+///
+/// - `first` maps each non-terminal to the terminals that can start it;
+/// - `follow` maps each terminal to the terminals that can follow it;
+/// - `end` lists the terminals after which the input can end;
+/// - `descriptions` maps terminals to their description, if they have one.
+#[derive(Debug, Serialize)]
+pub struct Completions {
+    pub first: BTreeMap<String, Vec<String>>,
+    pub follow: BTreeMap<String, Vec<String>>,
+    pub end: Vec<String>,
+    pub descriptions: BTreeMap<String, String>,
+}
+
+impl Completions {
+    /// Build the completion table of `grammar`.
+    pub fn new(grammar: &EarleyGrammar, lexer_grammar: &LexerGrammar) -> Self {
+        let sets = FirstFollow::new(grammar);
+        let names = |terminals: &mut dyn Iterator<Item = TerminalId>| {
+            terminals
+                .map(|id| lexer_grammar.name(id).to_string())
+                .collect::<Vec<_>>()
+        };
+        let first = (0..grammar.nb_non_terminals())
+            .map(NonTerminalId)
+            .map(|id| {
+                (
+                    grammar.name_of(id).to_string(),
+                    names(&mut sets.first(id).iter().copied()),
+                )
+            })
+            .collect();
+        let follow = lexer_grammar
+            .terminals()
+            .filter(|&id| !lexer_grammar.ignored(id))
+            .map(|id| {
+                (
+                    lexer_grammar.name(id).to_string(),
+                    names(&mut sets.follow_terminal(id)),
+                )
+            })
+            .collect();
+        let end = names(
+            &mut lexer_grammar
+                .terminals()
+                .filter(|&id| sets.terminal_can_end(id)),
+        );
+        let descriptions = lexer_grammar
+            .terminals()
+            .filter_map(|id| {
+                lexer_grammar.description_of(id).map(|description| {
+                    (
+                        lexer_grammar.name(id).to_string(),
+                        description.to_string(),
+                    )
+                })
+            })
+            .collect();
+        Self {
+            first,
+            follow,
+            end,
+            descriptions,
+        }
+    }
+}
+
+/// A likely mistake in a parser grammar, found by [`lint`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Lint {
+    /// No axiom derives the non-terminal, whose first rule is at `span`.
+    Unreachable { nonterminal: Symbol, span: Span },
+    /// The rule derives no sequence of terminals, as each of its derivations needs
+    /// one that is still unfinished.
+    NonProductive { rule: RuleId, span: Span },
+    /// The rule has the same elements as the rule of the same non-terminal at
+    /// `original`, so that any input it matches is ambiguous.
+    DuplicateRule {
+        rule: RuleId,
+        span: Span,
+        original: Span,
+    },
+    /// The terminal, defined at `span`, is neither ignored nor used by any rule.
+    UnusedTerminal { terminal: Symbol, span: Span },
+    /// The rule is part of a cycle through which a non-terminal derives itself, and
+    /// nothing else, so that what it derives has infinitely many derivations.
+    Cycle { rule: RuleId, span: Span },
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unreachable { nonterminal, span } => write!(
+                f,
+                "the non-terminal {nonterminal}, defined {span}, cannot be reached from \
+                 an axiom"
+            ),
+            Self::NonProductive { span, .. } => {
+                write!(f, "the rule {span} cannot derive any sequence of tokens")
+            }
+            Self::DuplicateRule { span, original, .. } => {
+                write!(f, "the rule {span} has the same elements as the rule {original}")
+            }
+            Self::UnusedTerminal { terminal, span } => write!(
+                f,
+                "the terminal {terminal}, defined {span}, is neither ignored nor used by \
+                 the parser grammar"
+            ),
+            Self::Cycle { span, .. } => write!(
+                f,
+                "the rule {span} is part of a cycle of rules that derive nothing but \
+                 their own non-terminal, so what it derives has infinitely many derivations"
+            ),
+        }
+    }
+}
+
+/// Find the non-terminals of `grammar` that no axiom derives, the rules that derive
+/// no sequence of terminals, the rules that repeat another rule of their
+/// non-terminal, the terminals of `lexer_grammar` that are neither ignored nor used
+/// by `grammar`, and the rules through which a non-terminal derives itself.
+pub fn lint(grammar: &EarleyGrammar, lexer_grammar: &LexerGrammar) -> Vec<Lint> {
+    let rules = grammar.rules();
+    let nb_non_terminals = grammar.nb_non_terminals();
+    let non_terminals = |rule: RuleId| {
+        rules[rule]
+            .elements
+            .iter()
+            .filter_map(|element| match element.element_type {
+                ElementType::NonTerminal(id) => Some(id),
+                ElementType::Terminal(_) => None,
+            })
+    };
+    let mut lints = Vec::new();
+
+    let mut reachable: Vec<_> = (0..nb_non_terminals)
+        .map(|id| grammar.is_axiom(NonTerminalId(id)))
+        .collect();
+    let mut stack: Vec<_> = (0..nb_non_terminals)
+        .filter(|&id| reachable[id])
+        .map(NonTerminalId)
+        .collect();
+    while let Some(id) = stack.pop() {
+        for &rule in grammar.rules_of(id) {
+            for child in non_terminals(rule) {
+                if !reachable[child.0] {
+                    reachable[child.0] = true;
+                    stack.push(child);
+                }
+            }
+        }
+    }
+    for id in (0..nb_non_terminals).map(NonTerminalId) {
+        if let (false, Some(&rule)) = (reachable[id.0], grammar.rules_of(id).first()) {
+            lints.push(Lint::Unreachable {
+                nonterminal: grammar.name_of(id),
+                span: rules[rule].span.clone(),
+            });
+        }
+    }
+
+    let mut productive = vec![false; nb_non_terminals];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (rule, id) in rules.iter().enumerate().map(|(i, rule)| (RuleId(i), rule.id)) {
+            if !productive[id.0] && non_terminals(rule).all(|child| productive[child.0]) {
+                productive[id.0] = true;
+                changed = true;
+            }
+        }
+    }
+    for rule in (0..rules.len()).map(RuleId) {
+        if !non_terminals(rule).all(|child| productive[child.0]) {
+            lints.push(Lint::NonProductive {
+                rule,
+                span: rules[rule].span.clone(),
+            });
+        }
+    }
+
+    for id in (0..nb_non_terminals).map(NonTerminalId) {
+        let mut bodies = HashMap::new();
+        for &rule in grammar.rules_of(id) {
+            let body: Vec<_> = rules[rule]
+                .elements
+                .iter()
+                .map(|element| element.element_type)
+                .collect();
+            if let Some(&original) = bodies.get(&body) {
+                lints.push(Lint::DuplicateRule {
+                    rule,
+                    span: rules[rule].span.clone(),
+                    original: rules[original].span.clone(),
+                });
+            } else {
+                bodies.insert(body, rule);
+            }
+        }
+    }
+
+    let used: BTreeSet<_> = rules
+        .iter()
+        .flat_map(|rule| &rule.elements)
+        .filter_map(|element| match element.element_type {
+            ElementType::Terminal(id) => Some(id),
+            ElementType::NonTerminal(_) => None,
+        })
+        .collect();
+    for id in lexer_grammar.terminals() {
+        if !lexer_grammar.ignored(id) && !used.contains(&id) {
+            lints.push(Lint::UnusedTerminal {
+                terminal: Symbol::intern(lexer_grammar.name(id)),
+                span: lexer_grammar.span_of(id).clone(),
+            });
+        }
+    }
+
+    // Cycles of rules that derive nothing are already reported as non-productive.
+    for &rule in grammar.cycles() {
+        if non_terminals(rule).all(|child| productive[child.0]) {
+            lints.push(Lint::Cycle {
+                rule,
+                span: rules[rule].span.clone(),
+            });
+        }
+    }
+    lints
+}
+
+/// A likely source of ambiguity in a parser grammar, found by [`ambiguity_hints`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum AmbiguityHint {
+    /// Both rules nest their non-terminal on both sides of an operator, and no
+    /// precedence table tells which binds tighter.
+    OverlappingOperators { span: Span, other: Span },
+    /// Both rules of a non-terminal start with the same `length` elements.
+    CommonPrefix {
+        span: Span,
+        other: Span,
+        length: usize,
+    },
+    /// The non-terminal derives the empty string, and can start with `terminals`,
+    /// which can also follow it.
+    OptionalOverlap {
+        nonterminal: Symbol,
+        span: Span,
+        terminals: Vec<Symbol>,
+    },
+}
+
+impl fmt::Display for AmbiguityHint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OverlappingOperators { span, other } => write!(
+                f,
+                "the operators of the rules {span} and {other} have no precedence"
+            ),
+            Self::CommonPrefix {
+                span,
+                other,
+                length,
+            } => write!(
+                f,
+                "the rules {span} and {other} start with the same {length} elements"
+            ),
+            Self::OptionalOverlap {
+                nonterminal,
+                span,
+                terminals,
+            } => write!(
+                f,
+                "the non-terminal {nonterminal}, defined {span}, may be empty, but can \
+                 start with what follows it: {}",
+                terminals.iter().join(", ")
+            ),
+        }
+    }
+}
+
+/// The rules of `grammar` through which a non-terminal derives itself, and nothing
+/// else: the rules `A ::= α B β`, where `α` and `β` derive the empty string and `B`
+/// derives `A` in the same way, as in `A ::= A A` when `A` derives the empty string.
+pub fn cyclic_rules(grammar: &EarleyGrammar) -> Vec<RuleId> {
+    let rules = grammar.rules();
+    let nb_non_terminals = grammar.nb_non_terminals();
+    // The non-terminals that `rule` derives alone, its other elements deriving the
+    // empty string.
+    let alone = |rule: &Rule| -> Vec<NonTerminalId> {
+        let nullable = |element: &Element| match element.element_type {
+            ElementType::NonTerminal(id) => grammar.is_nullable(id),
+            ElementType::Terminal(_) => false,
+        };
+        let mut alone = Vec::new();
+        for (i, element) in rule.elements.iter().enumerate() {
+            let ElementType::NonTerminal(id) = element.element_type else {
+                continue;
+            };
+            let rest = rule.elements.iter().enumerate().filter(|&(j, _)| j != i);
+            if rest.map(|(_, other)| other).all(nullable) {
+                alone.push(id);
+            }
+        }
+        alone
+    };
+    let mut successors = vec![BTreeSet::new(); nb_non_terminals];
+    for rule in rules.iter() {
+        successors[rule.id.0].extend(alone(rule).into_iter().map(|id| id.0));
+    }
+    // Whether each non-terminal derives each other one alone.
+    let reaches: Vec<Vec<bool>> = (0..nb_non_terminals)
+        .map(|from| {
+            let mut reached = vec![false; nb_non_terminals];
+            let mut stack: Vec<_> = successors[from].iter().copied().collect();
+            while let Some(id) = stack.pop() {
+                if !reached[id] {
+                    reached[id] = true;
+                    stack.extend(successors[id].iter().copied());
+                }
+            }
+            reached
+        })
+        .collect();
+    (0..rules.len())
+        .map(RuleId)
+        .filter(|&rule| {
+            let id = rules[rule].id;
+            alone(&rules[rule])
+                .into_iter()
+                .any(|child| child == id || reaches[child.0][id.0])
+        })
+        .collect()
+}
+
+/// Find the likely sources of ambiguity of `grammar`, whose terminals are the ones
+/// of `lexer_grammar`, without any input: the operator rules that the precedence
+/// table does not order, the rules of a non-terminal that start alike, and the
+/// non-terminals that may be empty and can start with what follows them. Cycles of
+/// rules are reported by [`lint`].
+pub fn ambiguity_hints(
+    grammar: &EarleyGrammar,
+    lexer_grammar: &LexerGrammar,
+) -> Vec<AmbiguityHint> {
+    let rules = grammar.rules();
+    let mut hints = Vec::new();
+
+    let is_operator = |rule: &Rule| {
+        let nests = |element: Option<&Element>| {
+            matches!(
+                element,
+                Some(element) if element.element_type == ElementType::NonTerminal(rule.id)
+            )
+        };
+        rule.elements.len() >= 3
+            && rule.precedence.is_none()
+            && nests(rule.elements.first())
+            && nests(rule.elements.last())
+    };
+    for id in (0..grammar.nb_non_terminals()).map(NonTerminalId) {
+        let rules_of = grammar.rules_of(id);
+        for (i, &first) in rules_of.iter().enumerate() {
+            for &second in &rules_of[i + 1..] {
+                let (first, second) = (&rules[first], &rules[second]);
+                if is_operator(first) && is_operator(second) {
+                    hints.push(AmbiguityHint::OverlappingOperators {
+                        span: first.span.clone(),
+                        other: second.span.clone(),
+                    });
+                    continue;
+                }
+                let length = first
+                    .elements
+                    .iter()
+                    .zip(&second.elements)
+                    .take_while(|(left, right)| left.element_type == right.element_type)
+                    .count();
+                if length > 0
+                    && length < first.elements.len()
+                    && length < second.elements.len()
+                {
+                    hints.push(AmbiguityHint::CommonPrefix {
+                        span: first.span.clone(),
+                        other: second.span.clone(),
+                        length,
+                    });
+                }
+            }
+        }
+    }
+
+    let sets = FirstFollow::new(grammar);
+    for id in (0..grammar.nb_non_terminals()).map(NonTerminalId) {
+        let Some(&rule) = grammar.rules_of(id).first() else {
+            continue;
+        };
+        if !grammar.is_nullable(id) {
+            continue;
+        }
+        let terminals: Vec<_> = sets
+            .first(id)
+            .intersection(sets.follow(id))
+            .map(|&terminal| Symbol::intern(lexer_grammar.name(terminal)))
+            .collect();
+        if !terminals.is_empty() {
+            hints.push(AmbiguityHint::OptionalOverlap {
+                nonterminal: grammar.name_of(id),
+                span: rules[rule].span.clone(),
+                terminals,
+            });
+        }
+    }
+    hints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Buildable;
+    use crate::lexer::Lexer;
+    use crate::stream::StringStream;
+    use std::path::Path;
+
+    const LEXER: &str = r#"
+NUMBER ::= ([0-9])
+PM ::= [-+]
+TD ::= [*/]
+LPAR ::= \(
+RPAR ::= \)
+"#;
+
+    const GRAMMAR: &str = r#"
+@Sum ::= Sum@left PM Product@right <>
+ Product@self <>;
+
+Product ::= Product@left TD Factor@right <>
+ Factor.self@self <>;
+
+Factor ::= LPAR Sum@self RPAR <>
+ NUMBER.0@self <>;"#;
+
+    fn names(lexer: &Lexer, terminals: impl IntoIterator<Item = TerminalId>) -> Vec<&str> {
+        terminals
+            .into_iter()
+            .map(|id| lexer.grammar().name(id))
+            .collect()
+    }
+
+    #[test]
+    fn first_follow() {
+        let lexer =
+            Lexer::build_from_plain(StringStream::new(Path::new("<lexer input>"), LEXER))
+                .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), GRAMMAR),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let sets = FirstFollow::new(&grammar);
+        let sum = grammar.id_of("Sum");
+        let factor = grammar.id_of("Factor");
+        assert_eq!(
+            names(&lexer, sets.first(sum).iter().copied()),
+            ["NUMBER", "LPAR"]
+        );
+        assert_eq!(
+            names(&lexer, sets.follow(factor).iter().copied()),
+            ["PM", "TD", "RPAR"]
+        );
+        assert!(sets.can_end(factor));
+        let number = lexer.grammar().id("NUMBER").unwrap();
+        let lpar = lexer.grammar().id("LPAR").unwrap();
+        assert_eq!(
+            names(&lexer, sets.follow_terminal(number)),
+            ["PM", "TD", "RPAR"]
+        );
+        assert_eq!(names(&lexer, sets.follow_terminal(lpar)), ["NUMBER", "LPAR"]);
+        assert!(sets.terminal_can_end(number));
+        assert!(!sets.terminal_can_end(lpar));
+    }
+
+    #[test]
+    fn lints() {
+        let lexer =
+            Lexer::build_from_plain(StringStream::new(Path::new("<lexer input>"), LEXER))
+                .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@Sum ::= Sum@left PM NUMBER@right <>\n NUMBER@self <>\n NUMBER@value <>;\n\
+                 Unused ::= LPAR Loop RPAR <>;\n\
+                 Loop ::= Loop@inner <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let lints = lint(&grammar, lexer.grammar());
+        let unreachable: Vec<_> = lints
+            .iter()
+            .filter_map(|lint| match lint {
+                Lint::Unreachable { nonterminal, .. } => Some(nonterminal.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(unreachable, ["Unused", "Loop"]);
+        let non_productive: Vec<_> = lints
+            .iter()
+            .filter_map(|lint| match lint {
+                Lint::NonProductive { rule, .. } => Some(rule.0),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(non_productive, [3, 4]);
+        assert!(lints
+            .iter()
+            .any(|lint| matches!(lint, Lint::DuplicateRule { rule: RuleId(2), .. })));
+        let unused: Vec<_> = lints
+            .iter()
+            .filter_map(|lint| match lint {
+                Lint::UnusedTerminal { terminal, .. } => Some(terminal.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(unused, ["TD"]);
+        assert!(!lints.iter().any(|lint| matches!(lint, Lint::Cycle { .. })));
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@A ::= <>\n B <>\n A PM A <>\n A TD A <>\n LPAR NUMBER RPAR <>;\n\
+                 B ::= A <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let cycles: Vec<_> = lint(&grammar, lexer.grammar())
+            .into_iter()
+            .filter_map(|lint| match lint {
+                Lint::Cycle { rule, .. } => Some(rule.0),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(cycles, [1, 5]);
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), GRAMMAR),
+            lexer.grammar(),
+        )
+        .unwrap();
+        assert!(lint(&grammar, lexer.grammar()).is_empty());
+    }
+
+    #[test]
+    fn hints() {
+        let lexer =
+            Lexer::build_from_plain(StringStream::new(Path::new("<lexer input>"), LEXER))
+                .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@E ::= E@left PM E@right <>\n E@left TD E@right <>\n LPAR NUMBER RPAR <>\n\
+                 LPAR NUMBER PM <>\n Sign NUMBER <>;\n\
+                 Sign ::= <>\n PM <>\n NUMBER <>;\n\
+                 Loop ::= <>\n Loop Loop <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        assert_eq!(cyclic_rules(&grammar), [RuleId(9)]);
+        let hints = ambiguity_hints(&grammar, lexer.grammar());
+        assert_eq!(hints.len(), 3);
+        assert!(matches!(hints[0], AmbiguityHint::OverlappingOperators { .. }));
+        assert!(matches!(hints[1], AmbiguityHint::CommonPrefix { length: 2, .. }));
+        assert!(matches!(
+            &hints[2],
+            AmbiguityHint::OptionalOverlap { terminals, .. } if terminals.len() == 1
+        ));
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), GRAMMAR),
+            lexer.grammar(),
+        )
+        .unwrap();
+        assert!(ambiguity_hints(&grammar, lexer.grammar()).is_empty());
+    }
+}
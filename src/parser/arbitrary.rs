@@ -0,0 +1,382 @@
+//! # Arbitrary
+//!
+//! Generation of random ASTs that follow the attribute schema of a grammar, to
+//! test the passes that consume them without writing sources by hand.
+//!
+//! An AST is generated from a sequence of choices, each one picking a rule or a
+//! lexeme, so that shorter sequences, and sequences of smaller numbers, give
+//! smaller ASTs. Property testing libraries can therefore generate and shrink a
+//! `Vec<u32>`, such as with `proptest::collection::vec(any::<u32>(), 0..64)`, and
+//! have [`AstGenerator::from_choices`] turn it into an AST.
+//!
+//! The generator also writes random sentences of the grammar, to test the lexer and
+//! the parser themselves, with [`AstGenerator::sentence`].
+
+use super::earley::EarleyGrammar;
+use super::grammar::{Attribute, Element, ElementType, RuleId};
+use super::parser::{NonTerminalId, AST};
+use crate::error::{ErrorKind, Result};
+use crate::interner::Symbol;
+use crate::lexer::{Grammar as LexerGrammar, TerminalId, Token};
+use crate::span::Span;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Where the choices made while generating an AST come from.
+trait Choices {
+    /// Pick a number in `0..bound`.
+    fn choose(&mut self, bound: usize) -> usize;
+}
+
+/// Choices replayed from a sequence, which then picks the first option forever.
+struct Replay<'a> {
+    choices: std::slice::Iter<'a, u32>,
+}
+
+impl Choices for Replay<'_> {
+    fn choose(&mut self, bound: usize) -> usize {
+        self.choices
+            .next()
+            .map_or(0, |&choice| choice as usize % bound)
+    }
+}
+
+/// Choices made by a xorshift generator.
+struct Random {
+    state: u64,
+}
+
+impl Choices for Random {
+    fn choose(&mut self, bound: usize) -> usize {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state % bound as u64) as usize
+    }
+}
+
+/// # Summary
+///
+/// [`AstGenerator`] generates random ASTs, as the parser would build them for
+/// some input of the grammar. Semantic actions are not run.
+///
+/// Past a maximum depth, only the rules that end the derivation the soonest are
+/// picked. Tokens are given the lexemes registered with
+/// [`AstGenerator::with_lexemes`], and their name otherwise, except in sentences,
+/// where they are given a text their regex matches if one is found.
+#[derive(Debug)]
+pub struct AstGenerator<'a> {
+    grammar: &'a EarleyGrammar,
+    lexer_grammar: &'a LexerGrammar,
+    max_depth: usize,
+    lexemes: HashMap<TerminalId, Vec<String>>,
+    /// The height of the smallest derivation of every non-terminal, `None` if it
+    /// derives nothing.
+    heights: Vec<Option<usize>>,
+    span: Span,
+}
+
+impl<'a> AstGenerator<'a> {
+    pub fn new(grammar: &'a EarleyGrammar, lexer_grammar: &'a LexerGrammar) -> Self {
+        Self {
+            grammar,
+            lexer_grammar,
+            max_depth: 8,
+            lexemes: HashMap::new(),
+            heights: Self::heights(grammar),
+            span: Span::new(Path::new("<generated>"), (0, 0), (0, 0), 0, 0, 0, 0, "", vec![0]),
+        }
+    }
+
+    /// Compute the height of the smallest derivation of every non-terminal, as a
+    /// fixed point.
+    fn heights(grammar: &EarleyGrammar) -> Vec<Option<usize>> {
+        let mut heights = vec![None; grammar.nb_non_terminals()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for rule in grammar.rules().iter() {
+                let Some(height) = Self::rule_height(rule.elements.iter(), &heights) else {
+                    continue;
+                };
+                match &mut heights[rule.id.0] {
+                    Some(current) if *current <= height => {}
+                    current => {
+                        *current = Some(height);
+                        changed = true;
+                    }
+                }
+            }
+        }
+        heights
+    }
+
+    fn rule_height<'b>(
+        elements: impl Iterator<Item = &'b Element>,
+        heights: &[Option<usize>],
+    ) -> Option<usize> {
+        let mut height = 1;
+        for element in elements {
+            if let ElementType::NonTerminal(id) = element.element_type {
+                height = height.max(heights[id.0]? + 1);
+            }
+        }
+        Some(height)
+    }
+
+    /// Stop picking rules freely past `max_depth` nested non-terminals.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Give the tokens of the terminal `name` one of `lexemes`, rather than its name.
+    pub fn with_lexemes(
+        mut self,
+        name: &str,
+        lexemes: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<Self> {
+        let Some(id) = self.lexer_grammar.id(name) else {
+            return ErrorKind::UnknownTerminal {
+                name: name.to_string(),
+            }
+            .err();
+        };
+        self.lexemes
+            .insert(id, lexemes.into_iter().map(Into::into).collect());
+        Ok(self)
+    }
+
+    /// Generate the AST described by `choices`, or `None` if no axiom derives
+    /// anything.
+    pub fn from_choices(&self, choices: &[u32]) -> Option<AST> {
+        self.generate(&mut Replay {
+            choices: choices.iter(),
+        })
+    }
+
+    /// Generate an AST at random, from `seed`.
+    pub fn random(&self, seed: u64) -> Option<AST> {
+        // Xorshift never leaves the zero state.
+        self.generate(&mut Random {
+            state: seed.max(1),
+        })
+    }
+
+    /// Generate a sentence of the grammar at random, from `seed`, or `None` if no
+    /// axiom derives anything. The lexemes of its tokens are separated by a space if
+    /// the lexer skips it, and follow each other otherwise.
+    pub fn sentence(&self, seed: u64) -> Option<String> {
+        let mut choices = Random {
+            state: seed.max(1),
+        };
+        let axiom = self.axiom(&mut choices)?;
+        let mut lexemes = Vec::new();
+        self.sentence_of(axiom, 0, &mut choices, &mut lexemes);
+        let separator = if self.lexer_grammar.skips(" ") { " " } else { "" };
+        Some(lexemes.join(separator))
+    }
+
+    fn generate(&self, choices: &mut impl Choices) -> Option<AST> {
+        let axiom = self.axiom(choices)?;
+        Some(self.non_terminal(axiom, 0, &HashMap::new(), choices))
+    }
+
+    /// Pick one of the axioms that derive something.
+    fn axiom(&self, choices: &mut impl Choices) -> Option<NonTerminalId> {
+        let axioms = (0..self.grammar.nb_non_terminals())
+            .map(NonTerminalId)
+            .filter(|&id| self.grammar.is_axiom(id) && self.heights[id.0].is_some())
+            .collect::<Vec<_>>();
+        if axioms.is_empty() {
+            return None;
+        }
+        Some(axioms[choices.choose(axioms.len())])
+    }
+
+    /// The rules to pick from to derive `id` at `depth`, the smallest first.
+    fn candidates(&self, id: NonTerminalId, depth: usize) -> Vec<(usize, RuleId)> {
+        let mut candidates = self
+            .grammar
+            .rules_of(id)
+            .iter()
+            .filter_map(|&rule| {
+                let elements = self.grammar.rules()[rule].elements.iter();
+                Some((Self::rule_height(elements, &self.heights)?, rule))
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+        if depth >= self.max_depth {
+            let smallest = candidates[0].0;
+            candidates.retain(|&(height, _)| height == smallest);
+        }
+        candidates
+    }
+
+    fn non_terminal(
+        &self,
+        id: NonTerminalId,
+        depth: usize,
+        inherited: &HashMap<Symbol, AST>,
+        choices: &mut impl Choices,
+    ) -> AST {
+        let candidates = self.candidates(id, depth);
+        let (_, rule) = candidates[choices.choose(candidates.len())];
+        let rule = &self.grammar.rules()[rule];
+        let mut children = Vec::with_capacity(rule.elements.len());
+        for (position, element) in rule.elements.iter().enumerate() {
+            let child = match element.element_type {
+                ElementType::NonTerminal(id) => {
+                    let inherited =
+                        rule.inherited_by(position, &children, inherited, &self.span);
+                    self.non_terminal(id, depth + 1, &inherited, choices)
+                }
+                ElementType::Terminal(id) => self.terminal(id, &element.attribute, choices),
+            };
+            children.push(child);
+        }
+        rule.build_node_inheriting(children, self.span.clone(), inherited)
+    }
+
+    /// Push the lexemes of a derivation of `id` at `depth` to `lexemes`.
+    fn sentence_of(
+        &self,
+        id: NonTerminalId,
+        depth: usize,
+        choices: &mut impl Choices,
+        lexemes: &mut Vec<String>,
+    ) {
+        let candidates = self.candidates(id, depth);
+        let (_, rule) = candidates[choices.choose(candidates.len())];
+        for element in self.grammar.rules()[rule].elements.iter() {
+            match element.element_type {
+                ElementType::NonTerminal(id) => {
+                    self.sentence_of(id, depth + 1, choices, lexemes)
+                }
+                ElementType::Terminal(id) => {
+                    let lexeme = self.registered_lexeme(id, choices).unwrap_or_else(|| {
+                        self.lexer_grammar
+                            .sample(id, &mut |bound| choices.choose(bound))
+                            .unwrap_or_else(|| self.lexer_grammar.name(id).to_string())
+                    });
+                    lexemes.push(lexeme);
+                }
+            }
+        }
+    }
+
+    /// Pick one of the lexemes registered for `id`, if there are any.
+    fn registered_lexeme(&self, id: TerminalId, choices: &mut impl Choices) -> Option<String> {
+        match self.lexemes.get(&id) {
+            Some(lexemes) if !lexemes.is_empty() => {
+                Some(lexemes[choices.choose(lexemes.len())].clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn terminal(
+        &self,
+        id: TerminalId,
+        attribute: &Attribute,
+        choices: &mut impl Choices,
+    ) -> AST {
+        let name = self.lexer_grammar.symbol(id);
+        let lexeme = self
+            .registered_lexeme(id, choices)
+            .unwrap_or_else(|| name.to_string());
+        let mut attributes = HashMap::from([(0, lexeme.clone())]);
+        if let Attribute::Indexed(index) = attribute {
+            attributes.insert(*index, lexeme);
+        }
+        AST::Terminal(Token::new(name, id, attributes, self.span.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Buildable;
+    use crate::lexer::Lexer;
+    use crate::parser::{earley::EarleyParser, Parser, Value};
+    use crate::stream::StringStream;
+
+    const LEXER: &str = "NUMBER ::= ([0-9]+)\nPM ::= [-+]\nLPAR ::= \\(\nRPAR ::= \\)";
+
+    const GRAMMAR: &str = r#"
+@Expr ::=
+  NUMBER.0@value <Literal>
+  Expr@left PM Expr@right <Operation>
+  LPAR Expr@value RPAR <Parenthesized>;
+"#;
+
+    fn depth(ast: &AST) -> usize {
+        match ast {
+            AST::Node { attributes, .. } => {
+                1 + attributes.values().map(depth).max().unwrap_or(0)
+            }
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn generate() {
+        let lexer =
+            Lexer::build_from_plain(StringStream::new(Path::new("<lexer input>"), LEXER))
+                .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), GRAMMAR),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let generator = AstGenerator::new(&grammar, lexer.grammar())
+            .with_max_depth(4)
+            .with_lexemes("NUMBER", ["0", "42"])
+            .unwrap();
+        assert!(AstGenerator::new(&grammar, lexer.grammar())
+            .with_lexemes("NOPE", ["0"])
+            .is_err());
+
+        let smallest = generator.from_choices(&[]).unwrap();
+        let AST::Node { attributes, .. } = &smallest else {
+            panic!("expected a node");
+        };
+        assert!(matches!(
+            &attributes["variant"],
+            AST::Literal { value: Value::Str(variant), .. } if &**variant == "Literal"
+        ));
+        assert!(matches!(
+            &attributes["value"],
+            AST::Literal { value: Value::Str(value), .. } if &**value == "0"
+        ));
+        assert_eq!(generator.from_choices(&[0, 1]), generator.from_choices(&[0, 1]));
+
+        for seed in 0..64 {
+            let ast = generator.random(seed).unwrap();
+            assert!(depth(&ast) <= 5);
+        }
+    }
+
+    #[test]
+    fn sentence() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            format!("{LEXER}\nignore SPACE ::= \\s+"),
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), GRAMMAR),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let generator = AstGenerator::new(&grammar, lexer.grammar()).with_max_depth(4);
+        assert_eq!(generator.sentence(7), generator.sentence(7));
+        let sentences: Vec<_> = (0..32).map(|seed| generator.sentence(seed).unwrap()).collect();
+        let parser = EarleyParser::new(grammar);
+        for sentence in sentences {
+            assert!(!sentence.contains("NUMBER"));
+            let mut stream = StringStream::new(Path::new("<input>"), sentence.as_str());
+            assert!(parser.parse(&mut lexer.lex(&mut stream)).is_ok(), "{sentence}");
+        }
+    }
+}
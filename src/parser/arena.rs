@@ -0,0 +1,206 @@
+//! # Arena
+//!
+//! ASTs stored in a single arena, rather than as nested maps. Nodes refer to
+//! their attributes through [`NodeId`] handles, and the attributes of every node
+//! are laid out in one buffer, so that building an AST does not allocate a map per
+//! node, and values that proxies move from a node to another are shared rather
+//! than cloned. [`AstArena::to_ast`] converts a node into the owned [`AST`].
+
+use super::parser::{NonTerminalId, Value, AST};
+use crate::interner::Symbol;
+use crate::lexer::Token;
+use crate::span::Span;
+use newty::newty;
+use std::ops::Range;
+
+newty! {
+    pub id NodeId
+}
+
+/// A node of an [`AstArena`], which mirrors [`AST`]. The attributes of a
+/// [`ArenaNode::Node`] are given by [`AstArena::attributes`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArenaNode {
+    Node {
+        nonterminal: NonTerminalId,
+        span: Span,
+    },
+    Literal {
+        value: Value,
+        span: Option<Span>,
+    },
+    Terminal(Token),
+}
+
+/// # Summary
+///
+/// [`AstArena`] holds the nodes of ASTs. A node may be the attribute of several
+/// others, in which case it is cloned once for each of them when converted into
+/// an [`AST`]. Clearing the arena keeps its memory for the next ASTs.
+#[derive(Debug, Clone, Default)]
+pub struct AstArena {
+    /// The nodes, with the range of their attributes in `attributes`.
+    nodes: Vec<(ArenaNode, Range<usize>)>,
+    attributes: Vec<(Symbol, NodeId)>,
+}
+
+impl AstArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Remove every node, keeping the memory allocated.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.attributes.clear();
+    }
+
+    pub fn node(&self, id: NodeId) -> &ArenaNode {
+        &self.nodes[id.0].0
+    }
+
+    /// The attributes of the node `id`, which only nodes of non-terminals have.
+    pub fn attributes(&self, id: NodeId) -> &[(Symbol, NodeId)] {
+        &self.attributes[self.nodes[id.0].1.clone()]
+    }
+
+    /// The attribute `key` of the node `id`, if it has one.
+    pub fn attribute(&self, id: NodeId, key: &str) -> Option<NodeId> {
+        self.attributes(id)
+            .iter()
+            .find(|(name, _)| name.as_str() == key)
+            .map(|&(_, value)| value)
+    }
+
+    fn push(&mut self, node: ArenaNode, attributes: Range<usize>) -> NodeId {
+        self.nodes.push((node, attributes));
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Add the node of `nonterminal`, with `attributes`, which should have
+    /// distinct keys.
+    pub fn push_node(
+        &mut self,
+        nonterminal: NonTerminalId,
+        attributes: impl IntoIterator<Item = (Symbol, NodeId)>,
+        span: Span,
+    ) -> NodeId {
+        let start = self.attributes.len();
+        self.attributes.extend(attributes);
+        let end = self.attributes.len();
+        self.push(ArenaNode::Node { nonterminal, span }, start..end)
+    }
+
+    pub fn push_literal(&mut self, value: Value, span: Option<Span>) -> NodeId {
+        self.push(ArenaNode::Literal { value, span }, 0..0)
+    }
+
+    pub fn push_terminal(&mut self, token: Token) -> NodeId {
+        self.push(ArenaNode::Terminal(token), 0..0)
+    }
+
+    /// Move the owned `ast` into the arena.
+    pub fn alloc(&mut self, ast: AST) -> NodeId {
+        match ast {
+            AST::Node {
+                nonterminal,
+                attributes,
+                span,
+            } => {
+                let attributes = attributes
+                    .into_iter()
+                    .map(|(key, value)| (key, self.alloc(value)))
+                    .collect::<Vec<_>>();
+                self.push_node(nonterminal, attributes, span)
+            }
+            AST::Literal { value, span } => self.push_literal(value, span),
+            AST::Terminal(token) => self.push_terminal(token),
+        }
+    }
+
+    /// Convert the node `id` into an owned [`AST`].
+    pub fn to_ast(&self, id: NodeId) -> AST {
+        match self.node(id) {
+            ArenaNode::Node { nonterminal, span } => AST::Node {
+                nonterminal: *nonterminal,
+                attributes: self
+                    .attributes(id)
+                    .iter()
+                    .map(|&(key, value)| (key, self.to_ast(value)))
+                    .collect(),
+                span: span.clone(),
+            },
+            ArenaNode::Literal { value, span } => AST::Literal {
+                value: value.clone(),
+                span: span.clone(),
+            },
+            ArenaNode::Terminal(token) => AST::Terminal(token.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Buildable;
+    use crate::lexer::Lexer;
+    use crate::parser::earley::{EarleyGrammar, EarleyParser};
+    use crate::parser::Parser;
+    use crate::stream::StringStream;
+    use std::path::Path;
+
+    #[test]
+    fn arena() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "NUMBER ::= ([0-9]+)\nMINUS ::= -",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), "@Expr ::=
+  NUMBER.0@value <Literal>
+  MINUS Expr@right <Operation, left: Expr {Literal, value: \"0\"}>;"),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let mut stream = StringStream::new(Path::new("<input>"), "-1");
+        let mut input = lexer.lex(&mut stream);
+        let (table, raw_input) = parser.recognise(&mut input).unwrap();
+        let forest = parser.to_forest(&table, &raw_input).unwrap();
+        let mut arena = AstArena::new();
+        let root = parser
+            .select_arena(&forest, &raw_input, input.last_span(), &mut arena)
+            .unwrap();
+
+        let value = |id: NodeId| match arena.node(arena.attribute(id, "value").unwrap()) {
+            ArenaNode::Literal {
+                value: Value::Str(value),
+                ..
+            } => value.to_string(),
+            node => panic!("expected a literal, got {node:?}"),
+        };
+        assert_eq!(value(arena.attribute(root, "left").unwrap()), "0");
+        let right = arena.attribute(root, "right").unwrap();
+        assert_eq!(value(right), "1");
+        let value = arena.attribute(right, "value").unwrap();
+        assert!(arena.attribute(root, "nope").is_none());
+        assert!(arena.attributes(value).is_empty());
+
+        let ast = arena.to_ast(root);
+        let mut stream = StringStream::new(Path::new("<input>"), "-1");
+        assert_eq!(ast, parser.parse(&mut lexer.lex(&mut stream)).unwrap().tree);
+        let copy = arena.alloc(ast.clone());
+        assert_eq!(arena.to_ast(copy), ast);
+        arena.clear();
+        assert!(arena.is_empty());
+    }
+}
@@ -1,6 +1,6 @@
 use std::{collections::HashMap, rc::Rc};
 
-use super::AST;
+use super::{earley::RulePreference, AST};
 use crate::{
     error::{ErrorKind, Result},
     span::Span,
@@ -31,6 +31,10 @@ impl Tree for Ast {
 pub(super) enum ToplevelDeclaration {
     Decl(Box<Declaration>),
     Macro(Box<MacroDeclaration>),
+    Prefer(RulePreference),
+    Mode(Box<LexerModeDeclaration>),
+    Version(LanguageVersionDeclaration),
+    Precedence(PrecedenceTable),
 }
 
 impl Tree for Spanned<ToplevelDeclaration> {
@@ -39,6 +43,12 @@ impl Tree for Spanned<ToplevelDeclaration> {
         Ok(match_variant! {(node) {
             Decl => ToplevelDeclaration::decl(get!(node => decl).to_tree()?),
             Macro => ToplevelDeclaration::r#macro(get!(node => decl).to_tree()?),
+            Prefer => ToplevelDeclaration::Prefer(
+                get!(node => preference).to_tree::<Spanned<_>>()?.inner
+            ),
+            Mode => ToplevelDeclaration::Mode(Box::new(get!(node => mode).to_tree()?)),
+            Version => ToplevelDeclaration::Version(get!(node => version).to_tree()?),
+            Precedence => ToplevelDeclaration::Precedence(get!(node => table).to_tree()?),
         }})
     }
 
@@ -57,10 +67,125 @@ impl ToplevelDeclaration {
     }
 }
 
+impl Tree for Spanned<RulePreference> {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(match_variant! {(node) {
+            First => RulePreference::First,
+            Last => RulePreference::Last,
+        }})
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct LexerModeDeclaration {
+    pub name: Spanned<Rc<str>>,
+    pub terminals: Vec<Spanned<Rc<str>>>,
+    pub span: Span,
+}
+
+impl Tree for LexerModeDeclaration {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self {
+            name: spanned_value!(node => name),
+            terminals: get!(node => terminals)
+                .to_tree::<Spanned<Vec<_>>>()?
+                .inner
+                .into_iter()
+                .map(|terminal: TerminalName| terminal.0)
+                .collect(),
+            span: span!(node),
+        })
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct LanguageVersionDeclaration(pub Spanned<Rc<str>>);
+
+impl Tree for LanguageVersionDeclaration {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self(spanned_value!(node => version)))
+    }
+
+    fn span(&self) -> &Span {
+        &self.0.span
+    }
+}
+
+/// `precedence { left PLUS MINUS; left '*'; }`, whose levels bind their operators
+/// more and more tightly.
+#[derive(Debug, Clone)]
+pub(super) struct PrecedenceTable {
+    pub levels: Vec<PrecedenceLevel>,
+    pub span: Span,
+}
+
+impl Tree for PrecedenceTable {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self {
+            levels: get!(node => levels).to_tree::<Spanned<_>>()?.inner,
+            span: span!(node),
+        })
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct PrecedenceLevel {
+    pub assoc: Spanned<Associativity>,
+    /// The terminals of the level, as names or as literals.
+    pub operators: Vec<Spanned<Item>>,
+    pub span: Span,
+}
+
+impl Tree for PrecedenceLevel {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self {
+            assoc: get!(node => assoc).to_tree()?,
+            operators: get!(node => operators).to_tree::<Spanned<_>>()?.inner,
+            span: span!(node),
+        })
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct TerminalName(Spanned<Rc<str>>);
+
+impl Tree for TerminalName {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self(spanned_value!(node => name)))
+    }
+
+    fn span(&self) -> &Span {
+        &self.0.span
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(super) struct MacroDeclaration {
     pub name: Spanned<Rc<str>>,
     pub args: Vec<Spanned<Rc<str>>>,
+    /// The rules as written, before [`expand_optionals`].
     pub rules: Vec<Rule>,
     pub span: Span,
 }
@@ -114,11 +239,28 @@ impl Tree for Comment {
     }
 }
 
+#[derive(Debug, Clone)]
+pub(super) struct ModeSpecifier(Spanned<Rc<str>>);
+
+impl Tree for ModeSpecifier {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self(spanned_value!(node => name)))
+    }
+
+    fn span(&self) -> &Span {
+        &self.0.span
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(super) struct Declaration {
     pub comment: Option<Spanned<Rc<str>>>,
     pub axiom: Spanned<bool>,
     pub name: Spanned<Rc<str>>,
+    /// The lexer mode the non-terminal is parsed in, if any.
+    pub mode: Option<Spanned<Rc<str>>>,
+    /// The rules as written, before [`expand_optionals`].
     pub rules: Vec<Rule>,
     pub span: Span,
 }
@@ -132,6 +274,10 @@ impl Tree for Declaration {
                 .transpose()
                 .map(|x| x.map(|y| y.0).merge()),
             axiom: get!(node => axiom).to_tree()?,
+            mode: get!(node => mode)
+                .to_tree::<Spanned<Option<ModeSpecifier>>>()?
+                .transpose()
+                .map(|x| x.map(|y| y.0).merge()),
             rules: get!(node => rules).to_tree::<Spanned<_>>()?.inner,
             name: spanned_value!(node => name),
             span: span!(node),
@@ -148,6 +294,10 @@ pub(super) struct Rule {
     pub elements: Vec<Element>,
     pub proxy: Proxy,
     pub left_associative: Option<Spanned<Associativity>>,
+    /// The weight of the rule, in `(weight N)`.
+    pub weight: Option<Weight>,
+    /// The name of the predicate of the rule, in `&{name}`.
+    pub predicate: Option<Spanned<Rc<str>>>,
     pub span: Span,
 }
 
@@ -158,6 +308,107 @@ impl Tree for Rule {
             elements: get!(node => elements).to_tree::<Spanned<_>>()?.inner,
             proxy: get!(node => proxy).to_tree()?,
             left_associative: get!(node => assoc).to_tree::<Spanned<_>>()?.inner,
+            weight: get!(node => weight).to_tree::<Spanned<_>>()?.inner,
+            predicate: get!(node => predicate)
+                .to_tree::<Spanned<Option<Predicate>>>()?
+                .inner
+                .map(|predicate| predicate.0),
+            span: span!(node),
+        })
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+/// Replace every rule that has optional elements by one rule for each choice of
+/// the elements that are present, so each optional element doubles the rules.
+/// The keys of the elements left out get their default values through the proxy.
+pub(super) fn expand_optionals(rules: Vec<Rule>) -> Result<Vec<Rule>> {
+    let mut expanded = Vec::with_capacity(rules.len());
+    for rule in rules {
+        let mut variants = vec![Rule {
+            elements: Vec::with_capacity(rule.elements.len()),
+            ..rule.clone()
+        }];
+        for mut element in rule.elements {
+            if let Some(default) = &element.default {
+                if !element.optional.inner {
+                    return ErrorKind::GrammarDefaultNotOptional {
+                        span: default.span.clone().into(),
+                    }
+                    .err();
+                } else if element.key.is_none() {
+                    return ErrorKind::GrammarDefaultWithoutKey {
+                        span: default.span.clone().into(),
+                    }
+                    .err();
+                }
+            }
+            if !element.optional.inner {
+                for variant in &mut variants {
+                    variant.elements.push(element.clone());
+                }
+                continue;
+            }
+            let default = element.default.take();
+            element.optional.inner = false;
+            let mut without = variants.clone();
+            if let (Some(default), Some(Key(key))) = (default, &element.key) {
+                for variant in &mut without {
+                    if let Some((_, old_span)) = variant
+                        .proxy
+                        .items
+                        .insert(key.inner.clone(), (default.clone(), key.span.clone()))
+                    {
+                        return ErrorKind::GrammarDuplicateProxyItem {
+                            name: key.inner.to_string(),
+                            span: key.span.clone().into(),
+                            old_span: old_span.into(),
+                        }
+                        .err();
+                    }
+                }
+            }
+            for variant in &mut variants {
+                variant.elements.push(element.clone());
+            }
+            variants.extend(without);
+        }
+        expanded.extend(variants);
+    }
+    Ok(expanded)
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Predicate(Spanned<Rc<str>>);
+
+impl Tree for Predicate {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self(spanned_value!(node => name)))
+    }
+
+    fn span(&self) -> &Span {
+        &self.0.span
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Weight {
+    /// The name of the annotation, which has to be `weight`.
+    pub name: Spanned<Rc<str>>,
+    pub value: Spanned<Rc<str>>,
+    pub span: Span,
+}
+
+impl Tree for Weight {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self {
+            name: spanned_value!(node => name),
+            value: spanned_value!(node => value),
             span: span!(node),
         })
     }
@@ -196,8 +447,15 @@ impl From<Associativity> for bool {
 #[derive(Debug, Clone)]
 pub(super) struct Element {
     pub item: Spanned<Item>,
+    /// The attributes given to the element, in `Item{key: value, ...}`.
+    pub inherited: HashMap<Rc<str>, (Spanned<Expression>, Span)>,
+    /// Whether the element can be left out, in `Item?`.
+    pub optional: Spanned<bool>,
     pub attribute: Option<Attribute>,
     pub key: Option<Key>,
+    /// The value of the key when the element is left out, in
+    /// `Item?@key = default(value)`.
+    pub default: Option<Spanned<Expression>>,
     pub span: Span,
 }
 
@@ -206,8 +464,17 @@ impl Tree for Element {
         let mut node = node!(ast);
         Ok(Self {
             item: get!(node => item).to_tree()?,
+            inherited: get!(node => inherited)
+                .to_tree::<Spanned<Option<Inherited>>>()?
+                .inner
+                .map_or_else(HashMap::new, |inherited| inherited.items),
+            optional: get!(node => optional).to_tree()?,
             attribute: get!(node => attribute).to_tree::<Spanned<_>>()?.inner,
             key: get!(node => key).to_tree::<Spanned<_>>()?.inner,
+            default: get!(node => default)
+                .to_tree::<Spanned<Option<DefaultValue>>>()?
+                .inner
+                .map(|default| default.0),
             span: span!(node),
         })
     }
@@ -223,10 +490,24 @@ pub(super) enum Item {
     Regular {
         name: Spanned<Rc<str>>,
     },
+    /// `!t NAME`, which refers to the terminal `NAME` even if a non-terminal has
+    /// the same name.
+    Terminal {
+        name: Spanned<Rc<str>>,
+    },
+    /// `!n NAME`, which refers to the non-terminal `NAME` even if a terminal has
+    /// the same name.
+    NonTerminal {
+        name: Spanned<Rc<str>>,
+    },
     MacroInvocation {
         name: Spanned<Rc<str>>,
         arguments: Vec<Spanned<Item>>,
     },
+    /// `'TEXT'`, which refers to the terminal that lexes `TEXT` as a single token.
+    Literal {
+        text: Spanned<Rc<str>>,
+    },
 }
 
 impl Tree for Spanned<Item> {
@@ -235,6 +516,9 @@ impl Tree for Spanned<Item> {
         Ok(match_variant! {(node) {
             SelfNonTerminal => Item::SelfNonTerminal,
             Regular => Item::Regular { name: spanned_value!(node => name) },
+            Terminal => Item::Terminal { name: spanned_value!(node => name) },
+            NonTerminal => Item::NonTerminal { name: spanned_value!(node => name) },
+            Literal => Item::Literal { text: spanned_value!(node => text) },
             MacroInvocation => Item::MacroInvocation {
 		name: spanned_value!(node => name),
 		arguments: get!(node => args).to_tree::<Spanned<_>>()?.inner,
@@ -273,6 +557,76 @@ impl Tree for Attribute {
     }
 }
 
+#[derive(Debug, Clone)]
+struct Inherited {
+    items: HashMap<Rc<str>, (Spanned<Expression>, Span)>,
+    span: Span,
+}
+
+impl Tree for Inherited {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        let vec_items: Vec<InheritedItem> = get!(node => items).to_tree::<Spanned<_>>()?.inner;
+        let mut items = HashMap::new();
+        for InheritedItem { key, value, .. } in vec_items {
+            if let Some((_, old_span)) =
+                items.insert(key.inner.clone(), (value, key.span.clone()))
+            {
+                return ErrorKind::GrammarDuplicateProxyItem {
+                    name: key.inner.to_string(),
+                    span: key.span.into(),
+                    old_span: old_span.into(),
+                }
+                .err();
+            }
+        }
+        Ok(Self {
+            items,
+            span: span!(node),
+        })
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+#[derive(Debug, Clone)]
+struct InheritedItem {
+    key: Spanned<Rc<str>>,
+    value: Spanned<Expression>,
+    span: Span,
+}
+
+impl Tree for InheritedItem {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self {
+            key: spanned_value!(node => key),
+            value: get!(node => value).to_tree()?,
+            span: span!(node),
+        })
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+#[derive(Debug, Clone)]
+struct DefaultValue(Spanned<Expression>);
+
+impl Tree for DefaultValue {
+    fn read(ast: AST) -> Result<Self> {
+        let mut node = node!(ast);
+        Ok(Self(get!(node => value).to_tree()?))
+    }
+
+    fn span(&self) -> &Span {
+        &self.0.span
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(super) struct Key(pub Spanned<Rc<str>>);
 
@@ -357,6 +711,7 @@ impl Tree for Spanned<ProxyItem> {
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
 pub(super) enum Expression {
     String(Rc<str>),
     Id(Rc<str>),
@@ -0,0 +1,668 @@
+//! # Codegen
+//!
+//! Generation of Rust code specific to a parser grammar. [`generate_visitor`]
+//! writes a visitor trait with a method per non-terminal and per variant, and
+//! [`generate_types`] writes a type per non-terminal, so that the users of a
+//! grammar need not match on [`AST::Node`](super::AST) by hand.
+
+use super::earley::EarleyGrammar;
+use super::grammar::{Attribute, ElementType, Rule, ValueTemplate};
+use super::parser::NonTerminalId;
+use crate::interner::Symbol;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+
+/// Convert the name of a non-terminal or of a variant to a snake case
+/// identifier, replacing everything that is not alphanumeric by underscores.
+fn snake_case(name: &str) -> String {
+    let mut result = String::new();
+    let mut previous_lower = false;
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            if c.is_ascii_uppercase() && previous_lower {
+                result.push('_');
+            }
+            result.push(c.to_ascii_lowercase());
+            previous_lower = !c.is_ascii_uppercase();
+        } else {
+            if !result.is_empty() && !result.ends_with('_') {
+                result.push('_');
+            }
+            previous_lower = false;
+        }
+    }
+    while result.ends_with('_') {
+        result.pop();
+    }
+    result
+}
+
+/// Pick a method name for `name`, which does not collide with the ones in
+/// `taken`, and mark it as taken.
+fn method_name(name: &str, taken: &mut HashSet<String>) -> String {
+    let base = format!("visit_{}", snake_case(name));
+    let mut method = base.clone();
+    let mut suffix = 1;
+    while !taken.insert(method.clone()) {
+        suffix += 1;
+        method = format!("{base}_{suffix}");
+    }
+    method
+}
+
+/// The variants of the rules of `id`, in the order of the rules.
+fn variants_of(grammar: &EarleyGrammar, id: NonTerminalId) -> Vec<&str> {
+    let mut variants = Vec::new();
+    for &rule in grammar.rules_of(id) {
+        let variant = grammar.rules()[rule].proxy.get("variant");
+        if let Some(ValueTemplate::String(variant)) = variant {
+            if !variants.contains(&&**variant) {
+                variants.push(&**variant);
+            }
+        }
+    }
+    variants
+}
+
+/// Generate the source of a Rust module that declares a `Visitor` trait for the
+/// ASTs of `grammar`, along with the `walk` and `walk_attributes` functions
+/// that dispatch nodes to its methods.
+///
+/// Each non-terminal gets a method, and so does each variant of its rules. By
+/// default, a method visits the attributes of the node, so implementations
+/// only override the ones they care about. Nodes are dispatched on the
+/// identifier of their non-terminal, so the module must be generated again
+/// whenever the grammar changes.
+pub fn generate_visitor(grammar: &EarleyGrammar) -> String {
+    let mut code = String::new();
+    write_visitor(&mut code, grammar).expect("writing to a string cannot fail");
+    code
+}
+
+fn write_visitor(code: &mut String, grammar: &EarleyGrammar) -> std::fmt::Result {
+    let mut taken = ["visit", "visit_terminal", "visit_literal"]
+        .into_iter()
+        .map(String::from)
+        .collect::<HashSet<_>>();
+    let nonterminals = (0..grammar.nb_non_terminals())
+        .map(NonTerminalId)
+        .map(|id| {
+            let name = grammar.name_of(id);
+            let method = method_name(&name, &mut taken);
+            let variants = variants_of(grammar, id)
+                .into_iter()
+                .map(|variant| {
+                    let method = method_name(&format!("{name} {variant}"), &mut taken);
+                    (variant, method)
+                })
+                .collect::<Vec<_>>();
+            (id, name, method, variants)
+        })
+        .collect::<Vec<_>>();
+
+    writeln!(code, "// Generated by `beans codegen`, do not edit.")?;
+    writeln!(code)?;
+    writeln!(code, "use beans::interner::Symbol;")?;
+    writeln!(code, "use beans::lexer::Token;")?;
+    writeln!(code, "use beans::parser::{{Value, AST}};")?;
+    writeln!(code, "use beans::span::Span;")?;
+    writeln!(code, "use std::collections::HashMap;")?;
+    writeln!(code)?;
+    writeln!(code, "/// The attributes of a node.")?;
+    writeln!(code, "pub type Attributes = HashMap<Symbol, AST>;")?;
+    writeln!(code)?;
+    writeln!(
+        code,
+        "/// Visit the ASTs of the grammar. Every method visits the attributes of the"
+    )?;
+    writeln!(code, "/// node by default.")?;
+    writeln!(code, "pub trait Visitor {{")?;
+    writeln!(code, "    fn visit(&mut self, ast: &AST) {{")?;
+    writeln!(code, "        walk(self, ast)")?;
+    writeln!(code, "    }}")?;
+    writeln!(code)?;
+    writeln!(code, "    fn visit_terminal(&mut self, _token: &Token) {{}}")?;
+    writeln!(code)?;
+    writeln!(
+        code,
+        "    fn visit_literal(&mut self, _value: &Value, _span: Option<&Span>) {{}}"
+    )?;
+    for (_, name, method, variants) in &nonterminals {
+        writeln!(code)?;
+        writeln!(code, "    /// Visit a node of `{name}`.")?;
+        if variants.is_empty() {
+            writeln!(
+                code,
+                "    fn {method}(&mut self, attributes: &Attributes, _span: &Span) {{"
+            )?;
+            writeln!(code, "        walk_attributes(self, attributes)")?;
+            writeln!(code, "    }}")?;
+            continue;
+        }
+        writeln!(code, "    fn {method}(&mut self, attributes: &Attributes, span: &Span) {{")?;
+        writeln!(code, "        match variant(attributes) {{")?;
+        for (variant, variant_method) in variants {
+            writeln!(
+                code,
+                "            Some({variant:?}) => self.{variant_method}(attributes, span),"
+            )?;
+        }
+        writeln!(code, "            _ => walk_attributes(self, attributes),")?;
+        writeln!(code, "        }}")?;
+        writeln!(code, "    }}")?;
+        for (variant, variant_method) in variants {
+            writeln!(code)?;
+            writeln!(code, "    /// Visit a node of `{name}`, of variant `{variant}`.")?;
+            writeln!(
+                code,
+                "    fn {variant_method}(&mut self, attributes: &Attributes, _span: &Span) {{"
+            )?;
+            writeln!(code, "        walk_attributes(self, attributes)")?;
+            writeln!(code, "    }}")?;
+        }
+    }
+    writeln!(code, "}}")?;
+    writeln!(code)?;
+    writeln!(code, "/// Call the method of `visitor` that matches `ast`.")?;
+    writeln!(code, "pub fn walk<V: Visitor + ?Sized>(visitor: &mut V, ast: &AST) {{")?;
+    writeln!(code, "    match ast {{")?;
+    writeln!(code, "        AST::Node {{")?;
+    writeln!(code, "            nonterminal,")?;
+    writeln!(code, "            attributes,")?;
+    writeln!(code, "            span,")?;
+    writeln!(code, "        }} => match nonterminal.0 {{")?;
+    for (id, _, method, _) in &nonterminals {
+        writeln!(
+            code,
+            "            {} => visitor.{method}(attributes, span),",
+            id.0
+        )?;
+    }
+    writeln!(code, "            _ => walk_attributes(visitor, attributes),")?;
+    writeln!(code, "        }},")?;
+    writeln!(
+        code,
+        "        AST::Literal {{ value, span }} => visitor.visit_literal(value, span.as_ref()),"
+    )?;
+    writeln!(code, "        AST::Terminal(token) => visitor.visit_terminal(token),")?;
+    writeln!(code, "    }}")?;
+    writeln!(code, "}}")?;
+    writeln!(code)?;
+    writeln!(code, "/// Visit the attributes of a node, sorted by name.")?;
+    writeln!(
+        code,
+        "pub fn walk_attributes<V: Visitor + ?Sized>(visitor: &mut V, attributes: &Attributes) {{"
+    )?;
+    writeln!(code, "    let mut attributes = attributes.iter().collect::<Vec<_>>();")?;
+    writeln!(code, "    attributes.sort_unstable_by_key(|(name, _)| *name);")?;
+    writeln!(code, "    for (_, value) in attributes {{")?;
+    writeln!(code, "        visitor.visit(value);")?;
+    writeln!(code, "    }}")?;
+    writeln!(code, "}}")?;
+    writeln!(code)?;
+    writeln!(code, "#[allow(dead_code)]")?;
+    writeln!(code, "fn variant(attributes: &Attributes) -> Option<&str> {{")?;
+    writeln!(code, "    match attributes.get(\"variant\")? {{")?;
+    writeln!(
+        code,
+        "        AST::Literal {{ value: Value::Str(variant), .. }} => Some(&**variant),"
+    )?;
+    writeln!(code, "        _ => None,")?;
+    writeln!(code, "    }}")?;
+    writeln!(code, "}}")
+}
+
+/// Convert the name of a non-terminal or of a variant to a Pascal case
+/// identifier, dropping everything that is not alphanumeric.
+fn pascal_case(name: &str) -> String {
+    let mut result = String::new();
+    for word in name.split(|c: char| !c.is_ascii_alphanumeric()) {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            result.push(first.to_ascii_uppercase());
+            result.extend(chars);
+        }
+    }
+    if !result.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        result.insert(0, 'T');
+    }
+    result
+}
+
+/// Pick a name based on `base` which is not in `taken`, and mark it as taken.
+fn fresh_name(base: String, taken: &mut HashSet<String>) -> String {
+    let mut name = base.clone();
+    let mut suffix = 1;
+    while !taken.insert(name.clone()) {
+        suffix += 1;
+        name = format!("{base}{suffix}");
+    }
+    name
+}
+
+/// The name of the field holding the attribute `name`, escaped if it is a
+/// keyword.
+fn field_name(name: &str, taken: &mut HashSet<String>) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else",
+        "enum", "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match",
+        "mod", "move", "mut", "pub", "ref", "return", "static", "struct", "trait", "true",
+        "try", "type", "unsafe", "use", "where", "while", "yield",
+    ];
+    let mut base = snake_case(name);
+    if base.is_empty() || base.starts_with(|c: char| c.is_ascii_digit()) {
+        base.insert_str(0, "field_");
+    }
+    let name = fresh_name(base, taken);
+    if KEYWORDS.contains(&name.as_str()) {
+        format!("r#{name}")
+    } else {
+        name
+    }
+}
+
+/// The type of an attribute, as far as the grammar tells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldType {
+    /// A node of the non-terminal.
+    Node(NonTerminalId),
+    Token,
+    Str,
+    /// Any AST, when the grammar does not tell.
+    Ast,
+}
+
+/// An attribute of the nodes built by some rules.
+#[derive(Debug)]
+struct Field {
+    attribute: Symbol,
+    field_type: FieldType,
+    /// Whether some of the rules leave the attribute out.
+    optional: bool,
+}
+
+/// Add the variables `template` refers to to `variables`.
+fn variables_of(template: &ValueTemplate, variables: &mut HashSet<Symbol>) {
+    match template {
+        ValueTemplate::String(_) => {}
+        ValueTemplate::Variable(name) => {
+            variables.insert(*name);
+        }
+        ValueTemplate::InlineRule { attributes, .. } => {
+            for template in attributes.values() {
+                variables_of(template, variables);
+            }
+        }
+    }
+}
+
+/// The attributes of the nodes `rule` builds, but its variant, and their types.
+fn attributes_of(rule: &Rule) -> BTreeMap<Symbol, FieldType> {
+    let keyed = rule
+        .elements
+        .iter()
+        .filter_map(|element| {
+            let key = element.key?;
+            let field_type = match (element.element_type, &element.attribute) {
+                (ElementType::NonTerminal(id), Attribute::None) => FieldType::Node(id),
+                (ElementType::Terminal(_), Attribute::None) => FieldType::Token,
+                (_, Attribute::Indexed(_)) => FieldType::Str,
+                (_, Attribute::Named(_)) => FieldType::Ast,
+            };
+            Some((key, field_type))
+        })
+        .collect::<HashMap<_, _>>();
+    let mut removed = HashSet::new();
+    for template in rule.proxy.values() {
+        variables_of(template, &mut removed);
+    }
+    let mut attributes = rule
+        .proxy
+        .iter()
+        .filter(|(key, _)| &***key != "variant")
+        .map(|(key, template)| {
+            let field_type = match template {
+                ValueTemplate::String(_) => FieldType::Str,
+                ValueTemplate::Variable(name) => {
+                    keyed.get(name).copied().unwrap_or(FieldType::Ast)
+                }
+                ValueTemplate::InlineRule { .. } => FieldType::Ast,
+            };
+            (*key, field_type)
+        })
+        .collect::<BTreeMap<_, _>>();
+    // Like when building nodes, the keyed elements that the proxy does not use
+    // take precedence.
+    attributes.extend(keyed.into_iter().filter(|(key, _)| !removed.contains(key)));
+    attributes
+}
+
+/// The fields of the nodes built by any of `rules`.
+fn fields_of(rules: &[&Rule]) -> Vec<Field> {
+    let attributes = rules.iter().map(|rule| attributes_of(rule)).collect::<Vec<_>>();
+    let names = attributes
+        .iter()
+        .flat_map(|attributes| attributes.keys().cloned())
+        .collect::<BTreeSet<_>>();
+    names
+        .into_iter()
+        .map(|name| {
+            let types = attributes
+                .iter()
+                .map(|attributes| attributes.get(&name).copied())
+                .collect::<Vec<_>>();
+            let mut found = types.iter().flatten();
+            let first = *found.next().unwrap();
+            let field_type = if found.all(|&other| other == first) {
+                first
+            } else {
+                FieldType::Ast
+            };
+            Field {
+                attribute: name,
+                field_type,
+                optional: types.contains(&None),
+            }
+        })
+        .collect()
+}
+
+/// Generate the source of a Rust module that declares a type per non-terminal
+/// of `grammar`, along with a conversion from the [`AST`](super::AST)s that
+/// the grammar builds.
+///
+/// A non-terminal whose rules all have a variant becomes an enum, with a
+/// variant per variant of the rules. The others become structs. Attributes
+/// that only some rules set are optional, and attributes whose type depends
+/// on the rule are left as ASTs. The conversions panic on ASTs that were not
+/// built by `grammar`.
+pub fn generate_types(grammar: &EarleyGrammar) -> String {
+    let mut code = String::new();
+    write_types(&mut code, grammar).expect("writing to a string cannot fail");
+    code
+}
+
+fn write_types(code: &mut String, grammar: &EarleyGrammar) -> std::fmt::Result {
+    let mut taken = [
+        "AST",
+        "Attributes",
+        "Box",
+        "HashMap",
+        "Option",
+        "Rc",
+        "Span",
+        "Symbol",
+        "Token",
+        "Value",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect::<HashSet<_>>();
+    let ids = (0..grammar.nb_non_terminals())
+        .map(NonTerminalId)
+        .collect::<Vec<_>>();
+    let type_names = ids
+        .iter()
+        .map(|&id| fresh_name(pascal_case(&grammar.name_of(id)), &mut taken))
+        .collect::<Vec<_>>();
+    let type_of = |field: &Field| {
+        let field_type = match field.field_type {
+            FieldType::Node(id) => format!("Box<{}>", type_names[id.0]),
+            FieldType::Token => "Token".to_string(),
+            FieldType::Str => "Rc<str>".to_string(),
+            FieldType::Ast => "AST".to_string(),
+        };
+        if field.optional {
+            format!("Option<{field_type}>")
+        } else {
+            field_type
+        }
+    };
+    let value_of = |field: &Field| {
+        let convert = |ast: &str| match field.field_type {
+            FieldType::Node(id) => format!("Box::new({}::from({ast}))", type_names[id.0]),
+            FieldType::Token => format!("token({ast})"),
+            FieldType::Str => format!("string({ast})"),
+            FieldType::Ast => ast.to_string(),
+        };
+        if field.optional {
+            format!(
+                "attributes.remove({:?}).map(|ast| {})",
+                field.attribute,
+                convert("ast")
+            )
+        } else {
+            convert(&format!("take(&mut attributes, {:?})", field.attribute))
+        }
+    };
+
+    writeln!(code, "// Generated by `beans compile ast`, do not edit.")?;
+    writeln!(code)?;
+    writeln!(code, "use beans::interner::Symbol;")?;
+    writeln!(code, "use beans::lexer::Token;")?;
+    writeln!(code, "use beans::parser::{{Value, AST}};")?;
+    writeln!(code, "use beans::span::Span;")?;
+    writeln!(code, "use std::collections::HashMap;")?;
+    writeln!(code, "use std::rc::Rc;")?;
+    writeln!(code)?;
+    writeln!(code, "type Attributes = HashMap<Symbol, AST>;")?;
+    for &id in &ids {
+        let name = grammar.name_of(id);
+        let type_name = &type_names[id.0];
+        let rules = grammar
+            .rules_of(id)
+            .iter()
+            .map(|&rule| &grammar.rules()[rule])
+            .collect::<Vec<_>>();
+        let mut variants: Vec<(&str, Vec<&Rule>)> = Vec::new();
+        let all_variants = rules.iter().all(|rule| {
+            let Some(ValueTemplate::String(variant)) = rule.proxy.get("variant") else {
+                return false;
+            };
+            match variants.iter_mut().find(|(other, _)| *other == &**variant) {
+                Some((_, variant_rules)) => variant_rules.push(*rule),
+                None => variants.push((&**variant, vec![*rule])),
+            }
+            true
+        });
+        writeln!(code)?;
+        writeln!(code, "/// A node of `{name}`.")?;
+        writeln!(code, "#[derive(Debug, Clone)]")?;
+        if !all_variants || rules.is_empty() {
+            let fields = fields_of(&rules);
+            let mut taken = HashSet::from(["span".to_string()]);
+            let names = fields
+                .iter()
+                .map(|field| field_name(&field.attribute, &mut taken))
+                .collect::<Vec<_>>();
+            writeln!(code, "pub struct {type_name} {{")?;
+            for (field, name) in fields.iter().zip(&names) {
+                writeln!(code, "    pub {name}: {},", type_of(field))?;
+            }
+            writeln!(code, "    pub span: Span,")?;
+            writeln!(code, "}}")?;
+            writeln!(code)?;
+            writeln!(code, "impl From<AST> for {type_name} {{")?;
+            writeln!(code, "    fn from(ast: AST) -> Self {{")?;
+            if fields.is_empty() {
+                writeln!(code, "        let (_, span) = node(ast);")?;
+            } else {
+                writeln!(code, "        let (mut attributes, span) = node(ast);")?;
+            }
+            writeln!(code, "        Self {{")?;
+            for (field, name) in fields.iter().zip(&names) {
+                writeln!(code, "            {name}: {},", value_of(field))?;
+            }
+            writeln!(code, "            span,")?;
+            writeln!(code, "        }}")?;
+            writeln!(code, "    }}")?;
+            writeln!(code, "}}")?;
+            continue;
+        }
+        let mut taken_variants = HashSet::new();
+        let variants = variants
+            .into_iter()
+            .map(|(variant, rules)| {
+                let variant_name = fresh_name(pascal_case(variant), &mut taken_variants);
+                let fields = fields_of(&rules);
+                let mut taken = HashSet::from(["span".to_string()]);
+                let names = fields
+                    .iter()
+                    .map(|field| field_name(&field.attribute, &mut taken))
+                    .collect::<Vec<_>>();
+                (variant, variant_name, fields, names)
+            })
+            .collect::<Vec<_>>();
+        writeln!(code, "pub enum {type_name} {{")?;
+        for (variant, variant_name, fields, names) in &variants {
+            writeln!(code, "    /// The variant `{variant}`.")?;
+            writeln!(code, "    {variant_name} {{")?;
+            for (field, name) in fields.iter().zip(names) {
+                writeln!(code, "        {name}: {},", type_of(field))?;
+            }
+            writeln!(code, "        span: Span,")?;
+            writeln!(code, "    }},")?;
+        }
+        writeln!(code, "}}")?;
+        writeln!(code)?;
+        writeln!(code, "impl From<AST> for {type_name} {{")?;
+        writeln!(code, "    fn from(ast: AST) -> Self {{")?;
+        writeln!(code, "        let (mut attributes, span) = node(ast);")?;
+        writeln!(code, "        match &*string(take(&mut attributes, \"variant\")) {{")?;
+        for (variant, variant_name, fields, names) in &variants {
+            writeln!(code, "            {variant:?} => Self::{variant_name} {{")?;
+            for (field, name) in fields.iter().zip(names) {
+                writeln!(code, "                {name}: {},", value_of(field))?;
+            }
+            writeln!(code, "                span,")?;
+            writeln!(code, "            }},")?;
+        }
+        writeln!(
+            code,
+            "            variant => panic!(\"unknown variant {{variant}} of `{name}`\"),"
+        )?;
+        writeln!(code, "        }}")?;
+        writeln!(code, "    }}")?;
+        writeln!(code, "}}")?;
+    }
+    writeln!(code)?;
+    writeln!(code, "#[allow(dead_code)]")?;
+    writeln!(code, "fn node(ast: AST) -> (Attributes, Span) {{")?;
+    writeln!(code, "    match ast {{")?;
+    writeln!(
+        code,
+        "        AST::Node {{ attributes, span, .. }} => (attributes, span),"
+    )?;
+    writeln!(code, "        ast => panic!(\"expected a node, found {{ast:?}}\"),")?;
+    writeln!(code, "    }}")?;
+    writeln!(code, "}}")?;
+    writeln!(code)?;
+    writeln!(code, "#[allow(dead_code)]")?;
+    writeln!(code, "fn take(attributes: &mut Attributes, name: &str) -> AST {{")?;
+    writeln!(code, "    attributes")?;
+    writeln!(code, "        .remove(name)")?;
+    writeln!(
+        code,
+        "        .unwrap_or_else(|| panic!(\"missing attribute `{{name}}`\"))"
+    )?;
+    writeln!(code, "}}")?;
+    writeln!(code)?;
+    writeln!(code, "#[allow(dead_code)]")?;
+    writeln!(code, "fn token(ast: AST) -> Token {{")?;
+    writeln!(code, "    match ast {{")?;
+    writeln!(code, "        AST::Terminal(token) => token,")?;
+    writeln!(code, "        ast => panic!(\"expected a token, found {{ast:?}}\"),")?;
+    writeln!(code, "    }}")?;
+    writeln!(code, "}}")?;
+    writeln!(code)?;
+    writeln!(code, "#[allow(dead_code)]")?;
+    writeln!(code, "fn string(ast: AST) -> Rc<str> {{")?;
+    writeln!(code, "    match ast {{")?;
+    writeln!(
+        code,
+        "        AST::Literal {{ value: Value::Str(string), .. }} => string,"
+    )?;
+    writeln!(code, "        ast => panic!(\"expected a string, found {{ast:?}}\"),")?;
+    writeln!(code, "    }}")?;
+    writeln!(code, "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{builder::Buildable, lexer::Lexer, stream::StringStream};
+    use std::path::Path;
+
+    #[test]
+    fn names() {
+        assert_eq!(snake_case("ToplevelDeclaration"), "toplevel_declaration");
+        assert_eq!(
+            snake_case("List[ToplevelDeclaration, Empty]"),
+            "list_toplevel_declaration_empty"
+        );
+        assert_eq!(snake_case("SSA2Form"), "ssa2_form");
+        let mut taken = HashSet::new();
+        assert_eq!(method_name("Expr", &mut taken), "visit_expr");
+        assert_eq!(method_name("expr", &mut taken), "visit_expr_2");
+    }
+
+    #[test]
+    fn visitor() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "NUMBER ::= [0-9]+\nPLUS ::= \\+",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@Expr ::= Expr@left PLUS Atom@right <Add>\n  Atom@value <Atom>;\n\
+                 Atom ::= NUMBER@value <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let code = generate_visitor(&grammar);
+        let signature = |method: &str, span: &str| {
+            format!("fn {method}(&mut self, attributes: &Attributes, {span}: &Span) {{")
+        };
+        assert!(code.contains(&signature("visit_expr", "span")));
+        assert!(code.contains(r#"Some("Add") => self.visit_expr_add(attributes, span),"#));
+        assert!(code.contains(&signature("visit_expr_atom", "_span")));
+        assert!(code.contains(&signature("visit_atom", "_span")));
+        let atom = grammar.id_of("Atom").0;
+        assert!(code.contains(&format!("{atom} => visitor.visit_atom(attributes, span),")));
+    }
+
+    #[test]
+    fn types() {
+        assert_eq!(
+            pascal_case("List[ToplevelDeclaration, Empty]"),
+            "ListToplevelDeclarationEmpty"
+        );
+        let mut taken = HashSet::new();
+        assert_eq!(field_name("type", &mut taken), "r#type");
+        assert_eq!(field_name("Type", &mut taken), "type2");
+
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "NUMBER ::= [0-9]+\nPLUS ::= \\+\nMINUS ::= -",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@Expr ::= Expr@left PLUS Atom@right <Add>\n  Atom@value <Atom>;\n\
+                 Atom ::= MINUS?@minus NUMBER.0@value <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let code = generate_types(&grammar);
+        assert!(code.contains("pub enum Expr {"));
+        assert!(code.contains("Add {\n        left: Box<Expr>,\n        right: Box<Atom>,"));
+        assert!(code.contains("pub struct Atom {\n    pub minus: Option<Token>,"));
+        assert!(code.contains("    pub value: Rc<str>,"));
+        assert!(code.contains(r#"value: string(take(&mut attributes, "value")),"#));
+    }
+}
@@ -0,0 +1,107 @@
+//! # Coverage
+//!
+//! Which rules of a grammar a test corpus exercises. Every input of the corpus is
+//! parsed, and the rules of the derivation the parser selects for it are counted,
+//! so that the rules no input derives with tell which productions the tests miss.
+//! Each alternative of a non-terminal is a rule of its own, and so is each of the
+//! ways of writing, or not, the optional elements of a rule.
+
+use super::earley::{EarleyGrammar, EarleyParser, FinalSet};
+use super::grammar::RuleId;
+use crate::error::Result;
+use crate::lexer::Token;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Buildable;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::stream::StringStream;
+    use std::path::Path;
+
+    #[test]
+    fn coverage() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "NUMBER ::= [0-9]+\nPM ::= [-+]\nLPAR ::= \\(\nRPAR ::= \\)",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@Expr ::= NUMBER <>\n Expr PM Expr <>\n LPAR Expr RPAR <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let mut coverage = Coverage::new(parser.grammar());
+        let record = |coverage: &mut Coverage, input: &str| {
+            let mut stream = StringStream::new(Path::new("<input>"), input);
+            let (table, raw_input) = parser.recognise(&mut lexer.lex(&mut stream)).unwrap();
+            let forest = parser.to_forest(&table, &raw_input).unwrap();
+            coverage.record(&parser, &forest, &raw_input).unwrap();
+        };
+        record(&mut coverage, "1+2");
+        assert_eq!(coverage.uncovered().collect::<Vec<_>>(), [RuleId(2)]);
+        assert_eq!((coverage.covered(), coverage.total()), (2, 3));
+        record(&mut coverage, "(3)");
+        assert_eq!(coverage.uncovered().count(), 0);
+        assert_eq!(coverage.hits(RuleId(0)), 3);
+        assert_eq!(coverage.hits(RuleId(1)), 1);
+    }
+}
+
+/// # Summary
+///
+/// [`Coverage`] counts the nodes built with each rule of a grammar, over the inputs
+/// it recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    nodes: Vec<usize>,
+}
+
+impl Coverage {
+    /// Start counting the rules of `grammar`, which no input exercises yet.
+    pub fn new(grammar: &EarleyGrammar) -> Self {
+        Self {
+            nodes: vec![0; grammar.rules().len()],
+        }
+    }
+
+    /// Record the rules of the derivation that `parser` selects for `raw_input`,
+    /// whose forest is `forest`.
+    pub fn record(
+        &mut self,
+        parser: &EarleyParser,
+        forest: &[FinalSet],
+        raw_input: &[Token],
+    ) -> Result<()> {
+        parser.count_derivation(forest, raw_input, &mut self.nodes)
+    }
+
+    /// Return the number of nodes built with `rule`, over every input.
+    pub fn hits(&self, rule: RuleId) -> usize {
+        self.nodes[rule.0]
+    }
+
+    /// Iterate over the rules that no input exercised, in definition order.
+    pub fn uncovered(&self) -> impl Iterator<Item = RuleId> + '_ {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|&(_, &nodes)| nodes == 0)
+            .map(|(rule, _)| RuleId(rule))
+    }
+
+    /// Return the number of rules that some input exercised.
+    pub fn covered(&self) -> usize {
+        self.nodes.iter().filter(|&&nodes| nodes > 0).count()
+    }
+
+    /// Return the number of rules of the grammar.
+    pub fn total(&self) -> usize {
+        self.nodes.len()
+    }
+}
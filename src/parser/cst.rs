@@ -0,0 +1,121 @@
+//! # Cst
+//!
+//! A concrete syntax tree keeps every token of the input, in order, under the
+//! nodes of the rules that derived them, whether the grammar gives them a key or
+//! not. Along with the text of the input, which holds the ignored tokens, it
+//! describes the source byte for byte, which is what refactoring tools need.
+
+use super::parser::NonTerminalId;
+use crate::lexer::Token;
+use crate::span::Span;
+
+/// # Summary
+///
+/// [`Cst`] is a node of a concrete syntax tree, built by
+/// [`EarleyParser::parse_cst`](super::earley::EarleyParser::parse_cst).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cst {
+    /// A rule deriving `nonterminal`, with a child per element of the rule.
+    Node {
+        nonterminal: NonTerminalId,
+        children: Vec<Cst>,
+        span: Span,
+    },
+    Token(Token),
+}
+
+impl Cst {
+    pub fn span(&self) -> &Span {
+        match self {
+            Self::Node { span, .. } => span,
+            Self::Token(token) => token.span(),
+        }
+    }
+
+    /// Every token of the tree, from left to right.
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a Token>) {
+        match self {
+            Self::Node { children, .. } => {
+                for child in children {
+                    child.collect_tokens(tokens);
+                }
+            }
+            Self::Token(token) => tokens.push(token),
+        }
+    }
+
+    /// The text between the end of the previous token, or the beginning of
+    /// `text`, and each token, that is, what the lexer ignored there.
+    pub fn trivia<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let mut end = 0;
+        self.tokens()
+            .into_iter()
+            .map(|token| {
+                let trivia = &text[end..token.span().start_byte()];
+                end = token.span().end_byte();
+                trivia
+            })
+            .collect()
+    }
+
+    /// The text the node covers, given the `text` of the input.
+    pub fn source<'a>(&self, text: &'a str) -> &'a str {
+        &text[self.span().byte_range()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Buildable;
+    use crate::lexer::Lexer;
+    use crate::parser::earley::{EarleyGrammar, EarleyParser};
+    use crate::parser::Parser;
+    use crate::stream::StringStream;
+    use std::path::Path;
+
+    #[test]
+    fn lossless() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "ignore SPACE ::= [ \\t\\n]+\nNUMBER ::= ([0-9]+)\nPLUS ::= \\+",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@Sum ::= Sum@left PLUS NUMBER.0@right <>\n NUMBER.0@self <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let text = "  1 + 2+ 3\n";
+        let mut stream = StringStream::new(Path::new("<input>"), text);
+        let cst = parser.parse_cst(&mut lexer.lex(&mut stream)).unwrap();
+        let tokens = cst.tokens();
+        let names = tokens.iter().map(|token| token.name()).collect::<Vec<_>>();
+        assert_eq!(names, ["NUMBER", "PLUS", "NUMBER", "PLUS", "NUMBER"]);
+        let trivia = cst.trivia(text);
+        assert_eq!(trivia, ["  ", " ", " ", "", " "]);
+        assert_eq!(cst.source(text), "1 + 2+ 3");
+        let mut source = String::new();
+        for (trivia, token) in trivia.into_iter().zip(tokens) {
+            source.push_str(trivia);
+            source.push_str(&text[token.span().byte_range()]);
+        }
+        source.push_str(&text[cst.span().end_byte()..]);
+        assert_eq!(source, text);
+        let Cst::Node { children, .. } = &cst else {
+            panic!("expected a node");
+        };
+        assert_eq!(children.len(), 3);
+        assert!(matches!(&children[1], Cst::Token(token) if token.name() == "PLUS"));
+    }
+}
@@ -0,0 +1,178 @@
+//! # Disambiguation
+//!
+//! When the input can be derived in several ways, the parser builds the heaviest
+//! derivation, and settles the derivations of equal weight node by node: among the
+//! ways of splitting the tokens a node covers between the elements of its rule, it
+//! picks the greatest according to a [`Disambiguator`]. By default, this is
+//! [`Standard`], which follows the associativity of the rules and the rule
+//! preference, but languages with other rules, such as the longest operand first,
+//! or what a comment says, give their own with [`EarleyParser::with_disambiguator`].
+//!
+//! [`EarleyParser::with_disambiguator`]: super::earley::EarleyParser::with_disambiguator
+
+use std::cmp::Ordering;
+
+use super::earley::{EarleyGrammar, RulePreference};
+pub use super::grammar::RuleId;
+use crate::{lexer::Token, span::Span};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::Buildable,
+        lexer::Lexer,
+        parser::{earley::EarleyParser, Parser, Value, AST},
+        stream::StringStream,
+    };
+    use std::path::Path;
+
+    /// Prefer the derivations whose first operand is the longest.
+    struct LongestFirst;
+
+    impl Disambiguator for LongestFirst {
+        fn compare(
+            &self,
+            _grammar: &EarleyGrammar,
+            _rule: RuleId,
+            left: &[Child],
+            right: &[Child],
+        ) -> Ordering {
+            let length = |children: &[Child]| {
+                children[0]
+                    .span
+                    .as_ref()
+                    .map_or(0, |span| span.byte_range().len())
+            };
+            length(left).cmp(&length(right))
+        }
+    }
+
+    #[test]
+    fn disambiguator() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "INT ::= ([0-9]+)\nMINUS ::= -",
+        ))
+        .unwrap();
+        let parser = || {
+            let grammar = EarleyGrammar::build_from_plain(
+                StringStream::new(
+                    Path::new("<grammar input>"),
+                    "@Expr ::= (right-assoc) Expr@left MINUS Expr@right <Sub>\n \
+                     INT.0@value <Int>;",
+                ),
+                lexer.grammar(),
+            )
+            .unwrap();
+            EarleyParser::new(grammar)
+        };
+        let left_is_sub = |parser: &EarleyParser| {
+            let mut stream = StringStream::new(Path::new("<input>"), "1-2-3");
+            let ast = parser.parse(&mut lexer.lex(&mut stream)).unwrap().tree;
+            let AST::Node { attributes, .. } = ast else {
+                panic!("expected a node");
+            };
+            let AST::Node { attributes, .. } = &attributes["left"] else {
+                panic!("expected a node");
+            };
+            matches!(
+                &attributes["variant"],
+                AST::Literal { value: Value::Str(variant), .. } if &**variant == "Sub"
+            )
+        };
+        assert!(!left_is_sub(&parser()));
+        assert!(left_is_sub(&parser().with_disambiguator(LongestFirst)));
+    }
+}
+
+/// What a child of a node covers, in one of the ways of deriving it.
+#[derive(Debug, Clone, Copy)]
+pub enum ChildKind<'a> {
+    /// A node built with the rule.
+    Rule(RuleId),
+    Token(&'a Token),
+}
+
+/// # Summary
+///
+/// [`Child`] is a child of a node, in one of the ways of splitting the tokens the
+/// node covers between the elements of its rule.
+#[derive(Debug, Clone)]
+pub struct Child<'a> {
+    pub kind: ChildKind<'a>,
+    /// The index of the first token the child covers.
+    pub start: usize,
+    /// The index of the token right after the child.
+    pub end: usize,
+    /// The span of the tokens the child covers, unless it covers none.
+    pub span: Option<Span>,
+}
+
+/// A strategy to pick between derivations of equal weight.
+pub trait Disambiguator {
+    /// Compare two ways of deriving a node of `rule`, a rule of `grammar`, given as
+    /// the children of the node, in the order of the elements of the rule. The
+    /// parser builds the greatest.
+    fn compare(
+        &self,
+        grammar: &EarleyGrammar,
+        rule: RuleId,
+        left: &[Child],
+        right: &[Child],
+    ) -> Ordering;
+}
+
+/// # Summary
+///
+/// [`Standard`] is the [`Disambiguator`] of the parser unless it is given another
+/// one. From the last child, it finds the first rule children the derivations
+/// differ on, and picks the one that nests to the left, or to the right, depending
+/// on the associativity of `rule`, then the one whose rule binds the least tightly
+/// according to the precedence table, and then the one whose rule the rule
+/// preference picks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Standard {
+    pub rule_preference: RulePreference,
+}
+
+impl Standard {
+    pub fn new(rule_preference: RulePreference) -> Self {
+        Self { rule_preference }
+    }
+}
+
+impl Disambiguator for Standard {
+    fn compare(
+        &self,
+        grammar: &EarleyGrammar,
+        rule: RuleId,
+        left: &[Child],
+        right: &[Child],
+    ) -> Ordering {
+        for (left, right) in left.iter().zip(right).rev() {
+            let ChildKind::Rule(left_rule) = left.kind else {
+                continue;
+            };
+            let ChildKind::Rule(right_rule) = right.kind else {
+                continue;
+            };
+            let assoc_ord = if grammar.rules()[rule].left_associative {
+                left.start.cmp(&right.start)
+            } else {
+                right.start.cmp(&left.start)
+            };
+            let ord = match assoc_ord {
+                Ordering::Equal => grammar
+                    .compare_precedence(left_rule, right_rule)
+                    .then_with(|| self.rule_preference.compare(left_rule, right_rule)),
+                other => other,
+            };
+            match ord {
+                Ordering::Equal => continue,
+                other => return other,
+            }
+        }
+        Ordering::Equal
+    }
+}
@@ -0,0 +1,196 @@
+//! # Dot
+//!
+//! Export of ASTs and of shared packed parse forests to the DOT language of
+//! Graphviz, to inspect large trees and ambiguous forests visually. The
+//! graphs can be rendered with, for instance, `dot -Tsvg`.
+
+use super::earley::EarleyGrammar;
+use super::forest::{NodeId, NodeKind, Sppf};
+use super::{Value, AST};
+use crate::lexer::Token;
+use std::fmt::Write;
+
+/// Escape `label` so that it can be written between double quotes.
+fn escape(label: &str) -> String {
+    let mut result = String::new();
+    for c in label.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Write the node of `ast`, named `n{id}`, along with its children, and return
+/// the next free identifier.
+fn write_ast(output: &mut String, ast: &AST, id: usize) -> usize {
+    let mut next = id + 1;
+    match ast {
+        AST::Node { attributes, .. } => {
+            let label = match attributes.get("variant") {
+                Some(AST::Literal {
+                    value: Value::Str(variant),
+                    ..
+                }) => variant.to_string(),
+                _ => String::new(),
+            };
+            writeln!(output, "  n{id} [label=\"{}\"];", escape(&label)).unwrap();
+            let mut keys: Vec<_> = attributes
+                .keys()
+                .filter(|key| &***key != "variant")
+                .collect();
+            keys.sort();
+            for key in keys {
+                writeln!(output, "  n{id} -> n{next} [label=\"{}\"];", escape(key)).unwrap();
+                next = write_ast(output, &attributes[key], next);
+            }
+        }
+        AST::Literal { value, .. } => {
+            let label = match value {
+                Value::Int(i) => i.to_string(),
+                Value::Str(string) => format!("{:?}", string),
+                Value::Float(f) => f.to_string(),
+                Value::Bool(b) => b.to_string(),
+                #[cfg(feature = "bigint")]
+                Value::BigInt(i) => i.to_string(),
+                #[cfg(feature = "decimal")]
+                Value::Decimal(d) => d.to_string(),
+                Value::External(_) => String::from("<external>"),
+            };
+            writeln!(output, "  n{id} [label=\"{}\", shape=plaintext];", escape(&label))
+                .unwrap();
+        }
+        AST::Terminal(token) => {
+            let mut attributes: Vec<_> = token.attributes().iter().collect();
+            attributes.sort();
+            let mut label = token.name().to_string();
+            for (_, value) in attributes {
+                write!(label, "\n{:?}", value).unwrap();
+            }
+            writeln!(output, "  n{id} [label=\"{}\", shape=box];", escape(&label)).unwrap();
+        }
+    }
+    next
+}
+
+/// Render `ast` as a DOT graph. Nodes are labeled with their variant, if they
+/// have one, and edges with the name of the attributes.
+pub fn ast_to_dot(ast: &AST) -> String {
+    let mut output = String::from("digraph ast {\n");
+    write_ast(&mut output, ast, 0);
+    output.push_str("}\n");
+    output
+}
+
+/// Render the forest `sppf`, built from `raw_input`, as a DOT graph.
+///
+/// Every node is labeled with what it derives and the tokens it spans.
+/// Ambiguous nodes are drawn in red, and each of their derivations goes
+/// through a point, which stands for the packed node.
+pub fn forest_to_dot(sppf: &Sppf, grammar: &EarleyGrammar, raw_input: &[Token]) -> String {
+    let mut output = String::from("digraph forest {\n");
+    for index in 0..sppf.len() {
+        let id = NodeId(index);
+        let node = sppf.node(id);
+        let (label, shape) = match node.kind {
+            NodeKind::NonTerminal(nonterminal) => {
+                (grammar.name_of(nonterminal).to_string(), "ellipse")
+            }
+            NodeKind::Token(position) => (raw_input[position].name().to_string(), "box"),
+            NodeKind::Intermediate { rule, position } => {
+                let name = grammar.name_of(grammar.rules()[rule].id);
+                (format!("{name}, rule {}, {position} elements", rule.0), "note")
+            }
+        };
+        let label = format!("{label}\n[{}, {})", node.start, node.end);
+        let ambiguous = node.packed.len() > 1;
+        let color = if ambiguous { ", color=red" } else { "" };
+        writeln!(
+            output,
+            "  n{index} [label=\"{}\", shape={shape}{color}];",
+            escape(&label)
+        )
+        .unwrap();
+        for (alternative, packed) in node.packed.iter().enumerate() {
+            let parent = if ambiguous {
+                let name = format!("p{index}_{alternative}");
+                writeln!(output, "  {name} [label=\"\", shape=point];").unwrap();
+                writeln!(output, "  n{index} -> {name};").unwrap();
+                name
+            } else {
+                format!("n{index}")
+            };
+            for child in packed.left.iter().chain(packed.right.iter()) {
+                writeln!(output, "  {parent} -> n{};", child.0).unwrap();
+            }
+        }
+    }
+    for root in sppf.roots() {
+        writeln!(output, "  n{} [peripheries=2];", root.0).unwrap();
+    }
+    output.push_str("}\n");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Buildable;
+    use crate::lexer::Lexer;
+    use crate::parser::earley::EarleyParser;
+    use crate::parser::Parser;
+    use crate::stream::StringStream;
+    use std::path::Path;
+
+    const LEXER: &str = r#"
+NUMBER ::= ([0-9])
+PM ::= [-+]
+"#;
+
+    const GRAMMAR: &str = r#"
+@Expr ::= Expr@left PM@op Expr@right <Op>
+ NUMBER.0@value <Literal>;"#;
+
+    fn parser() -> (Lexer, EarleyParser) {
+        let lexer =
+            Lexer::build_from_plain(StringStream::new(Path::new("<lexer input>"), LEXER))
+                .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), GRAMMAR),
+            lexer.grammar(),
+        )
+        .unwrap();
+        (lexer, EarleyParser::new(grammar))
+    }
+
+    #[test]
+    fn ast() {
+        let (lexer, parser) = parser();
+        let mut stream = StringStream::new(Path::new("<input>"), "1+2");
+        let ast = parser.parse(&mut lexer.lex(&mut stream)).unwrap().tree;
+        let dot = ast_to_dot(&ast);
+        assert!(dot.starts_with("digraph ast {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("n0 [label=\"Op\"];"));
+        assert!(dot.contains("n0 -> n1 [label=\"left\"];"));
+        assert!(dot.contains("[label=\"\\\"2\\\"\", shape=plaintext];"));
+        assert!(dot.contains("[label=\"PM\", shape=box];"));
+    }
+
+    #[test]
+    fn forest() {
+        let (lexer, parser) = parser();
+        let mut stream = StringStream::new(Path::new("<input>"), "1+2+3");
+        let (table, raw_input) = parser.recognise(&mut lexer.lex(&mut stream)).unwrap();
+        let forest = parser.to_forest(&table, &raw_input).unwrap();
+        let sppf = parser.to_sppf(&forest, &raw_input);
+        let dot = forest_to_dot(&sppf, parser.grammar(), &raw_input);
+        assert!(dot.starts_with("digraph forest {\n"));
+        assert!(dot.contains("label=\"Expr\\n[0, 5)\", shape=ellipse, color=red"));
+        assert_eq!(dot.matches("shape=point").count(), 2);
+        assert_eq!(dot.matches("peripheries=2").count(), 1);
+    }
+}
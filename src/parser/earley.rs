@@ -1,35 +1,47 @@
+use super::analysis::cyclic_rules;
+use super::arbitrary::AstGenerator;
+use super::arena::{AstArena, NodeId as ArenaNodeId};
 use super::ast::{
-    Ast, Attribute as AstAttribute, Element as AstElement, Expression, Item, Proxy as AstProxy,
-    Rule as AstRule, ToplevelDeclaration,
+    expand_optionals, Ast, Attribute as AstAttribute, Element as AstElement, Expression, Item,
+    MacroDeclaration, Proxy as AstProxy, Rule as AstRule, ToplevelDeclaration,
 };
+use super::cst::Cst;
+use super::disambiguation::{Child, ChildKind, Disambiguator, Standard};
+use super::forest::{Asts, Sppf};
 use super::grammar::{
     Attribute, Axioms, Element, ElementType, NonTerminalDescription, NonTerminalName,
     Nullables, Proxy, Rule, RuleId, Rules, ValueTemplate,
 };
-use super::parser::{NonTerminalId, ParseResult, Parser, Value, AST};
+use super::parser::{NonTerminalId, ParseResult, ParseSink, Parser, AST};
+use super::passes::PassManager;
+use super::version::LanguageVersion;
 use crate::typed::Spanned;
 use crate::{
     build_system,
-    builder::{select_format, Buildable, FileResult, Format},
+    builder::{
+        deserialize_compiled, select_format, Buildable, Compiled, CompiledKind, FileResult,
+        Format,
+    },
     error::{Error, ErrorKind, Result},
-    lexer::{Grammar as LexerGrammar, LexedStream, Lexer, TerminalId, Token},
+    interner::Symbol,
+    lexer::{Checkpoint, Grammar as LexerGrammar, LexedStream, Lexer, TerminalId, Token},
     list::List,
     regex::Allowed,
     span::Span,
     stream::StringStream,
     typed::Tree,
 };
-use bincode::deserialize;
-use fragile::Fragile;
 use itertools::Itertools;
 use newty::{newty, nvec};
 use serde::{Deserialize, Serialize};
-use std::cmp::{Ordering, Reverse};
+use std::cell::OnceCell;
+use std::cmp::Ordering;
 use std::collections::VecDeque;
 use std::collections::hash_map::Entry;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt;
 use std::fs::File;
+use std::hash::{Hash, Hasher};
 use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
@@ -42,13 +54,13 @@ pub fn print_sets(sets: &[StateSet], parser: &EarleyParser, lexer: &Lexer) {
             let rule = &parser.grammar().rules[item.rule];
             line.push_str(&parser.grammar().name_of[rule.id]);
             line.push_str(" -> ");
-            for i in 0..item.position {
-                line.push_str(&rule.elements[i].name(lexer.grammar(), parser.grammar()));
+            for i in 0..item.position as usize {
+                line.push_str(rule.elements[i].name(lexer.grammar(), parser.grammar()));
                 line.push(' ');
             }
             line.push_str("• ");
-            for i in item.position..rule.elements.len() {
-                line.push_str(&rule.elements[i].name(lexer.grammar(), parser.grammar()));
+            for i in item.position as usize..rule.elements.len() {
+                line.push_str(rule.elements[i].name(lexer.grammar(), parser.grammar()));
                 line.push(' ');
             }
             line.extend(format!("({})", item.origin).chars());
@@ -82,7 +94,74 @@ pub fn print_final_sets(sets: &[FinalSet], parser: &EarleyParser, lexer: &Lexer)
     }
 }
 
+/// Print, on the standard error, the `profile` of a parse by `parser`: what each rule
+/// cost, the rules that cost the most first.
+pub fn print_profile(profile: &[(RuleId, RuleProfile)], parser: &EarleyParser, lexer: &Lexer) {
+    eprintln!("{:>8} {:>8} {:>8} {:>8}  rule", "items", "predict", "complete", "nodes");
+    for (rule, counts) in profile {
+        let rule = &parser.grammar().rules[*rule];
+        let elements = rule
+            .elements
+            .iter()
+            .map(|element| element.name(lexer.grammar(), parser.grammar()))
+            .join(" ");
+        eprintln!(
+            "{:>8} {:>8} {:>8} {:>8}  {} -> {elements}",
+            counts.items,
+            counts.predictions,
+            counts.completions,
+            counts.nodes,
+            parser.grammar().name_of[rule.id],
+        );
+    }
+}
+
+/// What a rule cost during a parse, as counted by [`EarleyParser::profile`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RuleProfile {
+    /// The items of the rule in the table, which is how much it made the table grow.
+    pub items: usize,
+    /// The items of the rule that were predicted, and are at its start.
+    pub predictions: usize,
+    /// The items of the rule that are complete.
+    pub completions: usize,
+    /// The nodes of the selected derivation built with the rule.
+    pub nodes: usize,
+}
+
 type Table = Vec<StateSet>;
+
+/// The range of characters of a token, and the checkpoint of the lexer after it,
+/// if lexing can resume there.
+pub(crate) type Lexed = ((usize, usize), Option<Checkpoint>);
+
+/// How many state sets are added between two retirements of the sets that can no
+/// longer be completed into.
+const RETIREMENT_PERIOD: usize = 1024;
+
+/// Empty the state sets that no item can complete into anymore. An item of the
+/// last set, once complete, completes the items of the set of its origin, whose
+/// own origins are then needed, and so on. The other sets are not needed.
+fn retire_sets(sets: &mut Table) {
+    let last = sets.len() - 1;
+    let mut needed = vec![false; sets.len()];
+    needed[last] = true;
+    let mut stack = vec![last];
+    while let Some(index) = stack.pop() {
+        for item in sets[index].iter() {
+            let origin = item.origin as usize;
+            if !needed[origin] {
+                needed[origin] = true;
+                stack.push(origin);
+            }
+        }
+    }
+    for (set, needed) in sets.iter_mut().zip(needed) {
+        if !needed {
+            *set = StateSet::default();
+        }
+    }
+}
 type Forest = Vec<FinalSet>;
 
 newty! {
@@ -103,9 +182,9 @@ struct EarleyItem {
     /// `rule` is the identifier of the associated [`Rule`].
     rule: RuleId,
     /// `origin` is the identifier of the `EarleySet` this item was originated in.
-    origin: usize,
+    origin: u32,
     /// `position` is the advancement of the current item. It corresponds to the position of the fat dot.
-    position: usize,
+    position: u32,
     /// `parent_has_been_shown` indicates whether a parent item should be reported in case
     /// of failure. It should *not* be showed if it has no description, if it hash
     /// matched something already, or if a parent item is already a candidate
@@ -126,6 +205,53 @@ impl fmt::Display for FinalItem {
     }
 }
 
+/// # Summary
+///
+/// [`RulePreference`] settles the ambiguities that associativity does not: between
+/// two derivations that nest different rules at the same place, the parser builds
+/// the one whose rule comes first, or last, in the grammar. A grammar states its
+/// preference with `prefer first-rule;` or `prefer last-rule;`, the default being
+/// the last rule.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RulePreference {
+    First,
+    #[default]
+    Last,
+}
+
+impl RulePreference {
+    /// Compare two rules, the greatest being the preferred one.
+    pub fn compare(self, left: RuleId, right: RuleId) -> Ordering {
+        match self {
+            Self::First => right.cmp(&left),
+            Self::Last => left.cmp(&right),
+        }
+    }
+}
+
+/// # Summary
+///
+/// [`LexerMode`] is a set of terminals, declared with `mode NAME ::= TERMINALS;`,
+/// that are the only ones the lexer produces while the parser is within a
+/// non-terminal declared as `Name (mode NAME) ::= ...`, or within what it derives.
+/// The ignored terminals are only skipped if the mode lists them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LexerMode {
+    pub name: Symbol,
+    pub terminals: Vec<TerminalId>,
+}
+
+/// The terminals the lexer may produce while parsing a non-terminal, `None`
+/// standing for any of them.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct LexerContext {
+    /// Before the first token of the non-terminal, which is the context of its
+    /// parent.
+    entry: Option<Vec<TerminalId>>,
+    /// After the first token of the non-terminal.
+    body: Option<Vec<TerminalId>>,
+}
+
 /// # Summary
 /// `EarleyGrammar` is a grammar that uses the Earley algorithm.
 /// The general worst-time complexity for a context-free grammar is `O(n³)`.
@@ -143,13 +269,26 @@ pub struct EarleyGrammar {
     /// The nullables, indexed by NonTerminalId.
     nullables: Nullables,
     /// Maps the name of a non-terminal to its identifier.
-    id_of: HashMap<Rc<str>, NonTerminalId>,
+    id_of: HashMap<Symbol, NonTerminalId>,
     /// Maps the non-terminal to its name
     name_of: NonTerminalName,
     description_of: NonTerminalDescription,
     /// Maps the identifier of a non-terminal to the identifiers of its rules.
     /// Its rules are the rules of which it is the LHS.
     rules_of: RulesMap,
+    /// The fingerprint of the lexer grammar the grammar was built against.
+    lexer_fingerprint: u64,
+    /// Which of two rules the parser prefers, when associativity does not decide.
+    rule_preference: RulePreference,
+    /// The lexer modes of the non-terminals declared with one.
+    lexer_modes: HashMap<NonTerminalId, LexerMode>,
+    /// The lexer context of every non-terminal, indexed by its identifier, or
+    /// nothing if the grammar has no lexer mode.
+    lexer_contexts: Vec<LexerContext>,
+    /// The version of the language the grammar defines, if it declares one.
+    language_version: Option<LanguageVersion>,
+    /// The rules through which a non-terminal derives itself alone.
+    cycles: Vec<RuleId>,
 }
 
 impl EarleyGrammar {
@@ -162,9 +301,10 @@ impl EarleyGrammar {
     pub fn new(
         rules: Rules,
         axioms: Axioms,
-        id_of: HashMap<Rc<str>, NonTerminalId>,
+        id_of: HashMap<Symbol, NonTerminalId>,
         name_of: NonTerminalName,
         description_of: NonTerminalDescription,
+        lexer_fingerprint: u64,
     ) -> Result<Self> {
         let nb_non_terminals = axioms.len_as(); // Number of non terminals
                                                 // nullables[non_term_id]: bool is whether non terminal with
@@ -209,7 +349,7 @@ impl EarleyGrammar {
             }
         }
 
-        Ok(Self {
+        let mut grammar = Self {
             axioms,
             rules,
             nullables,
@@ -217,20 +357,568 @@ impl EarleyGrammar {
             name_of,
             description_of,
             rules_of,
-        })
+            lexer_fingerprint,
+            rule_preference: RulePreference::default(),
+            lexer_modes: HashMap::new(),
+            lexer_contexts: Vec::new(),
+            language_version: None,
+            cycles: Vec::new(),
+        };
+        grammar.cycles = cyclic_rules(&grammar);
+        Ok(grammar)
+    }
+
+    /// The rules through which a non-terminal derives itself, and nothing else, as
+    /// found by [`cyclic_rules`]. They give some inputs infinitely many derivations,
+    /// of which the parser builds one that goes through each of them at most once.
+    pub fn cycles(&self) -> &[RuleId] {
+        &self.cycles
+    }
+
+    /// Prefer the first, or the last, of two rules when associativity does not
+    /// decide.
+    pub fn with_rule_preference(mut self, rule_preference: RulePreference) -> Self {
+        self.rule_preference = rule_preference;
+        self
+    }
+
+    pub fn rule_preference(&self) -> RulePreference {
+        self.rule_preference
+    }
+
+    /// Declare the version of the language the grammar defines, which the ASTs it
+    /// builds are serialized with.
+    pub fn with_language_version(mut self, language_version: LanguageVersion) -> Self {
+        self.language_version = Some(language_version);
+        self
+    }
+
+    /// The version of the language the grammar defines, if it declares one.
+    pub fn language_version(&self) -> Option<LanguageVersion> {
+        self.language_version
+    }
+
+    /// Whether a rule has a weight, in which case the parser builds the heaviest
+    /// derivation of the input.
+    pub fn has_weights(&self) -> bool {
+        self.rules.iter().any(|rule| rule.weight != 0.0)
+    }
+
+    /// Give the rule `rule` the weight `weight`.
+    pub fn set_weight(&mut self, rule: RuleId, weight: f64) {
+        self.rules[rule].weight = weight;
+    }
+
+    /// Weigh every rule with the logarithm of its probability to derive its
+    /// non-terminal, as estimated from the concrete syntax trees of `treebank`,
+    /// so that the parser builds the most probable derivation. A node counts for
+    /// every rule whose elements match its children. Each rule is counted once more
+    /// than it appears, so that the rules `treebank` lacks are unlikely rather than
+    /// impossible.
+    pub fn learn_weights<'a>(&mut self, treebank: impl IntoIterator<Item = &'a Cst>) {
+        let mut counts = vec![0usize; self.rules.len()];
+        let mut stack = treebank.into_iter().collect::<Vec<_>>();
+        while let Some(cst) = stack.pop() {
+            let Cst::Node {
+                nonterminal,
+                children,
+                ..
+            } = cst
+            else {
+                continue;
+            };
+            for &rule in &self.rules_of[*nonterminal] {
+                let elements = &self.rules[rule].elements;
+                let matches = elements.len() == children.len()
+                    && elements.iter().zip(children).all(|(element, child)| {
+                        match (element.element_type, child) {
+                            (ElementType::Terminal(id), Cst::Token(token)) => token.id() == id,
+                            (ElementType::NonTerminal(id), Cst::Node { nonterminal, .. }) => {
+                                *nonterminal == id
+                            }
+                            _ => false,
+                        }
+                    });
+                if matches {
+                    counts[rule.0] += 1;
+                }
+            }
+            stack.extend(children);
+        }
+        for rules in self.rules_of.iter() {
+            let total = rules.iter().map(|rule| counts[rule.0] + 1).sum::<usize>();
+            for &rule in rules {
+                self.rules[rule].weight = ((counts[rule.0] + 1) as f64 / total as f64).ln();
+            }
+        }
+    }
+
+    /// Parse the non-terminals of `lexer_modes` in their lexer mode, and what
+    /// they derive, unless it has a lexer mode of its own.
+    pub fn with_lexer_modes(mut self, lexer_modes: HashMap<NonTerminalId, LexerMode>) -> Self {
+        self.lexer_modes = lexer_modes;
+        self.lexer_contexts = self.compute_lexer_contexts();
+        self
+    }
+
+    /// The lexer mode the non-terminal `id` is declared with, if any.
+    pub fn lexer_mode(&self, id: NonTerminalId) -> Option<&LexerMode> {
+        self.lexer_modes.get(&id)
+    }
+
+    /// Compute, as a fixed point, the modes each non-terminal can be parsed in, `None`
+    /// standing for outside of any mode, both before and after its first token.
+    fn compute_lexer_contexts(&self) -> Vec<LexerContext> {
+        if self.lexer_modes.is_empty() {
+            return Vec::new();
+        }
+        let nb_non_terminals = self.nb_non_terminals();
+        let mut entries = vec![BTreeSet::new(); nb_non_terminals];
+        let mut bodies = vec![BTreeSet::new(); nb_non_terminals];
+        for (id, body) in bodies.iter_mut().enumerate() {
+            let id = NonTerminalId(id);
+            if let Some(mode) = self.lexer_modes.get(&id) {
+                body.insert(Some(mode.name));
+            } else if self.axioms.contains(id) {
+                body.insert(None);
+            }
+            if self.axioms.contains(id) {
+                entries[id.0].insert(None);
+            }
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for rule in self.rules.iter() {
+                let mut at_start = true;
+                for element in rule.elements.iter() {
+                    let ElementType::NonTerminal(id) = element.element_type else {
+                        at_start = false;
+                        continue;
+                    };
+                    let outer = if at_start {
+                        entries[rule.id.0].clone()
+                    } else {
+                        bodies[rule.id.0].clone()
+                    };
+                    let before = entries[id.0].len();
+                    entries[id.0].extend(outer);
+                    changed |= entries[id.0].len() != before;
+                    if !self.lexer_modes.contains_key(&id) {
+                        let body = bodies[rule.id.0].clone();
+                        let before = bodies[id.0].len();
+                        bodies[id.0].extend(body);
+                        changed |= bodies[id.0].len() != before;
+                    }
+                    at_start &= self.nullables.contains(id);
+                }
+            }
+        }
+        let terminals_of = |modes: &BTreeSet<Option<Symbol>>| {
+            if modes.is_empty() || modes.contains(&None) {
+                return None;
+            }
+            let terminals = self
+                .lexer_modes
+                .values()
+                .filter(|mode| modes.contains(&Some(mode.name)))
+                .flat_map(|mode| mode.terminals.iter().copied())
+                .collect::<BTreeSet<_>>();
+            Some(terminals.into_iter().collect())
+        };
+        entries
+            .iter()
+            .zip(&bodies)
+            .map(|(entry, body)| LexerContext {
+                entry: terminals_of(entry),
+                body: terminals_of(body),
+            })
+            .collect()
     }
 
-    pub fn name_of(&self, id: NonTerminalId) -> Rc<str> {
-        self.name_of[id].clone()
+    pub fn name_of(&self, id: NonTerminalId) -> Symbol {
+        self.name_of[id]
     }
 
     pub fn description_of(&self, id: NonTerminalId) -> Option<Rc<str>> {
         self.description_of[id].as_ref().cloned()
     }
 
-    pub fn id_of(&self, name: Rc<str>) -> NonTerminalId {
-        self.id_of[&name]
+    pub fn id_of(&self, name: &str) -> NonTerminalId {
+        self.id_of[name]
+    }
+
+    /// Return the rules of the grammar, indexed by their identifier.
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    /// Compare two rules by the level of the precedence table they are at, the
+    /// greatest being the one that binds the least tightly, which the parser builds
+    /// around the other. Rules that are not both at a level are equal.
+    pub fn compare_precedence(&self, left: RuleId, right: RuleId) -> Ordering {
+        match (self.rules[left].precedence, self.rules[right].precedence) {
+            (Some(left), Some(right)) => right.cmp(&left),
+            _ => Ordering::Equal,
+        }
+    }
+
+    /// Return the identifiers of the rules whose LHS is `id`.
+    pub fn rules_of(&self, id: NonTerminalId) -> &[RuleId] {
+        &self.rules_of[id]
+    }
+
+    /// Return the number of non-terminals, including the ones
+    /// produced by macro invocations.
+    pub fn nb_non_terminals(&self) -> usize {
+        self.name_of.len()
+    }
+
+    /// Iterate over the identifiers of every non-terminal, in definition order.
+    pub fn non_terminals(&self) -> impl Iterator<Item = NonTerminalId> {
+        (0..self.nb_non_terminals()).map(NonTerminalId)
+    }
+
+    /// Return whether the non-terminal `id` can match the empty string.
+    pub fn is_nullable(&self, id: NonTerminalId) -> bool {
+        self.nullables.contains(id)
+    }
+
+    /// Return whether the non-terminal `id` is an axiom.
+    pub fn is_axiom(&self, id: NonTerminalId) -> bool {
+        self.axioms.contains(id)
+    }
+
+    /// Drop the proxies and the attributes of the rules, which only serve to build
+    /// ASTs. The grammar still recognises the same language, but its ASTs are
+    /// bare nodes.
+    pub fn strip_attributes(&mut self) {
+        for id in 0..self.rules.len() {
+            let rule = &mut self.rules[RuleId(id)];
+            rule.proxy.clear();
+            for element in rule.elements.iter_mut() {
+                element.attribute = Attribute::None;
+                element.key = None;
+                element.inherited.clear();
+            }
+        }
+    }
+
+    /// Generate a random sentence of the grammar from `seed`, whose tokens are lexed
+    /// by `lexer_grammar`. Past `depth_limit` nested non-terminals, the rules that end
+    /// the derivation the soonest are picked. Return `None` if no axiom derives
+    /// anything. See [`AstGenerator::sentence`].
+    pub fn generate(
+        &self,
+        lexer_grammar: &LexerGrammar,
+        depth_limit: usize,
+        seed: u64,
+    ) -> Option<String> {
+        AstGenerator::new(self, lexer_grammar)
+            .with_max_depth(depth_limit)
+            .sentence(seed)
+    }
+
+    /// Check that the grammar, loaded from `path`, was built against a lexer
+    /// grammar that agrees with `lexer_grammar` on the terminal identifiers.
+    pub fn check_lexer(&self, lexer_grammar: &LexerGrammar, path: &Path) -> Result<()> {
+        if self.lexer_fingerprint != lexer_grammar.fingerprint() {
+            return ErrorKind::LexerMismatch {
+                path: path.to_owned(),
+            }
+            .err();
+        }
+        Ok(())
+    }
+
+    /// The canonical form of the grammar: a text that only depends on what the
+    /// grammar parses and how it builds ASTs, and not on where it was defined.
+    /// Terminals are given by their identifier, which the fingerprint of the lexer
+    /// grammar the form starts with ties to their name. Two grammars are equal if
+    /// and only if their canonical forms are.
+    pub fn canonical(&self) -> String {
+        let mut canonical = format!(
+            "lexer {:016x}\nprefer {:?}\n",
+            self.lexer_fingerprint, self.rule_preference
+        );
+        if let Some(language_version) = self.language_version {
+            canonical.push_str(&format!("version {language_version}\n"));
+        }
+        for id in (0..self.nb_non_terminals()).map(NonTerminalId) {
+            if self.is_axiom(id) {
+                canonical.push('@');
+            }
+            canonical.push_str(&self.name_of[id]);
+            if let Some(description) = &self.description_of[id] {
+                canonical.push_str(&format!(" {description:?}"));
+            }
+            if let Some(mode) = self.lexer_modes.get(&id) {
+                let terminals: Vec<_> =
+                    mode.terminals.iter().map(|id| format!("#{}", id.0)).collect();
+                canonical.push_str(&format!(" (mode {}: {})", mode.name, terminals.join(" ")));
+            }
+            canonical.push('\n');
+        }
+        for rule in self.rules.iter() {
+            let associativity = if rule.left_associative { "left" } else { "right" };
+            let name = self.name_of[rule.id];
+            canonical.push_str(&format!("{name} ::= ({associativity}-assoc)"));
+            if let Some(level) = rule.precedence {
+                canonical.push_str(&format!(" (level {level})"));
+            }
+            if rule.weight != 0.0 {
+                canonical.push_str(&format!(" (weight {})", rule.weight));
+            }
+            if let Some(predicate) = rule.predicate {
+                canonical.push_str(&format!(" &{{{predicate}}}"));
+            }
+            for element in rule.elements.iter() {
+                canonical.push(' ');
+                canonical.push_str(&self.canonical_element(element));
+            }
+            canonical.push_str(&format!(" <{}>\n", self.canonical_proxy(&rule.proxy)));
+        }
+        canonical
+    }
+
+    fn canonical_element(&self, element: &Element) -> String {
+        let mut canonical = match element.element_type {
+            ElementType::Terminal(id) => format!("#{}", id.0),
+            ElementType::NonTerminal(id) => self.name_of[id].to_string(),
+        };
+        if !element.inherited.is_empty() {
+            canonical.push_str(&format!(" {{{}}}", self.canonical_proxy(&element.inherited)));
+        }
+        match &element.attribute {
+            Attribute::Named(attribute) => canonical.push_str(&format!(".{attribute}")),
+            Attribute::Indexed(index) => canonical.push_str(&format!(".{index}")),
+            Attribute::None => {}
+        }
+        if let Some(key) = element.key {
+            canonical.push_str(&format!("@{key}"));
+        }
+        canonical
+    }
+
+    /// The entries of `proxy`, sorted by key.
+    fn canonical_proxy(&self, proxy: &Proxy) -> String {
+        proxy
+            .iter()
+            .sorted_by_key(|&(&key, _)| key)
+            .map(|(key, value)| match value {
+                ValueTemplate::String(string) => format!("{key}: {string:?}"),
+                ValueTemplate::Variable(variable) => format!("{key}: {variable}"),
+                ValueTemplate::InlineRule {
+                    non_terminal,
+                    attributes,
+                } => format!(
+                    "{key}: {} {{{}}}",
+                    self.name_of[*non_terminal],
+                    self.canonical_proxy(attributes)
+                ),
+            })
+            .join(", ")
+    }
+
+    /// A parser grammar that builds this grammar, as far as the compiled grammar
+    /// tells: templates are written as the non-terminals their invocations produce,
+    /// a rule with optional elements as one rule with and one without each of them,
+    /// and the precedence table with the last terminal of each rule it orders. Terminals
+    /// are named after `lexer_grammar` if it is given, and written `#id` otherwise.
+    pub fn decompile(&self, lexer_grammar: Option<&LexerGrammar>) -> String {
+        let mut declarations = Vec::new();
+        if self.rule_preference == RulePreference::First {
+            declarations.push(String::from("prefer first-rule;"));
+        }
+        if let Some(language_version) = self.language_version {
+            declarations.push(format!("version \"{language_version}\";"));
+        }
+        let terminal = |id: TerminalId| {
+            lexer_grammar.map_or_else(
+                || format!("#{}", id.0),
+                |lexer_grammar| lexer_grammar.name(id).to_string(),
+            )
+        };
+
+        // A rule has the precedence, and the associativity, of its last operator.
+        let mut levels: BTreeMap<usize, (bool, Vec<TerminalId>)> = BTreeMap::new();
+        for rule in self.rules.iter() {
+            let Some(level) = rule.precedence else {
+                continue;
+            };
+            let operator = rule.elements.iter().rev().find_map(|element| {
+                let ElementType::Terminal(id) = element.element_type else {
+                    return None;
+                };
+                Some(id)
+            });
+            let (_, operators) = levels
+                .entry(level)
+                .or_insert_with(|| (rule.left_associative, Vec::new()));
+            if let Some(operator) = operator.filter(|operator| !operators.contains(operator)) {
+                operators.push(operator);
+            }
+        }
+        if !levels.is_empty() {
+            let mut table = String::from("precedence {\n");
+            for (left_associative, operators) in levels.into_values() {
+                table.push_str(if left_associative { "  left" } else { "  right" });
+                for &operator in &operators {
+                    table.push_str(&format!(" {}", terminal(operator)));
+                }
+                table.push_str(";\n");
+            }
+            table.push('}');
+            declarations.push(table);
+        }
+
+        let mut modes = HashSet::new();
+        for mode in self.lexer_modes.values().sorted_by_key(|mode| mode.name) {
+            if modes.insert(mode.name) {
+                let terminals: Vec<_> = mode.terminals.iter().map(|&id| terminal(id)).collect();
+                declarations.push(format!("mode {} ::= {};", mode.name, terminals.join(" ")));
+            }
+        }
+
+        for id in (0..self.nb_non_terminals()).map(NonTerminalId) {
+            let mut declaration = String::new();
+            if let Some(description) = &self.description_of[id] {
+                declaration.push_str(&format!("\"{description}\"\n"));
+            }
+            if self.is_axiom(id) {
+                declaration.push('@');
+            }
+            declaration.push_str(&self.name_of[id]);
+            if let Some(mode) = self.lexer_modes.get(&id) {
+                declaration.push_str(&format!(" (mode {})", mode.name));
+            }
+            declaration.push_str(" ::=");
+            for &rule in self.rules_of(id) {
+                let rule = &self.rules[rule];
+                let mut parts = Vec::new();
+                if !rule.left_associative && rule.precedence.is_none() {
+                    parts.push(String::from("(right-assoc)"));
+                }
+                if rule.weight != 0.0 {
+                    parts.push(format!("(weight {})", rule.weight));
+                }
+                if let Some(predicate) = rule.predicate {
+                    parts.push(format!("&{{{predicate}}}"));
+                }
+                for element in rule.elements.iter() {
+                    let mut part = match element.element_type {
+                        ElementType::Terminal(id) => terminal(id),
+                        ElementType::NonTerminal(id) => self.name_of[id].to_string(),
+                    };
+                    if !element.inherited.is_empty() {
+                        let inherited = self.decompile_entries(&element.inherited);
+                        part.push_str(&format!("{{{}}}", inherited.join(", ")));
+                    }
+                    match &element.attribute {
+                        Attribute::Named(attribute) => part.push_str(&format!(".{attribute}")),
+                        Attribute::Indexed(index) => part.push_str(&format!(".{index}")),
+                        Attribute::None => {}
+                    }
+                    if let Some(key) = element.key {
+                        part.push_str(&format!("@{key}"));
+                    }
+                    parts.push(part);
+                }
+                parts.push(format!("<{}>", self.decompile_entries(&rule.proxy).join(", ")));
+                declaration.push_str("\n  ");
+                declaration.push_str(&parts.join(" "));
+            }
+            declaration.push(';');
+            declarations.push(declaration);
+        }
+        let mut decompiled = declarations.join("\n\n");
+        decompiled.push('\n');
+        decompiled
+    }
+
+    /// The entries of `proxy`, as written in a parser grammar: the variant first, and
+    /// then the others, sorted by key.
+    fn decompile_entries(&self, proxy: &Proxy) -> Vec<String> {
+        let variant = proxy.iter().find_map(|(key, value)| match value {
+            ValueTemplate::String(variant) if key.as_str() == "variant" => Some(variant),
+            _ => None,
+        });
+        let entries = proxy
+            .iter()
+            .filter(|(key, _)| variant.is_none() || key.as_str() != "variant")
+            .sorted_by_key(|&(&key, _)| key)
+            .map(|(key, value)| match value {
+                ValueTemplate::String(string) => format!("{key}: \"{string}\""),
+                ValueTemplate::Variable(variable) => format!("{key}: {variable}"),
+                ValueTemplate::InlineRule {
+                    non_terminal,
+                    attributes,
+                } => format!(
+                    "{key}: {} {{{}}}",
+                    self.name_of[*non_terminal],
+                    self.decompile_entries(attributes).join(", ")
+                ),
+            });
+        variant.map(|variant| variant.to_string()).into_iter().chain(entries).collect()
+    }
+}
+
+impl PartialEq for EarleyGrammar {
+    fn eq(&self, other: &Self) -> bool {
+        self.canonical() == other.canonical()
+    }
+}
+
+impl Eq for EarleyGrammar {}
+
+/// Grammars hash like their canonical form, so with a
+/// [`StableHasher`](crate::stable::StableHasher), their hash is stable across builds.
+impl Hash for EarleyGrammar {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.canonical().hash(state)
+    }
+}
+
+/// The template rules every parser grammar can invoke without declaring them, such
+/// as `List[Item, COMMA]`, unless it declares one of the same name.
+const PRELUDE: &str = include_str!("prelude.gr");
+
+/// The template rules of [`PRELUDE`], parsed once per thread.
+fn prelude() -> Result<Vec<MacroDeclaration>> {
+    thread_local! {
+        static PRELUDE_MACROS: OnceCell<Vec<MacroDeclaration>> = const { OnceCell::new() };
     }
+    PRELUDE_MACROS.with(|macros| {
+        if let Some(macros) = macros.get() {
+            return Ok(macros.clone());
+        }
+        let parsed = parse_prelude()?;
+        Ok(macros.get_or_init(|| parsed).clone())
+    })
+}
+
+/// Parse the template rules of [`PRELUDE`].
+fn parse_prelude() -> Result<Vec<MacroDeclaration>> {
+    let (lexer, parser) = build_system!(
+        lexer => "parser.clx",
+        parser => "parser.cgr",
+    )?;
+    let mut source = StringStream::new(Path::new("<prelude>"), PRELUDE);
+    let tree = parser.parse(&mut lexer.lex(&mut source))?.tree;
+    Ok(Ast::read(tree)?
+        .decls
+        .into_iter()
+        .filter_map(|decl| match decl.inner {
+            ToplevelDeclaration::Macro(macro_decl) => Some(*macro_decl),
+            _ => None,
+        })
+        .collect())
+}
+
+impl crate::sealed::Sealed for EarleyGrammar {}
+
+impl Compiled for EarleyGrammar {
+    const KIND: CompiledKind = CompiledKind::EarleyGrammar;
 }
 
 impl EarleyGrammar {
@@ -242,15 +930,24 @@ impl EarleyGrammar {
         blob: &[u8],
         path: impl ToOwned<Owned = PathBuf>,
     ) -> Result<Self> {
-        deserialize(blob).map_err(|error| Error::with_file(error, path.to_owned()))
+        deserialize_compiled(blob, &path.to_owned())
     }
 
     pub fn build_from_ast(ast: AST, lexer_grammar: &LexerGrammar) -> Result<Self> {
+        Self::build_from_ast_with(ast, lexer_grammar, &PassManager::new())
+    }
+
+    /// Build the grammar from `ast`, once `passes` have transformed it.
+    pub fn build_from_ast_with(
+        ast: AST,
+        lexer_grammar: &LexerGrammar,
+        passes: &PassManager,
+    ) -> Result<Self> {
         type InvokedMacros = HashMap<(Rc<str>, Rc<[ElementType]>), NonTerminalId>;
         type MacroDeclarations = HashMap<Rc<str>, (Vec<Spanned<Rc<str>>>, Vec<AstRule>, Span)>;
         type FoundNonTerminals = HashMap<Rc<str>, (NonTerminalId, Span)>;
 
-        let typed_ast = Ast::read(ast)?;
+        let typed_ast = Ast::read(passes.run(ast, lexer_grammar)?)?;
         // `macro_declarations` holds every macro declaration found in a grammar. This will be
         // used to invoke the macros.
         //
@@ -266,15 +963,101 @@ impl EarleyGrammar {
         let mut id_of = HashMap::new();
         let mut name_of = NonTerminalName::new();
         let mut description_of = NonTerminalDescription::new();
+        let mut rule_preference = RulePreference::default();
+        let mut language_version = None;
+        let mut lexer_modes = HashMap::new();
+        let mut moded_nonterminals = Vec::new();
+        // The level of each operator of the precedence table, whether it is
+        // left-associative, and where it was given.
+        let mut precedences = HashMap::new();
+        let mut levels = 0;
 
         for decl in typed_ast.decls {
             match decl.inner {
+                ToplevelDeclaration::Prefer(preference) => rule_preference = preference,
+                ToplevelDeclaration::Version(version) => {
+                    let Spanned { inner, span } = version.0;
+                    match inner.parse() {
+                        Ok(version) => language_version = Some(version),
+                        Err(message) => {
+                            return ErrorKind::InvalidLanguageVersion {
+                                version: inner.to_string(),
+                                message,
+                                span: (&span).into(),
+                            }
+                            .err();
+                        }
+                    }
+                }
+                ToplevelDeclaration::Precedence(table) => {
+                    for level in table.levels {
+                        let left_associative: bool = level.assoc.inner.into();
+                        for operator in level.operators {
+                            let id = match &operator.inner {
+                                Item::Literal { text } => {
+                                    lexer_grammar.literal(&text.inner).ok_or_else(|| {
+                                        ErrorKind::GrammarUnknownLiteral {
+                                            literal: text.inner.to_string(),
+                                            span: text.span.clone().into(),
+                                        }
+                                    })?
+                                }
+                                Item::Regular { name } => {
+                                    lexer_grammar.id(&name.inner).ok_or_else(|| {
+                                        ErrorKind::GrammarUndefinedTerminal {
+                                            name: name.inner.to_string(),
+                                            span: name.span.clone().into(),
+                                        }
+                                    })?
+                                }
+                                _ => continue,
+                            };
+                            if let Some((_, _, old_span)) = precedences
+                                .insert(id, (levels, left_associative, operator.span.clone()))
+                            {
+                                return ErrorKind::GrammarDuplicatePrecedence {
+                                    name: lexer_grammar.name(id).to_string(),
+                                    span: operator.span.into(),
+                                    old_span: old_span.into(),
+                                }
+                                .err();
+                            }
+                        }
+                        levels += 1;
+                    }
+                }
+                ToplevelDeclaration::Mode(mode) => {
+                    let terminals = mode
+                        .terminals
+                        .iter()
+                        .map(|terminal| {
+                            lexer_grammar.id(&terminal.inner).ok_or_else(|| {
+                                ErrorKind::GrammarUndefinedTerminal {
+                                    name: terminal.inner.to_string(),
+                                    span: terminal.span.clone().into(),
+                                }
+                                .into()
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    if let Some((_, old_span)) = lexer_modes.insert(
+                        mode.name.inner.clone(),
+                        (terminals, mode.name.span.clone()),
+                    ) {
+                        return ErrorKind::GrammarDuplicateLexerMode {
+                            span: mode.name.span.into(),
+                            old_span: old_span.into(),
+                            name: mode.name.inner.to_string(),
+                        }
+                        .err();
+                    }
+                }
                 ToplevelDeclaration::Macro(macro_decl) => {
                     if let Some((_, _, old_span)) = macro_declarations.insert(
                         macro_decl.name.inner.clone(),
                         (
                             macro_decl.args,
-                            macro_decl.rules,
+                            expand_optionals(macro_decl.rules)?,
                             macro_decl.name.span.clone(),
                         ),
                     ) {
@@ -286,7 +1069,7 @@ impl EarleyGrammar {
                         .err();
                     }
                 }
-                ToplevelDeclaration::Decl(decl) => {
+                ToplevelDeclaration::Decl(mut decl) => {
                     let id = available_id.next();
                     if let Some((_, old_span)) = found_nonterminals
                         .insert(decl.name.inner.clone(), (id, decl.name.span.clone()))
@@ -298,13 +1081,27 @@ impl EarleyGrammar {
                         }
                         .err();
                     }
-                    id_of.insert(decl.name.inner.clone(), id);
-                    name_of.push(decl.name.inner.clone());
+                    let name = Symbol::intern(&decl.name.inner);
+                    id_of.insert(name, id);
+                    name_of.push(name);
                     description_of.push(decl.comment.as_ref().map(|o| o.inner.clone()));
+                    if let Some(mode) = &decl.mode {
+                        moded_nonterminals.push((id, mode.clone()));
+                    }
+                    decl.rules = expand_optionals(decl.rules)?;
                     non_terminal_declarations.push((decl, id));
                 }
             }
         }
+        for macro_decl in prelude()? {
+            macro_declarations
+                .entry(macro_decl.name.inner.clone())
+                .or_insert((
+                    macro_decl.args,
+                    expand_optionals(macro_decl.rules)?,
+                    macro_decl.name.span,
+                ));
+        }
 
         fn eval_rule(
             rule: &AstRule,
@@ -314,7 +1111,7 @@ impl EarleyGrammar {
             invoked_macros: &mut InvokedMacros,
             name_of: &mut NonTerminalName,
             description_of: &mut NonTerminalDescription,
-            id_of: &mut HashMap<Rc<str>, NonTerminalId>,
+            id_of: &mut HashMap<Symbol, NonTerminalId>,
             found_nonterminals: &FoundNonTerminals,
             macro_declarations: &MacroDeclarations,
             scope: &HashMap<Rc<str>, ElementType>,
@@ -342,7 +1139,7 @@ impl EarleyGrammar {
                 &rule.proxy,
                 found_nonterminals,
             )?;
-            Ok(Rule::new(
+            let mut new_rule = Rule::new(
                 macro_id,
                 new_elements,
                 proxy,
@@ -350,7 +1147,31 @@ impl EarleyGrammar {
                     .as_ref()
                     .map(|Spanned { inner, .. }| (*inner).into())
                     .unwrap_or(true),
-            ))
+                rule.span.clone(),
+            );
+            new_rule.predicate = rule
+                .predicate
+                .as_ref()
+                .map(|name| Symbol::intern(&name.inner));
+            let Some(weight) = &rule.weight else {
+                return Ok(new_rule);
+            };
+            if &*weight.name.inner != "weight" {
+                return ErrorKind::GrammarUnknownAnnotation {
+                    name: weight.name.inner.to_string(),
+                    span: weight.name.span.clone().into(),
+                }
+                .err();
+            }
+            let value = weight
+                .value
+                .inner
+                .parse::<u64>()
+                .map_err(|_| ErrorKind::IntegerTooBig {
+                    string: weight.value.inner.to_string(),
+                    span: weight.value.span.clone().into(),
+                })?;
+            Ok(new_rule.with_weight(value as f64))
         }
 
         fn invoke_macro(
@@ -362,7 +1183,7 @@ impl EarleyGrammar {
             invoked_macros: &mut InvokedMacros,
             name_of: &mut NonTerminalName,
             description_of: &mut NonTerminalDescription,
-            id_of: &mut HashMap<Rc<str>, NonTerminalId>,
+            id_of: &mut HashMap<Symbol, NonTerminalId>,
             found_nonterminals: &FoundNonTerminals,
             macro_declarations: &MacroDeclarations,
             lexer_grammar: &LexerGrammar,
@@ -420,7 +1241,7 @@ impl EarleyGrammar {
             invoked_macros: &mut InvokedMacros,
             name_of: &mut NonTerminalName,
             description_of: &mut NonTerminalDescription,
-            id_of: &mut HashMap<Rc<str>, NonTerminalId>,
+            id_of: &mut HashMap<Symbol, NonTerminalId>,
             found_nonterminals: &FoundNonTerminals,
             macro_declarations: &MacroDeclarations,
             scope: &HashMap<Rc<str>, ElementType>,
@@ -431,18 +1252,61 @@ impl EarleyGrammar {
                 Item::Regular { name } => {
                     if let Some(element) = scope.get(&name.inner) {
                         *element
-                    } else if let Some((id, _)) = found_nonterminals.get(&name.inner) {
-                        // The item is a non terminal
-                        ElementType::NonTerminal(*id)
-                    } else if let Some(id) = lexer_grammar.id(&name.inner) {
-                        ElementType::Terminal(id)
                     } else {
+                        match (
+                            found_nonterminals.get(&name.inner),
+                            lexer_grammar.id(&name.inner),
+                        ) {
+                            (Some((_, nonterminal_span)), Some(id)) => {
+                                return ErrorKind::GrammarAmbiguousName {
+                                    name: name.inner.to_string(),
+                                    span: name.span.clone().into(),
+                                    nonterminal_span: nonterminal_span.clone().into(),
+                                    terminal_span: lexer_grammar.span_of(id).clone().into(),
+                                }
+                                .err();
+                            }
+                            (Some((id, _)), None) => ElementType::NonTerminal(*id),
+                            (None, Some(id)) => ElementType::Terminal(id),
+                            (None, None) => {
+                                return ErrorKind::GrammarUndefinedNonTerminal {
+                                    name: name.inner.to_string(),
+                                    span: name.span.clone().into(),
+                                }
+                                .err();
+                            }
+                        }
+                    }
+                }
+                Item::Terminal { name } => {
+                    let Some(id) = lexer_grammar.id(&name.inner) else {
+                        return ErrorKind::GrammarUndefinedTerminal {
+                            name: name.inner.to_string(),
+                            span: name.span.clone().into(),
+                        }
+                        .err();
+                    };
+                    ElementType::Terminal(id)
+                }
+                Item::NonTerminal { name } => {
+                    let Some((id, _)) = found_nonterminals.get(&name.inner) else {
                         return ErrorKind::GrammarUndefinedNonTerminal {
                             name: name.inner.to_string(),
                             span: name.span.clone().into(),
                         }
                         .err();
-                    }
+                    };
+                    ElementType::NonTerminal(*id)
+                }
+                Item::Literal { text } => {
+                    let Some(id) = lexer_grammar.literal(&text.inner) else {
+                        return ErrorKind::GrammarUnknownLiteral {
+                            literal: text.inner.to_string(),
+                            span: text.span.clone().into(),
+                        }
+                        .err();
+                    };
+                    ElementType::Terminal(id)
                 }
                 Item::MacroInvocation { name, arguments } => {
                     let mut args = Vec::new();
@@ -471,14 +1335,14 @@ impl EarleyGrammar {
                         complete_name.extend(
                             args.iter()
                                 .map(|element| match element {
-                                    ElementType::NonTerminal(id) => &*name_of[*id],
+                                    ElementType::NonTerminal(id) => name_of[*id].as_str(),
                                     ElementType::Terminal(id) => lexer_grammar.name(*id),
                                 })
                                 .intersperse(", "),
                         );
                         complete_name.push(']');
-                        let complete_name: Rc<str> = Rc::from(complete_name);
-                        id_of.insert(complete_name.clone(), id);
+                        let complete_name = Symbol::intern(&complete_name);
+                        id_of.insert(complete_name, id);
                         name_of.push(complete_name);
                         description_of.push(None);
                         e.insert(id);
@@ -511,7 +1375,7 @@ impl EarleyGrammar {
             invoked_macros: &mut InvokedMacros,
             name_of: &mut NonTerminalName,
             description_of: &mut NonTerminalDescription,
-            id_of: &mut HashMap<Rc<str>, NonTerminalId>,
+            id_of: &mut HashMap<Symbol, NonTerminalId>,
             found_nonterminals: &HashMap<Rc<str>, (NonTerminalId, Span)>,
             macro_declarations: &MacroDeclarations,
             scope: &HashMap<Rc<str>, ElementType>,
@@ -522,7 +1386,7 @@ impl EarleyGrammar {
                     attribute,
                     named: Spanned { inner: true, .. },
                     span: _span,
-                }) => Attribute::Named(attribute.inner.clone()),
+                }) => Attribute::Named(Symbol::intern(&attribute.inner)),
                 Some(AstAttribute {
                     attribute,
                     named: Spanned { inner: false, .. },
@@ -555,7 +1419,31 @@ impl EarleyGrammar {
                 scope,
                 lexer_grammar,
             )?;
-            Ok(Element::new(attribute, key.map(|o| o.inner), element_type))
+            // Groups of terminals are referred to by their index.
+            let attribute = match (attribute, &element_type, &element.attribute) {
+                (Attribute::Named(name), &ElementType::Terminal(terminal), Some(ast)) => {
+                    let index = lexer_grammar.group_index(terminal, &name).ok_or_else(|| {
+                        ErrorKind::GrammarUnknownGroup {
+                            terminal: lexer_grammar.name(terminal).to_string(),
+                            name: name.to_string(),
+                            span: ast.span.clone().into(),
+                        }
+                    })?;
+                    Attribute::Indexed(index)
+                }
+                (attribute, ..) => attribute,
+            };
+            let inherited = eval_proxy(
+                &AstProxy {
+                    variant: None,
+                    items: element.inherited.clone(),
+                    span: element.span.clone(),
+                },
+                found_nonterminals,
+            )?;
+            let key = key.map(|key| Symbol::intern(&key.inner));
+            Ok(Element::new(attribute, key, element_type)
+                .with_inherited(inherited))
         }
 
         fn eval_proxy(
@@ -572,7 +1460,7 @@ impl EarleyGrammar {
             for (key, (expression, _)) in proxy.items.iter() {
                 let value = match &expression.inner {
                     Expression::String(string) => ValueTemplate::String(string.clone()),
-                    Expression::Id(id) => ValueTemplate::Variable(id.clone()),
+                    Expression::Id(id) => ValueTemplate::Variable(Symbol::intern(id)),
                     Expression::Instanciation {
                         name,
                         children,
@@ -601,48 +1489,184 @@ impl EarleyGrammar {
                         }
                     }
                 };
-                actual_proxy.insert(key.clone(), value);
+                actual_proxy.insert(Symbol::intern(key), value);
             }
             Ok(actual_proxy)
         }
 
-        let mut invoked_macros: InvokedMacros = HashMap::new();
-        let mut found_axioms = Vec::new();
-        let mut rules = Rules::new();
-        let empty_scope = HashMap::new();
-        for (declaration, id) in non_terminal_declarations {
-            if declaration.axiom.inner {
-                found_axioms.push(id);
-            }
-            for rule in declaration.rules {
-                let parsed_rule = eval_rule(
-                    &rule,
-                    id,
-                    &mut available_id,
-                    &mut rules,
-                    &mut invoked_macros,
-                    &mut name_of,
-                    &mut description_of,
-                    &mut id_of,
-                    &found_nonterminals,
-                    &macro_declarations,
-                    &empty_scope,
+        /// Push onto `undefined` an error for each terminal, non-terminal or literal
+        /// that `item` refers to but that is not defined, the names of `scope`
+        /// being defined.
+        fn undefined_references(
+            item: &Spanned<Item>,
+            scope: &[Spanned<Rc<str>>],
+            found_nonterminals: &FoundNonTerminals,
+            lexer_grammar: &LexerGrammar,
+            undefined: &mut Vec<Error>,
+        ) {
+            let error = match &item.inner {
+                Item::SelfNonTerminal => None,
+                Item::Regular { name } => {
+                    let defined = scope.iter().any(|arg| arg.inner == name.inner)
+                        || found_nonterminals.contains_key(&name.inner)
+                        || lexer_grammar.id(&name.inner).is_some();
+                    (!defined).then(|| ErrorKind::GrammarUndefinedNonTerminal {
+                        name: name.inner.to_string(),
+                        span: name.span.clone().into(),
+                    })
+                }
+                Item::Terminal { name } => lexer_grammar.id(&name.inner).is_none().then(|| {
+                    ErrorKind::GrammarUndefinedTerminal {
+                        name: name.inner.to_string(),
+                        span: name.span.clone().into(),
+                    }
+                }),
+                Item::NonTerminal { name } => {
+                    (!found_nonterminals.contains_key(&name.inner)).then(|| {
+                        ErrorKind::GrammarUndefinedNonTerminal {
+                            name: name.inner.to_string(),
+                            span: name.span.clone().into(),
+                        }
+                    })
+                }
+                Item::Literal { text } => lexer_grammar.literal(&text.inner).is_none().then(|| {
+                    ErrorKind::GrammarUnknownLiteral {
+                        literal: text.inner.to_string(),
+                        span: text.span.clone().into(),
+                    }
+                }),
+                Item::MacroInvocation { arguments, .. } => {
+                    for argument in arguments {
+                        undefined_references(
+                            argument,
+                            scope,
+                            found_nonterminals,
+                            lexer_grammar,
+                            undefined,
+                        );
+                    }
+                    None
+                }
+            };
+            undefined.extend(error.map(Error::new));
+        }
+
+        // Every undefined reference is reported at once, rather than the first one.
+        let mut undefined = Vec::new();
+        let declared_rules = non_terminal_declarations
+            .iter()
+            .flat_map(|(declaration, _)| &declaration.rules)
+            .map(|rule| (rule, &[][..]));
+        let macro_rules = macro_declarations
+            .values()
+            .flat_map(|(args, rules, _)| rules.iter().map(move |rule| (rule, &args[..])));
+        for (rule, scope) in declared_rules.chain(macro_rules) {
+            for element in rule.elements.iter() {
+                undefined_references(
+                    &element.item,
+                    scope,
+                    &found_nonterminals,
+                    lexer_grammar,
+                    &mut undefined,
+                );
+            }
+        }
+        if undefined.len() == 1 {
+            return Err(undefined.remove(0));
+        } else if !undefined.is_empty() {
+            return ErrorKind::GrammarUndefinedReferences { errors: undefined }.err();
+        }
+
+        let lexer_modes = moded_nonterminals
+            .into_iter()
+            .map(|(id, mode)| {
+                let Some((terminals, _)) = lexer_modes.get(&mode.inner) else {
+                    return ErrorKind::GrammarUndefinedLexerMode {
+                        name: mode.inner.to_string(),
+                        span: mode.span.into(),
+                    }
+                    .err();
+                };
+                let mode = LexerMode {
+                    name: Symbol::intern(&mode.inner),
+                    terminals: terminals.clone(),
+                };
+                Ok((id, mode))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        let mut invoked_macros: InvokedMacros = HashMap::new();
+        let mut found_axioms = Vec::new();
+        let mut rules = Rules::new();
+        let empty_scope = HashMap::new();
+        for (declaration, id) in non_terminal_declarations {
+            if declaration.axiom.inner {
+                found_axioms.push(id);
+            }
+            for rule in declaration.rules {
+                let parsed_rule = eval_rule(
+                    &rule,
+                    id,
+                    &mut available_id,
+                    &mut rules,
+                    &mut invoked_macros,
+                    &mut name_of,
+                    &mut description_of,
+                    &mut id_of,
+                    &found_nonterminals,
+                    &macro_declarations,
+                    &empty_scope,
                     lexer_grammar,
                 )?;
                 rules.push(parsed_rule);
             }
         }
+        // A rule takes the precedence, and the associativity, of its last operator.
+        for rule in rules.iter_mut() {
+            let operator = rule.elements.iter().rev().find_map(|element| {
+                let ElementType::Terminal(id) = element.element_type else {
+                    return None;
+                };
+                precedences.get(&id)
+            });
+            if let Some(&(level, left_associative, _)) = operator {
+                rule.precedence = Some(level);
+                rule.left_associative = left_associative;
+            }
+        }
         let mut axioms = Axioms::with_capacity(available_id.next());
         for axiom in found_axioms {
             axioms.put(axiom);
         }
-        let res = Self::new(rules, axioms, id_of, name_of, description_of)?;
-        Ok(res)
+        let res = Self::new(
+            rules,
+            axioms,
+            id_of,
+            name_of,
+            description_of,
+            lexer_grammar.fingerprint(),
+        )?
+        .with_rule_preference(rule_preference)
+        .with_lexer_modes(lexer_modes);
+        Ok(match language_version {
+            Some(language_version) => res.with_language_version(language_version),
+            None => res,
+        })
     }
 
     pub fn build_from_plain(
+        source: StringStream,
+        lexer_grammar: &LexerGrammar,
+    ) -> Result<Self> {
+        Self::build_from_plain_with(source, lexer_grammar, &PassManager::new())
+    }
+
+    /// Parse the grammar `source`, and build it once `passes` have transformed its
+    /// AST.
+    pub fn build_from_plain_with(
         mut source: StringStream,
         lexer_grammar: &LexerGrammar,
+        passes: &PassManager,
     ) -> Result<Self> {
         let (lexer, parser) = build_system!(
             lexer => "parser.clx",
@@ -650,11 +1674,21 @@ impl EarleyGrammar {
         )?;
         let mut input = lexer.lex(&mut source);
         let result = parser.parse(&mut input)?;
-        let grammar = Self::build_from_ast(result.tree, lexer_grammar)?;
+        let grammar = Self::build_from_ast_with(result.tree, lexer_grammar, passes)?;
         Ok(grammar)
     }
 
     pub fn build_from_path(path: &Path, lexer_grammar: &LexerGrammar) -> Result<Self> {
+        Self::build_from_path_with(path, lexer_grammar, &PassManager::new())
+    }
+
+    /// Build the grammar at `path`, like [`EarleyGrammar::build_from_path`], once
+    /// `passes` have transformed its AST. Compiled grammars are loaded as they are.
+    pub fn build_from_path_with(
+        path: &Path,
+        lexer_grammar: &LexerGrammar,
+        passes: &PassManager,
+    ) -> Result<Self> {
         let ast: AST = match select_format(
             path,
             &[
@@ -672,8 +1706,8 @@ impl EarleyGrammar {
                 })?
             }
             FileResult::Valid((actual_path, Format::Plain)) => {
-                let stream = StringStream::from_file(actual_path)?;
-                let result = Self::build_from_plain(stream, lexer_grammar)?;
+                let stream = StringStream::from_file_with_includes(actual_path)?;
+                let result = Self::build_from_plain_with(stream, lexer_grammar, passes)?;
                 return Ok(result);
             }
             FileResult::Valid((actual_path, Format::Compiled)) => {
@@ -682,7 +1716,8 @@ impl EarleyGrammar {
                 let mut buffer = Vec::new();
                 file.read_to_end(&mut buffer)
                     .map_err(|err| Error::with_file(err, &actual_path))?;
-                let result = Self::build_from_compiled(&buffer, actual_path)?;
+                let result = Self::build_from_compiled(&buffer, actual_path.clone())?;
+                result.check_lexer(lexer_grammar, &actual_path)?;
                 return Ok(result);
             }
             FileResult::WrongExtension(extension) => {
@@ -699,7 +1734,7 @@ impl EarleyGrammar {
                 .err();
             }
         };
-        let parser = Self::build_from_ast(ast, lexer_grammar)?;
+        let parser = Self::build_from_ast_with(ast, lexer_grammar, passes)?;
         Ok(parser)
     }
 
@@ -717,7 +1752,8 @@ impl EarleyGrammar {
             ],
         ) {
             FileResult::Valid((actual_path, Format::Compiled)) => {
-                let result = Self::build_from_compiled(blob, actual_path)?;
+                let result = Self::build_from_compiled(blob, actual_path.clone())?;
+                result.check_lexer(lexer_grammar, &actual_path)?;
                 return Ok(result);
             }
             FileResult::Valid((actual_path, Format::Ast)) => {
@@ -763,7 +1799,7 @@ newty! {
     map FinalSetIndex(Vec<FinalItemId>)[NonTerminalId]
 }
 
-#[derive(Default, Debug, Clone, Eq)]
+#[derive(Default, Debug, Clone)]
 pub struct FinalSet {
     /// An index mapping a nonterminal to every item in the set derived from that nonterminal.
     index: FinalSetIndex,
@@ -771,6 +1807,9 @@ pub struct FinalSet {
     set: FinalSetVec,
     /// The starting position of every item in this set, in the raw input.
     position: usize,
+    /// The weight of the heaviest derivation of every item, or nothing if the
+    /// rules of the grammar have no weight.
+    weights: Vec<f64>,
 }
 
 impl PartialEq for FinalSet {
@@ -779,6 +1818,8 @@ impl PartialEq for FinalSet {
     }
 }
 
+impl Eq for FinalSet {}
+
 impl FinalSet {
     fn add(&mut self, item: FinalItem, grammar: &EarleyGrammar) {
         self.index
@@ -792,6 +1833,33 @@ impl FinalSet {
     fn iter(&self) -> impl Iterator<Item = &FinalItem> + '_ {
         self.set.iter()
     }
+
+    /// The weight of the heaviest derivation of `rule`, which derives `id`, from
+    /// this set to `end`.
+    fn weight(&self, id: NonTerminalId, rule: RuleId, end: usize) -> f64 {
+        if self.weights.is_empty() {
+            return 0.0;
+        }
+        self.index
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .find(|&&item| self.set[item].rule == rule && self.set[item].end == end)
+            .map_or(f64::NEG_INFINITY, |&item| self.weights[item.0])
+    }
+
+    /// Iterate over the rules of `id` that were completed from this set,
+    /// along with the position where they ended.
+    pub(crate) fn completed(
+        &self,
+        id: NonTerminalId,
+    ) -> impl Iterator<Item = (RuleId, usize)> + '_ {
+        self.index
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .map(|&item| (self.set[item].rule, self.set[item].end))
+    }
 }
 
 impl std::fmt::Display for FinalSet {
@@ -810,7 +1878,7 @@ impl std::fmt::Display for FinalSet {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct StateSet {
     cache: HashSet<EarleyItem>,
     set: Vec<EarleyItem>,
@@ -854,10 +1922,23 @@ struct SyntaxicItem {
     end: usize,
 }
 
+impl SyntaxicItem {
+    /// Whether both items derive the same tokens with the same rule.
+    fn same_as(&self, other: &Self) -> bool {
+        self.start == other.start
+            && self.end == other.end
+            && match (&self.kind, &other.kind) {
+                (SyntaxicItemKind::Rule(left), SyntaxicItemKind::Rule(right)) => left == right,
+                (SyntaxicItemKind::Token(_), SyntaxicItemKind::Token(_)) => true,
+                _ => false,
+            }
+    }
+}
+
 #[derive(Clone, Debug)]
 enum SyntaxicItemKind {
     Rule(RuleId),
-    Token(Token),
+    Token(Box<Token>),
 }
 
 // #[derive(Debug, Clone)]
@@ -891,11 +1972,108 @@ enum SyntaxicItemKind {
 //     items: Vec<ScItem>,
 // }
 
+/// The result of [`EarleyParser::parse_all_errors`]: the tree, if the input could be
+/// parsed once the unexpected tokens were skipped, and every error found.
+#[derive(Debug)]
+pub struct RecoveredParse {
+    pub tree: Option<AST>,
+    pub errors: Vec<Error>,
+}
+
+/// One of the results of [`EarleyParser::parse_all_axioms`]: the tree of the input
+/// derived from the axiom `axiom`.
+#[derive(Debug)]
+pub struct AxiomParse {
+    pub axiom: Symbol,
+    pub tree: AST,
+}
+
+/// How much an [`Ambiguity`] deserves attention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum Severity {
+    /// The derivations split the tokens differently between the same elements, and
+    /// the associativity picked one of them.
+    Note,
+    /// The derivations use different rules, and the order of the rules picked one
+    /// of them.
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Note => write!(f, "note"),
+            Self::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A place where the input could be derived in several ways, and where
+/// [`EarleyParser::select_ast`] had to pick one of them.
+#[derive(Debug, Clone)]
+pub struct Ambiguity {
+    pub severity: Severity,
+    /// The non-terminal whose derivation was picked.
+    pub nonterminal: Symbol,
+    pub span: Span,
+    /// Where the rules the derivations differ on are defined, the picked one first.
+    pub rules: Vec<Span>,
+}
+
+impl fmt::Display for Ambiguity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: {} is ambiguous {}, the derivations differ on the rules",
+            self.severity, self.nonterminal, self.span
+        )?;
+        for rule in &self.rules {
+            writeln!(f, " - {rule}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The progress of a parse whose input is fed with [`EarleyParser::feed_tokens`].
+#[derive(Debug, Default, Clone)]
+pub struct PartialParse {
+    sets: Table,
+    raw_input: Vec<Token>,
+    /// Where the lexer stopped, with the blocks of layout mode open there.
+    checkpoint: Checkpoint,
+}
+
+/// A semantic action, which builds the value of a node from the values of the
+/// elements of its rule, in order, and the span it covers.
+type Action = Box<dyn Fn(Vec<AST>, &Span) -> AST>;
+
+/// A semantic predicate, which tells whether a rule may match the tokens it is
+/// given second, the tokens before them being given first.
+type Predicate = Box<dyn Fn(&[Token], &[Token]) -> bool>;
+
 /// # Summary
 /// [`EarleyParser`] is the parser related to the [`EarleyGrammar`](EarleyGrammar).
-#[derive(Debug)]
 pub struct EarleyParser {
     grammar: EarleyGrammar,
+    actions: HashMap<NonTerminalId, Action>,
+    /// Overrides the rule preference of the grammar.
+    rule_preference: Option<RulePreference>,
+    /// Replaces the [`Standard`] disambiguator.
+    disambiguator: Option<Box<dyn Disambiguator>>,
+    /// The predicates of the rules, by name.
+    predicates: HashMap<Symbol, Predicate>,
+}
+
+impl fmt::Debug for EarleyParser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EarleyParser")
+            .field("grammar", &self.grammar)
+            .field("rule_preference", &self.rule_preference)
+            .field("custom_disambiguator", &self.disambiguator.is_some())
+            .field("predicates", &self.predicates.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
 }
 
 impl EarleyParser {
@@ -905,102 +2083,295 @@ impl EarleyParser {
         lexer_grammar: &LexerGrammar,
     ) -> Result<Self> {
         let grammar = EarleyGrammar::build_from_blob(blob, path, lexer_grammar)?;
-        Ok(Self { grammar })
+        Ok(Self::new(grammar))
     }
 
-    fn find_children(
+    /// Prefer the first, or the last, of two rules when associativity does not
+    /// decide, whatever the grammar prefers.
+    pub fn with_rule_preference(mut self, rule_preference: RulePreference) -> Self {
+        self.rule_preference = Some(rule_preference);
+        self
+    }
+
+    /// The rule preference the parser follows, its own or the grammar's.
+    pub fn rule_preference(&self) -> RulePreference {
+        self.rule_preference.unwrap_or(self.grammar.rule_preference)
+    }
+
+    /// Pick between derivations of equal weight with `disambiguator` rather than
+    /// with associativity and the rule preference.
+    pub fn with_disambiguator(mut self, disambiguator: impl Disambiguator + 'static) -> Self {
+        self.disambiguator = Some(Box::new(disambiguator));
+        self
+    }
+
+    /// Give the rules whose predicate is `name`, as in `&{name}`, the predicate
+    /// `predicate`, replacing the previous one, if any. A rule only matches tokens
+    /// that `predicate` accepts, given the tokens before them, and then the tokens
+    /// themselves, so context-sensitive constructs, such as the type names of C,
+    /// can be told apart from the others while recognising the input.
+    pub fn with_predicate(
+        mut self,
+        name: &str,
+        predicate: impl Fn(&[Token], &[Token]) -> bool + 'static,
+    ) -> Self {
+        self.predicates.insert(Symbol::intern(name), Box::new(predicate));
+        self
+    }
+
+    /// Whether `item` may be added to a state set once `tokens` have been
+    /// recognised: unless it is complete, matches tokens, and the predicate of its
+    /// rule rejects them.
+    fn admits(&self, item: &EarleyItem, tokens: &[Token]) -> bool {
+        let rule = &self.grammar.rules[item.rule];
+        let Some(name) = rule.predicate else {
+            return true;
+        };
+        let complete = item.position as usize == rule.elements.len();
+        if !complete || item.origin as usize == tokens.len() {
+            return true;
+        }
+        let (before, matched) = tokens.split_at(item.origin as usize);
+        self.predicates
+            .get(&name)
+            .is_some_and(|predicate| predicate(before, matched))
+    }
+
+    /// Weigh the rules of the grammar from the concrete syntax trees of
+    /// `treebank`, which are usually the ones the parser built and a human fixed,
+    /// like [`EarleyGrammar::learn_weights`].
+    pub fn learn_weights<'a>(&mut self, treebank: impl IntoIterator<Item = &'a Cst>) {
+        self.grammar.learn_weights(treebank);
+    }
+
+    /// Build the nodes of the non-terminal `name` with `action` rather than from
+    /// the proxies of its rules, replacing the previous action, if any. `action`
+    /// gets the values of the elements of the rule, in order, and the span of the
+    /// node. Rules that use an attribute of the value, as in `Sum.left@key`, expect
+    /// it to be a node that has it.
+    pub fn on_rule(
+        &mut self,
+        name: &str,
+        action: impl Fn(Vec<AST>, &Span) -> AST + 'static,
+    ) -> Result<()> {
+        let Some(&id) = self.grammar.id_of.get(name) else {
+            return ErrorKind::UnknownNonTerminal {
+                name: name.to_string(),
+            }
+            .err();
+        };
+        self.actions.insert(id, Box::new(action));
+        Ok(())
+    }
+
+    /// Every way of splitting the tokens the rule item `element` covers between the
+    /// elements of its rule, each list of children starting from the last one.
+    fn candidate_children(
         &self,
-        element: SyntaxicItem,
+        rule: RuleId,
+        element: &SyntaxicItem,
         forest: &[FinalSet],
         raw_input: &[Token],
-    ) -> Vec<SyntaxicItem> {
-        match element.kind {
-            SyntaxicItemKind::Rule(rule) => {
-                let mut boundary = vec![(List::default(), element.start)];
-                for elem in self.grammar.rules[rule].elements.iter() {
-                    let mut next_boundary = Vec::new();
-                    for (children, curpos) in boundary.drain(..) {
-                        match elem.element_type {
-                            ElementType::NonTerminal(id) => {
-                                if let Some(rules) = forest[curpos].index.get(&id) {
-                                    for final_item in rules
-                                        .iter()
-                                        .map(|&rule| &forest[curpos].set[rule])
-                                        .filter(|final_item| final_item.end <= element.end)
-                                    {
-                                        next_boundary.push((
-                                            children.cons(SyntaxicItem {
-                                                kind: SyntaxicItemKind::Rule(final_item.rule),
-                                                start: curpos,
-                                                end: final_item.end,
-                                            }),
-                                            final_item.end,
-                                        ))
-                                    }
-                                }
-                            }
-                            ElementType::Terminal(id)
-                                if curpos < element.end && raw_input[curpos].id() == id =>
+    ) -> Vec<List<SyntaxicItem>> {
+        let mut boundary = vec![(List::default(), element.start)];
+        for elem in self.grammar.rules[rule].elements.iter() {
+            let mut next_boundary = Vec::new();
+            for (children, curpos) in boundary.drain(..) {
+                match elem.element_type {
+                    ElementType::NonTerminal(id) => {
+                        if let Some(rules) = forest[curpos].index.get(&id) {
+                            for final_item in rules
+                                .iter()
+                                .map(|&rule| &forest[curpos].set[rule])
+                                .filter(|final_item| final_item.end <= element.end)
                             {
                                 next_boundary.push((
                                     children.cons(SyntaxicItem {
-                                        kind: SyntaxicItemKind::Token(
-                                            raw_input[curpos].clone(),
-                                        ),
+                                        kind: SyntaxicItemKind::Rule(final_item.rule),
                                         start: curpos,
-                                        end: curpos + 1,
+                                        end: final_item.end,
                                     }),
-                                    curpos + 1,
+                                    final_item.end,
                                 ))
                             }
-                            _ => {}
                         }
                     }
-                    boundary.extend(next_boundary.into_iter().rev());
+                    ElementType::Terminal(id)
+                        if curpos < element.end && raw_input[curpos].id() == id =>
+                    {
+                        next_boundary.push((
+                            children.cons(SyntaxicItem {
+                                kind: SyntaxicItemKind::Token(Box::new(
+                                    raw_input[curpos].clone(),
+                                )),
+                                start: curpos,
+                                end: curpos + 1,
+                            }),
+                            curpos + 1,
+                        ))
+                    }
+                    _ => {}
+                }
+            }
+            boundary.extend(next_boundary.into_iter().rev());
+        }
+        boundary
+            .into_iter()
+            .filter_map(|(children, pos)| {
+                if pos == element.end {
+                    Some(children)
+                } else {
+                    None
                 }
-                let children = boundary
+            })
+            .collect()
+    }
+
+    /// The weight of the heaviest derivation of `item`.
+    fn weight_of(&self, item: &SyntaxicItem, forest: &[FinalSet]) -> f64 {
+        match item.kind {
+            SyntaxicItemKind::Rule(rule) => {
+                forest[item.start].weight(self.grammar.rules[rule].id, rule, item.end)
+            }
+            SyntaxicItemKind::Token(_) => 0.0,
+        }
+    }
+
+    /// The weight of the heaviest derivation of `children`.
+    fn weight_of_children(&self, children: &List<SyntaxicItem>, forest: &[FinalSet]) -> f64 {
+        children.iter().map(|child| self.weight_of(child, forest)).sum()
+    }
+
+    /// The candidate `children` of an item, listed from the last one, as the
+    /// disambiguator sees them, in order.
+    fn child_views<'a>(
+        children: &List<SyntaxicItem>,
+        raw_input: &'a [Token],
+    ) -> Vec<Child<'a>> {
+        let mut views = children
+            .iter()
+            .map(|child| Child {
+                kind: match child.kind {
+                    SyntaxicItemKind::Rule(rule) => ChildKind::Rule(rule),
+                    SyntaxicItemKind::Token(_) => ChildKind::Token(&raw_input[child.start]),
+                },
+                start: child.start,
+                end: child.end,
+                span: (child.start < child.end).then(|| {
+                    raw_input[child.start]
+                        .span()
+                        .sup(raw_input[child.end - 1].span())
+                }),
+            })
+            .collect::<Vec<_>>();
+        views.reverse();
+        views
+    }
+
+    /// Compare two candidate children of an item of `rule`, the greatest being the one
+    /// to build: the heaviest, and then the one the disambiguator picks.
+    fn compare_children(
+        &self,
+        rule: RuleId,
+        left_children: &List<SyntaxicItem>,
+        right_children: &List<SyntaxicItem>,
+        forest: &[FinalSet],
+        raw_input: &[Token],
+    ) -> Ordering {
+        let weight_ord = self
+            .weight_of_children(left_children, forest)
+            .partial_cmp(&self.weight_of_children(right_children, forest))
+            .unwrap_or(Ordering::Equal);
+        if weight_ord != Ordering::Equal {
+            return weight_ord;
+        }
+        let left = Self::child_views(left_children, raw_input);
+        let right = Self::child_views(right_children, raw_input);
+        let standard = Standard::new(self.rule_preference());
+        let disambiguator = self.disambiguator.as_deref().unwrap_or(&standard);
+        disambiguator.compare(&self.grammar, rule, &left, &right)
+    }
+
+    /// Whether one of `children` derives the same tokens as `item` with the rule of
+    /// `item`, or of one of the ancestors in `path`, which would build `item` again
+    /// and again on cyclic grammars.
+    fn revisits(item: &SyntaxicItem, path: &[RuleId], children: &List<SyntaxicItem>) -> bool {
+        let SyntaxicItemKind::Rule(rule) = item.kind else {
+            return false;
+        };
+        children.iter().any(|child| match child.kind {
+            SyntaxicItemKind::Rule(child_rule) => {
+                child.start == item.start
+                    && child.end == item.end
+                    && (child_rule == rule || path.contains(&child_rule))
+            }
+            SyntaxicItemKind::Token(_) => false,
+        })
+    }
+
+    /// The rules of the ancestors of `child` that derive the same tokens as it, given
+    /// the ones of its parent `item`. Only those may derive it again.
+    fn child_path(item: &SyntaxicItem, path: &[RuleId], child: &SyntaxicItem) -> Vec<RuleId> {
+        let same_tokens = child.start == item.start && child.end == item.end;
+        match item.kind {
+            SyntaxicItemKind::Rule(rule) if same_tokens => {
+                path.iter().copied().chain([rule]).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The children of `element` to build, `path` being the rules of its ancestors
+    /// that derive the same tokens as it.
+    fn find_children(
+        &self,
+        element: SyntaxicItem,
+        path: &[RuleId],
+        forest: &[FinalSet],
+        raw_input: &[Token],
+    ) -> Result<Vec<SyntaxicItem>> {
+        match element.kind {
+            SyntaxicItemKind::Rule(rule) => {
+                let Some(children) = self
+                    .candidate_children(rule, &element, forest, raw_input)
                     .into_iter()
-                    .filter_map(|(children, pos)| {
-                        if pos == element.end {
-                            Some(children)
-                        } else {
-                            None
-                        }
-                    })
-                    .max_by(|left_children, right_children| {
-                        for (left, right) in left_children.iter().zip(right_children.iter()) {
-                            let SyntaxicItemKind::Rule(left_rule) = left.kind else {
-				continue;
-			    };
-                            let SyntaxicItemKind::Rule(right_rule) = right.kind else {
-				continue;
-			    };
-                            let assoc_ord = if self.grammar.rules[rule].left_associative {
-                                left.start.cmp(&right.start)
-                            } else {
-                                right.start.cmp(&left.start)
-                            };
-                            let ord = match assoc_ord {
-                                Ordering::Equal => left_rule.cmp(&right_rule),
-                                other => other,
-                            };
-                            match ord {
-                                Ordering::Equal => continue,
-                                other => return other,
-                            }
-                        }
-                        Ordering::Equal
+                    .filter(|children| !Self::revisits(&element, path, children))
+                    .max_by(|left, right| {
+                        self.compare_children(rule, left, right, forest, raw_input)
                     })
-                    .unwrap();
-                children
+                else {
+                    return ErrorKind::InternalError {
+                        message: format!(
+                            "the forest has no derivation of {} from the token {} to {}",
+                            self.grammar.name_of(self.grammar.rules[rule].id),
+                            element.start,
+                            element.end,
+                        ),
+                    }
+                    .err();
+                };
+                Ok(children
                     .iter()
                     .cloned()
                     .collect::<Vec<_>>()
                     .into_iter()
                     .rev()
-                    .collect()
+                    .collect())
             }
-            SyntaxicItemKind::Token(_) => Vec::new(),
+            SyntaxicItemKind::Token(_) => Ok(Vec::new()),
+        }
+    }
+
+    /// The span of the tokens `item` covers.
+    fn span_of(&self, item: &SyntaxicItem, raw_input: &[Token], last_span: &Span) -> Span {
+        if raw_input.is_empty() {
+            last_span.clone()
+        } else if item.end == item.start {
+            raw_input[item.start].span().clone()
+        } else {
+            raw_input[item.start]
+                .span()
+                .sup(raw_input[item.end - 1].span())
         }
     }
 
@@ -1010,101 +2381,379 @@ impl EarleyParser {
         forest: &[FinalSet],
         raw_input: &[Token],
         last_span: &Span,
-    ) -> AST {
+    ) -> Result<AST> {
+        let mut arena = AstArena::new();
+        let root =
+            self.build_arena(item, &[], forest, raw_input, last_span, &[], &mut arena)?;
+        Ok(arena.to_ast(root))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn build_arena(
+        &self,
+        item: SyntaxicItem,
+        path: &[RuleId],
+        forest: &[FinalSet],
+        raw_input: &[Token],
+        last_span: &Span,
+        inherited: &[(Symbol, ArenaNodeId)],
+        arena: &mut AstArena,
+    ) -> Result<ArenaNodeId> {
         match item.kind {
             SyntaxicItemKind::Rule(rule) => {
-                let span = if raw_input.is_empty() {
-                    last_span.clone()
-                } else if item.end == item.start {
-                    raw_input[item.start].span().clone()
-                } else {
-                    raw_input[item.start]
-                        .span()
-                        .sup(raw_input[item.end - 1].span())
-                };
-                let all_attributes = self
-                    .find_children(item, forest, raw_input)
+                let span = self.span_of(&item, raw_input, last_span);
+                let rule = &self.grammar.rules[rule];
+                let mut children = Vec::with_capacity(rule.elements.len());
+                for (position, child) in self
+                    .find_children(item.clone(), path, forest, raw_input)?
                     .into_iter()
-                    .map(|item| self.build_ast(item, forest, raw_input, last_span))
-                    .zip(self.grammar.rules[rule].elements.iter())
-                    .filter_map(|(item, element)| {
-                        element.key.as_ref().map(|key| match &element.attribute {
-                            Attribute::Named(attr) => {
-                                let AST::Node { attributes, .. } = item else {
-				    unreachable!("{item:?}.{attr}")
-				};
-                                (key.clone(), attributes[attr].clone())
-                            }
-                            Attribute::Indexed(idx) => {
-                                let AST::Terminal(token) = item else {
-				    unreachable!("{item:?}.{idx}")
-				};
-                                (
-                                    key.clone(),
-                                    AST::Literal {
-                                        value: Value::Str(Rc::from(
-                                            token.attributes()[idx].as_str(),
-                                        )),
-                                        span: Some(token.span().clone()),
-                                    },
-                                )
-                            }
-                            Attribute::None => (key.clone(), item),
-                        })
-                    })
-                    .collect::<HashMap<Rc<str>, _>>();
-                let mut removed: HashSet<Rc<str>> = HashSet::new();
-                let nonterminal = self.grammar.rules[rule].id;
-                let mut attributes: HashMap<_, _> = self.grammar.rules[rule]
-                    .proxy
-                    .iter()
-                    .map(|(key, wanted)| {
-                        (
-                            key.clone(),
-                            wanted.evaluate(&all_attributes, &mut removed, &span),
-                        )
-                    })
-                    .collect();
-                attributes.extend(
-                    all_attributes
-                        .into_iter()
-                        .filter(|(key, _)| !removed.contains(key)),
-                );
-                AST::Node {
-                    nonterminal,
-                    attributes,
-                    span,
+                    .enumerate()
+                {
+                    let inherited =
+                        rule.inherited_by_in(position, &children, inherited, &span, arena);
+                    let child_path = Self::child_path(&item, path, &child);
+                    let child = self.build_arena(
+                        child, &child_path, forest, raw_input, last_span, &inherited, arena,
+                    )?;
+                    children.push(child);
                 }
+                Ok(match self.actions.get(&rule.id) {
+                    Some(action) => {
+                        let children = children.iter().map(|&child| arena.to_ast(child));
+                        let value = action(children.collect(), &span);
+                        arena.alloc(value)
+                    }
+                    None => rule.build_node_in(arena, &children, span, inherited),
+                })
             }
-            SyntaxicItemKind::Token(token) => AST::Terminal(token),
+            SyntaxicItemKind::Token(token) => Ok(arena.push_terminal(*token)),
         }
     }
 
-    /// Select one AST, assuming there is one.
-    pub fn select_ast(
+    fn walk(
         &self,
+        item: SyntaxicItem,
+        path: &[RuleId],
         forest: &[FinalSet],
         raw_input: &[Token],
         last_span: &Span,
-    ) -> AST {
-        forest[0]
-            .iter()
-            .filter(|item| {
-                item.end == raw_input.len()
-                    && self
-                        .grammar
-                        .axioms
-                        .contains(self.grammar.rules[item.rule].id)
-            })
-            .sorted_unstable_by_key(|item| Reverse(item.rule))
-            .map(|item| SyntaxicItem {
-                start: 0,
-                end: raw_input.len(),
-                kind: SyntaxicItemKind::Rule(item.rule),
-            })
-            .map(|item| self.build_ast(item, forest, raw_input, last_span))
-            .next()
-            .unwrap()
+        sink: &mut impl ParseSink,
+    ) -> Result<()> {
+        match item.kind {
+            SyntaxicItemKind::Rule(rule) => {
+                let span = self.span_of(&item, raw_input, last_span);
+                let nonterminal = self.grammar.rules[rule].id;
+                sink.enter_rule(nonterminal, &span);
+                for child in self.find_children(item.clone(), path, forest, raw_input)? {
+                    let child_path = Self::child_path(&item, path, &child);
+                    self.walk(child, &child_path, forest, raw_input, last_span, sink)?;
+                }
+                sink.exit_rule(nonterminal, &span);
+            }
+            SyntaxicItemKind::Token(token) => sink.token(&token),
+        }
+        Ok(())
+    }
+
+    /// Count the nodes built with each rule in the derivation of `item`.
+    fn count_nodes(
+        &self,
+        item: SyntaxicItem,
+        path: &[RuleId],
+        forest: &[FinalSet],
+        raw_input: &[Token],
+        nodes: &mut [usize],
+    ) -> Result<()> {
+        if let SyntaxicItemKind::Rule(rule) = item.kind {
+            nodes[rule.0] += 1;
+            for child in self.find_children(item.clone(), path, forest, raw_input)? {
+                let child_path = Self::child_path(&item, path, &child);
+                self.count_nodes(child, &child_path, forest, raw_input, nodes)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add, to the count of every rule in `nodes`, the nodes built with it in the
+    /// selected derivation of `raw_input`, whose forest is `forest`.
+    pub(super) fn count_derivation(
+        &self,
+        forest: &[FinalSet],
+        raw_input: &[Token],
+        nodes: &mut [usize],
+    ) -> Result<()> {
+        if !forest.is_empty() {
+            let item = self.select_item(forest, raw_input);
+            self.count_nodes(item, &[], forest, raw_input, nodes)?;
+        }
+        Ok(())
+    }
+
+    /// Count what each rule cost to parse `raw_input`, whose table is `table` and
+    /// whose forest is `forest`, to tell which rules make parses slow. Rules are
+    /// sorted by the number of items they added to the table, the largest first, and
+    /// the ones that added none are left out.
+    pub fn profile(
+        &self,
+        table: &[StateSet],
+        forest: &[FinalSet],
+        raw_input: &[Token],
+    ) -> Result<Vec<(RuleId, RuleProfile)>> {
+        let mut profile = vec![RuleProfile::default(); self.grammar.rules.len()];
+        for item in table.iter().flat_map(StateSet::slice) {
+            let counts = &mut profile[item.rule.0];
+            counts.items += 1;
+            if item.position == 0 {
+                counts.predictions += 1;
+            }
+            if item.position as usize == self.grammar.rules[item.rule].elements.len() {
+                counts.completions += 1;
+            }
+        }
+        let mut nodes = vec![0; self.grammar.rules.len()];
+        self.count_derivation(forest, raw_input, &mut nodes)?;
+        let mut profile = profile
+            .into_iter()
+            .zip(nodes)
+            .map(|(counts, nodes)| RuleProfile { nodes, ..counts })
+            .enumerate()
+            .map(|(rule, counts)| (RuleId(rule), counts))
+            .filter(|(_, counts)| counts.items > 0)
+            .collect::<Vec<_>>();
+        profile.sort_by_key(|(_, counts)| std::cmp::Reverse(counts.items));
+        Ok(profile)
+    }
+
+    fn build_cst(
+        &self,
+        item: SyntaxicItem,
+        path: &[RuleId],
+        forest: &[FinalSet],
+        raw_input: &[Token],
+        last_span: &Span,
+    ) -> Result<Cst> {
+        match item.kind {
+            SyntaxicItemKind::Rule(rule) => {
+                // Empty derivations get an empty span, rather than the one of the
+                // next token.
+                let span = if item.start < item.end {
+                    self.span_of(&item, raw_input, last_span)
+                } else if item.start < raw_input.len() {
+                    raw_input[item.start].span().start_point()
+                } else {
+                    last_span.start_point()
+                };
+                let children = self
+                    .find_children(item.clone(), path, forest, raw_input)?
+                    .into_iter()
+                    .map(|child| {
+                        let child_path = Self::child_path(&item, path, &child);
+                        self.build_cst(child, &child_path, forest, raw_input, last_span)
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(Cst::Node {
+                    nonterminal: self.grammar.rules[rule].id,
+                    children,
+                    span,
+                })
+            }
+            SyntaxicItemKind::Token(token) => Ok(Cst::Token(*token)),
+        }
+    }
+
+    /// Parse the input into a concrete syntax tree, which keeps every token,
+    /// whether the grammar gives it a key or not. The derivation is the one
+    /// [`EarleyParser::select_ast`] picks.
+    pub fn parse_cst<'input>(
+        &self,
+        input: &'input mut LexedStream<'input, 'input>,
+    ) -> Result<Cst> {
+        let (table, raw_input) = self.recognise(input)?;
+        let forest = self.to_forest(&table, &raw_input)?;
+        let item = self.select_item(&forest, &raw_input);
+        self.build_cst(item, &[], &forest, &raw_input, input.last_span())
+    }
+
+    /// Build the shared packed parse forest of every derivation of the input.
+    pub fn to_sppf(&self, forest: &[FinalSet], raw_input: &[Token]) -> Sppf {
+        Sppf::new(&self.grammar, forest, raw_input)
+    }
+
+    /// Iterate lazily over every AST of the input, rather than only the one that
+    /// [`EarleyParser::select_ast`] picks.
+    pub fn iter_asts<'a>(
+        &'a self,
+        forest: &[FinalSet],
+        raw_input: &'a [Token],
+        last_span: &Span,
+    ) -> impl Iterator<Item = AST> + 'a {
+        let sppf = self.to_sppf(forest, raw_input);
+        Asts::new(&self.grammar, sppf, raw_input, last_span.clone())
+    }
+
+    /// The derivations of the whole input, the one to build first.
+    fn root_candidates(&self, forest: &[FinalSet], raw_input: &[Token]) -> Vec<SyntaxicItem> {
+        forest[0]
+            .iter()
+            .filter(|item| {
+                item.end == raw_input.len()
+                    && self
+                        .grammar
+                        .axioms
+                        .contains(self.grammar.rules[item.rule].id)
+            })
+            .sorted_unstable_by(|left, right| {
+                let weight = |item: &FinalItem| {
+                    forest[0].weight(self.grammar.rules[item.rule].id, item.rule, item.end)
+                };
+                weight(right)
+                    .partial_cmp(&weight(left))
+                    .unwrap_or(Ordering::Equal)
+                    .then_with(|| self.grammar.compare_precedence(right.rule, left.rule))
+                    .then_with(|| self.rule_preference().compare(right.rule, left.rule))
+            })
+            .map(|item| SyntaxicItem {
+                start: 0,
+                end: raw_input.len(),
+                kind: SyntaxicItemKind::Rule(item.rule),
+            })
+            .collect()
+    }
+
+    /// Select the derivation of the input to build, assuming there is one.
+    fn select_item(&self, forest: &[FinalSet], raw_input: &[Token]) -> SyntaxicItem {
+        self.root_candidates(forest, raw_input).swap_remove(0)
+    }
+
+    /// Every place where [`EarleyParser::select_ast`] has to pick a derivation among
+    /// several, assuming the input has one.
+    pub fn ambiguities(
+        &self,
+        forest: &[FinalSet],
+        raw_input: &[Token],
+        last_span: &Span,
+    ) -> Vec<Ambiguity> {
+        let mut ambiguities = Vec::new();
+        let mut roots = self.root_candidates(forest, raw_input);
+        let root = roots.swap_remove(0);
+        if let SyntaxicItemKind::Rule(rule) = root.kind {
+            if !roots.is_empty() {
+                let nonterminal = self.grammar.rules[rule].id;
+                ambiguities.push(Ambiguity {
+                    severity: Severity::Warning,
+                    nonterminal: self.grammar.name_of(nonterminal),
+                    span: self.span_of(&root, raw_input, last_span),
+                    rules: Some(&root)
+                        .into_iter()
+                        .chain(roots.iter())
+                        .filter_map(|item| match item.kind {
+                            SyntaxicItemKind::Rule(rule) => {
+                                Some(self.grammar.rules[rule].span.clone())
+                            }
+                            SyntaxicItemKind::Token(_) => None,
+                        })
+                        .collect(),
+                });
+            }
+        }
+        self.collect_ambiguities(root, &[], forest, raw_input, last_span, &mut ambiguities);
+        ambiguities
+    }
+
+    fn collect_ambiguities(
+        &self,
+        item: SyntaxicItem,
+        path: &[RuleId],
+        forest: &[FinalSet],
+        raw_input: &[Token],
+        last_span: &Span,
+        ambiguities: &mut Vec<Ambiguity>,
+    ) {
+        let SyntaxicItemKind::Rule(rule) = item.kind else {
+            return;
+        };
+        let mut candidates = self.candidate_children(rule, &item, forest, raw_input);
+        candidates.retain(|children| !Self::revisits(&item, path, children));
+        let Some(picked) = (0..candidates.len()).max_by(|&left, &right| {
+            let (left, right) = (&candidates[left], &candidates[right]);
+            self.compare_children(rule, left, right, forest, raw_input)
+        }) else {
+            return;
+        };
+        let picked = candidates.swap_remove(picked);
+        if !candidates.is_empty() {
+            let mut severity = Severity::Note;
+            let mut rules = Vec::new();
+            for candidate in &candidates {
+                // The children are listed from the last one, so this finds the last
+                // element the derivations split differently.
+                let Some((left, right)) = picked
+                    .iter()
+                    .zip(candidate.iter())
+                    .find(|(left, right)| !left.same_as(right))
+                else {
+                    continue;
+                };
+                // Children that start at different tokens are picked by associativity.
+                match (&left.kind, &right.kind) {
+                    (SyntaxicItemKind::Rule(_), SyntaxicItemKind::Rule(_))
+                        if left.start != right.start => {}
+                    _ => severity = Severity::Warning,
+                }
+                for differing in [left, right] {
+                    if let SyntaxicItemKind::Rule(rule) = differing.kind {
+                        if !rules.contains(&rule) {
+                            rules.push(rule);
+                        }
+                    }
+                }
+            }
+            ambiguities.push(Ambiguity {
+                severity,
+                nonterminal: self.grammar.name_of(self.grammar.rules[rule].id),
+                span: self.span_of(&item, raw_input, last_span),
+                rules: rules
+                    .into_iter()
+                    .map(|rule| self.grammar.rules[rule].span.clone())
+                    .collect(),
+            });
+        }
+        let children = picked.iter().cloned().collect::<Vec<_>>();
+        for child in children.into_iter().rev() {
+            let child_path = Self::child_path(&item, path, &child);
+            self.collect_ambiguities(
+                child,
+                &child_path,
+                forest,
+                raw_input,
+                last_span,
+                ambiguities,
+            );
+        }
+    }
+
+    /// Select one AST, assuming there is one.
+    pub fn select_ast(
+        &self,
+        forest: &[FinalSet],
+        raw_input: &[Token],
+        last_span: &Span,
+    ) -> Result<AST> {
+        let item = self.select_item(forest, raw_input);
+        self.build_ast(item, forest, raw_input, last_span)
+    }
+
+    /// Select one AST, like [`EarleyParser::select_ast`], and build it into
+    /// `arena`, returning its root.
+    pub fn select_arena(
+        &self,
+        forest: &[FinalSet],
+        raw_input: &[Token],
+        last_span: &Span,
+        arena: &mut AstArena,
+    ) -> Result<ArenaNodeId> {
+        let item = self.select_item(forest, raw_input);
+        self.build_arena(item, &[], forest, raw_input, last_span, &[], arena)
     }
 
     pub fn to_forest(&self, table: &[StateSet], raw_input: &[Token]) -> Result<Forest> {
@@ -1122,9 +2771,11 @@ impl EarleyParser {
                 .err();
             }
             set.iter()
-                .filter(|item| item.position == self.grammar.rules[item.rule].elements.len())
+                .filter(|item| {
+                    item.position as usize == self.grammar.rules[item.rule].elements.len()
+                })
                 .for_each(|item| {
-                    forest[item.origin].add(
+                    forest[item.origin as usize].add(
                         FinalItem {
                             end: i,
                             rule: item.rule,
@@ -1133,46 +2784,339 @@ impl EarleyParser {
                     )
                 });
         }
+        if self.grammar.has_weights() {
+            self.weigh(&mut forest, raw_input);
+        }
         Ok(forest)
     }
 
+    /// Weigh every item of `forest` with its heaviest derivation, the shortest
+    /// items first. Items that span the same tokens may derive one another, so
+    /// their weights are relaxed at most once more than there are of them, which
+    /// keeps cycles of derivations from adding up forever.
+    fn weigh(&self, forest: &mut [FinalSet], raw_input: &[Token]) {
+        let spans = forest
+            .iter()
+            .flat_map(|set| set.iter().map(move |item| (item.end - set.position, set.position)))
+            .sorted_unstable()
+            .dedup()
+            .collect::<Vec<_>>();
+        for set in forest.iter_mut() {
+            set.weights = vec![f64::NEG_INFINITY; set.set.len()];
+        }
+        for (length, start) in spans {
+            let end = start + length;
+            let items = forest[start]
+                .iter()
+                .enumerate()
+                .filter(|(_, item)| item.end == end)
+                .map(|(i, item)| (i, item.rule))
+                .collect::<Vec<_>>();
+            for _ in 0..=items.len() {
+                let weights = items
+                    .iter()
+                    .map(|&(_, rule)| {
+                        let item = SyntaxicItem {
+                            kind: SyntaxicItemKind::Rule(rule),
+                            start,
+                            end,
+                        };
+                        self.candidate_children(rule, &item, forest, raw_input)
+                            .iter()
+                            .map(|children| self.weight_of_children(children, forest))
+                            .fold(f64::NEG_INFINITY, f64::max)
+                            + self.grammar.rules[rule].weight
+                    })
+                    .collect::<Vec<_>>();
+                let mut stable = true;
+                for (&(i, _), weight) in items.iter().zip(weights) {
+                    if weight > forest[start].weights[i] {
+                        forest[start].weights[i] = weight;
+                        stable = false;
+                    }
+                }
+                if stable {
+                    break;
+                }
+            }
+        }
+    }
+
     pub fn recognise<'input, 'linput: 'input>(
         &self,
         input: &'input mut LexedStream<'linput, 'linput>,
     ) -> Result<(Table, Vec<Token>)> {
-        let mut sets = Vec::new();
-        let mut first_state = StateSet::default();
-        let mut possible_first_nonterminals = HashSet::new();
-        let mut possible_first_terminals = HashSet::new();
-        (0..self.grammar().rules.len())
-            .map(RuleId)
-            .filter(|id| self.grammar.axioms.contains(self.grammar.rules[*id].id))
-            .for_each(|id| {
-                let parent_has_been_shown = if let Some(description) =
-                    self.grammar().description_of(self.grammar().rules[id].id)
-                {
-                    possible_first_nonterminals.insert(description);
-                    true
+        self.recognise_from(
+            input,
+            Vec::new(),
+            Vec::new(),
+            &mut Vec::new(),
+            &mut Vec::new(),
+            0,
+            false,
+        )
+    }
+
+    /// Check whether the input is recognised, without building anything. The state
+    /// sets that can no longer be completed into are freed along the way, so long
+    /// inputs only keep the sets that matter.
+    pub fn validate<'input, 'linput: 'input>(
+        &self,
+        input: &'input mut LexedStream<'linput, 'linput>,
+    ) -> Result<()> {
+        self.recognise_from(
+            input,
+            Vec::new(),
+            Vec::new(),
+            &mut Vec::new(),
+            &mut Vec::new(),
+            0,
+            true,
+        )
+        .map(|_| ())
+    }
+
+    /// Parse the input, skipping the tokens that cannot be parsed, until `max_errors`
+    /// syntax errors are found. The tree is built if the input without the skipped
+    /// tokens is recognised.
+    pub fn parse_all_errors<'input>(
+        &self,
+        input: &'input mut LexedStream<'input, 'input>,
+        max_errors: usize,
+    ) -> RecoveredParse {
+        let mut errors = Vec::new();
+        let result = self
+            .recognise_from(
+                input,
+                Vec::new(),
+                Vec::new(),
+                &mut Vec::new(),
+                &mut errors,
+                max_errors,
+                false,
+            )
+            .and_then(|(table, raw_input)| {
+                let forest = self.to_forest(&table, &raw_input)?;
+                self.select_ast(&forest, &raw_input, input.last_span())
+            });
+        let tree = match result {
+            Ok(tree) => Some(tree),
+            Err(error) => {
+                errors.push(error);
+                None
+            }
+        };
+        RecoveredParse { tree, errors }
+    }
+
+    /// Parse the input, and build a tree for every axiom that derives all of it,
+    /// rather than only for the one [`EarleyParser::select_ast`] picks. The trees
+    /// are in the order the axioms are defined in.
+    pub fn parse_all_axioms<'input>(
+        &self,
+        input: &'input mut LexedStream<'input, 'input>,
+    ) -> Result<Vec<AxiomParse>> {
+        let (table, raw_input) = self.recognise(input)?;
+        let forest = self.to_forest(&table, &raw_input)?;
+        let mut roots = Vec::new();
+        for item in self.root_candidates(&forest, &raw_input) {
+            let SyntaxicItemKind::Rule(rule) = item.kind else {
+                continue;
+            };
+            let axiom = self.grammar.rules[rule].id;
+            if roots.iter().all(|&(id, _)| id != axiom) {
+                roots.push((axiom, item));
+            }
+        }
+        roots.sort_unstable_by_key(|&(id, _)| id.0);
+        roots
+            .into_iter()
+            .map(|(axiom, item)| {
+                Ok(AxiomParse {
+                    axiom: self.grammar.name_of(axiom),
+                    tree: self.build_ast(item, &forest, &raw_input, input.last_span())?,
+                })
+            })
+            .collect()
+    }
+
+    /// Parse the tokens `input` can lex so far, after the ones already fed to
+    /// `partial`. Return the result once the stream of `input` is closed and all
+    /// of it has been parsed, or `None` if more input is needed. `input` should
+    /// be built with [`Lexer::lex_incremental`](crate::lexer::Lexer::lex_incremental)
+    /// each time the stream has been fed.
+    pub fn feed_tokens<'input>(
+        &self,
+        partial: &mut PartialParse,
+        input: &'input mut LexedStream<'input, 'input>,
+    ) -> Result<Option<ParseResult>> {
+        let PartialParse {
+            sets,
+            raw_input,
+            checkpoint,
+        } = std::mem::take(partial);
+        if let Some(token) = raw_input.last() {
+            input.restore(&checkpoint);
+            input.set_last_span(token.span().clone());
+        }
+        let (mut sets, raw_input) =
+            self.recognise_from(
+                input,
+                sets,
+                raw_input,
+                &mut Vec::new(),
+                &mut Vec::new(),
+                0,
+                false,
+            )?;
+        if input.starved() {
+            // The last state set is computed again from the last token when resuming.
+            sets.pop();
+            *partial = PartialParse {
+                sets,
+                raw_input,
+                checkpoint: input.suspend(),
+            };
+            return Ok(None);
+        }
+        let forest = self.to_forest(&sets, &raw_input)?;
+        let tree = self.select_ast(&forest, &raw_input, input.last_span())?;
+        Ok(Some(ParseResult { tree }))
+    }
+
+    /// The terminals the lexer may produce at the state set `pos`, given the items
+    /// `scans` that wait for each terminal, along with the ignored terminals it may
+    /// skip before. Lexer modes restrict both to the terminals of the mode.
+    fn possible_scans(
+        &self,
+        lexer_grammar: &LexerGrammar,
+        scans: &HashMap<TerminalId, Vec<EarleyItem>>,
+        pos: usize,
+    ) -> Vec<TerminalId> {
+        if self.grammar.lexer_contexts.is_empty() {
+            return lexer_grammar
+                .default_allowed()
+                .chain(scans.keys().copied())
+                .collect();
+        }
+        let mut possible_scans = BTreeSet::new();
+        let mut skips_ignored = scans.is_empty();
+        for (&id, items) in scans {
+            for item in items {
+                let context = &self.grammar.lexer_contexts[self.grammar.rules[item.rule].id.0];
+                if context.body.as_ref().is_none_or(|body| body.contains(&id)) {
+                    possible_scans.insert(id);
+                }
+                let skipped = if item.origin as usize == pos {
+                    &context.entry
                 } else {
-                    false
+                    &context.body
                 };
+                match skipped {
+                    Some(terminals) => possible_scans.extend(
+                        terminals
+                            .iter()
+                            .copied()
+                            .filter(|&terminal| lexer_grammar.ignored(terminal)),
+                    ),
+                    None => skips_ignored = true,
+                }
+            }
+        }
+        if skips_ignored {
+            possible_scans.extend(lexer_grammar.default_allowed());
+        }
+        possible_scans.into_iter().collect()
+    }
 
-                first_state.add(EarleyItem {
-                    rule: id,
-                    origin: 0,
-                    position: 0,
-                    parent_has_been_shown,
+    /// Resume the recognition after the tokens of `raw_input`, whose state sets are
+    /// `sets`, with the tokens of `input`. The range of characters of every token
+    /// lexed, and the checkpoint of the lexer after it, are pushed onto `lexed`. If
+    /// the lexer needs more input, the state sets computed so far are returned.
+    ///
+    /// Unexpected tokens are skipped, and the syntax errors they raise are pushed
+    /// onto `errors`, as long as there are less than `max_errors` of them.
+    ///
+    /// If `retire` is set, the state sets that can no longer be completed into are
+    /// regularly emptied, so the table returned only fits recognition.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn recognise_from<'input, 'linput: 'input>(
+        &self,
+        input: &'input mut LexedStream<'linput, 'linput>,
+        mut sets: Table,
+        mut raw_input: Vec<Token>,
+        lexed: &mut Vec<Lexed>,
+        errors: &mut Vec<Error>,
+        max_errors: usize,
+        retire: bool,
+    ) -> Result<(Table, Vec<Token>)> {
+        if let Some(name) = self.grammar.rules.iter().find_map(|rule| {
+            rule.predicate.filter(|name| !self.predicates.contains_key(name))
+        }) {
+            return ErrorKind::UndefinedPredicate {
+                name: name.to_string(),
+            }
+            .err();
+        }
+        let mut first_state = StateSet::default();
+        let mut possible_first_nonterminals = HashSet::new();
+        let mut possible_first_terminals = HashSet::new();
+        if let Some(token) = raw_input.last() {
+            let Some(last) = sets.last() else {
+                return ErrorKind::InternalError {
+                    message: String::from("resuming a parse without its last state set"),
+                }
+                .err();
+            };
+            last.iter()
+                .filter(|item| {
+                    matches!(
+                        self.grammar.rules[item.rule].elements.get(item.position as usize),
+                        Some(Element {
+                            element_type: ElementType::Terminal(id),
+                            ..
+                        }) if *id == token.id()
+                    )
                 })
-            });
-        let mut raw_input = Vec::new();
+                .map(|item| EarleyItem {
+                    position: item.position + 1,
+                    parent_has_been_shown: false,
+                    ..*item
+                })
+                .filter(|item| self.admits(item, &raw_input))
+                .for_each(|item| first_state.add(item));
+        } else {
+            (0..self.grammar().rules.len())
+                .map(RuleId)
+                .filter(|id| self.grammar.axioms.contains(self.grammar.rules[*id].id))
+                .for_each(|id| {
+                    let parent_has_been_shown = if let Some(description) =
+                        self.grammar().description_of(self.grammar().rules[id].id)
+                    {
+                        possible_first_nonterminals.insert(description);
+                        true
+                    } else {
+                        false
+                    };
+
+                    first_state.add(EarleyItem {
+                        rule: id,
+                        origin: 0,
+                        position: 0,
+                        parent_has_been_shown,
+                    })
+                });
+        }
         sets.push(first_state);
-        let mut pos = 0;
+        let mut pos = raw_input.len();
         'outer: loop {
+            // The set being completed, which is the last one.
+            let current = sets.len() - 1;
             let mut next_state = StateSet::default();
             let mut scans: HashMap<TerminalId, Vec<_>> = HashMap::new();
-            '_inner: while let Some(&item) = sets.last_mut().unwrap().next() {
+            '_inner: while let Some(&item) = sets[current].next() {
                 let mut to_be_added = Vec::new();
-                match self.grammar().rules[item.rule].elements.get(item.position) {
+                match self.grammar().rules[item.rule].elements.get(item.position as usize) {
                     Some(element) => match element.element_type {
                         // Prediction
                         ElementType::NonTerminal(id) => {
@@ -1189,7 +3133,7 @@ impl EarleyParser {
                                     };
                                 to_be_added.push(EarleyItem {
                                     rule,
-                                    origin: pos,
+                                    origin: pos as u32,
                                     position: 0,
                                     parent_has_been_shown,
                                 });
@@ -1225,13 +3169,13 @@ impl EarleyParser {
                     },
                     // Completion
                     None => {
-                        for &parent in sets[item.origin].slice() {
+                        for &parent in sets[item.origin as usize].slice() {
                             if let Some(Element {
                                 element_type: ElementType::NonTerminal(nonterminal),
                                 ..
                             }) = self.grammar().rules[parent.rule]
                                 .elements
-                                .get(parent.position)
+                                .get(parent.position as usize)
                             {
                                 if *nonterminal == self.grammar().rules[item.rule].id {
                                     to_be_added.push(EarleyItem {
@@ -1246,86 +3190,96 @@ impl EarleyParser {
                     }
                 }
                 for item in to_be_added {
-                    sets.last_mut().unwrap().add(item);
+                    if self.admits(&item, &raw_input) {
+                        sets[current].add(item);
+                    }
                 }
             }
 
-            let possible_scans = input
-                .lexer()
-                .grammar()
-                .default_allowed()
-                .chain(scans.keys().cloned())
-                .collect::<Vec<_>>();
-            let allowed = Allowed::Some(possible_scans.clone());
-            let next_token = match input.next(allowed) {
-                Ok(r) => r,
-                Err(error) => {
-                    if let ErrorKind::LexingError { .. } = *error.kind {
-                        let error = if let Some(token) = input.next(Allowed::All)? {
-                            let span = token.span().clone();
-                            let name = {
-                                let id = token.id();
-                                let name = token.name().to_string();
-                                if let Some(description) =
-                                    input.lexer().grammar().description_of(id)
-                                {
-                                    description.to_string()
-                                } else {
-                                    name
-                                }
-                            };
-                            ErrorKind::SyntaxError {
-                                name,
-                                alternatives: possible_first_nonterminals
-                                    .drain()
+            let possible_scans = self.possible_scans(input.lexer().grammar(), &scans, pos);
+            let next_token = loop {
+                let allowed = Allowed::Some(possible_scans.clone());
+                let error = match input.next(allowed) {
+                    Ok(r) => break r.cloned(),
+                    Err(error) => {
+                        if let ErrorKind::LexingError { .. } = *error.kind {
+                            let token = input.next(Allowed::All)?.cloned();
+                            ErrorKind::parse_error(
+                                input.lexer().grammar(),
+                                token.as_ref(),
+                                scans.keys().copied().collect(),
+                                possible_first_nonterminals
+                                    .iter()
                                     .map(|x| x.to_string())
-                                    .chain(possible_first_terminals.drain())
+                                    .chain(possible_first_terminals.iter().cloned())
                                     .collect(),
-                                span: Fragile::new(span),
-                            }
+                                input.last_span(),
+                            )
                         } else {
-                            ErrorKind::SyntaxErrorValidPrefix {
-                                span: input.last_span().into(),
-                            }
-                        };
-                        return error.err();
-                    } else {
-                        return Err(error);
+                            return Err(error);
+                        }
                     }
+                };
+                // The unexpected token has been consumed, so going on skips it.
+                if errors.len() + 1 < max_errors
+                    && matches!(error, ErrorKind::ParseError { found: Some(_), .. })
+                {
+                    errors.push(Error::new(error));
+                } else {
+                    return error.err();
                 }
             };
             possible_first_nonterminals.clear();
             possible_first_terminals.clear();
             if let Some(token) = next_token {
-                for item in scans.entry(token.id()).or_default() {
-                    next_state.add(*item);
+                let id = token.id();
+                raw_input.push(token);
+                for item in scans.entry(id).or_default() {
+                    if self.admits(item, &raw_input) {
+                        next_state.add(*item);
+                    }
                 }
-                raw_input.push(token.clone());
-            } else if sets.last().unwrap().set.iter().any(|item| {
+                lexed.extend(input.last_range().map(|range| (range, input.checkpoint())));
+            } else if input.starved() || sets[current].set.iter().any(|item| {
                 let rule = &self.grammar.rules[item.rule];
                 item.origin == 0
                     && self.grammar.axioms.contains(rule.id)
-                    && rule.elements.len() == item.position
+                    && rule.elements.len() == item.position as usize
             }) {
                 break 'outer Ok((sets, raw_input));
             } else {
-                return ErrorKind::SyntaxErrorValidPrefix {
-                    span: input.last_span().into(),
-                }
+                return ErrorKind::parse_error(
+                    input.lexer().grammar(),
+                    None,
+                    scans.keys().copied().collect(),
+                    Vec::new(),
+                    input.last_span(),
+                )
                 .err();
             };
 
             sets.push(next_state);
             pos += 1;
+            if retire && sets.len().is_multiple_of(RETIREMENT_PERIOD) {
+                retire_sets(&mut sets);
+            }
         }
     }
 }
 
+impl crate::sealed::Sealed for EarleyParser {}
+
 impl Parser<'_> for EarleyParser {
     type Grammar = EarleyGrammar;
 
     fn new(grammar: Self::Grammar) -> Self {
-        Self { grammar }
+        Self {
+            grammar,
+            actions: HashMap::new(),
+            rule_preference: None,
+            disambiguator: None,
+            predicates: HashMap::new(),
+        }
     }
 
     fn grammar(&self) -> &Self::Grammar {
@@ -1343,9 +3297,20 @@ impl Parser<'_> for EarleyParser {
         let (table, raw_input) = self.recognise(input)?;
         let forest = self.to_forest(&table, &raw_input)?;
         // print_final_sets(&forest, self);
-        let tree = self.select_ast(&forest, &raw_input, input.last_span());
+        let tree = self.select_ast(&forest, &raw_input, input.last_span())?;
         Ok(ParseResult { tree })
     }
+
+    fn parse_events<'input>(
+        &self,
+        input: &'input mut LexedStream<'input, 'input>,
+        sink: &mut impl ParseSink,
+    ) -> Result<()> {
+        let (table, raw_input) = self.recognise(input)?;
+        let forest = self.to_forest(&table, &raw_input)?;
+        let item = self.select_item(&forest, &raw_input);
+        self.walk(item, &[], &forest, &raw_input, input.last_span(), sink)
+    }
 }
 
 // impl Buildable for EarleyParser {
@@ -1368,6 +3333,9 @@ impl Parser<'_> for EarleyParser {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::printer::to_sexpr;
+    use crate::builder::{serialize_compiled, FORMAT_VERSION};
+    use crate::stable::stable_hash;
 
     const GRAMMAR_NUMBERS_LEXER: &str = r#"
 NUMBER ::= ([0-9])
@@ -1436,7 +3404,7 @@ RPAR ::= \)
         name: &'static str,
         left_elements: Vec<&'static str>,
         right_elements: Vec<&'static str>,
-        origin: usize,
+        origin: u32,
     }
 
     impl TestEarleyItem {
@@ -1465,9 +3433,10 @@ RPAR ::= \)
                 self.right_elements,
                 item.elements,
             );
+            let position = other.position as usize;
             assert_eq!(
                 self.left_elements.len(),
-                other.position,
+                position,
                 "{} fat dot position.",
                 error_message,
             );
@@ -1475,7 +3444,7 @@ RPAR ::= \)
             for i in 0..self.left_elements.len() {
                 assert_eq!(
                     self.left_elements[i],
-                    &*item.elements[i].name(lexer.grammar(), parser.grammar()),
+                    item.elements[i].name(lexer.grammar(), parser.grammar()),
                     "{} element #{}.",
                     error_message,
                     i
@@ -1484,10 +3453,10 @@ RPAR ::= \)
             for i in 0..self.right_elements.len() {
                 assert_eq!(
                     self.right_elements[i],
-                    &*item.elements[i + other.position].name(lexer.grammar(), parser.grammar()),
+                    item.elements[i + position].name(lexer.grammar(), parser.grammar()),
                     "{} elements #{}.",
                     error_message,
-                    i + other.position
+                    i + position
                 );
             }
         }
@@ -1558,7 +3527,7 @@ RPAR ::= \)
 	    fn find_item(grammar: &EarleyGrammar, lexer_grammar: &$crate::lexer::Grammar, name: &str, elements: &[&str], end: usize) -> FinalItem {
 		for &rule_identifier in grammar
 		    .id_of
-		    .get(&Rc::from(name))
+		    .get(name)
 		    .map(|&identifier| &grammar.rules_of[identifier])
 		    .expect(format!("The non-terminal {} does not exist.", name).as_str())
 		    .iter()
@@ -1569,7 +3538,7 @@ RPAR ::= \)
 			&& elements
 			.iter()
 			.zip(grammar.rules[rule_identifier].elements.iter())
-			.all(|(&left, right)| left == &*right.name(lexer_grammar, grammar))
+			.all(|(&left, right)| left == right.name(lexer_grammar, grammar))
 		    {
 			return FinalItem {
 			    rule: rule_identifier,
@@ -1663,11 +3632,11 @@ RPAR ::= \)
                         let tattributes = tattributes
                             .0
                             .iter()
-                            .map(|(key, value)| (Rc::<str>::from(*key), value))
+                            .map(|(key, value)| (*key, value))
                             .collect::<HashMap<_, _>>();
                         tattributes.len() == attributes.len()
                             && tattributes.iter().all(|(key, value)| {
-                                attributes.get(key).map_or(false, |v| *value == v)
+                                attributes.get(*key).is_some_and(|v| *value == v)
                             })
                     }
                 }
@@ -1703,20 +3672,1011 @@ RPAR ::= \)
     }
 
     #[test]
-    fn recognise_handle_empty_rules() {
-        let lexer_input = r#""#;
-        let grammar_input = r#"
-@A ::= <>
- B <>;
-B ::= A <>;"#;
-        let input = r#""#;
-        let lexer =
-            Lexer::build_from_plain(StringStream::new(Path::new("<lexer input>"), lexer_input))
-                .unwrap();
-        let grammar = EarleyGrammar::build_from_plain(
-            StringStream::new(Path::new("<grammar input>"), grammar_input),
-            lexer.grammar(),
-        )
+    fn lexer_fingerprint() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "A ::= a\nB ::= b",
+        ))
+        .unwrap();
+        let other_lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "B ::= b\nA ::= a",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), "@S ::= A B <>;"),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let path = Path::new("grammar.cgr");
+        assert!(grammar.check_lexer(lexer.grammar(), path).is_ok());
+        let error = grammar.check_lexer(other_lexer.grammar(), path).unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::LexerMismatch { .. }));
+    }
+
+    #[test]
+    fn cyclic_grammar() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "X ::= x",
+        ))
+        .unwrap();
+        for rules in ["<>\n A@left A@right <>", "A@left A@right <>\n <>"] {
+            let grammar = EarleyGrammar::build_from_plain(
+                StringStream::new(Path::new("<grammar input>"), format!("@A ::= {rules};")),
+                lexer.grammar(),
+            )
+            .unwrap();
+            assert_eq!(grammar.cycles().len(), 1);
+            let parser = EarleyParser::new(grammar);
+            let mut stream = StringStream::new(Path::new("<input>"), "");
+            let mut input = lexer.lex(&mut stream);
+            let (table, raw_input) = parser.recognise(&mut input).unwrap();
+            let forest = parser.to_forest(&table, &raw_input).unwrap();
+            parser.select_ast(&forest, &raw_input, input.last_span()).unwrap();
+            parser.ambiguities(&forest, &raw_input, input.last_span());
+            let mut stream = StringStream::new(Path::new("<input>"), "");
+            parser.parse_cst(&mut lexer.lex(&mut stream)).unwrap();
+        }
+    }
+
+    #[test]
+    fn feed_tokens() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "ignore SPACE ::= \\s+\nNUMBER ::= ([0-9]+)\nPLUS ::= \\+",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@Sum ::= Sum@left PLUS NUMBER.0@right <>\n NUMBER.0@self <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let mut stream = StringStream::open(Path::new("<input>"));
+        let mut partial = PartialParse::default();
+        for chunk in ["1 + 2", "3 +", " 4"] {
+            stream.feed(chunk);
+            let mut input = lexer.lex_incremental(&mut stream);
+            assert!(parser.feed_tokens(&mut partial, &mut input).unwrap().is_none());
+        }
+        stream.close();
+        let result = parser
+            .feed_tokens(&mut partial, &mut lexer.lex_incremental(&mut stream))
+            .unwrap()
+            .unwrap();
+        let mut stream = StringStream::new(Path::new("<input>"), "1 + 23 + 4");
+        let expected = parser.parse(&mut lexer.lex(&mut stream)).unwrap();
+        assert_eq!(result.tree, expected.tree);
+
+        let mut stream = StringStream::open(Path::new("<input>"));
+        let mut partial = PartialParse::default();
+        stream.feed("1 +");
+        let mut input = lexer.lex_incremental(&mut stream);
+        assert!(parser.feed_tokens(&mut partial, &mut input).unwrap().is_none());
+        // No more input makes a number start with `+`.
+        stream.feed(" + 2");
+        let mut input = lexer.lex_incremental(&mut stream);
+        assert!(parser.feed_tokens(&mut partial, &mut input).is_err());
+    }
+
+    #[test]
+    fn on_rule() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "NUMBER ::= ([0-9]+)\nPLUS ::= \\+",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@Sum ::= Sum@left PLUS NUMBER.0@right <>\n NUMBER.0@self <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let mut parser = EarleyParser::new(grammar);
+        parser
+            .on_rule("Sum", |children, span| {
+                let value = children
+                    .iter()
+                    .map(|child| match child {
+                        AST::Literal {
+                            value: crate::parser::Value::Int(value),
+                            ..
+                        } => *value,
+                        AST::Terminal(token) if token.name() == "NUMBER" => {
+                            token.attributes()[&0].parse().unwrap()
+                        }
+                        _ => 0,
+                    })
+                    .sum();
+                AST::Literal {
+                    value: crate::parser::Value::Int(value),
+                    span: Some(span.clone()),
+                }
+            })
+            .unwrap();
+        assert!(parser.on_rule("Product", |_, _| unreachable!()).is_err());
+        let mut stream = StringStream::new(Path::new("<input>"), "1+2+39");
+        let tree = parser.parse(&mut lexer.lex(&mut stream)).unwrap().tree;
+        assert!(matches!(
+            tree,
+            AST::Literal {
+                value: crate::parser::Value::Int(42),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn external_values() {
+        #[derive(Debug, PartialEq)]
+        struct Number(u64);
+
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "NUMBER ::= ([0-9]+)\nPLUS ::= \\+",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@Sum ::= Number@left PLUS Number@right <>;\n\
+                 Number ::= NUMBER <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let mut parser = EarleyParser::new(grammar);
+        parser
+            .on_rule("Number", |children, span| {
+                let AST::Terminal(token) = &children[0] else {
+                    unreachable!()
+                };
+                AST::Literal {
+                    value: crate::parser::Value::external(Number(
+                        token.attributes()[&0].parse().unwrap(),
+                    )),
+                    span: Some(span.clone()),
+                }
+            })
+            .unwrap();
+        let mut stream = StringStream::new(Path::new("<input>"), "12345678901+2");
+        let AST::Node { attributes, .. } =
+            parser.parse(&mut lexer.lex(&mut stream)).unwrap().tree
+        else {
+            panic!("expected a node");
+        };
+        let number = |key: &str| match &attributes[key] {
+            AST::Literal { value, .. } => value.downcast_ref::<Number>(),
+            _ => None,
+        };
+        assert_eq!(number("left"), Some(&Number(12345678901)));
+        assert_eq!(number("right"), Some(&Number(2)));
+        assert_eq!(attributes["left"], attributes["left"].clone());
+        assert_ne!(
+            crate::parser::Value::external(Number(2)),
+            crate::parser::Value::external(Number(2))
+        );
+    }
+
+    #[cfg(any(feature = "bigint", feature = "decimal"))]
+    fn literal_value(input: &str) -> crate::parser::Value {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "NUMBER ::= ([0-9]+(\\.[0-9]+)?)",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), "@Number ::= NUMBER.0@value <>;"),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let mut stream = StringStream::new(Path::new("<input>"), input);
+        let AST::Node { mut attributes, .. } =
+            parser.parse(&mut lexer.lex(&mut stream)).unwrap().tree
+        else {
+            panic!("expected a node");
+        };
+        let Some(AST::Literal { value, .. }) = attributes.remove("value") else {
+            panic!("expected a literal");
+        };
+        value
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn big_int_values() {
+        let value = literal_value("123456789012345678901234567890");
+        assert_eq!(
+            value.to_big_int().unwrap().to_string(),
+            "123456789012345678901234567890"
+        );
+        assert!(literal_value("1.5").to_big_int().is_none());
+        assert_eq!(crate::parser::Value::Int(-3).to_big_int(), Some((-3).into()));
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn decimal_values() {
+        let value = literal_value("0.1");
+        assert_eq!(value.to_decimal().unwrap().to_string(), "0.1");
+        assert_eq!(
+            literal_value("12345678901234.000001").to_decimal().unwrap().to_string(),
+            "12345678901234.000001"
+        );
+    }
+
+    #[test]
+    fn rule_preference() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "A ::= a",
+        ))
+        .unwrap();
+        let build = |grammar: &str| {
+            EarleyGrammar::build_from_plain(
+                StringStream::new(Path::new("<grammar input>"), grammar),
+                lexer.grammar(),
+            )
+            .unwrap()
+        };
+        let parse = |parser: &EarleyParser| {
+            let mut stream = StringStream::new(Path::new("<input>"), "a");
+            parser.parse(&mut lexer.lex(&mut stream)).unwrap().tree
+        };
+        fn variant(ast: &AST) -> &str {
+            let AST::Node { attributes, .. } = ast else {
+                panic!("expected a node");
+            };
+            let AST::Literal {
+                value: crate::parser::Value::Str(variant),
+                ..
+            } = &attributes["variant"]
+            else {
+                panic!("expected a variant");
+            };
+            variant
+        }
+        fn child<'a>(ast: &'a AST, key: &str) -> &'a AST {
+            let AST::Node { attributes, .. } = ast else {
+                panic!("expected a node");
+            };
+            &attributes[key]
+        }
+        const GRAMMAR: &str = "@S ::= X@x <ViaX> Y@y <ViaY>;
+X ::= A <One> A <Two>;
+Y ::= A <>;";
+
+        let grammar = build(GRAMMAR);
+        assert_eq!(grammar.rule_preference(), RulePreference::Last);
+        let parser = EarleyParser::new(grammar);
+        assert_eq!(variant(&parse(&parser)), "ViaY");
+        let parser = parser.with_rule_preference(RulePreference::First);
+        let ast = parse(&parser);
+        assert_eq!(variant(&ast), "ViaX");
+        assert_eq!(variant(child(&ast, "x")), "One");
+
+        let grammar = build(&format!("prefer first-rule;\n{GRAMMAR}"));
+        assert_eq!(grammar.rule_preference(), RulePreference::First);
+        let parser = EarleyParser::new(grammar);
+        let ast = parse(&parser);
+        assert_eq!(variant(&ast), "ViaX");
+        assert_eq!(variant(child(&ast, "x")), "One");
+        let parser = parser.with_rule_preference(RulePreference::Last);
+        assert_eq!(variant(&parse(&parser)), "ViaY");
+    }
+
+    #[test]
+    fn weights() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "A ::= a",
+        ))
+        .unwrap();
+        let build = |grammar: &str| {
+            EarleyGrammar::build_from_plain(
+                StringStream::new(Path::new("<grammar input>"), grammar),
+                lexer.grammar(),
+            )
+        };
+        let parse = |parser: &EarleyParser| {
+            let mut stream = StringStream::new(Path::new("<input>"), "a");
+            parser.parse(&mut lexer.lex(&mut stream)).unwrap().tree
+        };
+        let variants = |ast: &AST| {
+            let AST::Node { attributes, .. } = ast else {
+                panic!("expected a node");
+            };
+            let variant = |attributes: &HashMap<Symbol, AST>| match &attributes["variant"] {
+                AST::Literal {
+                    value: crate::parser::Value::Str(variant),
+                    ..
+                } => variant.to_string(),
+                _ => panic!("expected a variant"),
+            };
+            let child = match attributes.get("x") {
+                Some(AST::Node { attributes, .. }) => Some(variant(attributes)),
+                _ => None,
+            };
+            (variant(attributes), child)
+        };
+        const GRAMMAR: &str = "@S ::= X@x <ViaX> Y@y <ViaY>;
+X ::= A <One> A <Two>;
+Y ::= A <>;";
+
+        // The heaviest derivation wins over the rule preference, which still
+        // settles the derivations of equal weight.
+        let grammar = build(&GRAMMAR.replace("A <One>", "(weight 2) A <One>")).unwrap();
+        assert!(grammar.has_weights());
+        assert!(grammar.canonical().contains("(weight 2)"));
+        let parser = EarleyParser::new(grammar);
+        assert_eq!(variants(&parse(&parser)), ("ViaX".into(), Some("One".into())));
+        let grammar = build(&GRAMMAR.replace("Y ::= A", "Y ::= (weight 1) A")).unwrap();
+        let parser = EarleyParser::new(grammar).with_rule_preference(RulePreference::First);
+        assert_eq!(variants(&parse(&parser)), ("ViaY".into(), None));
+
+        let mut parser = EarleyParser::new(build(GRAMMAR).unwrap());
+        assert!(!parser.grammar().has_weights());
+        assert_eq!(variants(&parse(&parser)), ("ViaY".into(), None));
+        let treebank = EarleyParser::new(build(GRAMMAR).unwrap())
+            .with_rule_preference(RulePreference::First);
+        let mut stream = StringStream::new(Path::new("<input>"), "a");
+        let cst = treebank.parse_cst(&mut lexer.lex(&mut stream)).unwrap();
+        parser.learn_weights([&cst, &cst]);
+        assert!(parser.grammar().has_weights());
+        // Both rules of X match the tree, so the rule preference picks the last.
+        assert_eq!(variants(&parse(&parser)), ("ViaX".into(), Some("Two".into())));
+
+        let error = build(&GRAMMAR.replace("A <One>", "(height 2) A <One>")).unwrap_err();
+        assert!(matches!(
+            *error.kind,
+            ErrorKind::GrammarUnknownAnnotation { ref name, .. } if name == "height"
+        ));
+    }
+
+    #[test]
+    fn named_groups() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            r"FLOAT ::= (?P<int>[0-9]+)\.(?P<frac>[0-9]+)",
+        ))
+        .unwrap();
+        let build = |grammar: &str| {
+            EarleyGrammar::build_from_plain(
+                StringStream::new(Path::new("<grammar input>"), grammar),
+                lexer.grammar(),
+            )
+        };
+        let parser = EarleyParser::new(build("@Number ::= FLOAT.frac@frac <>;").unwrap());
+        let mut stream = StringStream::new(Path::new("<input>"), "12.5");
+        let tree = parser.parse(&mut lexer.lex(&mut stream)).unwrap().tree;
+        let AST::Node { attributes, .. } = tree else {
+            panic!("expected a node");
+        };
+        assert!(matches!(
+            &attributes["frac"],
+            AST::Literal { value: crate::parser::Value::Str(frac), .. } if &**frac == "5"
+        ));
+        // Named groups are resolved when the grammar is built.
+        assert_eq!(
+            build("@Number ::= FLOAT.frac@frac <>;").unwrap().canonical(),
+            build("@Number ::= FLOAT.1@frac <>;").unwrap().canonical()
+        );
+
+        let error = build("@Number ::= FLOAT.exp@exp <>;").unwrap_err();
+        assert!(matches!(
+            *error.kind,
+            ErrorKind::GrammarUnknownGroup { ref name, .. } if name == "exp"
+        ));
+    }
+
+    #[test]
+    fn canonical() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<PROXY>"),
+            GRAMMAR_PROXY_LEXER,
+        ))
+        .unwrap();
+        let build = |path: &str, grammar: &str| {
+            EarleyGrammar::build_from_plain(
+                StringStream::new(Path::new(path), grammar),
+                lexer.grammar(),
+            )
+            .unwrap()
+        };
+        let grammar = build("a.gr", GRAMMAR_PROXY);
+        let same = build("b.gr", &GRAMMAR_PROXY.replace("\n  ", "\n    "));
+        assert_eq!(grammar, same);
+        assert_eq!(stable_hash(&grammar), stable_hash(&same));
+        assert!(grammar.canonical().contains(
+            "Expression ::= (left-assoc) #1 Expression@right \
+             <left: Expression {value: \"0\", variant: \"Literal\"}, variant: \"Operation\">\n"
+        ));
+
+        let blob = serialize_compiled(&grammar).unwrap();
+        let compiled = EarleyGrammar::build_from_compiled(&blob, PathBuf::from("a.cgr"));
+        assert_eq!(compiled.unwrap(), grammar);
+        let blob = bincode::serialize(&grammar).unwrap();
+        let error = EarleyGrammar::build_from_compiled(&blob, PathBuf::from("a.cgr"))
+            .unwrap_err();
+        assert!(matches!(
+            *error.kind,
+            ErrorKind::IncompatibleBinaryVersion { found: None, .. }
+        ));
+        let mut blob = serialize_compiled(&grammar).unwrap();
+        blob[4] += 1;
+        let error = EarleyGrammar::build_from_compiled(&blob, PathBuf::from("a.cgr"))
+            .unwrap_err();
+        assert!(matches!(
+            *error.kind,
+            ErrorKind::IncompatibleBinaryVersion { found: Some((format, _)), .. }
+                if format == FORMAT_VERSION + 1
+        ));
+        let lexer_grammar = LexerGrammar::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            GRAMMAR_PROXY_LEXER,
+        ))
+        .unwrap();
+        let blob = serialize_compiled(&lexer_grammar).unwrap();
+        let error = EarleyGrammar::build_from_compiled(&blob, PathBuf::from("a.cgr"))
+            .unwrap_err();
+        assert!(matches!(
+            *error.kind,
+            ErrorKind::CompiledKindMismatch {
+                expected: CompiledKind::EarleyGrammar,
+                found: CompiledKind::LexerGrammar,
+                ..
+            }
+        ));
+
+        let annotated = GRAMMAR_PROXY.replace(
+            "  LPAR Expression@value RPAR <Parenthesized>;",
+            "  (right-assoc) LPAR Expression@value RPAR <Parenthesized>;",
+        );
+        assert_ne!(build("a.gr", &annotated), grammar);
+        let first = build("a.gr", GRAMMAR_PROXY).with_rule_preference(RulePreference::First);
+        assert_ne!(first, grammar);
+    }
+
+    #[test]
+    fn decompile() {
+        let build = |lexer: &Lexer, grammar: &str| {
+            EarleyGrammar::build_from_plain(
+                StringStream::new(Path::new("<grammar input>"), grammar),
+                lexer.grammar(),
+            )
+            .unwrap()
+        };
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            GRAMMAR_PROXY_LEXER,
+        ))
+        .unwrap();
+        let grammar = build(&lexer, GRAMMAR_PROXY);
+        let decompiled = grammar.decompile(Some(lexer.grammar()));
+        assert_eq!(
+            decompiled,
+            "@Expression ::=\n  \
+               NUMBER.0@value <Literal>\n  \
+               Expression@left OP Expression@right <Operation>\n  \
+               OP Expression@right <Operation, left: Expression {Literal, value: \"0\"}>\n  \
+               LPAR Expression@value RPAR <Parenthesized>;\n"
+        );
+        assert_eq!(build(&lexer, &decompiled), grammar);
+        assert!(grammar.decompile(None).contains("#2 Expression@value #3 <Parenthesized>"));
+
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            GRAMMAR_NUMBERS_LEXER,
+        ))
+        .unwrap();
+        let grammar = build(
+            &lexer,
+            "prefer first-rule;\nprecedence { left PM; right TD; }\n\
+             \"a sum\" @Sum ::= (weight 2) Sum PM Sum <> Sum TD Sum <> NUMBER <>;",
+        );
+        let decompiled = grammar.decompile(Some(lexer.grammar()));
+        assert!(decompiled.starts_with(
+            "prefer first-rule;\n\nprecedence {\n  left PM;\n  right TD;\n}\n\n\"a sum\"\n@Sum"
+        ));
+        assert_eq!(build(&lexer, &decompiled), grammar);
+    }
+
+    #[test]
+    fn lexer_modes() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "ignore SPACE ::= \\s+\nID ::= [a-z]+\nSLASH ::= /\nCHAR ::= ([^/])",
+        ))
+        .unwrap();
+        let build = |grammar: &str| {
+            EarleyGrammar::build_from_plain(
+                StringStream::new(Path::new("<grammar input>"), grammar),
+                lexer.grammar(),
+            )
+        };
+        const GRAMMAR: &str = "mode regex ::= SLASH CHAR;
+@File ::= ID Regex <>;
+Regex (mode regex) ::= SLASH Chars SLASH <>;
+Chars ::= <> CHAR Chars <>;";
+
+        let grammar = build(GRAMMAR).unwrap();
+        let mode = grammar.lexer_mode(grammar.id_of("Regex")).unwrap();
+        assert_eq!(mode.name, "regex");
+        assert_eq!(mode.terminals.len(), 2);
+        assert!(grammar.lexer_mode(grammar.id_of("Chars")).is_none());
+
+        let parser = EarleyParser::new(grammar);
+        let mut stream = StringStream::new(Path::new("<input>"), "re /a b/ ");
+        let mut input = lexer.lex(&mut stream);
+        let (_, raw_input) = parser.recognise(&mut input).unwrap();
+        assert_eq!(
+            raw_input.iter().map(Token::name).collect::<Vec<_>>(),
+            ["ID", "SLASH", "CHAR", "CHAR", "CHAR", "SLASH"]
+        );
+        assert_eq!(raw_input[3].content(), " ");
+
+        let error = build(&GRAMMAR.replace("(mode regex)", "(mode glob)")).unwrap_err();
+        assert!(matches!(
+            *error.kind,
+            ErrorKind::GrammarUndefinedLexerMode { ref name, .. } if name == "glob"
+        ));
+        let error = build(&GRAMMAR.replace("SLASH CHAR;", "SLASH CHAR NOPE;")).unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::GrammarUndefinedTerminal { .. }));
+        let error = build(&format!("mode regex ::= CHAR;\n{GRAMMAR}")).unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::GrammarDuplicateLexerMode { .. }));
+    }
+
+    #[test]
+    fn optional_default() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "A ::= a\nB ::= b",
+        ))
+        .unwrap();
+        let build = |grammar: &str| {
+            EarleyGrammar::build_from_plain(
+                StringStream::new(Path::new("<grammar input>"), grammar),
+                lexer.grammar(),
+            )
+        };
+        let parser = EarleyParser::new(
+            build(r#"@S ::= A@a B?@b = default("none") A?@c <>;"#).unwrap(),
+        );
+        assert_eq!(parser.grammar().rules().len(), 4);
+        let attributes = |input: &str| {
+            let mut stream = StringStream::new(Path::new("<input>"), input);
+            let AST::Node { attributes, .. } =
+                parser.parse(&mut lexer.lex(&mut stream)).unwrap().tree
+            else {
+                panic!("expected a node");
+            };
+            attributes
+        };
+        let attributes_a = attributes("a");
+        assert!(matches!(
+            &attributes_a["b"],
+            AST::Literal { value: crate::parser::Value::Str(value), .. } if &**value == "none"
+        ));
+        assert!(!attributes_a.contains_key("c"));
+        let attributes_aba = attributes("aba");
+        assert!(matches!(&attributes_aba["b"], AST::Terminal(token) if token.name() == "B"));
+        assert!(attributes_aba.contains_key("c"));
+
+        let error = build(r#"@S ::= A@a = default("none") <>;"#).unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::GrammarDefaultNotOptional { .. }));
+        let error = build(r#"@S ::= A B? = default("none") <>;"#).unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::GrammarDefaultWithoutKey { .. }));
+    }
+
+    #[test]
+    fn inherited_attributes() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "A ::= a\nB ::= b",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@S ::= A@ty T{expected: ty}@value <>;\n\
+                 T ::= B@b <expected: expected, found: b>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let mut stream = StringStream::new(Path::new("<input>"), "ab");
+        let AST::Node { attributes, .. } =
+            parser.parse(&mut lexer.lex(&mut stream)).unwrap().tree
+        else {
+            panic!("expected a node");
+        };
+        let AST::Node { attributes, .. } = &attributes["value"] else {
+            panic!("expected a node");
+        };
+        assert!(matches!(&attributes["expected"], AST::Terminal(token) if token.name() == "A"));
+        assert!(matches!(&attributes["found"], AST::Terminal(token) if token.name() == "B"));
+    }
+
+    #[test]
+    fn parse_all_axioms() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "NUMBER ::= ([0-9]+)\nID ::= ([a-z]+)",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@Numbers ::= NUMBER.0@value <>;\n\
+                 @Any ::= NUMBER.0@number <> ID.0@id <>;\n\
+                 @Ids ::= ID.0@value <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let mut stream = StringStream::new(Path::new("<input>"), "12");
+        let results = parser
+            .parse_all_axioms(&mut lexer.lex(&mut stream))
+            .unwrap();
+        let axioms = results
+            .iter()
+            .map(|result| &*result.axiom)
+            .collect::<Vec<_>>();
+        assert_eq!(axioms, ["Numbers", "Any"]);
+        assert!(results.iter().all(|result| matches!(
+            &result.tree,
+            AST::Node { attributes, .. } if attributes.len() == 1
+        )));
+        let mut stream = StringStream::new(Path::new("<input>"), "12ab");
+        assert!(parser
+            .parse_all_axioms(&mut lexer.lex(&mut stream))
+            .is_err());
+    }
+
+    #[test]
+    fn strip_attributes() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            GRAMMAR_NUMBERS_LEXER,
+        ))
+        .unwrap();
+        let mut grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), GRAMMAR_NUMBERS),
+            lexer.grammar(),
+        )
+        .unwrap();
+        grammar.strip_attributes();
+        let parser = EarleyParser::new(grammar);
+        let mut stream = StringStream::new(Path::new("<input>"), "1+(2*3-4)");
+        let AST::Node { attributes, .. } =
+            parser.parse(&mut lexer.lex(&mut stream)).unwrap().tree
+        else {
+            panic!("expected a node");
+        };
+        assert!(attributes.is_empty());
+        let mut stream = StringStream::new(Path::new("<input>"), "1+(2*3-4");
+        assert!(parser.recognise(&mut lexer.lex(&mut stream)).is_err());
+    }
+
+    #[test]
+    fn retire_sets() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            GRAMMAR_NUMBERS_LEXER,
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), GRAMMAR_NUMBERS),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let mut stream = StringStream::new(Path::new("<input>"), "1+2+3+4+5");
+        let (mut table, _) = parser.recognise(&mut lexer.lex(&mut stream)).unwrap();
+        super::retire_sets(&mut table);
+        // Only the set where the sum starts and the ones of the last number are
+        // needed to complete the last items.
+        assert!(!table[0].is_empty());
+        assert!(table[1..8].iter().all(StateSet::is_empty));
+        assert!(!table[8].is_empty());
+        assert!(!table[9].is_empty());
+
+        let long = (0..3000).map(|i| (i % 10).to_string()).join("+");
+        let mut stream = StringStream::new(Path::new("<input>"), long.as_str());
+        assert!(parser.validate(&mut lexer.lex(&mut stream)).is_ok());
+        let mut stream = StringStream::new(Path::new("<input>"), format!("{long}+"));
+        assert!(parser.validate(&mut lexer.lex(&mut stream)).is_err());
+    }
+
+    #[test]
+    fn profile() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            GRAMMAR_NUMBERS_LEXER,
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), GRAMMAR_NUMBERS),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let mut stream = StringStream::new(Path::new("<input>"), "1+2*3");
+        let (table, raw_input) = parser.recognise(&mut lexer.lex(&mut stream)).unwrap();
+        let forest = parser.to_forest(&table, &raw_input).unwrap();
+        let profile = parser.profile(&table, &forest, &raw_input).unwrap();
+        let items = table.iter().map(|set| set.slice().len()).sum::<usize>();
+        assert_eq!(profile.iter().map(|(_, counts)| counts.items).sum::<usize>(), items);
+        assert!(profile
+            .windows(2)
+            .all(|pair| pair[0].1.items >= pair[1].1.items));
+        // Sum ::= Sum PM Product, Sum ::= Product, Product ::= Product TD Factor,
+        // two Product ::= Factor and three Factor ::= NUMBER.
+        let nodes = |rule: usize| {
+            profile
+                .iter()
+                .find(|(id, _)| *id == RuleId(rule))
+                .map_or(0, |(_, counts)| counts.nodes)
+        };
+        assert_eq!((0..6).map(nodes).collect::<Vec<_>>(), [1, 1, 1, 2, 0, 3]);
+        for (_, counts) in &profile {
+            assert!(counts.predictions + counts.completions <= 2 * counts.items);
+        }
+    }
+
+    #[test]
+    fn ambiguities() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "A ::= a\nPLUS ::= \\+",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@S ::= S PLUS S <> T <>;\nT ::= A <> U <>;\nU ::= A <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let ambiguities = |input: &str| {
+            let mut stream = StringStream::new(Path::new("<input>"), input);
+            let mut input = lexer.lex(&mut stream);
+            let (table, raw_input) = parser.recognise(&mut input).unwrap();
+            let forest = parser.to_forest(&table, &raw_input).unwrap();
+            parser.ambiguities(&forest, &raw_input, input.last_span())
+        };
+        let single = ambiguities("a");
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].severity, Severity::Warning);
+        assert_eq!(&*single[0].nonterminal, "S");
+        assert_eq!(single[0].rules.len(), 2);
+        let sum = ambiguities("a+a+a");
+        let count = |severity| sum.iter().filter(|a| a.severity == severity).count();
+        assert_eq!(count(Severity::Note), 1);
+        assert_eq!(count(Severity::Warning), 3);
+    }
+
+    #[test]
+    fn name_collision() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "A ::= a\nB ::= b",
+        ))
+        .unwrap();
+        let build = |grammar: &str| {
+            EarleyGrammar::build_from_plain(
+                StringStream::new(Path::new("<grammar input>"), grammar),
+                lexer.grammar(),
+            )
+        };
+        let error = build("@S ::= B A <>;\nB ::= A <>;").unwrap_err();
+        let ErrorKind::GrammarAmbiguousName { name, .. } = *error.kind else {
+            panic!("wrong error: {error}");
+        };
+        assert_eq!(name, "B");
+        assert!(build("@S ::= !n B A <>\n !t B <>;\nB ::= A <>;").is_ok());
+        assert!(build("@S ::= !t S <>;").is_err());
+        assert!(build("@S ::= !n A <>;").is_err());
+    }
+
+    #[test]
+    fn literals() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "INT ::= ([0-9]+)\nLPAR ::= \\(\nRPAR ::= \\)\nQUOTE ::= '\nignore SPACE ::= [ ]+",
+        ))
+        .unwrap();
+        let build = |grammar: &str| {
+            EarleyGrammar::build_from_plain(
+                StringStream::new(Path::new("<grammar input>"), grammar),
+                lexer.grammar(),
+            )
+        };
+        let grammar = build("@E ::= '(' E@inner ')' <>\n '\\'' INT.0@value <>;").unwrap();
+        let parser = EarleyParser::new(grammar);
+        let mut stream = StringStream::new(Path::new("<input>"), "(( '1 ))");
+        assert!(parser.is_valid(&mut lexer.lex(&mut stream)));
+        assert_eq!(lexer.grammar().literal("12"), lexer.grammar().id("INT"));
+        assert_eq!(lexer.grammar().literal("1("), None);
+        assert_eq!(lexer.grammar().literal(" "), None);
+        let error = build("@E ::= '[' INT <>;").unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::GrammarUnknownLiteral { .. }));
+    }
+
+    #[test]
+    fn precedence_table() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "INT ::= ([0-9]+)\nPLUS ::= \\+\nTIMES ::= \\*\nPOW ::= \\^",
+        ))
+        .unwrap();
+        let build = |grammar: &str| {
+            EarleyGrammar::build_from_plain(
+                StringStream::new(Path::new("<grammar input>"), grammar),
+                lexer.grammar(),
+            )
+        };
+        let grammar = build(
+            "precedence {\n\
+               left PLUS;\n\
+               left '*';\n\
+               right POW;\n\
+             }\n\
+             @E ::=\n\
+               E@left POW E@right <Pow>\n\
+               E@left TIMES E@right <Mul>\n\
+               E@left PLUS E@right <Add>\n\
+               INT.0@value <Int>;",
+        )
+        .unwrap();
+        assert_eq!(grammar.rules()[RuleId(2)].precedence, Some(0));
+        assert!(!grammar.rules()[RuleId(0)].left_associative);
+        let parser = EarleyParser::new(grammar);
+        let parse = |input: &str| {
+            let mut stream = StringStream::new(Path::new("<input>"), input);
+            to_sexpr(&parser.parse(&mut lexer.lex(&mut stream)).unwrap().tree)
+        };
+        assert_eq!(
+            parse("1+2*3^4^5+6"),
+            "(Add :left (Add :left (Int :value \"1\") :right (Mul :left (Int :value \"2\") \
+             :right (Pow :left (Int :value \"3\") :right (Pow :left (Int :value \"4\") \
+             :right (Int :value \"5\"))))) :right (Int :value \"6\"))"
+        );
+        let error = build("precedence { left PLUS; right PLUS; }\n@E ::= INT <>;").unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::GrammarDuplicatePrecedence { .. }));
+    }
+
+    #[test]
+    fn predicates() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "keyword TYPEDEF ::= typedef\nID ::= ([a-z]+)\nSTAR ::= \\*\nSEMI ::= ;\n\
+             ignore SPACE ::= [ ]+",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@File ::= List[Stmt, SEMI]@stmts <>;\n\
+                 Stmt ::=\n\
+                   TYPEDEF ID.0@name <Typedef>\n\
+                   Type@type STAR ID.0@name <Declaration>\n\
+                   &{value} ID.0@left STAR ID.0@right <Product>;\n\
+                 Type ::= &{type} ID.0@name <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let parse = |parser: &EarleyParser, input: &str| {
+            let mut stream = StringStream::new(Path::new("<input>"), input);
+            parser
+                .parse(&mut lexer.lex(&mut stream))
+                .map(|result| to_sexpr(&result.tree))
+        };
+        let error = parse(&parser, "a * b").unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::UndefinedPredicate { .. }));
+
+        fn is_type(before: &[Token], name: &str) -> bool {
+            before
+                .windows(2)
+                .any(|pair| pair[0].name() == "TYPEDEF" && pair[1].get(0) == Some(name))
+        }
+        let parser = parser
+            .with_predicate("type", |before, matched| {
+                is_type(before, matched[0].force_get(0))
+            })
+            .with_predicate("value", |before, matched| {
+                !is_type(before, matched[0].force_get(0))
+            });
+        let tree = parse(&parser, "typedef t; t * x; a * b").unwrap();
+        assert!(tree.contains("Declaration") && tree.contains("Product"));
+        let tree = parse(&parser, "typedef a; a * b").unwrap();
+        assert!(tree.contains("Declaration") && !tree.contains("Product"));
+        let tree = parse(&parser, "a * b").unwrap();
+        assert!(!tree.contains("Declaration") && tree.contains("Product"));
+    }
+
+    #[test]
+    fn undefined_references() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "INT ::= ([0-9]+)\nPLUS ::= \\+",
+        ))
+        .unwrap();
+        let build = |grammar: &str| {
+            EarleyGrammar::build_from_plain(
+                StringStream::new(Path::new("<grammar input>"), grammar),
+                lexer.grammar(),
+            )
+        };
+        let error = build("@E ::= E PLUS INTEGER <>\n !t MINUS <>\n Paren['('] <>;\n\
+                           Paren[open] ::= open E ')' <>;")
+        .unwrap_err();
+        let ErrorKind::GrammarUndefinedReferences { errors } = *error.kind else {
+            panic!("expected several undefined references");
+        };
+        assert_eq!(errors.len(), 4);
+        let error = build("@E ::= E PLUS INTEGER <>;").unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::GrammarUndefinedNonTerminal { .. }));
+    }
+
+    #[test]
+    fn prelude_templates() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "INT ::= ([0-9]+)\nCOMMA ::= ,\nLPAR ::= \\(\nRPAR ::= \\)",
+        ))
+        .unwrap();
+        let parser = |grammar: &str| {
+            let grammar = EarleyGrammar::build_from_plain(
+                StringStream::new(Path::new("<grammar input>"), grammar),
+                lexer.grammar(),
+            )
+            .unwrap();
+            EarleyParser::new(grammar)
+        };
+        let is_valid = |parser: &EarleyParser, input: &str| {
+            let mut stream = StringStream::new(Path::new("<input>"), input);
+            parser.is_valid(&mut lexer.lex(&mut stream))
+        };
+        let ints = parser("@Ints ::= Delimited[LPAR, List[INT, COMMA], RPAR]@items <>;");
+        assert!(is_valid(&ints, "(1,2,3)"));
+        assert!(is_valid(&ints, "()"));
+        assert!(!is_valid(&ints, "(1,)"));
+        let int = parser(
+            "Option[content] ::= content@value <>;\n@Int ::= Option[INT]@int <>;",
+        );
+        assert!(is_valid(&int, "1"));
+        assert!(!is_valid(&int, ""));
+    }
+
+    #[test]
+    fn recognise_handle_empty_rules() {
+        let lexer_input = r#""#;
+        let grammar_input = r#"
+@A ::= <>
+ B <>;
+B ::= A <>;"#;
+        let input = r#""#;
+        let lexer =
+            Lexer::build_from_plain(StringStream::new(Path::new("<lexer input>"), lexer_input))
+                .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), grammar_input),
+            lexer.grammar(),
+        )
         .unwrap();
         let parser = EarleyParser::new(grammar);
         let sets = sets!(
@@ -1808,6 +4768,42 @@ int main() {
             .is_err());
     }
 
+    #[test]
+    fn all_errors() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<NUMBERS LEXER>"),
+            GRAMMAR_NUMBERS_LEXER,
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<NUMBERS>"), GRAMMAR_NUMBERS),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let parse = |input: &str, max_errors| {
+            let mut stream = StringStream::new(Path::new("<input>"), input);
+            parser.parse_all_errors(&mut lexer.lex(&mut stream), max_errors)
+        };
+        let result = parse("1+)2*+3", 10);
+        assert!(result.tree.is_some());
+        assert_eq!(result.errors.len(), 2);
+        assert!(result
+            .errors
+            .iter()
+            .all(|error| matches!(*error.kind, ErrorKind::ParseError { found: Some(_), .. })));
+        let result = parse("1+)2*+3", 1);
+        assert!(result.tree.is_none());
+        assert_eq!(result.errors.len(), 1);
+        let result = parse("1+)2*+", 10);
+        assert!(result.tree.is_none());
+        assert_eq!(result.errors.len(), 3);
+        assert!(matches!(
+            *result.errors[2].kind,
+            ErrorKind::ParseError { found: None, .. }
+        ));
+    }
+
     #[test]
     fn priority_associativity() {
         // Expected tree:
@@ -2028,7 +5024,7 @@ int main() {
         let mut lexed_input = lexer.lex(&mut input_stream);
         let (table, raw_input) = parser.recognise(&mut lexed_input).unwrap();
         let forest = parser.to_forest(&table, &raw_input).unwrap();
-        let ast = parser.select_ast(&forest, &raw_input, lexed_input.last_span());
+        let ast = parser.select_ast(&forest, &raw_input, lexed_input.last_span()).unwrap();
 
         let test_ast = {
             use super::super::parser::Value::*;
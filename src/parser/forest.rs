@@ -0,0 +1,627 @@
+//! # Forest
+//!
+//! A shared packed parse forest (SPPF) represents every derivation of an
+//! input at once. Sub-derivations that several parse trees have in common are
+//! shared, and the alternatives of an ambiguous node are packed together, so
+//! that the size of the forest stays polynomial in the size of the input.
+//!
+//! The forest is binarised: the derivation of a rule `A -> X_1 ... X_n` is
+//! made of the intermediate node of `X_1 ... X_{n-1}`, and of the node of
+//! `X_n`.
+
+use super::earley::{EarleyGrammar, FinalSet};
+use super::grammar::{ElementType, RuleId};
+use super::parser::{NonTerminalId, AST};
+use crate::interner::Symbol;
+use crate::lexer::Token;
+use crate::span::Span;
+use newty::newty;
+use std::collections::HashMap;
+
+newty! {
+    pub id NodeId
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    /// A non-terminal.
+    NonTerminal(NonTerminalId),
+    /// The token at the given position of the input.
+    Token(usize),
+    /// The first `position` elements of `rule`.
+    Intermediate { rule: RuleId, position: usize },
+}
+
+/// # Summary
+///
+/// One way of deriving a node. If the node is derived through the first `n`
+/// elements of `rule`, `left` is the intermediate node of the first `n-1`
+/// elements (`None` if `n ≤ 1`), and `right` the node of the `n`-th element
+/// (`None` if `n = 0`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packed {
+    pub rule: RuleId,
+    pub left: Option<NodeId>,
+    pub right: Option<NodeId>,
+}
+
+/// A node of the forest, which spans the tokens from `start` (inclusive) to
+/// `end` (exclusive). It is ambiguous if it has several packed nodes.
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub start: usize,
+    pub end: usize,
+    pub packed: Vec<Packed>,
+}
+
+/// # Summary
+///
+/// [`Sppf`] is a shared packed parse forest. Its roots are the axioms that
+/// derive the whole input.
+#[derive(Debug, Clone)]
+pub struct Sppf {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+}
+
+impl Sppf {
+    /// Build the forest from the completed items found by the Earley parser.
+    pub fn new(grammar: &EarleyGrammar, forest: &[FinalSet], raw_input: &[Token]) -> Self {
+        let mut builder = Builder {
+            grammar,
+            forest,
+            raw_input,
+            nodes: Vec::new(),
+            index: HashMap::new(),
+        };
+        let roots = (0..grammar.nb_non_terminals())
+            .map(NonTerminalId)
+            .filter(|&id| grammar.is_axiom(id))
+            .filter_map(|id| builder.non_terminal(id, 0, raw_input.len()))
+            .collect();
+        Self {
+            nodes: builder.nodes,
+            roots,
+        }
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Return whether there is more than one derivation of the input.
+    pub fn is_ambiguous(&self) -> bool {
+        self.roots.len() > 1 || self.nodes.iter().any(|node| node.packed.len() > 1)
+    }
+
+    /// Return every derivation of a non-terminal node, as the rule used and
+    /// the nodes of its elements, in order. Intermediate nodes are unfolded.
+    pub fn derivations(&self, id: NodeId) -> Vec<(RuleId, Vec<NodeId>)> {
+        self.nodes[id.0]
+            .packed
+            .iter()
+            .flat_map(|packed| {
+                self.sequences(packed)
+                    .into_iter()
+                    .map(|children| (packed.rule, children))
+            })
+            .collect()
+    }
+
+    /// Iterate lazily over every derivation tree of the non-terminal `nonterminal`
+    /// that spans the tokens from `start` to `end`. The forest only holds the
+    /// nodes of the derivations of the whole input, so the derivations of a
+    /// sub-span are the ones that take part in a derivation of the whole input.
+    pub fn derivations_of(
+        &self,
+        nonterminal: NonTerminalId,
+        start: usize,
+        end: usize,
+    ) -> Derivations<'_> {
+        let kind = NodeKind::NonTerminal(nonterminal);
+        let root = self
+            .nodes
+            .iter()
+            .position(|node| node.kind == kind && node.start == start && node.end == end)
+            .map(NodeId);
+        Derivations {
+            sppf: self,
+            root,
+            choices: Vec::new(),
+            done: root.is_none(),
+        }
+    }
+
+    fn sequences(&self, packed: &Packed) -> Vec<Vec<NodeId>> {
+        let prefixes = match packed.left {
+            Some(left) => self.nodes[left.0]
+                .packed
+                .iter()
+                .flat_map(|packed| self.sequences(packed))
+                .collect(),
+            None => vec![Vec::new()],
+        };
+        prefixes
+            .into_iter()
+            .map(|mut children| {
+                children.extend(packed.right);
+                children
+            })
+            .collect()
+    }
+}
+
+/// # Summary
+///
+/// [`Asts`] lazily enumerates every AST of a forest.
+///
+/// Each AST is described by the alternative chosen at every node met while
+/// building it, in pre-order, and the descriptions are enumerated like the
+/// digits of an odometer. Derivations that go through a node within itself,
+/// which only exist in cyclic grammars, are skipped, so that there are finitely
+/// many ASTs.
+#[derive(Debug)]
+pub struct Asts<'a> {
+    grammar: &'a EarleyGrammar,
+    sppf: Sppf,
+    raw_input: &'a [Token],
+    last_span: Span,
+    /// The alternatives to choose to build the next AST.
+    choices: Vec<usize>,
+    done: bool,
+}
+
+impl<'a> Asts<'a> {
+    pub(crate) fn new(
+        grammar: &'a EarleyGrammar,
+        sppf: Sppf,
+        raw_input: &'a [Token],
+        last_span: Span,
+    ) -> Self {
+        let done = sppf.roots().is_empty();
+        Self {
+            grammar,
+            sppf,
+            raw_input,
+            last_span,
+            choices: Vec::new(),
+            done,
+        }
+    }
+
+    /// Build the AST of the node `id`, which inherits the attributes `inherited`,
+    /// or `None` if the choices lead to a cycle.
+    fn build(
+        &self,
+        id: NodeId,
+        choices: &mut Vec<usize>,
+        widths: &mut Vec<usize>,
+        ancestors: &mut Vec<NodeId>,
+        inherited: &HashMap<Symbol, AST>,
+    ) -> Option<AST> {
+        let node = self.sppf.node(id);
+        if let NodeKind::Token(position) = node.kind {
+            return Some(AST::Terminal(self.raw_input[position].clone()));
+        }
+        if ancestors.contains(&id) {
+            return None;
+        }
+        let mut derivations = self.sppf.derivations(id);
+        let choice = choose(choices, widths, derivations.len());
+        let (rule, children) = derivations.swap_remove(choice);
+        let rule = &self.grammar.rules()[rule];
+        let span = if self.raw_input.is_empty() {
+            self.last_span.clone()
+        } else if node.start == node.end {
+            self.raw_input
+                .get(node.start)
+                .map_or(&self.last_span, Token::span)
+                .clone()
+        } else {
+            self.raw_input[node.start]
+                .span()
+                .sup(self.raw_input[node.end - 1].span())
+        };
+        ancestors.push(id);
+        let mut values = Vec::with_capacity(children.len());
+        for (position, child) in children.into_iter().enumerate() {
+            let inherited = rule.inherited_by(position, &values, inherited, &span);
+            let Some(value) = self.build(child, choices, widths, ancestors, &inherited) else {
+                ancestors.pop();
+                return None;
+            };
+            values.push(value);
+        }
+        ancestors.pop();
+        Some(rule.build_node_inheriting(values, span, inherited))
+    }
+}
+
+impl Iterator for Asts<'_> {
+    type Item = AST;
+
+    fn next(&mut self) -> Option<AST> {
+        while !self.done {
+            let mut choices = std::mem::take(&mut self.choices);
+            let mut widths = Vec::new();
+            let roots = self.sppf.roots();
+            let root = roots[choose(&mut choices, &mut widths, roots.len())];
+            let ast = self.build(
+                root,
+                &mut choices,
+                &mut widths,
+                &mut Vec::new(),
+                &HashMap::new(),
+            );
+            self.done = !advance(&mut choices, &widths);
+            self.choices = choices;
+            if ast.is_some() {
+                return ast;
+            }
+        }
+        None
+    }
+}
+
+/// Pick the alternative of the next node, among `nb_alternatives`.
+fn choose(choices: &mut Vec<usize>, widths: &mut Vec<usize>, nb_alternatives: usize) -> usize {
+    let index = widths.len();
+    widths.push(nb_alternatives);
+    if index == choices.len() {
+        choices.push(0);
+    }
+    choices[index]
+}
+
+/// Move on to the next choices: the choices made after the last one that can be
+/// incremented are reset. Return `false` if there is none.
+fn advance(choices: &mut Vec<usize>, widths: &[usize]) -> bool {
+    choices.truncate(widths.len());
+    match (0..choices.len()).rev().find(|&i| choices[i] + 1 < widths[i]) {
+        Some(i) => {
+            choices[i] += 1;
+            choices.truncate(i + 1);
+            true
+        }
+        None => false,
+    }
+}
+
+/// # Summary
+///
+/// [`Derivation`] is a derivation tree of a node of the forest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Derivation {
+    /// The node of a token.
+    Token(NodeId),
+    /// The node of a non-terminal, derived with `rule`, and the derivation of
+    /// each of its elements, in order.
+    Rule {
+        node: NodeId,
+        rule: RuleId,
+        children: Vec<Derivation>,
+    },
+}
+
+/// # Summary
+///
+/// [`Derivations`] lazily enumerates the derivation trees of a node, in the same
+/// way as [`Asts`] enumerates ASTs.
+#[derive(Debug)]
+pub struct Derivations<'a> {
+    sppf: &'a Sppf,
+    root: Option<NodeId>,
+    /// The alternatives to choose to build the next derivation.
+    choices: Vec<usize>,
+    done: bool,
+}
+
+impl Derivations<'_> {
+    /// Build the derivation of the node `id`, or `None` if the choices lead to a
+    /// cycle.
+    fn build(
+        &self,
+        id: NodeId,
+        choices: &mut Vec<usize>,
+        widths: &mut Vec<usize>,
+        ancestors: &mut Vec<NodeId>,
+    ) -> Option<Derivation> {
+        if let NodeKind::Token(_) = self.sppf.node(id).kind {
+            return Some(Derivation::Token(id));
+        }
+        if ancestors.contains(&id) {
+            return None;
+        }
+        let mut derivations = self.sppf.derivations(id);
+        let choice = choose(choices, widths, derivations.len());
+        let (rule, children) = derivations.swap_remove(choice);
+        ancestors.push(id);
+        let children = children
+            .into_iter()
+            .map(|child| self.build(child, choices, widths, ancestors))
+            .collect::<Option<Vec<_>>>();
+        ancestors.pop();
+        Some(Derivation::Rule {
+            node: id,
+            rule,
+            children: children?,
+        })
+    }
+}
+
+impl Iterator for Derivations<'_> {
+    type Item = Derivation;
+
+    fn next(&mut self) -> Option<Derivation> {
+        while let (false, Some(root)) = (self.done, self.root) {
+            let mut choices = std::mem::take(&mut self.choices);
+            let mut widths = Vec::new();
+            let derivation = self.build(root, &mut choices, &mut widths, &mut Vec::new());
+            self.done = !advance(&mut choices, &widths);
+            self.choices = choices;
+            if derivation.is_some() {
+                return derivation;
+            }
+        }
+        None
+    }
+}
+
+struct Builder<'a> {
+    grammar: &'a EarleyGrammar,
+    forest: &'a [FinalSet],
+    raw_input: &'a [Token],
+    nodes: Vec<Node>,
+    /// Maps every node that was looked for to its identifier, if it exists.
+    index: HashMap<(NodeKind, usize, usize), Option<NodeId>>,
+}
+
+impl Builder<'_> {
+    fn push(&mut self, kind: NodeKind, start: usize, end: usize) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Node {
+            kind,
+            start,
+            end,
+            packed: Vec::new(),
+        });
+        self.index.insert((kind, start, end), Some(id));
+        id
+    }
+
+    fn token(&mut self, position: usize) -> NodeId {
+        match self.index.get(&(NodeKind::Token(position), position, position + 1)) {
+            Some(&Some(id)) => id,
+            _ => self.push(NodeKind::Token(position), position, position + 1),
+        }
+    }
+
+    fn non_terminal(&mut self, id: NonTerminalId, start: usize, end: usize) -> Option<NodeId> {
+        let kind = NodeKind::NonTerminal(id);
+        if let Some(&node) = self.index.get(&(kind, start, end)) {
+            return node;
+        }
+        let rules = self.forest[start]
+            .completed(id)
+            .filter(|&(_, rule_end)| rule_end == end)
+            .map(|(rule, _)| rule)
+            .collect::<Vec<_>>();
+        if rules.is_empty() {
+            self.index.insert((kind, start, end), None);
+            return None;
+        }
+        // The node is created before its derivations, because they might
+        // refer to it if the grammar is cyclic.
+        let node = self.push(kind, start, end);
+        for rule in rules {
+            let length = self.grammar.rules()[rule].elements.len();
+            let packed = self.packed(rule, length, start, end);
+            self.nodes[node.0].packed.extend(packed);
+        }
+        Some(node)
+    }
+
+    fn intermediate(
+        &mut self,
+        rule: RuleId,
+        position: usize,
+        start: usize,
+        end: usize,
+    ) -> Option<NodeId> {
+        let kind = NodeKind::Intermediate { rule, position };
+        if let Some(&node) = self.index.get(&(kind, start, end)) {
+            return node;
+        }
+        let node = self.push(kind, start, end);
+        let packed = self.packed(rule, position, start, end);
+        if packed.is_empty() {
+            self.index.insert((kind, start, end), None);
+            return None;
+        }
+        self.nodes[node.0].packed = packed;
+        Some(node)
+    }
+
+    /// Find every way the first `position` elements of `rule` span the tokens
+    /// from `start` to `end`.
+    fn packed(
+        &mut self,
+        rule: RuleId,
+        position: usize,
+        start: usize,
+        end: usize,
+    ) -> Vec<Packed> {
+        if position == 0 {
+            return if start == end {
+                vec![Packed {
+                    rule,
+                    left: None,
+                    right: None,
+                }]
+            } else {
+                Vec::new()
+            };
+        }
+        let element = self.grammar.rules()[rule].elements[position - 1].element_type;
+        let splits = match element {
+            ElementType::Terminal(_) if end > start => end - 1..end,
+            ElementType::Terminal(_) => 0..0,
+            ElementType::NonTerminal(_) => start..end + 1,
+        };
+        let mut result = Vec::new();
+        for split in splits {
+            let right = match element {
+                ElementType::Terminal(id) if self.raw_input[split].id() == id => {
+                    Some(self.token(split))
+                }
+                ElementType::Terminal(_) => None,
+                ElementType::NonTerminal(id) => self.non_terminal(id, split, end),
+            };
+            let Some(right) = right else {
+                continue;
+            };
+            let left = if position == 1 {
+                if split != start {
+                    continue;
+                }
+                None
+            } else {
+                let Some(left) = self.intermediate(rule, position - 1, start, split) else {
+                    continue;
+                };
+                Some(left)
+            };
+            result.push(Packed {
+                rule,
+                left,
+                right: Some(right),
+            });
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Buildable;
+    use crate::lexer::Lexer;
+    use crate::parser::earley::EarleyParser;
+    use crate::parser::Parser;
+    use crate::stream::StringStream;
+    use itertools::Itertools;
+    use std::path::Path;
+
+    const LEXER: &str = r#"
+NUMBER ::= ([0-9])
+PM ::= [-+]
+"#;
+
+    const GRAMMAR: &str = r#"
+@Expr ::= Expr@left PM Expr@right <>
+ NUMBER.0@self <>;"#;
+
+    fn sppf(input: &str) -> Sppf {
+        let lexer =
+            Lexer::build_from_plain(StringStream::new(Path::new("<lexer input>"), LEXER))
+                .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), GRAMMAR),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let mut stream = StringStream::new(Path::new("<input>"), input);
+        let (table, raw_input) = parser.recognise(&mut lexer.lex(&mut stream)).unwrap();
+        let forest = parser.to_forest(&table, &raw_input).unwrap();
+        parser.to_sppf(&forest, &raw_input)
+    }
+
+    #[test]
+    fn unambiguous() {
+        let sppf = sppf("1+2");
+        assert_eq!(sppf.roots().len(), 1);
+        assert!(!sppf.is_ambiguous());
+        let derivations = sppf.derivations(sppf.roots()[0]);
+        assert_eq!(derivations.len(), 1);
+        assert_eq!(derivations[0].1.len(), 3);
+    }
+
+    #[test]
+    fn ambiguous() {
+        let sppf = sppf("1+2+3");
+        assert!(sppf.is_ambiguous());
+        let root = sppf.roots()[0];
+        let derivations = sppf.derivations(root);
+        assert_eq!(derivations.len(), 2);
+        let splits = derivations
+            .iter()
+            .map(|(_, children)| {
+                let left = sppf.node(children[0]);
+                (left.start, left.end)
+            })
+            .collect::<Vec<_>>();
+        assert!(splits.contains(&(0, 1)));
+        assert!(splits.contains(&(0, 3)));
+    }
+
+    #[test]
+    fn derivations_of() {
+        let sppf = sppf("1+2+3+4");
+        let NodeKind::NonTerminal(expr) = sppf.node(sppf.roots()[0]).kind else {
+            panic!("expected a non-terminal root");
+        };
+        let derivations = sppf.derivations_of(expr, 0, 7).collect::<Vec<_>>();
+        assert_eq!(derivations.len(), 5);
+        assert!(derivations.iter().tuple_combinations().all(|(a, b)| a != b));
+        assert_eq!(sppf.derivations_of(expr, 0, 5).count(), 2);
+        assert_eq!(sppf.derivations_of(expr, 1, 3).count(), 0);
+
+        let derivations = sppf.derivations_of(expr, 2, 3).collect::<Vec<_>>();
+        let [Derivation::Rule { node, children, .. }] = &derivations[..] else {
+            panic!("expected a single derivation");
+        };
+        assert_eq!((sppf.node(*node).start, sppf.node(*node).end), (2, 3));
+        let [Derivation::Token(token)] = &children[..] else {
+            panic!("expected a single token");
+        };
+        assert_eq!(sppf.node(*token).kind, NodeKind::Token(2));
+    }
+
+    #[test]
+    fn asts() {
+        let lexer =
+            Lexer::build_from_plain(StringStream::new(Path::new("<lexer input>"), LEXER))
+                .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), GRAMMAR),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        for (input, nb_asts) in [("1", 1), ("1+2", 1), ("1+2+3", 2), ("1+2+3+4", 5)] {
+            let mut stream = StringStream::new(Path::new("<input>"), input);
+            let mut input = lexer.lex(&mut stream);
+            let (table, raw_input) = parser.recognise(&mut input).unwrap();
+            let forest = parser.to_forest(&table, &raw_input).unwrap();
+            let asts = parser
+                .iter_asts(&forest, &raw_input, input.last_span())
+                .collect::<Vec<_>>();
+            assert_eq!(asts.len(), nb_asts);
+            assert!(asts.iter().tuple_combinations().all(|(a, b)| a != b));
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! # Format
+//!
+//! The canonical layout of parser grammars, which `beans fmt` writes: declarations
+//! are separated by a blank line, each rule of a non-terminal is on its own line,
+//! indented by two spaces, and wrapped between its parts past a given width, and the
+//! entries of proxies and of inherited attributes are sorted by key, after the
+//! variant. Formatting keeps the meaning of a grammar, and the `include` and
+//! `import` lines where they are, so that formatting a formatted grammar changes
+//! nothing.
+
+use std::{collections::HashMap, fmt::Write, path::Path, rc::Rc};
+
+use super::ast::{
+    Ast, Associativity, Element, Expression, Item, Proxy, Rule, ToplevelDeclaration,
+};
+use super::earley::RulePreference;
+use super::Parser;
+use crate::{
+    build_system,
+    builder::Buildable,
+    error::Result,
+    span::Span,
+    stream::{blank_inclusions, StringStream},
+    typed::{Spanned, Tree},
+};
+
+/// The width past which `beans fmt` wraps rules by default.
+pub const DEFAULT_WIDTH: usize = 96;
+
+/// Format the parser grammar `text`, read from the file `path`, in the canonical
+/// layout, wrapping the rules longer than `width` chars.
+pub fn format_parser_grammar(path: &Path, text: &str, width: usize) -> Result<String> {
+    let (lexer, parser) = build_system!(
+        lexer => "parser.clx",
+        parser => "parser.cgr",
+    )?;
+    let (source, inclusions) = blank_inclusions(text);
+    let mut stream = StringStream::new(path, source);
+    let tree = parser.parse(&mut lexer.lex(&mut stream))?.tree;
+    let mut blocks = inclusions
+        .into_iter()
+        .map(|(offset, line)| (offset, true, line.to_string()))
+        .collect::<Vec<_>>();
+    for decl in Ast::read(tree)?.decls {
+        blocks.push((decl.span.start_byte(), false, declaration(&decl.inner, width)));
+    }
+    blocks.sort_by_key(|&(offset, ..)| offset);
+    let mut formatted = String::new();
+    let mut last_inclusion = None;
+    for (_, inclusion, block) in blocks {
+        match last_inclusion {
+            Some(true) if inclusion => formatted.push('\n'),
+            Some(_) => formatted.push_str("\n\n"),
+            None => {}
+        }
+        formatted.push_str(&block);
+        last_inclusion = Some(inclusion);
+    }
+    if last_inclusion.is_some() {
+        formatted.push('\n');
+    }
+    Ok(formatted)
+}
+
+fn declaration(decl: &ToplevelDeclaration, width: usize) -> String {
+    match decl {
+        ToplevelDeclaration::Decl(decl) => {
+            let mut text = String::new();
+            if let Some(comment) = &decl.comment {
+                writeln!(text, "\"{}\"", comment.inner).unwrap();
+            }
+            if decl.axiom.inner {
+                text.push('@');
+            }
+            text.push_str(&decl.name.inner);
+            if let Some(mode) = &decl.mode {
+                write!(text, " (mode {})", mode.inner).unwrap();
+            }
+            text.push_str(" ::=");
+            push_rules(&mut text, &decl.rules, width);
+            text
+        }
+        ToplevelDeclaration::Macro(decl) => {
+            let args = decl.args.iter().map(|arg| &*arg.inner).collect::<Vec<_>>();
+            let mut text = format!("{}[{}] ::=", decl.name.inner, args.join(", "));
+            push_rules(&mut text, &decl.rules, width);
+            text
+        }
+        ToplevelDeclaration::Prefer(preference) => match preference {
+            RulePreference::First => String::from("prefer first-rule;"),
+            RulePreference::Last => String::from("prefer last-rule;"),
+        },
+        ToplevelDeclaration::Mode(mode) => {
+            let mut text = format!("mode {} ::=", mode.name.inner);
+            for terminal in &mode.terminals {
+                write!(text, " {}", terminal.inner).unwrap();
+            }
+            text.push(';');
+            text
+        }
+        ToplevelDeclaration::Version(version) => format!("version \"{}\";", version.0.inner),
+        ToplevelDeclaration::Precedence(table) => {
+            let mut text = String::from("precedence {\n");
+            for level in &table.levels {
+                text.push_str(match level.assoc.inner {
+                    Associativity::Left => "  left",
+                    Associativity::Right => "  right",
+                });
+                for operator in &level.operators {
+                    write!(text, " {}", item(&operator.inner)).unwrap();
+                }
+                text.push_str(";\n");
+            }
+            text.push('}');
+            text
+        }
+    }
+}
+
+/// Write `rules`, each on its own line, and the `;` that ends the declaration. A rule
+/// that would go past `width` chars goes on on the next lines, indented further,
+/// from the first of its parts that would.
+fn push_rules(text: &mut String, rules: &[Rule], width: usize) {
+    if rules.is_empty() {
+        text.push(';');
+    }
+    for (i, rule) in rules.iter().enumerate() {
+        let mut parts = Vec::new();
+        match rule.left_associative.as_ref().map(|assoc| assoc.inner) {
+            Some(Associativity::Left) => parts.push(String::from("(left-assoc)")),
+            Some(Associativity::Right) => parts.push(String::from("(right-assoc)")),
+            None => {}
+        }
+        if let Some(weight) = &rule.weight {
+            parts.push(format!("({} {})", weight.name.inner, weight.value.inner));
+        }
+        if let Some(predicate) = &rule.predicate {
+            parts.push(format!("&{{{}}}", predicate.inner));
+        }
+        parts.extend(rule.elements.iter().map(element));
+        let mut last = proxy(&rule.proxy);
+        if i + 1 == rules.len() {
+            last.push(';');
+        }
+        parts.push(last);
+        text.push_str("\n  ");
+        let mut column = 2;
+        for (j, part) in parts.iter().enumerate() {
+            let length = part.chars().count();
+            if j > 0 && column + 1 + length > width {
+                text.push_str("\n    ");
+                column = 4;
+            } else if j > 0 {
+                text.push(' ');
+                column += 1;
+            }
+            text.push_str(part);
+            column += length;
+        }
+    }
+}
+
+fn element(element: &Element) -> String {
+    let mut text = item(&element.item.inner);
+    if !element.inherited.is_empty() {
+        write!(text, "{{{}}}", entries(&element.inherited).join(", ")).unwrap();
+    }
+    if element.optional.inner {
+        text.push('?');
+    }
+    if let Some(attribute) = &element.attribute {
+        write!(text, ".{}", attribute.attribute.inner).unwrap();
+    }
+    if let Some(key) = &element.key {
+        write!(text, "@{}", key.0.inner).unwrap();
+    }
+    if let Some(default) = &element.default {
+        write!(text, " = default({})", expression(&default.inner)).unwrap();
+    }
+    text
+}
+
+fn item(value: &Item) -> String {
+    match value {
+        Item::SelfNonTerminal => String::from("Self"),
+        Item::Regular { name } => name.inner.to_string(),
+        Item::Terminal { name } => format!("!t {}", name.inner),
+        Item::NonTerminal { name } => format!("!n {}", name.inner),
+        Item::MacroInvocation { name, arguments } => {
+            let arguments = arguments
+                .iter()
+                .map(|argument| item(&argument.inner))
+                .collect::<Vec<_>>();
+            format!("{}[{}]", name.inner, arguments.join(", "))
+        }
+        Item::Literal { text } => format!("'{}'", escape(&text.inner)),
+    }
+}
+
+/// Escape the text of a literal, which the lexer unescapes.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for chr in text.chars() {
+        match chr {
+            '\\' => escaped.push_str("\\\\"),
+            '\'' => escaped.push_str("\\'"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\0' => escaped.push_str("\\0"),
+            chr if chr.is_control() => write!(escaped, "\\u{{{:x}}}", chr as u32).unwrap(),
+            chr => escaped.push(chr),
+        }
+    }
+    escaped
+}
+
+fn proxy(proxy: &Proxy) -> String {
+    let variant = proxy.variant.iter().map(|variant| variant.inner.to_string());
+    let items = variant.chain(entries(&proxy.items)).collect::<Vec<_>>();
+    format!("<{}>", items.join(", "))
+}
+
+/// The entries `key: value` of a proxy, or of inherited attributes, sorted by key.
+fn entries(items: &HashMap<Rc<str>, (Spanned<Expression>, Span)>) -> Vec<String> {
+    let mut items = items.iter().collect::<Vec<_>>();
+    items.sort_unstable_by_key(|&(key, _)| key);
+    items
+        .into_iter()
+        .map(|(key, (value, _))| format!("{key}: {}", expression(&value.inner)))
+        .collect()
+}
+
+fn expression(expression: &Expression) -> String {
+    match expression {
+        Expression::String(value) => format!("\"{value}\""),
+        Expression::Id(name) => name.to_string(),
+        Expression::Instanciation {
+            name,
+            children,
+            variant,
+        } => {
+            let variant = variant.iter().map(|variant| variant.inner.to_string());
+            let items = variant.chain(entries(children)).collect::<Vec<_>>();
+            format!("{} {{{}}}", name.inner, items.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(text: &str) -> String {
+        format_parser_grammar(Path::new("<grammar input>"), text, DEFAULT_WIDTH).unwrap()
+    }
+
+    #[test]
+    fn canonical_layout() {
+        let formatted = format(
+            "include \"common.gr\"\nprefer   last-rule ;\n\"an expression\" @Expr::= \
+             (left-assoc) Expr@left  '+'  Expr@right <Add>\n\n\n Atom?@atom = default( \
+             \"none\") <Atom , value : \"atom\">;\n\
+             precedence { left '+' MINUS; right POW ; }\n\
+             Pair[a,b]::=a{kind :  \"l\", depth: Depth}.0@first b <Pair>;",
+        );
+        assert_eq!(
+            formatted,
+            "include \"common.gr\"\n\
+             \n\
+             prefer last-rule;\n\
+             \n\
+             \"an expression\"\n\
+             @Expr ::=\n  \
+               (left-assoc) Expr@left '+' Expr@right <Add>\n  \
+               Atom?@atom = default(\"none\") <Atom, value: \"atom\">;\n\
+             \n\
+             precedence {\n  \
+               left '+' MINUS;\n  \
+               right POW;\n\
+             }\n\
+             \n\
+             Pair[a, b] ::=\n  \
+               a{depth: Depth, kind: \"l\"}.0@first b <Pair>;\n"
+        );
+        assert_eq!(format(&formatted), formatted);
+    }
+
+    #[test]
+    fn literals() {
+        let formatted = format("@Quote ::= (weight 2)  &{even} '\\'' Self<>;");
+        assert_eq!(formatted, "@Quote ::=\n  (weight 2) &{even} '\\'' Self <>;\n");
+        assert_eq!(format(&formatted), formatted);
+    }
+
+    #[test]
+    fn wrapped_rules() {
+        let grammar = "@Call ::= Name@function LPAR Arguments@arguments RPAR <Call>\n\
+                       Name@function <Call, arguments: \"none\">;";
+        let formatted = format_parser_grammar(Path::new("<grammar input>"), grammar, 36);
+        let formatted = formatted.unwrap();
+        assert_eq!(
+            formatted,
+            "@Call ::=\n  \
+               Name@function LPAR\n    \
+                 Arguments@arguments RPAR <Call>\n  \
+               Name@function\n    \
+                 <Call, arguments: \"none\">;\n"
+        );
+        let reformatted = format_parser_grammar(Path::new("<grammar input>"), &formatted, 36);
+        assert_eq!(reformatted.unwrap(), formatted);
+    }
+
+    #[test]
+    fn bundled_grammars() {
+        for grammar in [
+            include_str!("parser.gr"),
+            include_str!("prelude.gr"),
+            include_str!("gmrs/dummy.gr"),
+            include_str!("gmrs/petitc.gr"),
+            include_str!("../lexer/lexer.gr"),
+            include_str!("../../benches/grammars/ambiguous.gr"),
+            include_str!("../../benches/grammars/keywords.gr"),
+            include_str!("../../benches/grammars/left.gr"),
+            include_str!("../../benches/grammars/nullable.gr"),
+            include_str!("../../benches/grammars/right.gr"),
+        ] {
+            assert_eq!(format(grammar), grammar);
+        }
+    }
+}
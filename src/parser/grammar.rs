@@ -2,17 +2,23 @@ use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
 use crate::{
-    lexer::{Grammar as LexerGrammar, TerminalId},
+    interner::Symbol,
+    lexer::{Grammar as LexerGrammar, TerminalId, Token},
     span::Span,
 };
 use newty::newty;
 use serde::{Deserialize, Serialize};
 
-use super::{earley::EarleyGrammar, parser::NonTerminalId, Value, AST};
+use super::{
+    arena::{ArenaNode, AstArena, NodeId},
+    earley::EarleyGrammar,
+    parser::NonTerminalId,
+    Value, AST,
+};
 
 newty! {
     #[derive(Serialize, Deserialize)]
-    pub vec NonTerminalName(Rc<str>)[NonTerminalId]
+    pub vec NonTerminalName(Symbol)[NonTerminalId]
 }
 
 newty! {
@@ -38,7 +44,7 @@ newty! {
     pub id RuleId
 }
 
-pub type Proxy = HashMap<Rc<str>, ValueTemplate>;
+pub type Proxy = HashMap<Symbol, ValueTemplate>;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ElementType {
@@ -48,31 +54,94 @@ pub enum ElementType {
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Attribute {
-    Named(Rc<str>),
+    Named(Symbol),
     Indexed(usize),
     None,
 }
 
+/// The value of the attribute `idx` of `token`: the one the lexer converted it to, or
+/// its text, if the token has it.
+fn attribute_value(token: &Token, idx: usize) -> Option<Value> {
+    match token.value(idx) {
+        Some(value) => Some(value.clone()),
+        None => Some(Value::Str(Rc::from(token.get(idx)?))),
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Element {
     pub attribute: Attribute,
-    pub key: Option<Rc<str>>,
+    pub key: Option<Symbol>,
     pub element_type: ElementType,
+    /// The attributes the element inherits from the rule, computed from the
+    /// attributes the rule inherits and from the elements on its left.
+    pub inherited: Proxy,
 }
 
 impl Element {
-    pub fn new(attribute: Attribute, key: Option<Rc<str>>, element_type: ElementType) -> Self {
+    pub fn new(attribute: Attribute, key: Option<Symbol>, element_type: ElementType) -> Self {
         Self {
             attribute,
             key,
             element_type,
+            inherited: Proxy::new(),
         }
     }
 
-    pub fn name(&self, lexer_grammar: &LexerGrammar, grammar: &EarleyGrammar) -> Rc<str> {
+    pub fn with_inherited(mut self, inherited: Proxy) -> Self {
+        self.inherited = inherited;
+        self
+    }
+
+    /// The value bound to the key of the element, given the AST it matched, unless
+    /// the AST lacks the attribute the element takes. The grammar checks that it
+    /// does not, so the key is then left out rather than failing.
+    fn keyed_value(&self, item: AST) -> Option<AST> {
+        match &self.attribute {
+            Attribute::Named(attr) => {
+                let AST::Node { mut attributes, .. } = item else {
+                    return None;
+                };
+                attributes.remove(attr)
+            }
+            Attribute::Indexed(idx) => {
+                let AST::Terminal(token) = item else {
+                    return None;
+                };
+                Some(AST::Literal {
+                    value: attribute_value(&token, *idx)?,
+                    span: Some(token.span().clone()),
+                })
+            }
+            Attribute::None => Some(item),
+        }
+    }
+
+    /// The value bound to the key of the element, given the node it matched, like
+    /// [`Element::keyed_value`].
+    fn keyed_node(&self, item: NodeId, arena: &mut AstArena) -> Option<NodeId> {
+        match &self.attribute {
+            Attribute::Named(attr) => arena.attribute(item, attr),
+            Attribute::Indexed(idx) => {
+                let ArenaNode::Terminal(token) = arena.node(item) else {
+                    return None;
+                };
+                let value = attribute_value(token, *idx)?;
+                let span = Some(token.span().clone());
+                Some(arena.push_literal(value, span))
+            }
+            Attribute::None => Some(item),
+        }
+    }
+
+    pub fn name<'a>(
+        &self,
+        lexer_grammar: &'a LexerGrammar,
+        grammar: &EarleyGrammar,
+    ) -> &'a str {
         match self.element_type {
-            ElementType::Terminal(id) => lexer_grammar.name(id).into(),
-            ElementType::NonTerminal(id) => grammar.name_of(id),
+            ElementType::Terminal(id) => lexer_grammar.name(id),
+            ElementType::NonTerminal(id) => grammar.name_of(id).as_str(),
         }
     }
 }
@@ -80,7 +149,7 @@ impl Element {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ValueTemplate {
     String(Rc<str>),
-    Variable(Rc<str>),
+    Variable(Symbol),
     InlineRule {
         non_terminal: NonTerminalId,
         attributes: Proxy,
@@ -88,47 +157,126 @@ pub enum ValueTemplate {
 }
 
 impl ValueTemplate {
+    /// Evaluate the template. Variables are looked up among `all_attributes`, the
+    /// ones found being added to `removed`, and then among `inherited`. Return `None`
+    /// if a variable is found in neither, and leave out the attributes of inline
+    /// rules that are.
     pub fn evaluate(
         &self,
-        all_attributes: &HashMap<Rc<str>, AST>,
-        removed: &mut HashSet<Rc<str>>,
+        all_attributes: &HashMap<Symbol, AST>,
+        removed: &mut HashSet<Symbol>,
+        inherited: &HashMap<Symbol, AST>,
         span: &Span,
-    ) -> AST {
+    ) -> Option<AST> {
         match self {
-            ValueTemplate::String(string) => AST::Literal {
+            ValueTemplate::String(string) => Some(AST::Literal {
                 value: Value::Str(string.clone()),
                 span: None,
+            }),
+            ValueTemplate::Variable(name) => match all_attributes.get(name) {
+                Some(value) => {
+                    removed.insert(*name);
+                    Some(value.clone())
+                }
+                None => inherited.get(name).cloned(),
             },
-            ValueTemplate::Variable(name) => {
-                removed.insert(name.clone());
-                all_attributes[name].clone()
-            }
             ValueTemplate::InlineRule {
                 non_terminal,
                 attributes,
-            } => AST::Node {
+            } => Some(AST::Node {
                 nonterminal: *non_terminal,
                 attributes: attributes
                     .iter()
-                    .map(|(key, value_template)| {
-                        (
-                            key.clone(),
-                            value_template.evaluate(all_attributes, removed, span),
-                        )
+                    .filter_map(|(key, value_template)| {
+                        let value =
+                            value_template.evaluate(all_attributes, removed, inherited, span)?;
+                        Some((*key, value))
                     })
                     .collect(),
                 span: span.clone(),
+            }),
+        }
+    }
+
+    /// Evaluate the template into `arena`, like [`ValueTemplate::evaluate`].
+    fn evaluate_in(
+        &self,
+        arena: &mut AstArena,
+        all_attributes: &[(Symbol, NodeId)],
+        removed: &mut HashSet<Symbol>,
+        inherited: &[(Symbol, NodeId)],
+        span: &Span,
+    ) -> Option<NodeId> {
+        match self {
+            ValueTemplate::String(string) => {
+                Some(arena.push_literal(Value::Str(string.clone()), None))
+            }
+            ValueTemplate::Variable(name) => match lookup(all_attributes, *name) {
+                Some(value) => {
+                    removed.insert(*name);
+                    Some(value)
+                }
+                None => lookup(inherited, *name),
             },
+            ValueTemplate::InlineRule {
+                non_terminal,
+                attributes,
+            } => {
+                let attributes = attributes
+                    .iter()
+                    .filter_map(|(key, value_template)| {
+                        let value = value_template.evaluate_in(
+                            arena,
+                            all_attributes,
+                            removed,
+                            inherited,
+                            span,
+                        )?;
+                        Some((*key, value))
+                    })
+                    .collect::<Vec<_>>();
+                Some(arena.push_node(*non_terminal, attributes, span.clone()))
+            }
         }
     }
 }
 
+/// The value of the attribute `key` among `attributes`.
+fn lookup(attributes: &[(Symbol, NodeId)], key: Symbol) -> Option<NodeId> {
+    attributes
+        .iter()
+        .find(|&&(name, _)| name == key)
+        .map(|&(_, value)| value)
+}
+
+/// Set the attribute `key` to `value` among `attributes`, replacing its previous
+/// value, if any.
+fn insert(attributes: &mut Vec<(Symbol, NodeId)>, key: Symbol, value: NodeId) {
+    match attributes.iter_mut().find(|(name, _)| *name == key) {
+        Some(attribute) => attribute.1 = value,
+        None => attributes.push((key, value)),
+    }
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Rule {
     pub id: NonTerminalId,
     pub elements: Vec<Element>,
     pub proxy: Proxy,
     pub left_associative: bool,
+    /// The weight of the rule. The weight of a derivation is the sum of the weights
+    /// of its rules, and the parser builds the heaviest one.
+    pub weight: f64,
+    /// The level of the precedence table of the last terminal of the rule that has
+    /// one, if any. Rules of higher levels bind more tightly.
+    pub precedence: Option<usize>,
+    /// The name of the predicate that the tokens the rule matches must satisfy, if
+    /// any, given with [`EarleyParser::with_predicate`].
+    ///
+    /// [`EarleyParser::with_predicate`]: super::earley::EarleyParser::with_predicate
+    pub predicate: Option<Symbol>,
+    /// Where the rule was defined in the grammar.
+    pub span: Span,
 }
 
 impl Rule {
@@ -137,12 +285,170 @@ impl Rule {
         elements: Vec<Element>,
         proxy: Proxy,
         left_associative: bool,
+        span: Span,
     ) -> Self {
         Self {
             id,
             elements,
             proxy,
             left_associative,
+            weight: 0.0,
+            precedence: None,
+            predicate: None,
+            span,
+        }
+    }
+
+    /// Give the rule `weight`, rather than `0`.
+    pub fn with_weight(mut self, weight: f64) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Build the node of this rule, given the ASTs matched by each of its
+    /// elements, in order.
+    pub(crate) fn build_node(
+        &self,
+        children: impl IntoIterator<Item = AST>,
+        span: Span,
+    ) -> AST {
+        self.build_node_inheriting(children, span, &HashMap::new())
+    }
+
+    /// Compute the attributes that the element at `position` inherits, given the
+    /// ASTs matched by the elements on its left, in order, and the attributes
+    /// that the node, which covers `span`, inherits.
+    pub(crate) fn inherited_by(
+        &self,
+        position: usize,
+        left: &[AST],
+        inherited: &HashMap<Symbol, AST>,
+        span: &Span,
+    ) -> HashMap<Symbol, AST> {
+        let element = &self.elements[position];
+        if element.inherited.is_empty() {
+            return HashMap::new();
+        }
+        let siblings = left
+            .iter()
+            .zip(self.elements.iter())
+            .filter_map(|(item, element)| {
+                let key = element.key?;
+                Some((key, element.keyed_value(item.clone())?))
+            })
+            .collect();
+        element
+            .inherited
+            .iter()
+            .filter_map(|(key, template)| {
+                let value = template.evaluate(&siblings, &mut HashSet::new(), inherited, span)?;
+                Some((*key, value))
+            })
+            .collect()
+    }
+
+    /// Build the node of this rule, like [`Rule::build_node`], for a node that
+    /// inherits the attributes `inherited`.
+    pub(crate) fn build_node_inheriting(
+        &self,
+        children: impl IntoIterator<Item = AST>,
+        span: Span,
+        inherited: &HashMap<Symbol, AST>,
+    ) -> AST {
+        let all_attributes = children
+            .into_iter()
+            .zip(self.elements.iter())
+            .filter_map(|(item, element)| {
+                let key = element.key?;
+                Some((key, element.keyed_value(item)?))
+            })
+            .collect::<HashMap<Symbol, _>>();
+        let mut removed: HashSet<Symbol> = HashSet::new();
+        let mut attributes: HashMap<_, _> = self
+            .proxy
+            .iter()
+            .filter_map(|(key, wanted)| {
+                let value = wanted.evaluate(&all_attributes, &mut removed, inherited, &span)?;
+                Some((*key, value))
+            })
+            .collect();
+        attributes.extend(
+            all_attributes
+                .into_iter()
+                .filter(|(key, _)| !removed.contains(key)),
+        );
+        AST::Node {
+            nonterminal: self.id,
+            attributes,
+            span,
+        }
+    }
+
+    /// Compute the attributes that the element at `position` inherits into
+    /// `arena`, like [`Rule::inherited_by`].
+    pub(crate) fn inherited_by_in(
+        &self,
+        position: usize,
+        left: &[NodeId],
+        inherited: &[(Symbol, NodeId)],
+        span: &Span,
+        arena: &mut AstArena,
+    ) -> Vec<(Symbol, NodeId)> {
+        let element = &self.elements[position];
+        if element.inherited.is_empty() {
+            return Vec::new();
+        }
+        let mut siblings = Vec::new();
+        for (&item, element) in left.iter().zip(self.elements.iter()) {
+            if let Some(key) = element.key {
+                if let Some(value) = element.keyed_node(item, arena) {
+                    insert(&mut siblings, key, value);
+                }
+            }
+        }
+        element
+            .inherited
+            .iter()
+            .filter_map(|(key, template)| {
+                let mut removed = HashSet::new();
+                let value =
+                    template.evaluate_in(arena, &siblings, &mut removed, inherited, span)?;
+                Some((*key, value))
+            })
+            .collect()
+    }
+
+    /// Build the node of this rule into `arena`, like
+    /// [`Rule::build_node_inheriting`].
+    pub(crate) fn build_node_in(
+        &self,
+        arena: &mut AstArena,
+        children: &[NodeId],
+        span: Span,
+        inherited: &[(Symbol, NodeId)],
+    ) -> NodeId {
+        let mut all_attributes = Vec::with_capacity(children.len());
+        for (&item, element) in children.iter().zip(self.elements.iter()) {
+            if let Some(key) = element.key {
+                if let Some(value) = element.keyed_node(item, arena) {
+                    insert(&mut all_attributes, key, value);
+                }
+            }
+        }
+        let mut removed: HashSet<Symbol> = HashSet::new();
+        let mut attributes = Vec::with_capacity(self.proxy.len() + all_attributes.len());
+        for (key, wanted) in self.proxy.iter() {
+            if let Some(value) =
+                wanted.evaluate_in(arena, &all_attributes, &mut removed, inherited, &span)
+            {
+                attributes.push((*key, value));
+            }
+        }
+        for (key, value) in all_attributes {
+            if !removed.contains(&key) {
+                insert(&mut attributes, key, value);
+            }
         }
+        arena.push_node(self.id, attributes, span)
     }
 }
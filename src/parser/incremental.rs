@@ -0,0 +1,319 @@
+//! # Incremental
+//!
+//! Reparsing of an input after an edit. The state sets of an Earley parser
+//! only depend on the tokens before them, so the ones of the tokens that
+//! precede the edit are kept from the previous parse, and only the rest of the
+//! input is relexed and reparsed. For the same reason, inputs that start with the
+//! same prefix can all resume from the state sets of the prefix.
+
+use std::path::Path;
+use std::rc::Rc;
+
+use super::earley::{EarleyParser, Lexed, PartialParse, StateSet};
+use super::parser::ParseResult;
+use crate::{
+    error::Result,
+    lexer::{Lexer, Token},
+    stream::StringStream,
+};
+
+/// What is kept from the previous parse.
+#[derive(Debug, Default)]
+struct Chart {
+    sets: Vec<StateSet>,
+    raw_input: Vec<Token>,
+    /// The range of characters of each token, and where lexing can resume after it.
+    lexed: Vec<Lexed>,
+}
+
+/// # Summary
+///
+/// [`IncrementalParser`] parses successive versions of an input, such as a
+/// file being edited, reusing the work done on the unchanged beginning of the
+/// input.
+#[derive(Debug)]
+pub struct IncrementalParser<'a> {
+    lexer: &'a Lexer,
+    parser: &'a EarleyParser,
+    chart: Option<Chart>,
+    reused: usize,
+}
+
+impl<'a> IncrementalParser<'a> {
+    pub fn new(lexer: &'a Lexer, parser: &'a EarleyParser) -> Self {
+        Self {
+            lexer,
+            parser,
+            chart: None,
+            reused: 0,
+        }
+    }
+
+    /// Parse `stream` from scratch.
+    pub fn parse(&mut self, stream: &mut StringStream) -> Result<ParseResult> {
+        self.chart = None;
+        self.reparse(stream, 0)
+    }
+
+    /// Parse `stream`, which is the previously parsed input where the characters
+    /// from position `start` on might have been edited. The tokens before the edit
+    /// are not parsed again. If the previous parse failed, the whole input is.
+    pub fn reparse(&mut self, stream: &mut StringStream, start: usize) -> Result<ParseResult> {
+        let Chart {
+            mut sets,
+            mut raw_input,
+            mut lexed,
+        } = self.chart.take().unwrap_or_default();
+        // The token that ends right before the edit might go on after it, and the
+        // lexer might have stopped the one before it because of what followed.
+        // Both are lexed again, and so are the synthetic tokens of layout mode
+        // before them, which depend on the token that follows them.
+        let mut kept = lexed
+            .iter()
+            .take_while(|&&((_, end), _)| end < start)
+            .count()
+            .saturating_sub(1);
+        while kept > 0 && lexed[kept - 1].1.is_none() {
+            kept -= 1;
+        }
+        sets.truncate(kept);
+        raw_input.truncate(kept);
+        lexed.truncate(kept);
+        // The spans of the kept tokens must refer to the new input.
+        for (token, &((token_start, token_end), _)) in raw_input.iter_mut().zip(&lexed) {
+            if token_start == token_end {
+                // Synthetic tokens cover no character, and keep their span.
+                continue;
+            }
+            *token = Token::new(
+                token.symbol(),
+                token.id(),
+                token.attributes().clone(),
+                stream.span_between(token_start, token_end - 1),
+            )
+            .with_names(token.group_names().to_vec());
+        }
+        let checkpoint = lexed
+            .last()
+            .and_then(|(_, checkpoint)| checkpoint.clone())
+            .unwrap_or_default();
+        let mut input = self.lexer.resume(stream, &checkpoint);
+        if let Some(token) = raw_input.last() {
+            input.set_last_span(token.span().clone());
+        }
+        let (sets, raw_input) = self.parser.recognise_from(
+            &mut input,
+            sets,
+            raw_input,
+            &mut lexed,
+            &mut Vec::new(),
+            0,
+            false,
+        )?;
+        let forest = self.parser.to_forest(&sets, &raw_input)?;
+        let tree = self.parser.select_ast(&forest, &raw_input, input.last_span())?;
+        self.reused = kept;
+        self.chart = Some(Chart {
+            sets,
+            raw_input,
+            lexed,
+        });
+        Ok(ParseResult { tree })
+    }
+
+    /// Return how many tokens of the previous parse the last one reused.
+    pub fn reused(&self) -> usize {
+        self.reused
+    }
+}
+
+/// # Summary
+///
+/// [`PrefixParser`] parses inputs that all start with the same prefix, such as an
+/// implicit header, or the prelude of a REPL session. The prefix is parsed once,
+/// and each input resumes from where it stopped, so that only what follows the
+/// prefix is lexed and parsed again.
+#[derive(Debug)]
+pub struct PrefixParser<'a> {
+    lexer: &'a Lexer,
+    parser: &'a EarleyParser,
+    /// The prefix, left open, the lexer having stopped where the last token of the
+    /// prefix could go on.
+    stream: StringStream,
+    partial: PartialParse,
+}
+
+impl<'a> PrefixParser<'a> {
+    /// Parse `prefix`, the beginning of inputs that come from `origin`. Fail if no
+    /// input can start with it.
+    pub fn new(
+        lexer: &'a Lexer,
+        parser: &'a EarleyParser,
+        origin: impl Into<Rc<Path>>,
+        prefix: &str,
+    ) -> Result<Self> {
+        let mut stream = StringStream::open(origin);
+        stream.feed(prefix);
+        let mut partial = PartialParse::default();
+        parser.feed_tokens(&mut partial, &mut lexer.lex_incremental(&mut stream))?;
+        Ok(Self {
+            lexer,
+            parser,
+            stream,
+            partial,
+        })
+    }
+
+    /// Parse the input made of the prefix followed by `continuation`. The spans of
+    /// the tree are those of the whole input.
+    pub fn parse(&self, continuation: &str) -> Result<ParseResult> {
+        let mut stream = self.stream.clone();
+        stream.feed(continuation);
+        stream.close();
+        let mut partial = self.partial.clone();
+        let mut input = self.lexer.lex_incremental(&mut stream);
+        let result = self.parser.feed_tokens(&mut partial, &mut input)?;
+        Ok(result.expect("a closed stream is parsed to its end"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Buildable;
+    use crate::lexer::Layout;
+    use crate::parser::earley::EarleyGrammar;
+    use crate::parser::Parser;
+    use std::path::Path;
+
+    #[test]
+    fn reparse() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "ignore SPACE ::= \\s+\nNUMBER ::= ([0-9]+)\nPLUS ::= \\+",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@Sum ::= Sum@left PLUS NUMBER.0@right <>\n NUMBER.0@self <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let mut incremental = IncrementalParser::new(&lexer, &parser);
+        let mut stream = StringStream::new(Path::new("<input>"), "1 + 2 + 3");
+        incremental.parse(&mut stream).unwrap();
+        assert_eq!(incremental.reused(), 0);
+
+        for (text, start, reused) in [
+            ("1 + 2 + 34", 9, 3),
+            ("1 + 2 + 34 + 5", 10, 3),
+            ("1 + 2 +", 6, 2),
+            ("1 + 2 + 6", 8, 0),
+            ("10 + 2 + 6", 1, 0),
+        ] {
+            let mut stream = StringStream::new(Path::new("<input>"), text);
+            let result = incremental.reparse(&mut stream, start);
+            let mut stream = StringStream::new(Path::new("<input>"), text);
+            let expected = parser.parse(&mut lexer.lex(&mut stream));
+            match (result, expected) {
+                (Ok(result), Ok(expected)) => {
+                    assert_eq!(incremental.reused(), reused);
+                    assert_eq!(result.tree, expected.tree);
+                }
+                (Err(_), Err(_)) => {}
+                _ => panic!("The incremental parse of {text:?} differs from the full one"),
+            }
+        }
+    }
+
+    #[test]
+    fn reparse_layout() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "ID ::= ([a-z]+)\nCOLON ::= :\nINDENT ::= INDENT\nDEDENT ::= DEDENT\n\
+             NEWLINE ::= \\n\nignore SPACE ::= [ \\t\\n]+",
+        ))
+        .unwrap();
+        let layout =
+            Layout::from_names(lexer.grammar(), "INDENT", "DEDENT", "NEWLINE").unwrap();
+        let lexer = lexer.with_layout(layout);
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@Block ::= Block@rest Statement@statement <>\n Statement@statement <>;\n\
+                 Statement ::= ID.0@name NEWLINE <>\n\
+                  ID.0@name COLON NEWLINE INDENT Block@body DEDENT <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let mut incremental = IncrementalParser::new(&lexer, &parser);
+        let text = "a:\n  b:\n    c\n    d\n  e\nf";
+        let mut stream = StringStream::new(Path::new("<input>"), text);
+        incremental.parse(&mut stream).unwrap();
+
+        // Each edit is inside the block of `b`, which is nested in the one of `a`.
+        for (text, start) in [
+            ("a:\n  b:\n    c\n    dd\n  e\nf", 17),
+            ("a:\n  b:\n    c\n    dd\n    g\n  e\nf", 19),
+            ("a:\n  b:\n    c\n    dd\n  e\nf", 19),
+            ("a:\n  b:\n    c\n    dd\n      h\n  e\nf", 19),
+        ] {
+            let mut stream = StringStream::new(Path::new("<input>"), text);
+            let result = incremental.reparse(&mut stream, start);
+            let mut stream = StringStream::new(Path::new("<input>"), text);
+            let expected = parser.parse(&mut lexer.lex(&mut stream));
+            match (result, expected) {
+                (Ok(result), Ok(expected)) => {
+                    assert!(incremental.reused() > 0);
+                    assert_eq!(result.tree, expected.tree);
+                }
+                (Err(_), Err(_)) => {}
+                _ => panic!("The incremental parse of {text:?} differs from the full one"),
+            }
+        }
+
+        let prefix = "a:\n  b:\n    c";
+        let prefix_parser = PrefixParser::new(&lexer, &parser, Path::new("<input>"), prefix);
+        let prefix_parser = prefix_parser.unwrap();
+        for continuation in ["\n    d\n  e", "\n  e\nf", "\nf"] {
+            let result = prefix_parser.parse(continuation).unwrap();
+            let text = format!("{prefix}{continuation}");
+            let mut stream = StringStream::new(Path::new("<input>"), text);
+            let expected = parser.parse(&mut lexer.lex(&mut stream)).unwrap();
+            assert_eq!(result.tree, expected.tree);
+        }
+    }
+
+    #[test]
+    fn prefix() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "ignore SPACE ::= \\s+\nNUMBER ::= ([0-9]+)\nPLUS ::= \\+",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@Sum ::= Sum@left PLUS NUMBER.0@right <>\n NUMBER.0@self <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let prefix = PrefixParser::new(&lexer, &parser, Path::new("<input>"), "1 + 2").unwrap();
+        for continuation in ["", " + 3", "3 + 4", " + 5 + 6"] {
+            let result = prefix.parse(continuation).unwrap();
+            let text = format!("1 + 2{continuation}");
+            let mut stream = StringStream::new(Path::new("<input>"), text);
+            let expected = parser.parse(&mut lexer.lex(&mut stream)).unwrap();
+            assert_eq!(result.tree, expected.tree);
+        }
+        assert!(prefix.parse(" +").is_err());
+        assert!(PrefixParser::new(&lexer, &parser, Path::new("<input>"), "+ 1").is_err());
+    }
+}
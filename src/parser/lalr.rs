@@ -0,0 +1,932 @@
+//! # LALR
+//!
+//! An alternative backend, which compiles a parser grammar into LALR(1)
+//! tables. Parsing is then linear in the size of the input, but only
+//! grammars without conflicts are accepted: grammars that are ambiguous, or
+//! that need more than one terminal of lookahead, are rejected when the
+//! tables are built, along with the spans of the rules involved in each
+//! conflict.
+//!
+//! The ASTs built by [`LalrParser`] are the same as the ones built by the
+//! [`EarleyParser`](super::earley::EarleyParser).
+
+use super::analysis::FirstFollow;
+use super::earley::EarleyGrammar;
+use super::grammar::{Attribute, Element, ElementType, Rule, RuleId};
+use super::parser::{NonTerminalId, ParseResult, ParseSink, Parser, AST};
+use crate::{
+    builder::{deserialize_compiled, select_format, Compiled, CompiledKind, FileResult, Format},
+    error::{Error, ErrorKind, Result},
+    lexer::{Grammar as LexerGrammar, LexedStream, TerminalId, Token},
+    progress::{Phase, Progress, ProgressReporter},
+    regex::Allowed,
+    span::Span,
+};
+use fragile::Fragile;
+use newty::newty;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+newty! {
+    pub id StateId
+}
+
+/// What to do in a given state, given the next terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    /// Consume the terminal, and go to the given state.
+    Shift(StateId),
+    /// Reduce the given rule, without consuming the terminal.
+    Reduce(RuleId),
+    /// The whole input has been recognised.
+    Accept,
+}
+
+/// # Summary
+///
+/// The LALR(1) tables of a grammar.
+///
+/// `actions[state]` tells what to do in `state` for each lookahead terminal,
+/// `end_actions[state]` what to do if the input is over, and `gotos[state]`
+/// in which state to go after having reduced a rule of a given non-terminal.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LalrTable {
+    actions: Vec<HashMap<TerminalId, Action>>,
+    end_actions: Vec<Option<Action>>,
+    gotos: Vec<HashMap<NonTerminalId, StateId>>,
+}
+
+impl LalrTable {
+    pub fn nb_states(&self) -> usize {
+        self.actions.len()
+    }
+
+    /// Return the action to take in `state`, if the next terminal is
+    /// `lookahead` (or if the input is over, if it is `None`).
+    pub fn action(&self, state: StateId, lookahead: Option<TerminalId>) -> Option<Action> {
+        match lookahead {
+            Some(id) => self.actions[state.0].get(&id).copied(),
+            None => self.end_actions[state.0],
+        }
+    }
+
+    pub fn goto(&self, state: StateId, id: NonTerminalId) -> Option<StateId> {
+        self.gotos[state.0].get(&id).copied()
+    }
+
+    /// Return the terminals that are valid in `state`, sorted.
+    pub fn expected(&self, state: StateId) -> Vec<TerminalId> {
+        let mut expected = self.actions[state.0].keys().copied().collect::<Vec<_>>();
+        expected.sort();
+        expected
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    ShiftReduce,
+    ReduceReduce,
+}
+
+/// # Summary
+///
+/// A conflict found while building the LALR(1) tables of a grammar.
+///
+/// `rule` is a rule that could be reduced when `lookahead` is seen. `other_rule`
+/// is either a rule in which `lookahead` could be shifted (for a shift/reduce
+/// conflict), or another rule that could be reduced (for a reduce/reduce one).
+#[derive(Debug)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    pub lookahead: String,
+    pub rule: String,
+    pub span: Fragile<Span>,
+    pub other_rule: String,
+    pub other_span: Fragile<Span>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ConflictKind::ShiftReduce => write!(
+                f,
+                "shift/reduce conflict on {}: `{}` {} could be reduced, but `{}` {} shifts it",
+                self.lookahead, self.rule, self.span, self.other_rule, self.other_span,
+            ),
+            ConflictKind::ReduceReduce => write!(
+                f,
+                "reduce/reduce conflict on {}: both `{}` {} and `{}` {} could be reduced",
+                self.lookahead, self.rule, self.span, self.other_rule, self.other_span,
+            ),
+        }
+    }
+}
+
+/// An LR(0) item, `(production, position of the dot)`.
+type Item = (usize, usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Lookahead {
+    Terminal(TerminalId),
+    End,
+    /// Placeholder used to find out which lookaheads are propagated.
+    Propagated,
+}
+
+struct TableBuilder<'a> {
+    grammar: &'a EarleyGrammar,
+    lexer_grammar: &'a LexerGrammar,
+    sets: FirstFollow,
+    /// The right-hand side of every production. The first ones are the rules
+    /// of the grammar, with the same identifiers. Then, there is one
+    /// production `S' -> A` for each axiom `A`.
+    productions: Vec<Vec<Element>>,
+    axioms: Vec<NonTerminalId>,
+}
+
+impl<'a> TableBuilder<'a> {
+    fn new(grammar: &'a EarleyGrammar, lexer_grammar: &'a LexerGrammar) -> Self {
+        let axioms = (0..grammar.nb_non_terminals())
+            .map(NonTerminalId)
+            .filter(|&id| grammar.is_axiom(id))
+            .collect::<Vec<_>>();
+        let productions = grammar
+            .rules()
+            .iter()
+            .map(|rule| rule.elements.clone())
+            .chain(axioms.iter().map(|&axiom| {
+                vec![Element::new(
+                    Attribute::None,
+                    None,
+                    ElementType::NonTerminal(axiom),
+                )]
+            }))
+            .collect();
+        Self {
+            grammar,
+            lexer_grammar,
+            sets: FirstFollow::new(grammar),
+            productions,
+            axioms,
+        }
+    }
+
+    fn nb_rules(&self) -> usize {
+        self.grammar.rules().len()
+    }
+
+    fn next_symbol(&self, (production, position): Item) -> Option<ElementType> {
+        self.productions[production]
+            .get(position)
+            .map(|element| element.element_type)
+    }
+
+    fn closure(&self, kernel: &BTreeSet<Item>) -> Vec<Item> {
+        let mut items = kernel.iter().copied().collect::<Vec<_>>();
+        let mut predicted = HashSet::new();
+        let mut i = 0;
+        while i < items.len() {
+            if let Some(ElementType::NonTerminal(id)) = self.next_symbol(items[i]) {
+                if predicted.insert(id) {
+                    items.extend(self.grammar.rules_of(id).iter().map(|rule| (rule.0, 0)));
+                }
+            }
+            i += 1;
+        }
+        items
+    }
+
+    fn closure_with_lookaheads(
+        &self,
+        mut stack: Vec<(Item, Lookahead)>,
+    ) -> BTreeSet<(Item, Lookahead)> {
+        let mut items = BTreeSet::new();
+        while let Some((item, lookahead)) = stack.pop() {
+            if !items.insert((item, lookahead)) {
+                continue;
+            }
+            let Some(ElementType::NonTerminal(id)) = self.next_symbol(item) else {
+                continue;
+            };
+            let (first, nullable) = self
+                .sets
+                .first_of(&self.productions[item.0][item.1 + 1..], self.grammar);
+            let mut lookaheads = first.into_iter().map(Lookahead::Terminal).collect::<Vec<_>>();
+            if nullable {
+                lookaheads.push(lookahead);
+            }
+            for rule in self.grammar.rules_of(id) {
+                stack.extend(lookaheads.iter().map(|&lookahead| ((rule.0, 0), lookahead)));
+            }
+        }
+        items
+    }
+
+    fn describe(&self, production: usize) -> (String, Option<Span>) {
+        if production >= self.nb_rules() {
+            let axiom = self.axioms[production - self.nb_rules()];
+            let span = self
+                .grammar
+                .rules_of(axiom)
+                .first()
+                .map(|&rule| self.grammar.rules()[rule].span.clone());
+            return (format!("{} (axiom)", self.grammar.name_of(axiom)), span);
+        }
+        let rule = &self.grammar.rules()[RuleId(production)];
+        let mut description = format!("{} ->", self.grammar.name_of(rule.id));
+        if rule.elements.is_empty() {
+            description.push_str(" ε");
+        }
+        for element in rule.elements.iter() {
+            description.push(' ');
+            description.push_str(element.name(self.lexer_grammar, self.grammar));
+        }
+        (description, Some(rule.span.clone()))
+    }
+
+    /// Find the production responsible for `action`, among `items`.
+    fn production_of(&self, items: &[Item], action: Action, lookahead: Lookahead) -> usize {
+        let found = match action {
+            Action::Reduce(rule) => return rule.0,
+            Action::Accept => items.iter().find(|&&(production, position)| {
+                production >= self.nb_rules() && position == 1
+            }),
+            Action::Shift(_) => {
+                let Lookahead::Terminal(id) = lookahead else {
+                    unreachable!("only terminals can be shifted")
+                };
+                items
+                    .iter()
+                    .find(|&&item| self.next_symbol(item) == Some(ElementType::Terminal(id)))
+            }
+        };
+        found.expect("an action always comes from an item of its state").0
+    }
+
+    fn conflict(
+        &self,
+        items: &[Item],
+        lookahead: Lookahead,
+        old: Action,
+        new: Action,
+    ) -> Conflict {
+        let (kind, reduced, other) = match (old, new) {
+            (Action::Shift(_), _) => (ConflictKind::ShiftReduce, new, old),
+            (_, Action::Shift(_)) => (ConflictKind::ShiftReduce, old, new),
+            _ => (ConflictKind::ReduceReduce, new, old),
+        };
+        let (rule, span) = self.describe(self.production_of(items, reduced, lookahead));
+        let (other_rule, other_span) =
+            self.describe(self.production_of(items, other, lookahead));
+        // At most one of the two productions is an augmented one, which might
+        // have no span.
+        let span = span.or_else(|| other_span.clone()).unwrap();
+        let other_span = other_span.unwrap_or_else(|| span.clone());
+        let lookahead = match lookahead {
+            Lookahead::Terminal(id) => self.lexer_grammar.name(id).to_string(),
+            _ => String::from("EOF"),
+        };
+        Conflict {
+            kind,
+            lookahead,
+            rule,
+            span: Fragile::new(span),
+            other_rule,
+            other_span: Fragile::new(other_span),
+        }
+    }
+
+    /// Build the table. The lookaheads of every state, and then its actions, are
+    /// reported as steps of the [`Phase::Automaton`] phase.
+    fn build(self, reporter: &mut dyn ProgressReporter) -> Result<LalrTable> {
+        // Build the LR(0) automaton. Each state is represented by its kernel.
+        let initial = (self.nb_rules()..self.productions.len())
+            .map(|production| (production, 0))
+            .collect::<BTreeSet<_>>();
+        let mut kernels = vec![initial.clone()];
+        let mut index = HashMap::from([(initial, 0)]);
+        let mut transitions = Vec::new();
+        let mut state = 0;
+        while state < kernels.len() {
+            let mut successors: Vec<(ElementType, BTreeSet<Item>)> = Vec::new();
+            for item in self.closure(&kernels[state]) {
+                let Some(symbol) = self.next_symbol(item) else {
+                    continue;
+                };
+                let next = (item.0, item.1 + 1);
+                match successors.iter_mut().find(|(other, _)| *other == symbol) {
+                    Some((_, kernel)) => {
+                        kernel.insert(next);
+                    }
+                    None => successors.push((symbol, BTreeSet::from([next]))),
+                }
+            }
+            let mut edges = HashMap::new();
+            for (symbol, kernel) in successors {
+                let target = match index.entry(kernel) {
+                    Entry::Occupied(entry) => *entry.get(),
+                    Entry::Vacant(entry) => {
+                        kernels.push(entry.key().clone());
+                        *entry.insert(kernels.len() - 1)
+                    }
+                };
+                edges.insert(symbol, target);
+            }
+            transitions.push(edges);
+            state += 1;
+        }
+
+        // Compute the lookaheads of the kernel items, by finding which ones
+        // are generated spontaneously and which ones are propagated, and then
+        // propagating them until a fixpoint is reached.
+        let mut lookaheads = kernels
+            .iter()
+            .map(|kernel| {
+                kernel
+                    .iter()
+                    .map(|&item| (item, BTreeSet::new()))
+                    .collect::<BTreeMap<_, _>>()
+            })
+            .collect::<Vec<_>>();
+        for set in lookaheads[0].values_mut() {
+            set.insert(Lookahead::End);
+        }
+        let nb_states = kernels.len();
+        let mut propagations = Vec::new();
+        for (state, kernel) in kernels.iter().enumerate() {
+            reporter.report(Progress::new(Phase::Automaton, state, 2 * nb_states));
+            for &kernel_item in kernel {
+                let items =
+                    self.closure_with_lookaheads(vec![(kernel_item, Lookahead::Propagated)]);
+                for (item, lookahead) in items {
+                    let Some(symbol) = self.next_symbol(item) else {
+                        continue;
+                    };
+                    let target = transitions[state][&symbol];
+                    let next = (item.0, item.1 + 1);
+                    if lookahead == Lookahead::Propagated {
+                        propagations.push(((state, kernel_item), (target, next)));
+                    } else {
+                        lookaheads[target].get_mut(&next).unwrap().insert(lookahead);
+                    }
+                }
+            }
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &((state, item), (target, next)) in &propagations {
+                let propagated = lookaheads[state][&item].clone();
+                let target = lookaheads[target].get_mut(&next).unwrap();
+                let before = target.len();
+                target.extend(propagated);
+                changed |= before != target.len();
+            }
+        }
+
+        // Fill the tables.
+        let mut table = LalrTable {
+            actions: vec![HashMap::new(); nb_states],
+            end_actions: vec![None; nb_states],
+            gotos: vec![HashMap::new(); nb_states],
+        };
+        let mut conflicts = Vec::new();
+        for state in 0..nb_states {
+            reporter.report(Progress::new(
+                Phase::Automaton,
+                nb_states + state,
+                2 * nb_states,
+            ));
+            for (&symbol, &target) in &transitions[state] {
+                match symbol {
+                    ElementType::Terminal(id) => {
+                        table.actions[state].insert(id, Action::Shift(StateId(target)));
+                    }
+                    ElementType::NonTerminal(id) => {
+                        table.gotos[state].insert(id, StateId(target));
+                    }
+                }
+            }
+            let items = self.closure_with_lookaheads(
+                lookaheads[state]
+                    .iter()
+                    .flat_map(|(&item, lookaheads)| {
+                        lookaheads.iter().map(move |&lookahead| (item, lookahead))
+                    })
+                    .collect(),
+            );
+            let state_items = self.closure(&kernels[state]);
+            for (item, lookahead) in items {
+                if self.next_symbol(item).is_some() {
+                    continue;
+                }
+                let action = if item.0 < self.nb_rules() {
+                    Action::Reduce(RuleId(item.0))
+                } else {
+                    Action::Accept
+                };
+                let old = match lookahead {
+                    Lookahead::Terminal(id) => match table.actions[state].entry(id) {
+                        Entry::Occupied(entry) => Some(*entry.get()),
+                        Entry::Vacant(entry) => {
+                            entry.insert(action);
+                            None
+                        }
+                    },
+                    Lookahead::End => table.end_actions[state].replace(action),
+                    Lookahead::Propagated => unreachable!(),
+                };
+                if let Some(old) = old {
+                    if let Lookahead::End = lookahead {
+                        table.end_actions[state] = Some(old);
+                    }
+                    if old != action {
+                        conflicts.push(self.conflict(&state_items, lookahead, old, action));
+                    }
+                }
+            }
+        }
+
+        reporter.report(Progress::new(Phase::Automaton, 2 * nb_states, 2 * nb_states));
+        if conflicts.is_empty() {
+            Ok(table)
+        } else {
+            ErrorKind::LalrConflicts { conflicts }.err()
+        }
+    }
+}
+
+/// # Summary
+///
+/// [`LalrGrammar`] is a parser grammar, along with its LALR(1) tables.
+/// It is serialized as a whole in `.cgr` files.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LalrGrammar {
+    grammar: EarleyGrammar,
+    table: LalrTable,
+}
+
+impl crate::sealed::Sealed for LalrGrammar {}
+
+impl Compiled for LalrGrammar {
+    const KIND: CompiledKind = CompiledKind::LalrGrammar;
+}
+
+impl LalrGrammar {
+    const PLAIN_EXTENSION: &str = "gr";
+    const COMPILED_EXTENSION: &str = "cgr";
+    const AST_EXTENSION: &str = "gr.ast";
+
+    /// Build the LALR(1) tables of `grammar`. Fail if `grammar` has conflicts.
+    pub fn new(grammar: EarleyGrammar, lexer_grammar: &LexerGrammar) -> Result<Self> {
+        Self::with_progress(grammar, lexer_grammar, &mut |_: Progress| {})
+    }
+
+    /// Build the LALR(1) tables of `grammar`, reporting the states of the
+    /// automaton that are done to `reporter`.
+    pub fn with_progress(
+        grammar: EarleyGrammar,
+        lexer_grammar: &LexerGrammar,
+        reporter: &mut dyn ProgressReporter,
+    ) -> Result<Self> {
+        if let Some(rule) = grammar
+            .rules()
+            .iter()
+            .find(|rule| rule.elements.iter().any(|element| !element.inherited.is_empty()))
+        {
+            return ErrorKind::LalrInheritedAttributes {
+                span: rule.span.clone().into(),
+            }
+            .err();
+        }
+        let table = TableBuilder::new(&grammar, lexer_grammar).build(reporter)?;
+        Ok(Self { grammar, table })
+    }
+
+    pub fn grammar(&self) -> &EarleyGrammar {
+        &self.grammar
+    }
+
+    pub fn table(&self) -> &LalrTable {
+        &self.table
+    }
+
+    pub fn build_from_compiled(
+        blob: &[u8],
+        path: impl ToOwned<Owned = PathBuf>,
+    ) -> Result<Self> {
+        deserialize_compiled(blob, &path.to_owned())
+    }
+
+    pub fn build_from_path(path: &Path, lexer_grammar: &LexerGrammar) -> Result<Self> {
+        match select_format(
+            path,
+            &[
+                (Self::COMPILED_EXTENSION, Format::Compiled),
+                (Self::AST_EXTENSION, Format::Ast),
+                (Self::PLAIN_EXTENSION, Format::Plain),
+            ],
+        ) {
+            FileResult::Valid((actual_path, Format::Compiled)) => {
+                let mut file = File::open(&actual_path)
+                    .map_err(|err| Error::with_file(err, &actual_path))?;
+                let mut buffer = Vec::new();
+                file.read_to_end(&mut buffer)
+                    .map_err(|err| Error::with_file(err, &actual_path))?;
+                let result = Self::build_from_compiled(&buffer, actual_path.clone())?;
+                result.grammar.check_lexer(lexer_grammar, &actual_path)?;
+                Ok(result)
+            }
+            FileResult::Valid((actual_path, _)) => {
+                let grammar = EarleyGrammar::build_from_path(&actual_path, lexer_grammar)?;
+                Self::new(grammar, lexer_grammar)
+            }
+            FileResult::WrongExtension(extension) => ErrorKind::UnrecognisedExtension {
+                extension,
+                path: path.to_owned(),
+            }
+            .err(),
+            FileResult::NonExisting => ErrorKind::GrammarNotFound {
+                path: path.to_owned(),
+            }
+            .err(),
+        }
+    }
+}
+
+/// # Summary
+/// [`LalrParser`] is the parser related to the [`LalrGrammar`].
+#[derive(Debug)]
+pub struct LalrParser {
+    grammar: LalrGrammar,
+}
+
+impl LalrParser {
+    fn syntax_error(
+        &self,
+        input: &LexedStream<'_, '_>,
+        token: Option<&Token>,
+        state: StateId,
+    ) -> Error {
+        let lexer_grammar = input.lexer().grammar();
+        let describe = |id: TerminalId| {
+            lexer_grammar
+                .description_of(id)
+                .unwrap_or_else(|| lexer_grammar.name(id))
+                .to_string()
+        };
+        let expected = self.grammar.table.expected(state);
+        let alternatives = expected.iter().copied().map(describe).collect();
+        Error::new(ErrorKind::parse_error(
+            lexer_grammar,
+            token,
+            expected,
+            alternatives,
+            input.last_span(),
+        ))
+    }
+
+    /// Run the automaton on `input`, building the tree of the derivation with
+    /// `leaf` for the tokens and `node` for the rules, given the trees of their
+    /// elements and the span they cover.
+    fn run<T>(
+        &self,
+        input: &mut LexedStream<'_, '_>,
+        mut leaf: impl FnMut(Token) -> T,
+        mut node: impl FnMut(&Rule, Vec<T>, Span) -> T,
+    ) -> Result<T> {
+        let table = &self.grammar.table;
+        let rules = self.grammar.grammar.rules();
+        let mut states = vec![StateId(0)];
+        // Each tree of the stack comes with the span of the tokens it covers,
+        // which is `None` if it matched the empty string.
+        let mut stack: Vec<(T, Option<Span>)> = Vec::new();
+        loop {
+            let state = *states.last().unwrap();
+            let allowed = Allowed::Some(
+                input
+                    .lexer()
+                    .grammar()
+                    .default_allowed()
+                    .chain(table.expected(state))
+                    .collect(),
+            );
+            let token = match input.next(allowed) {
+                Ok(token) => token.cloned(),
+                Err(error) => {
+                    if let ErrorKind::LexingError { .. } = *error.kind {
+                        let token = input.next(Allowed::All)?.cloned();
+                        return Err(self.syntax_error(input, token.as_ref(), state));
+                    }
+                    return Err(error);
+                }
+            };
+            loop {
+                let state = *states.last().unwrap();
+                match table.action(state, token.as_ref().map(Token::id)) {
+                    Some(Action::Shift(next)) => {
+                        let token = token.expect("the end of the input cannot be shifted");
+                        let span = token.span().clone();
+                        states.push(next);
+                        stack.push((leaf(token), Some(span)));
+                        break;
+                    }
+                    Some(Action::Reduce(rule)) => {
+                        let rule = &rules[rule];
+                        let children = stack.split_off(stack.len() - rule.elements.len());
+                        states.truncate(states.len() - rule.elements.len());
+                        let mut spans = children.iter().filter_map(|(_, span)| span.as_ref());
+                        let covered = spans
+                            .next()
+                            .map(|first| first.sup(spans.next_back().unwrap_or(first)));
+                        let span = covered.clone().unwrap_or_else(|| match &token {
+                            Some(token) => token.span().clone(),
+                            None => input.last_span().clone(),
+                        });
+                        let children = children.into_iter().map(|(tree, _)| tree).collect();
+                        let tree = node(rule, children, span);
+                        let Some(next) = table.goto(*states.last().unwrap(), rule.id) else {
+                            return ErrorKind::InternalError {
+                                message: format!(
+                                    "No transition on {} in the LALR table",
+                                    self.grammar.grammar.name_of(rule.id),
+                                ),
+                            }
+                            .err();
+                        };
+                        states.push(next);
+                        stack.push((tree, covered));
+                    }
+                    Some(Action::Accept) => {
+                        let (tree, _) = stack.pop().unwrap();
+                        return Ok(tree);
+                    }
+                    None => return Err(self.syntax_error(input, token.as_ref(), state)),
+                }
+            }
+        }
+    }
+}
+
+impl crate::sealed::Sealed for LalrParser {}
+
+impl Parser<'_> for LalrParser {
+    type Grammar = LalrGrammar;
+
+    fn new(grammar: Self::Grammar) -> Self {
+        Self { grammar }
+    }
+
+    fn grammar(&self) -> &Self::Grammar {
+        &self.grammar
+    }
+
+    fn parse<'input>(
+        &self,
+        input: &'input mut LexedStream<'input, 'input>,
+    ) -> Result<ParseResult> {
+        let tree = self.run(input, AST::Terminal, |rule, children, span| {
+            rule.build_node(children, span)
+        })?;
+        Ok(ParseResult { tree })
+    }
+
+    fn parse_events<'input>(
+        &self,
+        input: &'input mut LexedStream<'input, 'input>,
+        sink: &mut impl ParseSink,
+    ) -> Result<()> {
+        let derivation = self.run(input, Derivation::Token, |rule, children, span| {
+            Derivation::Rule(rule.id, span, children)
+        })?;
+        derivation.walk(sink);
+        Ok(())
+    }
+}
+
+/// The derivation of an input, without the attributes that the AST would hold.
+enum Derivation {
+    Rule(NonTerminalId, Span, Vec<Derivation>),
+    Token(Token),
+}
+
+impl Derivation {
+    fn walk(&self, sink: &mut impl ParseSink) {
+        match self {
+            Self::Rule(nonterminal, span, children) => {
+                sink.enter_rule(*nonterminal, span);
+                for child in children {
+                    child.walk(sink);
+                }
+                sink.exit_rule(*nonterminal, span);
+            }
+            Self::Token(token) => sink.token(token),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Buildable;
+    use crate::lexer::Lexer;
+    use crate::parser::earley::EarleyParser;
+    use crate::parser::Event;
+    use crate::stream::StringStream;
+
+    const LEXER: &str = r#"
+NUMBER ::= ([0-9])
+PM ::= [-+]
+TD ::= [*/]
+LPAR ::= \(
+RPAR ::= \)
+"#;
+
+    const GRAMMAR: &str = r#"
+@Sum ::= Sum@left PM Product@right <>
+ Product@self <>;
+
+Product ::= Product@left TD Factor@right <>
+ Factor.self@self <>;
+
+Factor ::= LPAR Sum@self RPAR <>
+ NUMBER.0@self <>;"#;
+
+    const AMBIGUOUS_GRAMMAR: &str = r#"
+@Expr ::= Expr@left PM Expr@right <>
+ NUMBER.0@self <>;"#;
+
+    fn build(grammar: &str) -> (Lexer, Result<LalrGrammar>) {
+        let lexer =
+            Lexer::build_from_plain(StringStream::new(Path::new("<lexer input>"), LEXER))
+                .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), grammar),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let grammar = LalrGrammar::new(grammar, lexer.grammar());
+        (lexer, grammar)
+    }
+
+    #[test]
+    fn same_ast_as_earley() {
+        let input = "1+(2*3-4)";
+        let (lexer, grammar) = build(GRAMMAR);
+        let parser = LalrParser::new(grammar.unwrap());
+        let mut stream = StringStream::new(Path::new("<input>"), input);
+        let lalr_tree = parser.parse(&mut lexer.lex(&mut stream)).unwrap().tree;
+
+        let earley_grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), GRAMMAR),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(earley_grammar);
+        let mut stream = StringStream::new(Path::new("<input>"), input);
+        let earley_tree = parser.parse(&mut lexer.lex(&mut stream)).unwrap().tree;
+        assert_eq!(lalr_tree, earley_tree);
+    }
+
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl ParseSink for Recorder {
+        fn enter_rule(&mut self, nonterminal: NonTerminalId, _span: &Span) {
+            self.0.push(format!("enter {}", nonterminal.0));
+        }
+
+        fn exit_rule(&mut self, nonterminal: NonTerminalId, _span: &Span) {
+            self.0.push(format!("exit {}", nonterminal.0));
+        }
+
+        fn token(&mut self, token: &Token) {
+            self.0.push(token.name().to_string());
+        }
+    }
+
+    #[test]
+    fn same_events_as_earley() {
+        let input = "1+(2*3-4)";
+        let (lexer, grammar) = build(GRAMMAR);
+        let parser = LalrParser::new(grammar.unwrap());
+        let mut stream = StringStream::new(Path::new("<input>"), input);
+        let mut lalr_events = Recorder::default();
+        parser
+            .parse_events(&mut lexer.lex(&mut stream), &mut lalr_events)
+            .unwrap();
+        let tokens = lalr_events
+            .0
+            .iter()
+            .filter(|event| !event.contains(' '))
+            .count();
+        assert_eq!(tokens, 9);
+        assert_eq!(lalr_events.0.first().unwrap(), "enter 0");
+        assert_eq!(lalr_events.0.last().unwrap(), "exit 0");
+
+        let earley_grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), GRAMMAR),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(earley_grammar);
+        let mut stream = StringStream::new(Path::new("<input>"), input);
+        let mut earley_events = Recorder::default();
+        parser
+            .parse_events(&mut lexer.lex(&mut stream), &mut earley_events)
+            .unwrap();
+        assert_eq!(lalr_events.0, earley_events.0);
+    }
+
+    #[test]
+    fn closure_events() {
+        let input = "1+(2*3-4)";
+        let (lexer, grammar) = build(GRAMMAR);
+        let parser = LalrParser::new(grammar.unwrap());
+        let mut stream = StringStream::new(Path::new("<input>"), input);
+        let mut recorded = Recorder::default();
+        parser
+            .parse_events(&mut lexer.lex(&mut stream), &mut recorded)
+            .unwrap();
+        let mut stream = StringStream::new(Path::new("<input>"), input);
+        let mut events = Vec::new();
+        let mut depth = 0;
+        parser
+            .parse_events(&mut lexer.lex(&mut stream), &mut |event: Event<'_>| {
+                events.push(match event {
+                    Event::Enter { nonterminal, .. } => {
+                        depth += 1;
+                        format!("enter {}", nonterminal.0)
+                    }
+                    Event::Token(token) => token.name().to_string(),
+                    Event::Exit { nonterminal, .. } => {
+                        depth -= 1;
+                        format!("exit {}", nonterminal.0)
+                    }
+                })
+            })
+            .unwrap();
+        assert_eq!(depth, 0);
+        assert_eq!(events, recorded.0);
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let (lexer, grammar) = build(GRAMMAR);
+        let parser = LalrParser::new(grammar.unwrap());
+        let mut stream = StringStream::new(Path::new("<input>"), "1+)");
+        let error = parser.parse(&mut lexer.lex(&mut stream)).unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::ParseError { found: Some(_), .. }));
+        let mut stream = StringStream::new(Path::new("<input>"), "(1+2");
+        let error = parser.parse(&mut lexer.lex(&mut stream)).unwrap_err();
+        let ErrorKind::ParseError {
+            found: None,
+            expected,
+            ..
+        } = *error.kind
+        else {
+            panic!("expected the input to end too early");
+        };
+        assert!(expected.contains(&lexer.grammar().id("RPAR").unwrap()));
+    }
+
+    #[test]
+    fn conflicts() {
+        let (_, grammar) = build(AMBIGUOUS_GRAMMAR);
+        let kind = *grammar.unwrap_err().kind;
+        let ErrorKind::LalrConflicts { conflicts } = kind else {
+            panic!("expected conflicts, found {kind}");
+        };
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].kind, ConflictKind::ShiftReduce);
+        assert_eq!(conflicts[0].lookahead, "PM");
+        assert_eq!(conflicts[0].rule, "Expr -> Expr PM Expr");
+        assert_eq!(conflicts[0].other_rule, "Expr -> Expr PM Expr");
+    }
+
+    #[test]
+    fn progress() {
+        let lexer =
+            Lexer::build_from_plain(StringStream::new(Path::new("<lexer input>"), LEXER))
+                .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(Path::new("<grammar input>"), GRAMMAR),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let mut reports = Vec::new();
+        let mut reporter = |progress: Progress| reports.push(progress);
+        let grammar =
+            LalrGrammar::with_progress(grammar, lexer.grammar(), &mut reporter).unwrap();
+        let total = 2 * grammar.table().nb_states();
+        assert_eq!(reports.len(), total + 1);
+        assert!(reports.iter().enumerate().all(|(done, progress)| {
+            *progress == Progress::new(Phase::Automaton, done, total)
+        }));
+    }
+}
@@ -1,10 +1,13 @@
 use crate::error::Result;
+use crate::interner::Symbol;
 use crate::lexer::{LexedStream, Token};
 use crate::span::Span;
 use crate::typed::Tree;
 use newty::newty;
 use serde::{Deserialize, Serialize};
+use std::any::Any;
 use std::collections::HashMap;
+use std::fmt;
 use std::rc::Rc;
 
 newty! {
@@ -23,13 +26,92 @@ pub enum Value {
     Str(Rc<str>),
     Float(f32),
     Bool(bool),
+    /// An integer of any size.
+    #[cfg(feature = "bigint")]
+    BigInt(num_bigint::BigInt),
+    /// A decimal number, without the rounding errors of floats.
+    #[cfg(feature = "decimal")]
+    Decimal(rust_decimal::Decimal),
+    /// A value of a type of the user, built by a semantic action. It cannot be
+    /// serialized.
+    #[serde(skip)]
+    External(External),
+}
+
+impl Value {
+    /// Wrap `value` in a [`Value::External`].
+    pub fn external(value: impl Any) -> Self {
+        Self::External(External::new(value))
+    }
+
+    /// Convert the value to an integer of any size, parsing it if it is a string, as
+    /// the ones a proxy gets from the groups of a token.
+    #[cfg(feature = "bigint")]
+    pub fn to_big_int(&self) -> Option<num_bigint::BigInt> {
+        match self {
+            Self::Int(i) => Some((*i).into()),
+            Self::Str(string) => string.parse().ok(),
+            Self::BigInt(i) => Some(i.clone()),
+            _ => None,
+        }
+    }
+
+    /// Convert the value to a decimal number, parsing it if it is a string, as the
+    /// ones a proxy gets from the groups of a token.
+    #[cfg(feature = "decimal")]
+    pub fn to_decimal(&self) -> Option<rust_decimal::Decimal> {
+        match self {
+            Self::Int(i) => Some((*i).into()),
+            Self::Str(string) => string.parse().ok(),
+            Self::Float(f) => rust_decimal::Decimal::try_from(*f).ok(),
+            Self::Decimal(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    /// Return the value of a [`Value::External`], if it is a `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        match self {
+            Self::External(external) => external.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// A value of any type, which semantic actions can store in the attributes of
+/// the nodes they build, such as interned symbols or big integers. Clones
+/// share the value, and two externals are only equal if they share it.
+#[derive(Clone)]
+pub struct External(Rc<dyn Any>);
+
+impl External {
+    pub fn new(value: impl Any) -> Self {
+        Self(Rc::new(value))
+    }
+
+    /// Return the value, if it is a `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.0.downcast_ref()
+    }
+}
+
+impl fmt::Debug for External {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("External(..)")
+    }
+}
+
+impl PartialEq for External {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AST {
     Node {
         nonterminal: NonTerminalId,
-        attributes: HashMap<Rc<str>, AST>,
+        attributes: HashMap<Symbol, AST>,
         span: Span,
     },
     Literal {
@@ -59,9 +141,55 @@ pub struct ParseResult {
     pub tree: AST,
 }
 
+/// Receives the derivation selected for an input by [`Parser::parse_events`],
+/// walked depth-first and left to right. The methods do nothing by default.
+pub trait ParseSink {
+    /// A rule deriving `nonterminal`, whose derivation covers `span`, starts.
+    fn enter_rule(&mut self, _nonterminal: NonTerminalId, _span: &Span) {}
+    /// The last rule that started, and did not end yet, ends.
+    fn exit_rule(&mut self, _nonterminal: NonTerminalId, _span: &Span) {}
+    /// The next token of the input.
+    fn token(&mut self, _token: &Token) {}
+}
+
+/// A step of the walk of a derivation, as received by a [`ParseSink`].
+#[derive(Debug, Clone, Copy)]
+pub enum Event<'a> {
+    /// See [`ParseSink::enter_rule`].
+    Enter {
+        nonterminal: NonTerminalId,
+        span: &'a Span,
+    },
+    /// See [`ParseSink::token`].
+    Token(&'a Token),
+    /// See [`ParseSink::exit_rule`].
+    Exit {
+        nonterminal: NonTerminalId,
+        span: &'a Span,
+    },
+}
+
+/// Closures receiving [`Event`]s are sinks, so that events can be handled in a
+/// single `match`, as in `parser.parse_events(input, &mut |event| ...)`.
+impl<F: FnMut(Event<'_>)> ParseSink for F {
+    fn enter_rule(&mut self, nonterminal: NonTerminalId, span: &Span) {
+        self(Event::Enter { nonterminal, span })
+    }
+
+    fn exit_rule(&mut self, nonterminal: NonTerminalId, span: &Span) {
+        self(Event::Exit { nonterminal, span })
+    }
+
+    fn token(&mut self, token: &Token) {
+        self(Event::Token(token))
+    }
+}
+
 /// Something that implements [`Parser`] is able to, given a certain grammar,
 /// parse a [`LexedStream`] following the grammar.
-pub trait Parser<'deserializer> {
+///
+/// This trait is sealed: only the parsers of this crate implement it.
+pub trait Parser<'deserializer>: crate::sealed::Sealed {
     /// The grammar given to the parser.
     type Grammar;
     /// Getter to the grammar.
@@ -73,6 +201,13 @@ pub trait Parser<'deserializer> {
         &self,
         input: &'input mut LexedStream<'input, 'input>,
     ) -> Result<ParseResult>;
+    /// Parse the given [`LexedStream`], walking the derivation selected for it
+    /// with `sink` rather than building its AST.
+    fn parse_events<'input>(
+        &self,
+        input: &'input mut LexedStream<'input, 'input>,
+        sink: &mut impl ParseSink,
+    ) -> Result<()>;
     /// Just return whether the input is recognised.
     fn is_valid<'input>(&self, input: &'input mut LexedStream<'input, 'input>) -> bool {
         self.parse(input).is_ok()
@@ -0,0 +1,192 @@
+//! # Passes
+//!
+//! Transformations of the AST of parser grammars, run once a grammar is parsed and
+//! before an [`EarleyGrammar`] is built from it, so that other crates can extend
+//! the grammar language, with sugar that lowers to plain rules, or check grammars,
+//! with lints that reject them, without forking Beans.
+//!
+//! The AST is the one that `.gr.ast` files hold, so passes apply to grammars
+//! written in plain text and to their ASTs, but not to compiled grammars, which
+//! were built once passes had run.
+//!
+//! [`EarleyGrammar`]: super::earley::EarleyGrammar
+
+use std::fmt;
+
+use super::AST;
+use crate::{error::Result, lexer::Grammar as LexerGrammar};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::Buildable,
+        error::ErrorKind,
+        lexer::Lexer,
+        parser::{earley::EarleyGrammar, Value},
+        stream::StringStream,
+    };
+    use std::path::Path;
+
+    /// Replace every string `from` of `ast` with `to`.
+    fn rename(ast: AST, from: &str, to: &str) -> AST {
+        match ast {
+            AST::Node {
+                nonterminal,
+                attributes,
+                span,
+            } => AST::Node {
+                nonterminal,
+                attributes: attributes
+                    .into_iter()
+                    .map(|(key, value)| (key, rename(value, from, to)))
+                    .collect(),
+                span,
+            },
+            AST::Literal {
+                value: Value::Str(string),
+                span,
+            } if &*string == from => AST::Literal {
+                value: Value::Str(to.into()),
+                span,
+            },
+            ast => ast,
+        }
+    }
+
+    #[test]
+    fn passes() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "INT ::= ([0-9]+)\nPLUS ::= \\+",
+        ))
+        .unwrap();
+        let grammar = || {
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@Sum ::= Sum@left PLUS NUMBER.0@right <>\n NUMBER.0@self <>;",
+            )
+        };
+        assert!(EarleyGrammar::build_from_plain(grammar(), lexer.grammar()).is_err());
+
+        let passes = PassManager::new()
+            .with_fn("number", |ast, _| Ok(rename(ast, "NUMBER", "NUM")))
+            .with_fn("num", |ast, lexer_grammar| {
+                let name = lexer_grammar.name(lexer_grammar.id("INT").unwrap());
+                Ok(rename(ast, "NUM", name))
+            });
+        assert_eq!(passes.names().collect::<Vec<_>>(), ["number", "num"]);
+        let grammar_with_passes =
+            EarleyGrammar::build_from_plain_with(grammar(), lexer.grammar(), &passes).unwrap();
+        let renamed = StringStream::new(
+            Path::new("<grammar input>"),
+            "@Sum ::= Sum@left PLUS INT.0@right <>\n INT.0@self <>;",
+        );
+        assert_eq!(
+            grammar_with_passes,
+            EarleyGrammar::build_from_plain(renamed, lexer.grammar()).unwrap()
+        );
+
+        let lint = PassManager::new().with_fn("lint", |_, _| {
+            ErrorKind::GrammarPass {
+                pass: String::from("lint"),
+                message: String::from("rejected"),
+            }
+            .err()
+        });
+        let error = EarleyGrammar::build_from_plain_with(grammar(), lexer.grammar(), &lint)
+            .unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::GrammarPass { .. }));
+    }
+}
+
+/// A transformation of the AST of parser grammars.
+pub trait Pass {
+    /// The name of the pass, for diagnostics.
+    fn name(&self) -> &str;
+
+    /// Transform `ast`, the AST of a grammar whose terminals are defined by
+    /// `lexer_grammar`. Passes that only check grammars return `ast` unchanged,
+    /// or an error, such as [`ErrorKind::GrammarPass`].
+    ///
+    /// [`ErrorKind::GrammarPass`]: crate::error::ErrorKind::GrammarPass
+    fn run(&self, ast: AST, lexer_grammar: &LexerGrammar) -> Result<AST>;
+}
+
+/// A [`Pass`] made of a closure.
+struct FnPass<F> {
+    name: String,
+    run: F,
+}
+
+impl<F> Pass for FnPass<F>
+where
+    F: Fn(AST, &LexerGrammar) -> Result<AST>,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn run(&self, ast: AST, lexer_grammar: &LexerGrammar) -> Result<AST> {
+        (self.run)(ast, lexer_grammar)
+    }
+}
+
+/// # Summary
+///
+/// [`PassManager`] holds the passes to run on the AST of a grammar, in the order
+/// they were added. Grammars are built with them by
+/// [`EarleyGrammar::build_from_plain_with`], [`EarleyGrammar::build_from_ast_with`]
+/// and [`EarleyGrammar::build_from_path_with`].
+///
+/// [`EarleyGrammar::build_from_plain_with`]: super::earley::EarleyGrammar::build_from_plain_with
+/// [`EarleyGrammar::build_from_ast_with`]: super::earley::EarleyGrammar::build_from_ast_with
+/// [`EarleyGrammar::build_from_path_with`]: super::earley::EarleyGrammar::build_from_path_with
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `pass`, which runs after the passes already added.
+    pub fn with_pass(mut self, pass: impl Pass + 'static) -> Self {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// Add the pass `name`, which runs `run`, after the passes already added.
+    pub fn with_fn(
+        self,
+        name: impl Into<String>,
+        run: impl Fn(AST, &LexerGrammar) -> Result<AST> + 'static,
+    ) -> Self {
+        self.with_pass(FnPass {
+            name: name.into(),
+            run,
+        })
+    }
+
+    /// Return the names of the passes, in the order they run.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.passes.iter().map(|pass| pass.name())
+    }
+
+    /// Run every pass on `ast`, each on the result of the previous one.
+    pub fn run(&self, ast: AST, lexer_grammar: &LexerGrammar) -> Result<AST> {
+        self.passes
+            .iter()
+            .try_fold(ast, |ast, pass| pass.run(ast, lexer_grammar))
+    }
+}
+
+impl fmt::Debug for PassManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PassManager")
+            .field("passes", &self.names().collect::<Vec<_>>())
+            .finish()
+    }
+}
@@ -0,0 +1,295 @@
+//! # Resolve
+//!
+//! Edition of the source of a parser grammar to settle its ambiguities, which is
+//! what `beans resolve` writes back. Between two derivations, the parser picks the
+//! one whose nested rule is the latest in the grammar, or the earliest if it
+//! prefers the first rule, and the one that nests to the left or to the right of a
+//! rule depending on its associativity. So an ambiguity is settled either by
+//! annotating a rule with `(left-assoc)` or `(right-assoc)`, or by moving the rule
+//! that binds tighter where the parser prefers it.
+
+use super::earley::{Ambiguity, EarleyGrammar, RulePreference};
+use super::lalr::{Conflict, ConflictKind};
+use crate::span::{Location, Span};
+use std::ops::Range;
+
+const LEFT: &str = "left-assoc";
+const RIGHT: &str = "right-assoc";
+
+/// # Summary
+///
+/// [`Question`] is an ambiguity to settle: `example` can be derived both with the
+/// rule defined at `rule`, and with the one defined at `other`.
+#[derive(Debug, Clone)]
+pub struct Question {
+    pub title: String,
+    pub example: String,
+    pub rule: Span,
+    pub other: Span,
+    /// Whether both rules derive the same non-terminal, and can thus be ordered.
+    pub siblings: bool,
+}
+
+impl Question {
+    fn new(
+        grammar: &EarleyGrammar,
+        title: String,
+        example: String,
+        rule: Span,
+        other: Span,
+    ) -> Self {
+        let nonterminal_of = |span: &Span| {
+            grammar
+                .rules()
+                .iter()
+                .find(|rule| &rule.span == span)
+                .map(|rule| rule.id)
+        };
+        let nonterminal = nonterminal_of(&rule);
+        let siblings = nonterminal.is_some() && nonterminal == nonterminal_of(&other);
+        Self {
+            title,
+            example,
+            rule,
+            other,
+            siblings,
+        }
+    }
+
+    /// Whether both derivations come from the same rule, which its associativity
+    /// settles.
+    pub fn same_rule(&self) -> bool {
+        self.rule == self.other
+    }
+}
+
+/// The questions the LALR(1) `conflicts` of `grammar` raise. The conflicts that
+/// involve the axioms themselves cannot be settled in the grammar, and are left out.
+pub fn conflict_questions(grammar: &EarleyGrammar, conflicts: &[Conflict]) -> Vec<Question> {
+    conflicts
+        .iter()
+        .filter(|conflict| {
+            !conflict.rule.ends_with("(axiom)") && !conflict.other_rule.ends_with("(axiom)")
+        })
+        .map(|conflict| {
+            let title = match conflict.kind {
+                ConflictKind::ShiftReduce => "shift/reduce conflict",
+                ConflictKind::ReduceReduce => "reduce/reduce conflict",
+            };
+            Question::new(
+                grammar,
+                format!("{title} on {}", conflict.lookahead),
+                conflict_example(conflict),
+                conflict.span.get().clone(),
+                conflict.other_span.get().clone(),
+            )
+        })
+        .collect()
+}
+
+/// The questions the `ambiguities` found while parsing `text` with `grammar` raise.
+pub fn ambiguity_questions(
+    grammar: &EarleyGrammar,
+    ambiguities: &[Ambiguity],
+    text: &str,
+) -> Vec<Question> {
+    ambiguities
+        .iter()
+        .filter(|ambiguity| ambiguity.rules.len() >= 2)
+        .map(|ambiguity| {
+            Question::new(
+                grammar,
+                format!("{} is ambiguous {}", ambiguity.nonterminal, ambiguity.span),
+                text[ambiguity.span.byte_range()].to_string(),
+                ambiguity.rules[0].clone(),
+                ambiguity.rules[1].clone(),
+            )
+        })
+        .collect()
+}
+
+/// The byte range, within `text`, that `span` covers, `text` being the content
+/// of the file `span` points to. Spans of streams that chain several files do not
+/// give offsets within the files, but their locations do.
+pub fn range_in(text: &str, span: &Span) -> Range<usize> {
+    let start = offset_of(text, span.start());
+    start..start + span.byte_range().len()
+}
+
+/// The byte offset of `location` in `text`, whose columns count `char`s.
+fn offset_of(text: &str, (line, column): Location) -> usize {
+    let line_start = text
+        .split_inclusive('\n')
+        .take(line)
+        .map(str::len)
+        .sum::<usize>();
+    let column_offset = text[line_start..]
+        .char_indices()
+        .nth(column)
+        .map_or(text.len() - line_start, |(offset, _)| offset);
+    line_start + column_offset
+}
+
+/// The text of `rule` without its associativity annotation, if it has one.
+pub fn strip_associativity(rule: &str) -> &str {
+    let annotation = rule
+        .strip_prefix('(')
+        .map(str::trim_start)
+        .and_then(|rest| rest.strip_prefix(LEFT).or_else(|| rest.strip_prefix(RIGHT)))
+        .map(str::trim_start)
+        .and_then(|rest| rest.strip_prefix(')'));
+    annotation.map_or(rule, str::trim_start)
+}
+
+/// Annotate the rule at `rule` in `text` as left associative, or as right
+/// associative, replacing the annotation it has.
+pub fn set_associativity(text: &str, rule: Range<usize>, left: bool) -> String {
+    let annotation = if left { LEFT } else { RIGHT };
+    format!(
+        "{}({annotation}) {}{}",
+        &text[..rule.start],
+        strip_associativity(&text[rule.clone()]),
+        &text[rule.end..],
+    )
+}
+
+/// Make the rule at `tighter` in `text` bind tighter than the one at `looser`,
+/// that is, come after it, or before it if `preference` is for the first rule, by
+/// swapping them. Return `None` if it already does.
+pub fn prioritize(
+    text: &str,
+    tighter: Range<usize>,
+    looser: Range<usize>,
+    preference: RulePreference,
+) -> Option<String> {
+    let (first, last) = match preference {
+        RulePreference::First => (looser, tighter),
+        RulePreference::Last => (tighter, looser),
+    };
+    if first.start > last.start {
+        return None;
+    }
+    Some(format!(
+        "{}{}{}{}{}",
+        &text[..first.start],
+        &text[last.clone()],
+        &text[first.end..last.start],
+        &text[first],
+        &text[last.end..],
+    ))
+}
+
+/// A sentential form that leads to `conflict`, the dot standing for where the
+/// parser cannot decide what to do.
+pub fn conflict_example(conflict: &Conflict) -> String {
+    let (_, body) = conflict
+        .rule
+        .split_once(" -> ")
+        .unwrap_or(("", &conflict.rule));
+    let body = body.trim_end_matches(" ε");
+    match conflict.kind {
+        ConflictKind::ShiftReduce => format!("{body} • {} …", conflict.lookahead),
+        ConflictKind::ReduceReduce => format!("{body} • {}", conflict.lookahead),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Buildable;
+    use crate::error::ErrorKind;
+    use crate::lexer::Lexer;
+    use crate::parser::earley::EarleyParser;
+    use crate::parser::Parser;
+    use crate::parser::lalr::LalrGrammar;
+    use crate::stream::StringStream;
+    use std::path::Path;
+
+    const GRAMMAR: &str = "@Expr ::=
+  Expr@left PM Expr@right <Sum>
+  Expr@left TD Expr@right <Product>
+  NUMBER.0@value <Literal>;";
+
+    #[test]
+    fn resolve() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "NUMBER ::= ([0-9]+)\nPM ::= [-+]\nTD ::= [*/]",
+        ))
+        .unwrap();
+        let build = |grammar: &str| {
+            EarleyGrammar::build_from_plain(
+                StringStream::new(Path::new("<grammar input>"), grammar),
+                lexer.grammar(),
+            )
+            .unwrap()
+        };
+        let ambiguities = |grammar: &str| {
+            let parser = EarleyParser::new(build(grammar));
+            let mut stream = StringStream::new(Path::new("<input>"), "1*2+3");
+            let mut input = lexer.lex(&mut stream);
+            let (table, raw_input) = parser.recognise(&mut input).unwrap();
+            let forest = parser.to_forest(&table, &raw_input).unwrap();
+            parser.ambiguities(&forest, &raw_input, input.last_span())
+        };
+
+        let ambiguity = &ambiguities(GRAMMAR)[0];
+        let rules = ambiguity
+            .rules
+            .iter()
+            .map(|rule| &GRAMMAR[range_in(GRAMMAR, rule)])
+            .collect::<Vec<_>>();
+        assert_eq!(rules.len(), 2);
+        assert!(rules.contains(&"Expr@left PM Expr@right <Sum>"));
+        assert!(rules.contains(&"Expr@left TD Expr@right <Product>"));
+
+        let sum = range_in(GRAMMAR, &ambiguity.rules[0]);
+        let product = range_in(GRAMMAR, &ambiguity.rules[1]);
+        let (sum, product) = if GRAMMAR[sum.clone()].contains("Sum") {
+            (sum, product)
+        } else {
+            (product, sum)
+        };
+        let last = RulePreference::Last;
+        assert_eq!(prioritize(GRAMMAR, product.clone(), sum.clone(), last), None);
+        assert_eq!(
+            prioritize(GRAMMAR, sum.clone(), product.clone(), RulePreference::First),
+            None
+        );
+        let swapped = prioritize(GRAMMAR, sum, product.clone(), last).unwrap();
+        assert_eq!(
+            swapped,
+            "@Expr ::=
+  Expr@left TD Expr@right <Product>
+  Expr@left PM Expr@right <Sum>
+  NUMBER.0@value <Literal>;"
+        );
+
+        let annotated = set_associativity(GRAMMAR, product, false);
+        assert!(annotated.contains("\n  (right-assoc) Expr@left TD Expr@right <Product>\n"));
+        let product = ambiguities(&annotated)[0]
+            .rules
+            .iter()
+            .map(|rule| range_in(&annotated, rule))
+            .find(|rule| annotated[rule.clone()].contains("Product"))
+            .unwrap();
+        assert!(annotated[product.clone()].starts_with("(right-assoc)"));
+        let annotated = set_associativity(&annotated, product, true);
+        assert!(annotated.contains("\n  (left-assoc) Expr@left TD Expr@right <Product>\n"));
+        assert_eq!(
+            strip_associativity("( right-assoc ) Expr TD Expr <>"),
+            "Expr TD Expr <>"
+        );
+
+        let error = LalrGrammar::new(build(GRAMMAR), lexer.grammar()).unwrap_err();
+        let ErrorKind::LalrConflicts { conflicts } = *error.kind else {
+            panic!("expected conflicts");
+        };
+        let questions = conflict_questions(&build(GRAMMAR), &conflicts);
+        assert!(!questions.is_empty());
+        assert!(questions.iter().all(|question| question.siblings));
+        assert!(questions.iter().any(|question| {
+            question.same_rule() && question.example == "Expr PM Expr • PM …"
+        }));
+    }
+}
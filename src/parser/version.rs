@@ -0,0 +1,174 @@
+//! # Version
+//!
+//! Parser grammars may declare the version of the language they define, as in
+//! `version "1.2.0";`, following semantic versioning: the minor version grows when
+//! the language only gains constructs, and the major version when ASTs change in a
+//! way that breaks the tools that read them. Compiled grammars keep the version,
+//! and ASTs are serialized along with it as [`VersionedAst`]s, so that tools reading
+//! serialized ASTs reject those of a language version they were not built for.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::AST;
+use crate::error::{ErrorKind, Result};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::Buildable,
+        lexer::Lexer,
+        parser::{earley::EarleyGrammar, Value},
+        stream::StringStream,
+    };
+    use std::path::Path;
+
+    #[test]
+    fn language_version() {
+        let version = |text: &str| text.parse::<LanguageVersion>();
+        assert_eq!(version("1.2.3").unwrap(), LanguageVersion::new(1, 2, 3));
+        assert_eq!(version("1.2.3").unwrap().to_string(), "1.2.3");
+        assert!(version("1.2").is_err());
+        assert!(version("1.2.3.4").is_err());
+        assert!(version("1.x.3").is_err());
+
+        let tool = version("1.2.0").unwrap();
+        assert!(tool.accepts(&version("1.0.5").unwrap()));
+        assert!(tool.accepts(&version("1.2.0").unwrap()));
+        assert!(!tool.accepts(&version("1.3.0").unwrap()));
+        assert!(!tool.accepts(&version("2.0.0").unwrap()));
+        let tool = version("0.2.1").unwrap();
+        assert!(tool.accepts(&version("0.2.0").unwrap()));
+        assert!(!tool.accepts(&version("0.1.0").unwrap()));
+    }
+
+    #[test]
+    fn versioned_ast() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "INT ::= ([0-9]+)",
+        ))
+        .unwrap();
+        let grammar = |source: &str| {
+            EarleyGrammar::build_from_plain(
+                StringStream::new(Path::new("<grammar input>"), source),
+                lexer.grammar(),
+            )
+        };
+        let versioned = grammar("version \"1.2.0\";\n@Number ::= INT.0@value <>;").unwrap();
+        assert_eq!(versioned.language_version(), Some(LanguageVersion::new(1, 2, 0)));
+        let unversioned = grammar("@Number ::= INT.0@value <>;").unwrap();
+        assert_eq!(unversioned.language_version(), None);
+        assert_ne!(versioned, unversioned);
+        let error = grammar("version \"one\";\n@Number ::= INT.0@value <>;").unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::InvalidLanguageVersion { .. }));
+
+        let tree = AST::Literal {
+            value: Value::Int(1),
+            span: None,
+        };
+        let ast = VersionedAst::new(versioned.language_version(), tree.clone());
+        let json = serde_json::to_string(&ast).unwrap();
+        let ast: VersionedAst = serde_json::from_str(&json).unwrap();
+        assert_eq!(ast.clone().check(&LanguageVersion::new(1, 4, 0)).unwrap(), tree);
+        let error = ast.check(&LanguageVersion::new(2, 0, 0)).unwrap_err();
+        assert!(matches!(*error.kind, ErrorKind::IncompatibleLanguageVersion { .. }));
+        let error = VersionedAst::new(None, tree)
+            .check(&LanguageVersion::new(1, 0, 0))
+            .unwrap_err();
+        assert!(matches!(
+            *error.kind,
+            ErrorKind::IncompatibleLanguageVersion { found: None, .. }
+        ));
+    }
+}
+
+/// # Summary
+///
+/// [`LanguageVersion`] is the semantic version of the language a grammar defines,
+/// written `MAJOR.MINOR.PATCH`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct LanguageVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl LanguageVersion {
+    pub fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Return whether tools built for this version of the language can read the
+    /// ASTs of the version `produced`: those that are not newer, of the same major
+    /// version, or of the same minor version before `1.0.0`.
+    pub fn accepts(&self, produced: &Self) -> bool {
+        let compatible = if self.major == 0 {
+            produced.major == 0 && produced.minor == self.minor
+        } else {
+            produced.major == self.major
+        };
+        compatible && produced <= self
+    }
+}
+
+impl FromStr for LanguageVersion {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        let numbers = text
+            .split('.')
+            .map(|number| {
+                number
+                    .parse()
+                    .map_err(|_| format!("{number:?} is not a version number"))
+            })
+            .collect::<std::result::Result<Vec<u64>, _>>()?;
+        let [major, minor, patch] = numbers[..] else {
+            return Err(String::from("expected MAJOR.MINOR.PATCH"));
+        };
+        Ok(Self::new(major, minor, patch))
+    }
+}
+
+impl fmt::Display for LanguageVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// An AST, along with the version of the language of the grammar that built it,
+/// if the grammar declares one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VersionedAst {
+    pub language_version: Option<LanguageVersion>,
+    pub tree: AST,
+}
+
+impl VersionedAst {
+    pub fn new(language_version: Option<LanguageVersion>, tree: AST) -> Self {
+        Self {
+            language_version,
+            tree,
+        }
+    }
+
+    /// Return the AST, if tools built for the version `expected` of the language can
+    /// read it, or an [`ErrorKind::IncompatibleLanguageVersion`] otherwise.
+    pub fn check(self, expected: &LanguageVersion) -> Result<AST> {
+        match self.language_version {
+            Some(found) if expected.accepts(&found) => Ok(self.tree),
+            found => ErrorKind::IncompatibleLanguageVersion {
+                expected: *expected,
+                found,
+            }
+            .err(),
+        }
+    }
+}
@@ -0,0 +1,19 @@
+//! # Prelude
+//!
+//! The supported surface of Beans, meant to be glob-imported by the projects that
+//! build on it, with `use beans::prelude::*;`. What is not exported here may change
+//! between minor versions.
+
+pub use crate::build_system;
+pub use crate::builder::Buildable;
+pub use crate::error::{Error, ErrorKind};
+pub use crate::interner::Symbol;
+pub use crate::lexer::{Grammar as LexerGrammar, LexedStream, Lexer, Token};
+pub use crate::parser::earley::{Ambiguity, EarleyGrammar, EarleyParser, Severity};
+pub use crate::parser::lalr::{LalrGrammar, LalrParser};
+pub use crate::parser::passes::{Pass, PassManager};
+pub use crate::parser::{Event, External, ParseResult, ParseSink, Parser, Value, AST};
+pub use crate::printer::{print_ast, to_sexpr};
+pub use crate::span::Span;
+pub use crate::stream::{ColumnEncoding, StringStream};
+pub use crate::typed::{Spanned, Tree};
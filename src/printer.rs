@@ -34,6 +34,26 @@ fn build_tree(tree: &mut TreeBuilder, ast: &AST) {
         } => {
             tree.add_empty_child(b.to_string());
         }
+        #[cfg(feature = "bigint")]
+        AST::Literal {
+            value: Value::BigInt(i),
+            ..
+        } => {
+            tree.add_empty_child(i.to_string());
+        }
+        #[cfg(feature = "decimal")]
+        AST::Literal {
+            value: Value::Decimal(d),
+            ..
+        } => {
+            tree.add_empty_child(d.to_string());
+        }
+        AST::Literal {
+            value: Value::External(_),
+            ..
+        } => {
+            tree.add_empty_child(String::from("<external>"));
+        }
         AST::Terminal(ter) => {
             tree.add_empty_child(ter.name().to_string());
         }
@@ -46,3 +66,127 @@ pub fn print_ast(ast: &AST) -> std::io::Result<()> {
     let tree = tree.build();
     print_tree(&tree)
 }
+
+fn write_sexpr(output: &mut String, ast: &AST) {
+    match ast {
+        AST::Node { attributes, .. } => {
+            output.push('(');
+            let mut keys: Vec<_> = attributes.keys().collect();
+            keys.sort();
+            let mut first = true;
+            if let Some(AST::Literal {
+                value: Value::Str(variant),
+                ..
+            }) = attributes.get("variant")
+            {
+                output.push_str(variant);
+                keys.retain(|key| &***key != "variant");
+                first = false;
+            }
+            for key in keys {
+                if !first {
+                    output.push(' ');
+                }
+                first = false;
+                output.push(':');
+                output.push_str(key);
+                output.push(' ');
+                write_sexpr(output, &attributes[key]);
+            }
+            output.push(')');
+        }
+        AST::Literal {
+            value: Value::Int(i),
+            ..
+        } => output.push_str(&i.to_string()),
+        AST::Literal {
+            value: Value::Str(string),
+            ..
+        } => output.push_str(&format!("{:?}", string)),
+        AST::Literal {
+            value: Value::Float(f),
+            ..
+        } => output.push_str(&f.to_string()),
+        AST::Literal {
+            value: Value::Bool(b),
+            ..
+        } => output.push_str(&b.to_string()),
+        #[cfg(feature = "bigint")]
+        AST::Literal {
+            value: Value::BigInt(i),
+            ..
+        } => output.push_str(&i.to_string()),
+        #[cfg(feature = "decimal")]
+        AST::Literal {
+            value: Value::Decimal(d),
+            ..
+        } => output.push_str(&d.to_string()),
+        AST::Literal {
+            value: Value::External(_),
+            ..
+        } => output.push_str("#<external>"),
+        AST::Terminal(ter) => {
+            if ter.attributes().is_empty() {
+                output.push_str(ter.name());
+            } else {
+                let mut attributes: Vec<_> = ter.attributes().iter().collect();
+                attributes.sort();
+                output.push('(');
+                output.push_str(ter.name());
+                for (_, value) in attributes {
+                    output.push_str(&format!(" {:?}", value));
+                }
+                output.push(')');
+            }
+        }
+    }
+}
+
+/// Render `ast` as a compact, single line, S-expression.
+///
+/// Nodes are lists of `:key value` pairs, sorted by key, headed by their
+/// variant if they have one. Tokens without attributes are written as their
+/// name, the others as a list of their name followed by their attributes.
+/// Spans are left out, so that the output is stable and easy to diff.
+pub fn to_sexpr(ast: &AST) -> String {
+    let mut output = String::new();
+    write_sexpr(&mut output, ast);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_sexpr;
+    use crate::builder::Buildable;
+    use crate::lexer::Lexer;
+    use crate::parser::earley::{EarleyGrammar, EarleyParser};
+    use crate::parser::Parser;
+    use crate::stream::StringStream;
+    use std::path::Path;
+
+    #[test]
+    fn sexpr() {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "NUMBER ::= ([0-9]+)\nOP ::= \\+",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@Expression ::=\n\
+                   NUMBER.0@value <Literal>\n\
+                   Expression@left OP@op NUMBER@right <Operation>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        let parser = EarleyParser::new(grammar);
+        let mut stream = StringStream::new(Path::new("<input>"), "1+23");
+        let ast = parser.parse(&mut lexer.lex(&mut stream)).unwrap().tree;
+        assert_eq!(
+            to_sexpr(&ast),
+            r#"(Operation :left (Literal :value "1") :op OP :right (NUMBER "23"))"#
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! # Progress
+//!
+//! Reports of how far long tasks are, such as building the automaton of a large
+//! grammar or parsing many sources, so that front-ends can show it rather than
+//! sit silent.
+
+use std::fmt;
+use std::io::Write;
+
+/// A phase of a long task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Building lexer grammars.
+    LexerGrammar,
+    /// Building parser grammars.
+    ParserGrammar,
+    /// Building the LALR(1) automaton of a parser grammar.
+    Automaton,
+    /// Parsing sources.
+    Corpus,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::LexerGrammar => "lexer grammars",
+            Self::ParserGrammar => "parser grammars",
+            Self::Automaton => "automaton",
+            Self::Corpus => "sources",
+        };
+        f.write_str(name)
+    }
+}
+
+/// How far a phase is: `done` steps out of `total`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub phase: Phase,
+    pub done: usize,
+    pub total: usize,
+}
+
+impl Progress {
+    pub fn new(phase: Phase, done: usize, total: usize) -> Self {
+        Self { phase, done, total }
+    }
+
+    /// The percentage of the phase that is done. A phase without any step is done.
+    pub fn percentage(&self) -> usize {
+        (self.done.min(self.total) * 100)
+            .checked_div(self.total)
+            .unwrap_or(100)
+    }
+
+    /// Whether every step of the phase is done.
+    pub fn is_done(&self) -> bool {
+        self.done >= self.total
+    }
+}
+
+/// # Summary
+///
+/// [`ProgressReporter`] receives the progress of long tasks. Closures taking a
+/// [`Progress`] are reporters.
+pub trait ProgressReporter {
+    fn report(&mut self, progress: Progress);
+}
+
+impl<F: FnMut(Progress)> ProgressReporter for F {
+    fn report(&mut self, progress: Progress) {
+        self(progress)
+    }
+}
+
+/// # Summary
+///
+/// [`ProgressBar`] draws the progress on a single line of a terminal, as in
+/// `automaton [##########----------]  50% (120/240)`, and moves to the next line
+/// once a phase is done. The line is only redrawn when the percentage changes.
+#[derive(Debug)]
+pub struct ProgressBar<W> {
+    output: W,
+    width: usize,
+    last: Option<(Phase, usize)>,
+}
+
+impl<W: Write> ProgressBar<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            output,
+            width: 20,
+            last: None,
+        }
+    }
+}
+
+impl<W: Write> ProgressReporter for ProgressBar<W> {
+    fn report(&mut self, progress: Progress) {
+        let percentage = progress.percentage();
+        if self.last == Some((progress.phase, percentage)) && !progress.is_done() {
+            return;
+        }
+        self.last = Some((progress.phase, percentage));
+        let filled = percentage * self.width / 100;
+        // Failing to draw the bar should not stop the task it reports on.
+        let _ = write!(
+            self.output,
+            "\r{} [{}{}] {percentage:>3}% ({}/{})",
+            progress.phase,
+            "#".repeat(filled),
+            "-".repeat(self.width - filled),
+            progress.done,
+            progress.total,
+        );
+        if progress.is_done() {
+            self.last = None;
+            let _ = writeln!(self.output);
+        }
+        let _ = self.output.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_bar() {
+        let mut output = Vec::new();
+        let mut bar = ProgressBar::new(&mut output);
+        for done in 0..=8 {
+            bar.report(Progress::new(Phase::Automaton, done / 2, 4));
+        }
+        bar.report(Progress::new(Phase::Corpus, 0, 0));
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "\rautomaton [--------------------]   0% (0/4)\
+             \rautomaton [#####---------------]  25% (1/4)\
+             \rautomaton [##########----------]  50% (2/4)\
+             \rautomaton [###############-----]  75% (3/4)\
+             \rautomaton [####################] 100% (4/4)\n\
+             \rsources [####################] 100% (0/0)\n"
+        );
+        let mut reports = Vec::new();
+        let mut reporter = |progress: Progress| reports.push(progress.percentage());
+        reporter.report(Progress::new(Phase::Corpus, 1, 3));
+        assert_eq!(reports, [33]);
+    }
+}
@@ -1,5 +1,21 @@
+// With `hardened`, the engine that runs on inputs does not panic.
+#![cfg_attr(
+    all(feature = "hardened", not(test)),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::unreachable,
+        clippy::todo,
+        clippy::unimplemented
+    )
+)]
+
 mod api;
+mod dfa;
 mod matching;
+// Regexes come from grammars, which are trusted.
+#[allow(clippy::unwrap_used, clippy::expect_used, clippy::panic, clippy::unreachable)]
 mod parsing;
 
 pub use api::*;
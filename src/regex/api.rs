@@ -1,9 +1,13 @@
+use super::dfa::Dfa;
 use super::matching::InstructionPointer;
 use super::matching::{self, AllowedTerminals, Instruction, Program};
-use super::parsing::{build, read, Regex, RegexError};
+use super::parsing::{build, case_insensitive, read_named, Regex, RegexError};
 use crate::lexer::TerminalId;
 use newty::newty;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Bound::{Included, Unbounded};
 
 #[cfg(test)]
 mod tests {
@@ -18,9 +22,9 @@ mod tests {
         assert_eq!(regex.size, 0);
 
         let regex = RegexBuilder::new()
-            .with_named_regex("a+", String::from("As"), false)
+            .with_named_regex("a+", String::from("As"), false, false)
             .unwrap()
-            .with_named_regex("b", String::from("B"), false)
+            .with_named_regex("b", String::from("B"), false, false)
             .unwrap()
             .build();
         assert_eq!(
@@ -45,9 +49,9 @@ mod tests {
         assert_eq!(regex.size, 0);
 
         let regex = RegexBuilder::new()
-            .with_named_regex("(a+)", String::from("As"), false)
+            .with_named_regex("(a+)", String::from("As"), false, false)
             .unwrap()
-            .with_named_regex("(b)", String::from("B"), false)
+            .with_named_regex("(b)", String::from("B"), false, false)
             .unwrap()
             .build();
         assert_eq!(
@@ -82,9 +86,9 @@ mod tests {
     #[test]
     fn find() {
         let regex = RegexBuilder::new()
-            .with_named_regex("(a+)", String::from("As"), false)
+            .with_named_regex("(a+)", String::from("As"), false, false)
             .unwrap()
-            .with_named_regex("(b)(c)", String::from("BC"), false)
+            .with_named_regex("(b)(c)", String::from("BC"), false, false)
             .unwrap()
             .build();
         let text = "aaacd";
@@ -119,9 +123,9 @@ mod tests {
     #[test]
     fn groups() {
         let regex = RegexBuilder::new()
-            .with_named_regex("'(.*)'", String::from("STRING"), false)
+            .with_named_regex("'(.*)'", String::from("STRING"), false, false)
             .unwrap()
-            .with_named_regex("\"(.*)\"", String::from("STRING"), false)
+            .with_named_regex("\"(.*)\"", String::from("STRING"), false, false)
             .unwrap()
             .build();
         let text = "'blabla'";
@@ -135,10 +139,50 @@ mod tests {
         assert_eq!(handle.text(text), "blabla");
     }
 
+    #[test]
+    fn group_names() {
+        let regex = RegexBuilder::new()
+            .with_named_regex("(a)(?P<b>b)", String::from("A"), false, false)
+            .unwrap()
+            .with_named_regex("(?P<c>c)(?P<b>b)", String::from("C"), false, false)
+            .unwrap()
+            .build();
+        assert_eq!(regex.group_index(TerminalId(0), "b"), Some(1));
+        assert_eq!(regex.group_index(TerminalId(0), "c"), None);
+        assert_eq!(regex.group_index(TerminalId(1), "b"), Some(1));
+        assert_eq!(
+            regex.group_names(TerminalId(1)),
+            [(String::from("c"), 0), (String::from("b"), 1)]
+        );
+        let found = regex.find("cb", &Allowed::All).unwrap();
+        let handle = found.groups()[regex.group_index(found.id(), "c").unwrap()].as_ref();
+        assert_eq!(handle.unwrap().text("cb"), "c");
+    }
+
+    #[test]
+    fn insensitive() {
+        let regex = RegexBuilder::new()
+            .with_named_regex("select", String::from("SELECT"), true, true)
+            .unwrap()
+            .with_named_regex("[a-cé]+[^x]", String::from("ABC"), false, true)
+            .unwrap()
+            .build();
+        let find = |text: &str| regex.find(text, &Allowed::All).map(|found| found.name);
+        assert_eq!(find("SeLeCt"), Some("SELECT"));
+        assert_eq!(find("selectx"), None);
+        assert_eq!(find("AbÉcy"), Some("ABC"));
+        assert_eq!(find("aX"), None);
+        let sensitive = RegexBuilder::new()
+            .with_named_regex("select", String::from("SELECT"), true, false)
+            .unwrap()
+            .build();
+        assert!(sensitive.find("SELECT", &Allowed::All).is_none());
+    }
+
     #[test]
     fn any() {
         let regex = RegexBuilder::new()
-            .with_named_regex(".*", String::from("Default"), false)
+            .with_named_regex(".*", String::from("Default"), false, false)
             .unwrap()
             .build();
         assert_eq!(
@@ -148,6 +192,80 @@ mod tests {
         assert_eq!(regex.find("012", &Allowed::All).unwrap().chars_length, 3);
         assert_eq!(regex.find("", &Allowed::All).unwrap().chars_length, 0);
     }
+
+    #[test]
+    fn dump() {
+        let builder = || {
+            RegexBuilder::new()
+                .with_named_regex("a[0-9]+", String::from("A"), false, false)
+                .unwrap()
+        };
+        let dump = builder().build().dump().to_string();
+        assert!(dump.contains("switch to A at 1"));
+        assert!(dump.contains("in '0'..='9'"));
+        assert!(dump.contains("match A"));
+        assert!(dump.contains("goes to state 4 on 'a'"));
+        assert!(dump.contains("matches A before the end of the input"));
+        let dump = builder().with_dfa(false).build().dump().to_string();
+        assert!(dump.ends_with("no DFA\n"));
+    }
+}
+
+/// The listing of a [`CompiledRegex`], returned by [`CompiledRegex::dump`].
+struct Dump<'regex>(&'regex CompiledRegex);
+
+impl fmt::Display for Dump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let regex = self.0;
+        let name = |id: TerminalId| regex.names[id].as_str();
+        writeln!(f, "{} instructions", regex.program.len())?;
+        for (ip, instruction) in regex.program.iter().enumerate() {
+            write!(f, "{ip:>5}  ")?;
+            match instruction {
+                Instruction::Switch(targets) => {
+                    let targets = targets
+                        .iter()
+                        .map(|&(id, target)| format!("{} at {}", name(id), target.0))
+                        .collect::<Vec<_>>();
+                    writeln!(f, "switch to {}", targets.join(", "))?
+                }
+                Instruction::Save(register) => writeln!(f, "save to group {register}")?,
+                Instruction::Split(first, second) => {
+                    writeln!(f, "split to {} and {}", first.0, second.0)?
+                }
+                Instruction::Char(chr) => writeln!(f, "char {chr:?}")?,
+                Instruction::Jump(target) => writeln!(f, "jump to {}", target.0)?,
+                Instruction::Match(id) => writeln!(f, "match {}", name(*id))?,
+                Instruction::WordChar => writeln!(f, "word char")?,
+                Instruction::Digit => writeln!(f, "digit")?,
+                Instruction::WordBoundary => writeln!(f, "word boundary")?,
+                Instruction::LineStart => writeln!(f, "start of line")?,
+                Instruction::LineEnd => writeln!(f, "end of line")?,
+                Instruction::Whitespace => writeln!(f, "whitespace")?,
+                Instruction::CharacterClass(class, negated) => {
+                    let intervals = class
+                        .get_interval_overlaps(&(Unbounded::<char>, Unbounded))
+                        .into_iter()
+                        .map(|interval| match interval {
+                            (Included(start), Included(end)) if start == end => {
+                                format!("{start:?}")
+                            }
+                            (Included(start), Included(end)) => format!("{start:?}..={end:?}"),
+                            interval => format!("{interval:?}"),
+                        })
+                        .collect::<Vec<_>>();
+                    let not = if *negated { "not " } else { "" };
+                    writeln!(f, "{not}in {}", intervals.join(", "))?
+                }
+                Instruction::EOF => writeln!(f, "end of input")?,
+                Instruction::Any => writeln!(f, "any char")?,
+            }
+        }
+        match &regex.dfa {
+            Some(dfa) => dfa.dump(f, name),
+            None => writeln!(f, "no DFA"),
+        }
+    }
 }
 
 newty! {
@@ -160,12 +278,20 @@ newty! {
     vec GroupNames(String)[TerminalId]
 }
 
+newty! {
+    #[derive(PartialEq, Eq, Serialize, Deserialize)]
+    vec Captures(Vec<(String, usize)>)[TerminalId]
+}
+
 /// The allowed named regex in a single match.
 /// This is useful is you want to prevent the engine from matching certain regex by not allowing them.
 /// It is very efficient in the sense that the complexity of a match depends only on the number of allowed regex,
 /// not on the number of compiled regex, which means it is a good idea to compile all regex at once, into a single
 /// engine, and then filter the one used for a certain match.
-#[derive(Debug)]
+///
+/// When lexing, ignored terminals are only skipped if they are allowed. Build it from an
+/// [`AllowedSet`](crate::lexer::AllowedSet) rather than by hand to combine sets of terminals.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Allowed {
     /// Allow all regex.
     All,
@@ -174,6 +300,14 @@ pub enum Allowed {
 }
 
 impl Allowed {
+    /// Return whether the regex `id` is allowed.
+    pub fn contains(&self, id: TerminalId) -> bool {
+        match self {
+            Allowed::All => true,
+            Allowed::Some(rules) => rules.contains(&id),
+        }
+    }
+
     fn convert(&self, size: usize) -> matching::Allowed {
         match self {
             Allowed::All => matching::Allowed::All,
@@ -188,6 +322,38 @@ impl Allowed {
     }
 }
 
+/// # Summary
+///
+/// [`Failures`] remembers, for a text, the states of the DFA of a [`CompiledRegex`]
+/// from which no allowed regex matches, starting from some position of the text,
+/// so that later scans of the text stop there. Finding the matches of a text one
+/// after the other, each starting where the previous one ends, then takes linear
+/// time, whatever the regex: a scan goes through pairs of a state and a position
+/// that are either before the end of its match, where no later scan starts, or
+/// remembered, so no pair is gone through twice. This is the maximal munch
+/// tokenization of Reps. The pairs are forgotten when the allowed regex change.
+#[derive(Debug, Default)]
+pub struct Failures {
+    pub(super) pairs: HashSet<(usize, usize)>,
+    /// The allowed regex that the pairs were found for.
+    allowed: Option<Allowed>,
+    /// The number of chars the scans went through.
+    pub(super) scanned: usize,
+}
+
+impl Failures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the number of chars the scans went through, which, as long as the
+    /// allowed regex do not change, is at most proportional to the length of the
+    /// text times the number of states of the DFA.
+    pub fn scanned(&self) -> usize {
+        self.scanned
+    }
+}
+
 /// # Summary
 ///
 /// `Handle` represents a region of the input captured by a group.
@@ -279,25 +445,125 @@ impl Match<'_> {
 /// # Method
 ///
 /// `find`: match against a given input
+/// `has_dfa`: return whether matches are found by a DFA
 #[cfg_attr(test, derive(PartialEq))]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CompiledRegex {
     names: GroupNames,
     program: Program,
     groups: Groups,
+    /// The named groups of each regex, with their index among its groups.
+    captures: Captures,
     size: usize,
+    /// The DFA of the program, if it was built. It finds the match, and the
+    /// program then only runs to find its groups, if the regex has any.
+    dfa: Option<Dfa>,
 }
 
 impl CompiledRegex {
-    fn new(program: Program, names: GroupNames, groups: Groups, size: usize) -> Self {
+    fn new(
+        program: Program,
+        names: GroupNames,
+        groups: Groups,
+        captures: Captures,
+        size: usize,
+        dfa: Option<Dfa>,
+    ) -> Self {
         Self {
             names,
             program,
             groups,
+            captures,
             size,
+            dfa,
         }
     }
 
+    /// Return the named groups of the regex `id`, with their index among the
+    /// groups of its matches.
+    pub fn group_names(&self, id: TerminalId) -> &[(String, usize)] {
+        &self.captures[id]
+    }
+
+    /// Return the index of the group `name` of the regex `id`, among the groups of
+    /// its matches, if it has a group with that name.
+    pub fn group_index(&self, id: TerminalId, name: &str) -> Option<usize> {
+        self.captures[id]
+            .iter()
+            .find(|(group, _)| group == name)
+            .map(|&(_, index)| index)
+    }
+
+    /// Return whether matches are found by a DFA, rather than by simulating the
+    /// regex.
+    pub fn has_dfa(&self) -> bool {
+        self.dfa.is_some()
+    }
+
+    /// Return the pairs of regexes that both match a same text, the first regex of
+    /// each pair first, or `None` if the regex has no DFA to tell.
+    pub fn ties(&self) -> Option<Vec<(TerminalId, TerminalId)>> {
+        self.dfa.as_ref().map(|dfa| dfa.ties().into_iter().collect())
+    }
+
+    /// Return a listing of the program that the regex compile to, an instruction per
+    /// line, followed by the states of its DFA, if it has one, to tell why a regex
+    /// matches more or less than expected.
+    pub fn dump(&self) -> impl fmt::Display + '_ {
+        Dump(self)
+    }
+
+    /// Return whether more text after `input`, which follows the char `last` of a
+    /// text, may make an `allowed` regex match at its start. Without a DFA to tell,
+    /// it is assumed to.
+    pub fn may_extend(&self, input: &str, last: Option<char>, allowed: &Allowed) -> bool {
+        let allowed = allowed.convert(self.names.len());
+        self.dfa
+            .as_ref()
+            .is_none_or(|dfa| dfa.may_extend(input, last, &allowed))
+    }
+
+    /// Find the match of the regex, as `matching::find_after` would, with the DFA.
+    fn find_with_dfa(
+        &self,
+        dfa: &Dfa,
+        input: &str,
+        last: Option<char>,
+        allowed: &matching::Allowed,
+        memory: Option<(usize, &mut Failures)>,
+    ) -> Option<matching::Match> {
+        let (length, id) = dfa.find(input, last, allowed, memory)?;
+        let (begin_groups, end_groups) = self.groups[id];
+        if begin_groups == end_groups {
+            return Some(matching::Match {
+                char_pos: length,
+                id,
+                groups: Vec::new(),
+            });
+        }
+        // The regex `id` alone has the same match, since it is the longest of all.
+        let mut only = AllowedTerminals::with_raw_capacity(self.names.len());
+        only.insert(id);
+        matching::find_after(
+            &self.program,
+            input,
+            last,
+            self.size,
+            &matching::Allowed::Some(only),
+        )
+    }
+
+    /// Build a text that the regex `id` matches, following the branches that `choose`
+    /// picks: it is given the number of branches, and returns the one to follow. Return
+    /// `None` if `choose` does not lead to a match soon enough.
+    pub fn sample(
+        &self,
+        id: TerminalId,
+        choose: &mut dyn FnMut(usize) -> usize,
+    ) -> Option<String> {
+        matching::sample(&self.program, id, choose, 1024)
+    }
+
     /// Match against a given input. Will return only one match, if many were possibles,
     /// according to the priority rules.
     pub fn find<'pattern>(
@@ -305,22 +571,63 @@ impl CompiledRegex {
         input: &str,
         allowed: &Allowed,
     ) -> Option<Match<'pattern>> {
+        self.find_remembering(input, None, allowed, None)
+    }
+
+    /// Match against `input`, like [`CompiledRegex::find`], `input` following the
+    /// char `last` of a text, if it is not its start. Anchors and word boundaries at
+    /// the start of `input` depend on `last`.
+    pub fn find_after<'pattern>(
+        &'pattern self,
+        input: &str,
+        last: Option<char>,
+        allowed: &Allowed,
+    ) -> Option<Match<'pattern>> {
+        self.find_remembering(input, last, allowed, None)
+    }
+
+    /// Match against `input`, like [`CompiledRegex::find_after`], `input` being what
+    /// is left of a text from its char `position` on. The matches of a same text
+    /// share `failures`, which makes finding them one after the other take linear
+    /// time if the regex has a DFA. The text must not change in the meantime.
+    pub fn find_at<'pattern>(
+        &'pattern self,
+        input: &str,
+        position: usize,
+        last: Option<char>,
+        allowed: &Allowed,
+        failures: &mut Failures,
+    ) -> Option<Match<'pattern>> {
+        if failures.allowed.as_ref() != Some(allowed) {
+            failures.pairs.clear();
+            failures.allowed = Some(allowed.clone());
+        }
+        self.find_remembering(input, last, allowed, Some((position, failures)))
+    }
+
+    fn find_remembering<'pattern>(
+        &'pattern self,
+        input: &str,
+        last: Option<char>,
+        allowed: &Allowed,
+        memory: Option<(usize, &mut Failures)>,
+    ) -> Option<Match<'pattern>> {
+        let allowed = allowed.convert(self.names.len());
+        let found = match &self.dfa {
+            Some(dfa) => self.find_with_dfa(dfa, input, last, &allowed, memory),
+            None => matching::find_after(&self.program, input, last, self.size, &allowed),
+        };
         if let Some(matching::Match {
             char_pos: length,
             id,
             groups,
             ..
-        }) = matching::find(
-            &self.program,
-            input,
-            self.size,
-            &allowed.convert(self.names.len()),
-        ) {
+        }) = found
+        {
             let (begin_groups, end_groups) = self.groups[id];
             let mut grps = Vec::new();
             for i in begin_groups..end_groups {
-                if let Some(start) = groups[2 * i] {
-                    let end = groups[2 * i + 1].unwrap();
+                if let (Some(start), Some(end)) = (groups[2 * i], groups[2 * i + 1]) {
                     let handle = Handle {
                         bytes_start: start,
                         bytes_end: end,
@@ -356,13 +663,16 @@ impl CompiledRegex {
 ///
 /// `new`: create a new `RegexBuilder`
 /// `with_named_regex`: add a regex, and bind it to the given name
+/// `with_dfa`: choose whether to build a DFA
 /// `build`: consume the `RegexBuilder` and return the `CompiledRegex`
 #[derive(Debug)]
 pub struct RegexBuilder {
     names: Vec<String>,
     regexes: Vec<Regex>,
     groups: Vec<(usize, usize)>,
+    captures: Vec<Vec<(String, usize)>>,
     current: usize,
+    dfa: bool,
 }
 
 impl RegexBuilder {
@@ -372,27 +682,51 @@ impl RegexBuilder {
             names: Vec::new(),
             regexes: Vec::new(),
             groups: Vec::new(),
+            captures: Vec::new(),
             current: 0,
+            dfa: true,
         }
     }
 
+    /// Choose whether to build a DFA, which is the default. A DFA finds matches
+    /// in a single pass over the input, but takes longer to build, and is not built
+    /// anyway for regex that would need too many states.
+    pub fn with_dfa(mut self, dfa: bool) -> Self {
+        self.dfa = dfa;
+        self
+    }
+
     /// Add a regex, and bind it to the given name.
     /// The regex is read when it is added, so `with_named_regex`
-    /// might fail, if the provided regex is malformed.
+    /// might fail, if the provided regex is malformed. A `keyword` regex only
+    /// matches up to a word boundary, and an `insensitive` one matches regardless
+    /// of the case of letters.
     pub fn with_named_regex(
         mut self,
         regex: &str,
         name: String,
         keyword: bool,
+        insensitive: bool,
     ) -> Result<Self, RegexError> {
         self.names.push(name);
-        let (regex, groups) = read(regex, self.current)?;
+        let (regex, groups, names) = read_named(regex, self.current)?;
+        let regex = if insensitive {
+            case_insensitive(regex)
+        } else {
+            regex
+        };
         let regex = if keyword {
             Regex::Concat(Box::new(regex), Box::new(Regex::WordBoundary))
         } else {
             regex
         };
         self.groups.push((self.current, groups));
+        self.captures.push(
+            names
+                .into_iter()
+                .map(|(name, group)| (name, group - self.current))
+                .collect(),
+        );
         self.current = groups;
         self.regexes.push(regex);
         Ok(self)
@@ -405,7 +739,9 @@ impl RegexBuilder {
                 Program::new(),
                 self.names.into(),
                 self.groups.into(),
+                self.captures.into(),
                 self.current,
+                None,
             );
         }
         let mut program = Program::new();
@@ -424,7 +760,15 @@ impl RegexBuilder {
         }
 
         program[InstructionPointer(0)] = Instruction::Switch(switch);
-        CompiledRegex::new(program, self.names.into(), self.groups.into(), self.current)
+        let dfa = if self.dfa { Dfa::new(&program) } else { None };
+        CompiledRegex::new(
+            program,
+            self.names.into(),
+            self.groups.into(),
+            self.captures.into(),
+            self.current,
+            dfa,
+        )
     }
 }
 
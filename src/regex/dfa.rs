@@ -0,0 +1,596 @@
+use super::api::Failures;
+use super::matching::{is_word_char, Allowed, Instruction, InstructionPointer, Program};
+use crate::lexer::TerminalId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::ops::Bound;
+
+#[cfg(test)]
+mod tests {
+    use super::super::api::{Allowed, CompiledRegex, Failures, RegexBuilder};
+    use crate::lexer::TerminalId;
+
+    /// The length, the regex and the groups of the match of `regex` on `input`,
+    /// which follows `last`.
+    fn summary(
+        regex: &CompiledRegex,
+        input: &str,
+        last: Option<char>,
+        allowed: &Allowed,
+    ) -> Option<(usize, TerminalId, Vec<Option<String>>)> {
+        let found = regex.find_after(input, last, allowed)?;
+        let groups = found
+            .groups()
+            .iter()
+            .map(|group| group.as_ref().map(|group| group.text(input).to_string()))
+            .collect();
+        Some((found.chars_length(), found.id(), groups))
+    }
+
+    /// Check that the DFA finds the same matches as the VM, groups included.
+    fn same_matches(regexes: &[&str], inputs: &[&str]) {
+        let build = |dfa: bool| {
+            let mut builder = RegexBuilder::new().with_dfa(dfa);
+            for (i, regex) in regexes.iter().enumerate() {
+                builder = builder
+                    .with_named_regex(regex, format!("R{i}"), false, false)
+                    .unwrap();
+            }
+            builder.build()
+        };
+        let (dfa, vm) = (build(true), build(false));
+        assert!(dfa.has_dfa());
+        assert!(!vm.has_dfa());
+        let mut allowed = vec![Allowed::All, Allowed::Some(Vec::new())];
+        allowed.extend((0..regexes.len()).map(|id| Allowed::Some(vec![TerminalId(id)])));
+        for input in inputs {
+            for allowed in allowed.iter() {
+                for last in [None, Some('a'), Some('\n')] {
+                    assert_eq!(
+                        summary(&dfa, input, last, allowed),
+                        summary(&vm, input, last, allowed),
+                        "{regexes:?} on {input:?} after {last:?}, allowing {allowed:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dfa() {
+        same_matches(
+            &["if", "[a-z]+", r"[0-9]+(\.[0-9]+)?", r"\s+", "(.)"],
+            &["if", "iffy", "if x", "3.14", "3.", "  \tx", "é", "", "éa"],
+        );
+        same_matches(
+            &[r"if\b", r"\w+", r"a\Z", r"\d", "[^a-c]*"],
+            &["if", "ifa", "if+", "a", "ab", "42", "deé", "日本", "x\n"],
+        );
+        same_matches(
+            &[r"/\*([^*]|\*[^/])*\*/", "/", r"\b"],
+            &["/* a */b", "/* a", "/", "", "é/"],
+        );
+        same_matches(
+            &[r"^#[a-z]+$", r"\bif\b", "[a-z]+", r"\s+", r"\n^", r"$\n?"],
+            &["#if", "#if\n", "#if x", "if", "ifx", " if", "\n\n", "", "\nif"],
+        );
+    }
+
+    /// Find the matches of `regex` in `text` one after the other, as a lexer does,
+    /// and return their lengths, and how many chars the scans went through.
+    fn find_all(regex: &CompiledRegex, text: &str) -> (Vec<usize>, usize) {
+        let mut failures = Failures::new();
+        let mut lengths = Vec::new();
+        let (mut position, mut rest, mut last) = (0, text, None);
+        while let Some(found) =
+            regex.find_at(rest, position, last, &Allowed::All, &mut failures)
+        {
+            let expected = regex.find_after(rest, last, &Allowed::All).unwrap();
+            assert_eq!(found.chars_length(), expected.chars_length());
+            assert_eq!(found.id(), expected.id());
+            if found.chars_length() == 0 {
+                break;
+            }
+            lengths.push(found.chars_length());
+            position += found.chars_length();
+            let (matched, remaining) = rest.split_at(
+                rest.char_indices()
+                    .nth(found.chars_length())
+                    .map_or(rest.len(), |(i, _)| i),
+            );
+            last = matched.chars().next_back();
+            rest = remaining;
+        }
+        (lengths, failures.scanned())
+    }
+
+    #[test]
+    fn linear() {
+        let length = 2000;
+        let text = "a".repeat(length);
+        for regexes in [&["a", "a*b"][..], &["a", "(a|aa)*c"], &["(a*)*b", "a", r"\w+\b!"]] {
+            let mut builder = RegexBuilder::new();
+            for (i, regex) in regexes.iter().enumerate() {
+                builder = builder
+                    .with_named_regex(regex, format!("R{i}"), false, false)
+                    .unwrap();
+            }
+            let regex = builder.build();
+            assert!(regex.has_dfa());
+            let (lengths, scanned) = find_all(&regex, &text);
+            assert_eq!(lengths, vec![1; length], "{regexes:?}");
+            // Without remembering the failures, each scan would go to the end.
+            assert!(scanned <= 4 * length, "{regexes:?} went through {scanned} chars");
+        }
+        let regex = RegexBuilder::new()
+            .with_named_regex("[a-z]+", String::from("ID"), false, false)
+            .unwrap()
+            .with_named_regex(" ", String::from("SPACE"), false, false)
+            .unwrap()
+            .build();
+        let (lengths, _) = find_all(&regex, "ab c  def");
+        assert_eq!(lengths, [2, 1, 1, 1, 1, 3]);
+    }
+
+    #[test]
+    fn may_extend() {
+        let regex = RegexBuilder::new()
+            .with_named_regex(r#""[a-z]*""#, String::from("STRING"), false, false)
+            .unwrap()
+            .with_named_regex("[0-9]+", String::from("INT"), false, false)
+            .unwrap()
+            .build();
+        let string = Allowed::Some(vec![TerminalId(0)]);
+        assert!(regex.may_extend("", None, &Allowed::All));
+        assert!(regex.may_extend("\"ab", None, &Allowed::All));
+        assert!(regex.may_extend("\"ab", None, &string));
+        assert!(!regex.may_extend("\"ab", None, &Allowed::Some(vec![TerminalId(1)])));
+        assert!(!regex.may_extend("\"a1", None, &string));
+        assert!(!regex.may_extend("+", None, &Allowed::All));
+    }
+}
+
+/// The side of a position of the input, either the char before it or the one
+/// after it, as far as the instructions that do not consume a char care.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Side {
+    /// The position is the start, or the end, of the input.
+    None,
+    Word,
+    Other,
+    Newline,
+}
+
+impl Side {
+    const ALL: [Self; 4] = [Side::None, Side::Word, Side::Other, Side::Newline];
+
+    fn of(chr: char) -> Self {
+        if is_word_char(chr) {
+            Side::Word
+        } else if chr == '\n' {
+            Side::Newline
+        } else {
+            Side::Other
+        }
+    }
+
+    fn before(last: Option<char>) -> Self {
+        last.map_or(Side::None, Side::of)
+    }
+}
+
+/// The transition to the dead state, from which nothing matches anymore.
+const DEAD: u32 = u32::MAX;
+
+/// The number of states past which a program is not worth a DFA.
+const MAX_STATES: usize = 4096;
+
+#[cfg_attr(test, derive(PartialEq))]
+#[derive(Debug, Serialize, Deserialize)]
+struct State {
+    /// The index, in the live sets, of the regexes that may still match from the
+    /// state.
+    live: usize,
+    /// The regexes that match at the state, sorted, when it is followed by the end
+    /// of the input, by a word char, by any other char but a newline, and by a
+    /// newline, respectively.
+    accepts: [Vec<TerminalId>; 4],
+}
+
+/// # Summary
+///
+/// `Dfa` is a deterministic automaton equivalent to a program of the VM, which
+/// finds the same match, but not its groups, in a single pass over the input.
+///
+/// Chars are split into classes that every instruction treats alike: the intervals
+/// between `bounds`, each cut in two by whether its chars are word chars, which
+/// word boundaries depend on. A state is the set of threads of the VM past the
+/// last consumed char, along with the side of that char. The first states are the
+/// start of a match, after each side, in the order of `Side::ALL`.
+#[cfg_attr(test, derive(PartialEq))]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Dfa {
+    /// The first char of every interval but the first, sorted.
+    bounds: Vec<char>,
+    states: Vec<State>,
+    live_sets: Vec<Vec<TerminalId>>,
+    /// The transitions of state `s` on class `c` are at `s * nb_classes + c`.
+    transitions: Vec<u32>,
+}
+
+impl Dfa {
+    /// Build the DFA of `program`, whose first instruction is the `Switch` to every
+    /// regex. Return `None` if it would have too many states.
+    pub fn new(program: &Program) -> Option<Self> {
+        if program.is_empty() {
+            return None;
+        }
+        let Instruction::Switch(switch) = &program[InstructionPointer(0)] else {
+            return None;
+        };
+        let mut starts = switch.iter().map(|&(id, ip)| (ip.0, id)).collect::<Vec<_>>();
+        starts.sort_unstable();
+        let owner = |ip: usize| {
+            let i = starts.partition_point(|&(start, _)| start <= ip);
+            Some(starts[i.checked_sub(1)?].1)
+        };
+
+        let bounds = Self::bounds(program);
+        let representatives = Self::representatives(&bounds);
+        let nb_classes = representatives.len();
+        let mut dfa = Self {
+            bounds,
+            states: Vec::new(),
+            live_sets: Vec::new(),
+            transitions: Vec::new(),
+        };
+        let mut ids = HashMap::new();
+        let mut live_ids = HashMap::new();
+        let mut queue = VecDeque::new();
+        for (id, side) in Side::ALL.into_iter().enumerate() {
+            ids.insert((vec![0], side), id);
+            queue.push_back((vec![0], side));
+        }
+        while let Some((kernel, last)) = queue.pop_front() {
+            if dfa.states.len() >= MAX_STATES {
+                return None;
+            }
+            let mut live = if kernel == [0] {
+                starts.iter().map(|&(_, id)| id).collect::<Vec<_>>()
+            } else {
+                kernel.iter().filter_map(|&ip| owner(ip)).collect()
+            };
+            live.sort_unstable();
+            live.dedup();
+            let next_live = live_ids.len();
+            let live = *live_ids.entry(live.clone()).or_insert_with(|| {
+                dfa.live_sets.push(live);
+                next_live
+            });
+
+            let closures = Side::ALL.map(|next| Self::closure(program, &kernel, last, next));
+            let accepts = closures.clone().map(|closure| {
+                let mut accepts = closure
+                    .iter()
+                    .filter_map(|&ip| match &program[InstructionPointer(ip)] {
+                        Instruction::Match(id) => Some(*id),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                accepts.sort_unstable();
+                accepts
+            });
+            dfa.states.push(State { live, accepts });
+
+            for representative in representatives.iter() {
+                let Some(chr) = *representative else {
+                    dfa.transitions.push(DEAD);
+                    continue;
+                };
+                let side = Side::of(chr);
+                let closure = &closures[side as usize];
+                let mut next = closure
+                    .iter()
+                    .filter(|&&ip| Self::consumes(&program[InstructionPointer(ip)], chr))
+                    .map(|&ip| ip + 1)
+                    .collect::<Vec<_>>();
+                if next.is_empty() {
+                    dfa.transitions.push(DEAD);
+                    continue;
+                }
+                next.sort_unstable();
+                next.dedup();
+                let nb_states = ids.len();
+                let id = *ids.entry((next.clone(), side)).or_insert_with(|| {
+                    queue.push_back((next, side));
+                    nb_states
+                });
+                dfa.transitions.push(id as u32);
+            }
+        }
+        debug_assert_eq!(dfa.transitions.len(), dfa.states.len() * nb_classes);
+        Some(dfa)
+    }
+
+    /// The chars at which the instructions of `program` may start behaving
+    /// differently, sorted.
+    fn bounds(program: &Program) -> Vec<char> {
+        fn after(chr: char) -> Option<char> {
+            char::from_u32(chr as u32 + 1).or((chr == '\u{d7ff}').then_some('\u{e000}'))
+        }
+        let mut bounds = vec!['\t', '\n', '\u{b}', ' ', '!', '0', ':'];
+        for instruction in program.iter() {
+            match instruction {
+                Instruction::Char(chr) => {
+                    bounds.push(*chr);
+                    bounds.extend(after(*chr));
+                }
+                Instruction::CharacterClass(class, _) => {
+                    let everything: (Bound<char>, Bound<char>) =
+                        (Bound::Unbounded, Bound::Unbounded);
+                    for (start, end) in class.get_interval_overlaps(&everything) {
+                        bounds.extend(match start {
+                            Bound::Included(start) => Some(*start),
+                            Bound::Excluded(start) => after(*start),
+                            Bound::Unbounded => None,
+                        });
+                        bounds.extend(match end {
+                            Bound::Included(end) => after(*end),
+                            Bound::Excluded(end) => Some(*end),
+                            Bound::Unbounded => None,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        bounds.sort_unstable();
+        bounds.dedup();
+        bounds
+    }
+
+    /// A char of every class, if it has any: the class `2 * i + word` holds the
+    /// word chars of the interval `i` if `word` is 1, and its other chars otherwise.
+    fn representatives(bounds: &[char]) -> Vec<Option<char>> {
+        let starts = std::iter::once('\0').chain(bounds.iter().copied());
+        let ends = bounds.iter().map(|&bound| Some(bound)).chain([None]);
+        starts
+            .zip(ends)
+            .flat_map(|(start, end)| {
+                let mut chars = (start..=char::MAX).take_while(move |&chr| Some(chr) != end);
+                let other = chars.clone().find(|&chr| !is_word_char(chr));
+                let word = chars.find(|&chr| is_word_char(chr));
+                [other, word]
+            })
+            .collect()
+    }
+
+    /// Write the states of the DFA to `f`: for each, the regexes that may still
+    /// match from it, the ones that match there depending on what follows, and its
+    /// transitions on each class of chars. Regexes are written as `name` calls them.
+    pub fn dump<'a>(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        name: impl Fn(TerminalId) -> &'a str,
+    ) -> fmt::Result {
+        const STARTS: [&str; 4] = [
+            "at the start of the input",
+            "after a word char",
+            "after another char",
+            "after a newline",
+        ];
+        const FOLLOWS: [&str; 4] = [
+            "before the end of the input",
+            "before a word char",
+            "before another char",
+            "before a newline",
+        ];
+        let names = |ids: &[TerminalId]| ids.iter().map(|&id| name(id)).collect::<Vec<_>>();
+        let representatives = Self::representatives(&self.bounds);
+        let nb_classes = representatives.len();
+        // Classes are written as their interval, told apart by the kind of their
+        // chars when the interval has both word chars and other chars.
+        let classes = representatives
+            .chunks(2)
+            .enumerate()
+            .flat_map(|(i, halves)| {
+                let start = if i == 0 { '\0' } else { self.bounds[i - 1] };
+                let end = self.bounds.get(i).map_or(char::MAX, |&bound| {
+                    char::from_u32(bound as u32 - 1).unwrap_or('\u{d7ff}')
+                });
+                let interval = if start == end {
+                    format!("{start:?}")
+                } else {
+                    format!("{start:?}..={end:?}")
+                };
+                let mixed = halves.iter().all(Option::is_some);
+                ["other", "word"].map(|kind| {
+                    if mixed {
+                        format!("{interval} ({kind} chars)")
+                    } else {
+                        interval.clone()
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        writeln!(f, "{} states", self.states.len())?;
+        for (id, state) in self.states.iter().enumerate() {
+            match STARTS.get(id) {
+                Some(start) => writeln!(f, "state {id}, which starts matches {start}")?,
+                None => writeln!(f, "state {id}")?,
+            }
+            writeln!(f, "  may match {}", names(&self.live_sets[state.live]).join(", "))?;
+            for (accepts, follow) in state.accepts.iter().zip(FOLLOWS) {
+                if !accepts.is_empty() {
+                    writeln!(f, "  matches {} {follow}", names(accepts).join(", "))?;
+                }
+            }
+            let mut targets: BTreeMap<u32, Vec<&str>> = BTreeMap::new();
+            for (class, description) in classes.iter().enumerate() {
+                match self.transitions[id * nb_classes + class] {
+                    DEAD => {}
+                    target => targets.entry(target).or_default().push(description),
+                }
+            }
+            for (target, classes) in targets {
+                writeln!(f, "  goes to state {target} on {}", classes.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn class(&self, chr: char) -> usize {
+        2 * self.bounds.partition_point(|&bound| bound <= chr) + usize::from(is_word_char(chr))
+    }
+
+    /// The threads that `kernel` leads to without consuming a char, between a
+    /// char on the `last` side and one on the `next` side: the ones that consume
+    /// a char, and the ones that match.
+    fn closure(program: &Program, kernel: &[usize], last: Side, next: Side) -> Vec<usize> {
+        let mut done = vec![false; program.len()];
+        let mut stack = kernel.to_vec();
+        let mut closure = Vec::new();
+        while let Some(ip) = stack.pop() {
+            if std::mem::replace(&mut done[ip], true) {
+                continue;
+            }
+            match &program[InstructionPointer(ip)] {
+                Instruction::Switch(targets) => {
+                    stack.extend(targets.iter().map(|(_, target)| target.0));
+                }
+                Instruction::Save(_) => stack.push(ip + 1),
+                Instruction::Split(first, second) => stack.extend([first.0, second.0]),
+                Instruction::Jump(target) => stack.push(target.0),
+                Instruction::WordBoundary => {
+                    if (last == Side::Word) != (next == Side::Word) {
+                        stack.push(ip + 1);
+                    }
+                }
+                Instruction::LineStart => {
+                    if matches!(last, Side::None | Side::Newline) {
+                        stack.push(ip + 1);
+                    }
+                }
+                Instruction::LineEnd => {
+                    if matches!(next, Side::None | Side::Newline) {
+                        stack.push(ip + 1);
+                    }
+                }
+                Instruction::EOF => {
+                    if next == Side::None {
+                        stack.push(ip + 1);
+                    }
+                }
+                _ => closure.push(ip),
+            }
+        }
+        closure.sort_unstable();
+        closure
+    }
+
+    /// Whether `instruction` consumes `chr`.
+    fn consumes(instruction: &Instruction, chr: char) -> bool {
+        match instruction {
+            Instruction::Char(expected) => *expected == chr,
+            Instruction::Any => true,
+            Instruction::WordChar => is_word_char(chr),
+            Instruction::Digit => chr.is_ascii_digit(),
+            Instruction::Whitespace => chr == ' ' || chr == '\t',
+            Instruction::CharacterClass(class, negated) => negated ^ class.contains_point(&chr),
+            _ => false,
+        }
+    }
+
+    /// The pairs of regexes that both match a same text, when followed by a same
+    /// char, the first regex of each pair first.
+    pub fn ties(&self) -> BTreeSet<(TerminalId, TerminalId)> {
+        let mut ties = BTreeSet::new();
+        for accepts in self.states.iter().flat_map(|state| &state.accepts) {
+            for (i, &first) in accepts.iter().enumerate() {
+                ties.extend(accepts[i + 1..].iter().map(|&second| (first, second)));
+            }
+        }
+        ties
+    }
+
+    /// Find the longest match of an `allowed` regex at the start of `input`, which
+    /// follows the char `last` of the text, the first regex winning ties. Return its
+    /// length in chars.
+    ///
+    /// With `memory`, `input` is what is left of a text from the char `position` on,
+    /// and the scan stops at the states that `failures` knows to lead to no match
+    /// from where they are, and remembers the ones it finds.
+    pub fn find(
+        &self,
+        input: &str,
+        last: Option<char>,
+        allowed: &Allowed,
+        memory: Option<(usize, &mut Failures)>,
+    ) -> Option<(usize, TerminalId)> {
+        let nb_classes = 2 * (self.bounds.len() + 1);
+        let first_allowed =
+            |ids: &[TerminalId]| ids.iter().copied().find(|&id| allowed.contains(id));
+        let mut state = Side::before(last) as usize;
+        let mut checked_live = usize::MAX;
+        let mut best = None;
+        let mut chars_pos = 0;
+        let mut chars = input.chars();
+        // The states gone through since the last match, from which there is none.
+        let mut trail = Vec::new();
+        loop {
+            if let Some((position, failures)) = &memory {
+                if failures.pairs.contains(&(state, position + chars_pos)) {
+                    break;
+                }
+                trail.push((state, chars_pos));
+            }
+            let current = &self.states[state];
+            if current.live != checked_live {
+                if first_allowed(&self.live_sets[current.live]).is_none() {
+                    break;
+                }
+                checked_live = current.live;
+            }
+            let Some(chr) = chars.next() else {
+                if let Some(id) = first_allowed(&current.accepts[Side::None as usize]) {
+                    best = Some((chars_pos, id));
+                    trail.clear();
+                }
+                break;
+            };
+            if let Some(id) = first_allowed(&current.accepts[Side::of(chr) as usize]) {
+                best = Some((chars_pos, id));
+                trail.clear();
+            }
+            match self.transitions[state * nb_classes + self.class(chr)] {
+                DEAD => break,
+                next => state = next as usize,
+            }
+            chars_pos += 1;
+        }
+        if let Some((position, failures)) = memory {
+            failures.scanned += chars_pos;
+            failures
+                .pairs
+                .extend(trail.into_iter().map(|(state, pos)| (state, position + pos)));
+        }
+        best
+    }
+
+    /// Return whether an `allowed` regex may match a text that starts with `input`,
+    /// which follows the char `last`, once more chars follow `input`.
+    pub fn may_extend(&self, input: &str, last: Option<char>, allowed: &Allowed) -> bool {
+        let nb_classes = 2 * (self.bounds.len() + 1);
+        let mut state = Side::before(last) as usize;
+        for chr in input.chars() {
+            match self.transitions[state * nb_classes + self.class(chr)] {
+                DEAD => return false,
+                next => state = next as usize,
+            }
+        }
+        self.live_sets[self.states[state].live]
+            .iter()
+            .any(|&id| allowed.contains(id))
+    }
+}
@@ -79,6 +79,13 @@ mod tests {
                 r".\b.",
                 vec![("a ", true), (" a", true), ("  ", false), ("aa", false)],
             ),
+            (r"\D", vec![("a", true), ("0", false)]),
+            (r"\W", vec![("%", true), ("é", false), ("_", false)]),
+            (r"\S", vec![("\n", true), (" ", false), ("\t", false)]),
+            (r"[\d_]", vec![("0", true), ("_", true), ("a", false)]),
+            (r"[\w\-]", vec![("é", true), ("-", true), ("%", false)]),
+            (r"[^\s\d]", vec![("a", true), (" ", false), ("1", false)]),
+            (r"[\W\d]", vec![("%", true), ("\u{10ffff}", true), ("1", true), ("a", false)]),
         ];
         for (regex, tests) in escaped {
             let (program, _) = compile(regex, TerminalId(0)).unwrap();
@@ -88,6 +95,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn anchors() {
+        let tests = [
+            (r"^a", vec![("a", None, true), ("a", Some('\n'), true), ("a", Some('b'), false)]),
+            (r"a$", vec![("a", None, true), ("a\nb", None, true), ("ab", None, false)]),
+            (r"^$", vec![("", None, true), ("\n", Some('\n'), true), ("a", None, false)]),
+            (r"\ba", vec![("a", None, true), ("a", Some(' '), true), ("a", Some('b'), false)]),
+            (r"\b", vec![("", None, false), (" ", None, false), ("", Some('a'), true)]),
+        ];
+        for (regex, tests) in tests {
+            let (program, _) = compile(regex, TerminalId(0)).unwrap();
+            for (string, last, result) in tests {
+                assert_eq!(
+                    find_after(&program, string, last, 0, &Allowed::All).is_some(),
+                    result,
+                    "/{regex}/ on {string:?} after {last:?}"
+                );
+            }
+        }
+    }
+
     #[test]
     fn greedy() {
         let (program, nb_groups) = compile("(a+)(a+)", TerminalId(0)).unwrap();
@@ -101,6 +129,31 @@ mod tests {
         assert_eq!(results, vec![Some(0), Some(3), Some(3), Some(4)]);
     }
 
+    #[test]
+    fn bounded() {
+        let (program, nb_groups) = compile("a{2,3}", TerminalId(0)).unwrap();
+        let find_end = |input: &str| {
+            find(&program, input, nb_groups, &Allowed::All).map(|m| m.char_pos)
+        };
+        assert_eq!(find_end("aaaa"), Some(3));
+        assert_eq!(find_end("aab"), Some(2));
+        assert_eq!(find_end("a"), None);
+
+        let (program, nb_groups) = compile("[0-9]{2,}", TerminalId(0)).unwrap();
+        let Match { char_pos: end, .. } =
+            find(&program, "12345x", nb_groups, &Allowed::All).unwrap();
+        assert_eq!(end, 5);
+
+        let (program, nb_groups) = compile("(ab){2}", TerminalId(0)).unwrap();
+        let Match {
+            char_pos: end,
+            groups: results,
+            ..
+        } = find(&program, "ababab", nb_groups, &Allowed::All).unwrap();
+        assert_eq!(end, 4);
+        assert_eq!(results, vec![Some(2), Some(4)]);
+    }
+
     #[test]
     fn partial() {
         let (program, nb_groups) = compile("a+", TerminalId(0)).unwrap();
@@ -141,6 +194,8 @@ newty! {
 /// `Match(id: usize)`: stop the thread and record it as a successful match of the regex `id`
 /// `WordChar`: match /[A-Za-z0-9_]/ at the current location, or stop the thread if it doesn't match
 /// `WordBoundary`: match a word boundary (meaning, the end of the beginning of a word)
+/// `LineStart`: match the start of the text, or the position after a `\n`
+/// `LineEnd`: match the end of the text, or the position before a `\n`
 /// `CharacterClass(
 ///      class: IntervalTree<char>,
 ///      negated: bool
@@ -162,6 +217,8 @@ pub enum Instruction {
     WordChar,
     Digit,
     WordBoundary,
+    LineStart,
+    LineEnd,
     Whitespace,
     CharacterClass(IntervalTree<char>, bool),
     EOF,
@@ -267,6 +324,11 @@ impl ThreadList {
         }
     }
 
+    /// Return whether there is no `Thread` left to pop.
+    fn is_empty(&self) -> bool {
+        self.threads.is_empty()
+    }
+
     /// Pop a `Thread` from the `ThreadList`. This will **not** make the `ThreadList` accept the same `Thread` again.
     fn get(&mut self) -> Option<Thread> {
         self.threads.pop()
@@ -332,6 +394,12 @@ impl Thread {
     }
 }
 
+/// Return whether `chr` is a word char,
+/// matched by /[a-zA-Z0-9_]/.
+pub fn is_word_char(chr: char) -> bool {
+    chr.is_alphanumeric() || chr == '_'
+}
+
 /// Execute a single instruction for `thread`, in a given context.
 #[allow(clippy::too_many_arguments)]
 fn match_next(
@@ -346,12 +414,6 @@ fn match_next(
     last: Option<char>,
     allowed: &Allowed,
 ) {
-    /// Return whether `chr` is a word char,
-    /// matched by /[a-zA-Z0-9_]/.
-    fn is_word_char(chr: char) -> bool {
-        chr.is_alphanumeric() || chr == '_'
-    }
-
     /// Return whether `chr` is a digit,
     /// matched by /[0-9]/.
     fn is_digit(chr: char) -> bool {
@@ -449,11 +511,19 @@ fn match_next(
             }
         }
         Instruction::WordBoundary => {
-            if let Some(last) = last {
-                if is_word_char(last) ^ is_word_char(chr) {
-                    advance(thread, Some(current));
-                }
-            } else {
+            // The start and the end of the text are not word chars.
+            let after = next.is_some() && is_word_char(chr);
+            if last.is_some_and(is_word_char) ^ after {
+                advance(thread, Some(current));
+            }
+        }
+        Instruction::LineStart => {
+            if matches!(last, None | Some('\n')) {
+                advance(thread, Some(current));
+            }
+        }
+        Instruction::LineEnd => {
+            if next.is_none() || chr == '\n' {
                 advance(thread, Some(current));
             }
         }
@@ -465,14 +535,98 @@ fn match_next(
     }
 }
 
+/// Walk the program `prog` from the start of the regex `id` to build a text it
+/// matches, picking a branch of every split with `choose`, which is given the number
+/// of branches and returns the one to follow. Anchors and word boundaries are not
+/// checked, so the text may not actually match. Return `None` if the walk does not
+/// end within `max_steps` instructions, or ends on another regex.
+pub fn sample(
+    prog: &ProgramSlice,
+    id: TerminalId,
+    choose: &mut dyn FnMut(usize) -> usize,
+    max_steps: usize,
+) -> Option<String> {
+    /// Pick a printable ASCII char that satisfies `accepts`.
+    fn pick(
+        accepts: impl Fn(char) -> bool,
+        choose: &mut dyn FnMut(usize) -> usize,
+    ) -> Option<char> {
+        let chars: Vec<char> = (' '..='~').filter(|&chr| accepts(chr)).collect();
+        if chars.is_empty() {
+            None
+        } else {
+            chars.get(choose(chars.len())).copied()
+        }
+    }
+
+    let mut text = String::new();
+    let mut ip = InstructionPointer(0);
+    for _ in 0..max_steps {
+        if ip.0 >= prog.len() {
+            return None;
+        }
+        let chr = match &prog[ip] {
+            Instruction::Switch(entries) => {
+                let &(_, start) = entries.iter().find(|&&(entry, _)| entry == id)?;
+                ip = start;
+                continue;
+            }
+            Instruction::Split(left, right) => {
+                ip = if choose(2) == 0 { *left } else { *right };
+                continue;
+            }
+            Instruction::Jump(target) => {
+                ip = *target;
+                continue;
+            }
+            Instruction::Match(matched) => return (*matched == id).then_some(text),
+            Instruction::Char(chr) => Some(*chr),
+            Instruction::Any => pick(|_| true, choose),
+            Instruction::WordChar => pick(is_word_char, choose),
+            Instruction::Digit => pick(|chr| chr.is_ascii_digit(), choose),
+            Instruction::Whitespace => Some(' '),
+            // A class of chars none of which are printable ASCII gives up.
+            Instruction::CharacterClass(class, negated) => {
+                let chr = pick(|chr| negated ^ class.contains_point(&chr), choose)?;
+                Some(chr)
+            }
+            Instruction::Save(_)
+            | Instruction::WordBoundary
+            | Instruction::LineStart
+            | Instruction::LineEnd
+            | Instruction::EOF => None,
+        };
+        text.extend(chr);
+        ip = ip.incr();
+    }
+    None
+}
+
 /// Simulate a VM with program `prog` on `input`. There should be `size` groups.
+#[cfg(test)]
 pub fn find(prog: &ProgramSlice, input: &str, size: usize, allowed: &Allowed) -> Option<Match> {
+    find_after(prog, input, None, size, allowed)
+}
+
+/// Simulate a VM with program `prog` on `input`, which follows the char `last` in
+/// the text, if it is not its start. There should be `size` groups.
+pub fn find_after(
+    prog: &ProgramSlice,
+    input: &str,
+    mut last: Option<char>,
+    size: usize,
+    allowed: &Allowed,
+) -> Option<Match> {
     let mut current =
         ThreadList::from(vec![Thread::new(InstructionPointer(0), size)], prog.len());
     let mut best_match = None;
-    let mut last = None;
     let mut bytes_pos = 0;
-    for (chars_pos, chr) in input.chars().enumerate() {
+    let mut chars_pos = 0;
+    for chr in input.chars() {
+        // Once every thread has stopped, nothing can match anymore.
+        if current.is_empty() {
+            return best_match;
+        }
         let mut next = ThreadList::new(prog.len());
         while let Some(thread) = current.get() {
             match_next(
@@ -491,8 +645,8 @@ pub fn find(prog: &ProgramSlice, input: &str, size: usize, allowed: &Allowed) ->
         current = next;
         last = Some(chr);
         bytes_pos += chr.len_utf8();
+        chars_pos += 1;
     }
-    let chars_pos = input.len();
     while let Some(thread) = current.get() {
         match_next(
             '#',
@@ -1,9 +1,19 @@
 use std::{iter::Enumerate, str::Chars};
 
-use super::matching::{Instruction, Program};
+use super::matching::{is_word_char, Instruction, Program};
 use crate::regex::matching::InstructionPointer;
+use either::Either;
+use std::ops::Bound::{Included, Unbounded};
+use std::sync::OnceLock;
 use unbounded_interval_tree::interval_tree::IntervalTree;
 
+/// The largest bound of a repetition, past which programs would be too large.
+const MAX_REPETITIONS: usize = 1000;
+
+/// The largest range of a character class whose every char gets its other case,
+/// when matching regardless of case. Only the ASCII letters of larger ones do.
+const MAX_FOLDED_RANGE: u32 = 1024;
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -80,6 +90,25 @@ pub mod tests {
         assert_eq!(read(r"\b", 0).unwrap(), (WordBoundary, 0));
     }
 
+    #[test]
+    fn read_anchors() {
+        use Regex::*;
+        assert_eq!(
+            read(r"^a$", 0).unwrap(),
+            (
+                Concat(
+                    Box::new(Concat(Box::new(LineStart), Box::new(Char('a')))),
+                    Box::new(LineEnd)
+                ),
+                0
+            )
+        );
+        assert_eq!(
+            read(r"\^\$", 0).unwrap(),
+            (Concat(Box::new(Char('^')), Box::new(Char('$'))), 0)
+        );
+    }
+
     #[test]
     fn read_eof() {
         use Regex::*;
@@ -132,6 +161,18 @@ pub mod tests {
         assert_eq!(read(r"\w", 0).unwrap(), (WordChar, 0));
     }
 
+    #[test]
+    fn read_shorthand_classes() {
+        assert!(read(r"[\d\w\s\D\W\S]", 0).is_ok());
+        assert_eq!(
+            read(r"[a-\d]", 0).unwrap_err(),
+            RegexError {
+                position: 2,
+                message: String::from("A shorthand class cannot end a range."),
+            }
+        );
+    }
+
     #[test]
     #[should_panic]
     fn read_wrong_escaped() {
@@ -265,6 +306,122 @@ pub mod tests {
         }
     }
 
+    #[test]
+    fn read_bounded() {
+        use Regex::*;
+        assert_eq!(
+            read("a{3}", 0).unwrap(),
+            (Bounded(Box::new(Char('a')), 3, Some(3)), 0)
+        );
+        assert_eq!(
+            read("ab{2,}", 0).unwrap(),
+            (
+                Concat(
+                    Box::new(Char('a')),
+                    Box::new(Bounded(Box::new(Char('b')), 2, None))
+                ),
+                0
+            )
+        );
+        assert_eq!(
+            read("(a){1,5}", 0).unwrap(),
+            (Bounded(Box::new(Group(Box::new(Char('a')), 0)), 1, Some(5)), 1)
+        );
+        assert_eq!(read("{", 0).unwrap(), (Char('{'), 0));
+        assert_eq!(
+            read(r"\{1}", 0).unwrap(),
+            (
+                Concat(
+                    Box::new(Concat(Box::new(Char('{')), Box::new(Char('1')))),
+                    Box::new(Char('}'))
+                ),
+                0
+            )
+        );
+        assert!(read("a{b}", 0).is_ok());
+    }
+
+    #[test]
+    fn fold_case() {
+        use Regex::*;
+        let mut tree = IntervalTree::default();
+        tree.insert((Included('A'), Included('Z')));
+        tree.insert((Included('a'), Included('z')));
+        let (regex, _) = read("[a-zA-F]", 0).unwrap();
+        assert_eq!(case_insensitive(regex), CharacterClass(tree, false));
+        let mut tree = IntervalTree::default();
+        tree.insert((Included('0'), Included('9')));
+        tree.insert((Included('A'), Included('A')));
+        tree.insert((Included('a'), Included('a')));
+        let (regex, _) = read("[0-9a]", 0).unwrap();
+        assert_eq!(case_insensitive(regex), CharacterClass(tree, false));
+        assert_eq!(case_insensitive(Char('1')), Char('1'));
+    }
+
+    #[test]
+    fn read_named_groups() {
+        use Regex::*;
+        let (regex, groups, names) = read_named("(?P<int>a)(b)(?P<frac>c)", 2).unwrap();
+        assert_eq!(
+            regex,
+            Concat(
+                Box::new(Concat(
+                    Box::new(Group(Box::new(Char('a')), 2)),
+                    Box::new(Group(Box::new(Char('b')), 3)),
+                )),
+                Box::new(Group(Box::new(Char('c')), 4)),
+            )
+        );
+        assert_eq!(groups, 5);
+        assert_eq!(
+            names,
+            vec![(String::from("int"), 2), (String::from("frac"), 4)]
+        );
+        assert_eq!(read("(?P<a>b)", 0).unwrap(), (Group(Box::new(Char('b')), 0), 1));
+    }
+
+    #[test]
+    fn read_malformed_group_names() {
+        let wrong_regex = [
+            ("(?a)", 0),
+            ("(?P<>a)", 4),
+            ("(?P<1a>a)", 4),
+            ("(?P<a-b>a)", 5),
+            ("(?P<a", 5),
+            ("(?P<a>b)(?P<a>c)", 8),
+        ];
+        for &(regex, p) in wrong_regex.iter() {
+            if let Err(RegexError { position: pos, .. }) = read(regex, 0) {
+                assert_eq!(pos, p, "/{regex}/");
+            } else {
+                panic!("(/{}/ shouldn't succeed.", regex);
+            }
+        }
+    }
+
+    #[test]
+    fn read_malformed_bounds() {
+        let wrong_regex = [
+            ("{2}", 0),
+            ("a{3,1}", 1),
+            ("a{2", 3),
+            ("a{2x}", 3),
+            ("a{2,3,4}", 5),
+            ("a{1001}", 5),
+            ("a*{2}", 2),
+            ("a{2}{3}", 4),
+            ("a{2}?", 4),
+            ("a{2}*", 4),
+        ];
+        for &(regex, p) in wrong_regex.iter() {
+            if let Err(RegexError { position: pos, .. }) = read(regex, 0) {
+                assert_eq!(pos, p, "/{regex}/");
+            } else {
+                panic!("(/{}/ shouldn't succeed.", regex);
+            }
+        }
+    }
+
     #[test]
     fn build_basic() {
         use Instruction::*;
@@ -323,6 +480,16 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn build_anchors() {
+        use Instruction::*;
+        let program = compile(r"^$", TerminalId(0)).unwrap();
+        assert_eq!(
+            program,
+            (Program::from(vec![LineStart, LineEnd, Match(TerminalId(0))]), 0)
+        );
+    }
+
     #[test]
     fn build_eof() {
         use Instruction::*;
@@ -426,6 +593,25 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn build_bounded() {
+        use Instruction::*;
+        let program = compile("a{2,3}", TerminalId(0)).unwrap();
+        assert_eq!(
+            program,
+            (
+                Program::from(vec![
+                    Char('a'),
+                    Char('a'),
+                    Split(InstructionPointer(3), InstructionPointer(4)),
+                    Char('a'),
+                    Match(TerminalId(0))
+                ]),
+                0
+            )
+        );
+    }
+
     #[test]
     fn build_any() {
         use Instruction::*;
@@ -501,6 +687,8 @@ pub enum Regex {
     Optional(Box<Regex>),
     Repetition(Box<Regex>),
     KleeneStar(Box<Regex>),
+    /// Between a minimum and an optional maximum number of repetitions.
+    Bounded(Box<Regex>, usize, Option<usize>),
     Concat(Box<Regex>, Box<Regex>),
     Group(Box<Regex>, usize),
     CharacterClass(IntervalTree<char>, bool),
@@ -508,11 +696,47 @@ pub enum Regex {
     Digit,
     Whitespace,
     WordBoundary,
+    /// `^`: the start of the text, or of a line.
+    LineStart,
+    /// `$`: the end of the text, or of a line.
+    LineEnd,
     EOF,
     Any,
     Empty,
 }
 
+impl Clone for Regex {
+    fn clone(&self) -> Self {
+        let boxed = |regex: &Regex| Box::new(regex.clone());
+        match self {
+            Regex::Char(c) => Regex::Char(*c),
+            Regex::Option(r1, r2) => Regex::Option(boxed(r1), boxed(r2)),
+            Regex::Optional(r) => Regex::Optional(boxed(r)),
+            Regex::Repetition(r) => Regex::Repetition(boxed(r)),
+            Regex::KleeneStar(r) => Regex::KleeneStar(boxed(r)),
+            Regex::Bounded(r, min, max) => Regex::Bounded(boxed(r), *min, *max),
+            Regex::Concat(r1, r2) => Regex::Concat(boxed(r1), boxed(r2)),
+            Regex::Group(r, i) => Regex::Group(boxed(r), *i),
+            Regex::CharacterClass(class, negated) => {
+                let mut copy = IntervalTree::default();
+                for interval in class.get_interval_overlaps(&(Unbounded::<char>, Unbounded)) {
+                    copy.insert(*interval);
+                }
+                Regex::CharacterClass(copy, *negated)
+            }
+            Regex::WordChar => Regex::WordChar,
+            Regex::Digit => Regex::Digit,
+            Regex::Whitespace => Regex::Whitespace,
+            Regex::WordBoundary => Regex::WordBoundary,
+            Regex::LineStart => Regex::LineStart,
+            Regex::LineEnd => Regex::LineEnd,
+            Regex::EOF => Regex::EOF,
+            Regex::Any => Regex::Any,
+            Regex::Empty => Regex::Empty,
+        }
+    }
+}
+
 /// # Summary
 ///
 /// `RegexError` is an alias of a couple (usize, String).
@@ -587,6 +811,26 @@ pub fn build(regex: Regex, program: &mut Program) {
             build(*r, program);
             program.push(Instruction::Split(init_pos, program.len_ip().incr()));
         }
+        Regex::Bounded(r, min, max) => {
+            for _ in 0..min {
+                build((*r).clone(), program);
+            }
+            match max {
+                None => build(Regex::KleeneStar(r), program),
+                Some(max) => {
+                    // `r{0,n}` is `(r(r(...)?)?)?`, rather than `r?r?...`, so that
+                    // each repetition is only tried once the previous one matched.
+                    let mut optional = Regex::Empty;
+                    for _ in min..max {
+                        optional = Regex::Optional(Box::new(Regex::Concat(
+                            Box::new((*r).clone()),
+                            Box::new(optional),
+                        )));
+                    }
+                    build(optional, program);
+                }
+            }
+        }
         Regex::Group(r, i) => {
             program.push(Instruction::Save(2 * i));
             build(*r, program);
@@ -596,6 +840,8 @@ pub fn build(regex: Regex, program: &mut Program) {
         Regex::WordChar => program.push(Instruction::WordChar),
         Regex::Digit => program.push(Instruction::Digit),
         Regex::WordBoundary => program.push(Instruction::WordBoundary),
+        Regex::LineStart => program.push(Instruction::LineStart),
+        Regex::LineEnd => program.push(Instruction::LineEnd),
         Regex::CharacterClass(class, negated) => {
             program.push(Instruction::CharacterClass(class, negated))
         }
@@ -605,10 +851,203 @@ pub fn build(regex: Regex, program: &mut Program) {
     };
 }
 
+/// The other cases of `chr`, if they are single chars.
+fn other_cases(chr: char) -> impl Iterator<Item = char> {
+    fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+        let first = chars.next();
+        first.filter(|_| chars.next().is_none())
+    }
+    let lower = single(chr.to_lowercase());
+    let upper = single(chr.to_uppercase());
+    lower
+        .into_iter()
+        .chain(upper.filter(|&upper| Some(upper) != lower))
+        .filter(move |&other| other != chr)
+}
+
+/// Make a character class match the other case of its chars too.
+fn fold_class(class: &IntervalTree<char>) -> IntervalTree<char> {
+    let mut intervals = Vec::new();
+    for interval in class.get_interval_overlaps(&(Unbounded::<char>, Unbounded)) {
+        // Classes are read with inclusive bounds.
+        let &(Included(start), Included(end)) = interval else {
+            unreachable!("{interval:?}")
+        };
+        intervals.push((start, end));
+        if end as u32 - start as u32 <= MAX_FOLDED_RANGE {
+            for chr in start..=end {
+                intervals.extend(other_cases(chr).map(|other| (other, other)));
+            }
+        } else {
+            for (from, to) in [('a', 'A'), ('A', 'a')] {
+                let (low, high) = (start.max(from), end.min((from as u8 + 25) as char));
+                if low <= high {
+                    let shift = |c: char| (c as u8 - from as u8 + to as u8) as char;
+                    intervals.push((shift(low), shift(high)));
+                }
+            }
+        }
+    }
+    merge(intervals)
+}
+
+/// Build the character class of the chars in any of `intervals`, which may overlap.
+fn merge(mut intervals: Vec<(char, char)>) -> IntervalTree<char> {
+    intervals.sort_unstable();
+    let mut merged: Vec<(char, char)> = Vec::new();
+    for (start, end) in intervals {
+        match merged.last_mut() {
+            Some((_, last)) if start as u32 <= *last as u32 + 1 => *last = (*last).max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    let mut class = IntervalTree::default();
+    for (start, end) in merged {
+        class.insert((Included(start), Included(end)));
+    }
+    class
+}
+
+/// The chars of the shorthand class `\{shorthand}`, as sorted disjoint intervals.
+/// `\d`, `\w` and `\s` match the same chars as they do outside of character classes,
+/// and `\D`, `\W` and `\S` every other char.
+fn shorthand_intervals(shorthand: char) -> Vec<(char, char)> {
+    static WORD_CHARS: OnceLock<Vec<(char, char)>> = OnceLock::new();
+    let intervals = match shorthand.to_ascii_lowercase() {
+        'd' => vec![('0', '9')],
+        's' => vec![('\t', '\t'), (' ', ' ')],
+        'w' => WORD_CHARS
+            .get_or_init(|| {
+                let mut intervals: Vec<(char, char)> = Vec::new();
+                for chr in (0..=char::MAX as u32).filter_map(char::from_u32) {
+                    if !is_word_char(chr) {
+                        continue;
+                    }
+                    match intervals.last_mut() {
+                        Some((_, end)) if *end as u32 + 1 == chr as u32 => *end = chr,
+                        _ => intervals.push((chr, chr)),
+                    }
+                }
+                intervals
+            })
+            .clone(),
+        _ => unreachable!("\\{shorthand} is not a shorthand class"),
+    };
+    if shorthand.is_ascii_lowercase() {
+        return intervals;
+    }
+    let mut complement = Vec::new();
+    let mut next = Some('\0');
+    for (start, end) in intervals {
+        if let Some(from) = next.filter(|&from| from < start) {
+            let before = char::from_u32(start as u32 - 1).unwrap_or('\u{d7ff}');
+            complement.push((from, before));
+        }
+        next = char::from_u32(end as u32 + 1).or((end == '\u{d7ff}').then_some('\u{e000}'));
+    }
+    complement.extend(next.map(|from| (from, char::MAX)));
+    complement
+}
+
+/// Make `regex` match regardless of case: each char also matches its other case,
+/// if it is a single char.
+pub fn case_insensitive(regex: Regex) -> Regex {
+    let fold = |regex: Box<Regex>| Box::new(case_insensitive(*regex));
+    match regex {
+        Regex::Char(chr) if other_cases(chr).next().is_none() => Regex::Char(chr),
+        Regex::Char(chr) => {
+            let mut class = IntervalTree::default();
+            class.insert((Included(chr), Included(chr)));
+            Regex::CharacterClass(fold_class(&class), false)
+        }
+        Regex::CharacterClass(class, negated) => {
+            Regex::CharacterClass(fold_class(&class), negated)
+        }
+        Regex::Option(r1, r2) => Regex::Option(fold(r1), fold(r2)),
+        Regex::Optional(r) => Regex::Optional(fold(r)),
+        Regex::Repetition(r) => Regex::Repetition(fold(r)),
+        Regex::KleeneStar(r) => Regex::KleeneStar(fold(r)),
+        Regex::Bounded(r, min, max) => Regex::Bounded(fold(r), min, max),
+        Regex::Concat(r1, r2) => Regex::Concat(fold(r1), fold(r2)),
+        Regex::Group(r, i) => Regex::Group(fold(r), i),
+        regex @ (Regex::WordChar
+        | Regex::Digit
+        | Regex::Whitespace
+        | Regex::WordBoundary
+        | Regex::LineStart
+        | Regex::LineEnd
+        | Regex::EOF
+        | Regex::Any
+        | Regex::Empty) => regex,
+    }
+}
+
+/// Parse a regex, like [`read_named`], leaving out the names of its groups.
+#[cfg(test)]
+pub fn read(regex: &str, groups: usize) -> Result<(Regex, usize), RegexError> {
+    read_named(regex, groups).map(|(regex, groups, _)| (regex, groups))
+}
+
+/// The name of a named group `(?P<name>...)`, with its number.
+pub type GroupName = (String, usize);
+
 /// Parse a regex. The parsing technique is quite efficient,
-/// essentially linear time.
+/// essentially linear time. Return the number of the next group, and the name
+/// of each named group `(?P<name>...)`, with its number.
 /// **This is a private function, please use the API instead.**
-pub fn read(regex: &str, mut groups: usize) -> Result<(Regex, usize), RegexError> {
+pub fn read_named(
+    regex: &str,
+    mut groups: usize,
+) -> Result<(Regex, usize, Vec<GroupName>), RegexError> {
+    /// Parse the name of a group, after `(?`, up to the closing `>`.
+    fn read_group_name(
+        input: &mut Enumerate<Chars<'_>>,
+        size: usize,
+        actual: usize,
+    ) -> Result<String, RegexError> {
+        if !matches!(input.next(), Some((_, 'P'))) || !matches!(input.next(), Some((_, '<'))) {
+            return Err(RegexError {
+                position: actual,
+                message: String::from(
+                    "Expected a named group (?P<name>...), try /\\?/ to match '?'",
+                ),
+            });
+        }
+        let mut name = String::new();
+        for (pos, chr) in input.by_ref() {
+            match chr {
+                '>' if name.is_empty() => {
+                    return Err(RegexError {
+                        position: pos,
+                        message: String::from("The name of a group cannot be empty."),
+                    })
+                }
+                '>' => return Ok(name),
+                c if c.is_ascii_alphanumeric() || c == '_' => {
+                    if name.is_empty() && c.is_ascii_digit() {
+                        return Err(RegexError {
+                            position: pos,
+                            message: String::from(
+                                "The name of a group cannot start with a digit.",
+                            ),
+                        });
+                    }
+                    name.push(c)
+                }
+                c => {
+                    return Err(RegexError {
+                        position: pos,
+                        message: format!("Unexpected character {c:?} in the name of a group."),
+                    })
+                }
+            }
+        }
+        Err(RegexError {
+            position: size,
+            message: String::from("Expected '>' after the name of a group, but found EOF."),
+        })
+    }
+
     /// Parse a character class.
     fn read_char_class(
         input: &mut std::iter::Enumerate<std::str::Chars<'_>>,
@@ -646,19 +1085,25 @@ pub fn read(regex: &str, mut groups: usize) -> Result<(Regex, usize), RegexError
         let mut last = None;
         let mut overlaps = Vec::new();
         let mut negated = false;
+        // The chars of the shorthand classes, which may overlap with anything.
+        let mut shorthands = Vec::new();
 
+        /// Read an escaped char, or the intervals of a shorthand class.
         fn read_escaped_char(
             input: &mut Enumerate<Chars<'_>>,
             pos: usize,
-        ) -> Result<char, RegexError> {
+        ) -> Result<Either<char, Vec<(char, char)>>, RegexError> {
             if let Some((pos, c)) = input.next() {
                 match c {
-                    ']' => Ok(c),
-                    't' => Ok('\t'),
-                    'n' => Ok('\n'),
-                    '^' => Ok('^'),
-                    '-' => Ok('-'),
-                    '\\' => Ok('\\'),
+                    ']' => Ok(Either::Left(c)),
+                    't' => Ok(Either::Left('\t')),
+                    'n' => Ok(Either::Left('\n')),
+                    '^' => Ok(Either::Left('^')),
+                    '-' => Ok(Either::Left('-')),
+                    '\\' => Ok(Either::Left('\\')),
+                    'd' | 'w' | 's' | 'D' | 'W' | 'S' => {
+                        Ok(Either::Right(shorthand_intervals(c)))
+                    }
                     _ => {
                         Err(RegexError {
                             position: pos,
@@ -682,10 +1127,13 @@ pub fn read(regex: &str, mut groups: usize) -> Result<(Regex, usize), RegexError
         while let Some((pos, chr)) = input.next() {
             match chr {
                 '\\' => {
-                    if let Some(cr) = last {
+                    if let Some(cr) = last.take() {
                         insert((Included(cr), Included(cr)), &mut tree, &mut overlaps);
                     }
-                    last = Some(read_escaped_char(input, pos)?);
+                    match read_escaped_char(input, pos)? {
+                        Either::Left(chr) => last = Some(chr),
+                        Either::Right(intervals) => shorthands.extend(intervals),
+                    }
                 }
                 '^' if pos == actual + 1 => {
                     negated = true;
@@ -694,7 +1142,17 @@ pub fn read(regex: &str, mut groups: usize) -> Result<(Regex, usize), RegexError
                     if let Some(c1) = last {
                         if let Some((_, c2)) = input.next() {
                             let chr = if c2 == '\\' {
-                                read_escaped_char(input, pos)?
+                                match read_escaped_char(input, pos)? {
+                                    Either::Left(chr) => chr,
+                                    Either::Right(_) => {
+                                        return Err(RegexError {
+                                            position: pos,
+                                            message: String::from(
+                                                "A shorthand class cannot end a range.",
+                                            ),
+                                        })
+                                    }
+                                }
                             } else {
                                 c2
                             };
@@ -735,8 +1193,17 @@ pub fn read(regex: &str, mut groups: usize) -> Result<(Regex, usize), RegexError
                             position: pos + 1,
                             message: err_str,
                         });
-                    } else {
+                    } else if shorthands.is_empty() {
                         return Ok(Regex::CharacterClass(tree, negated));
+                    } else {
+                        let everything: (Bound<char>, Bound<char>) = (Unbounded, Unbounded);
+                        for interval in tree.get_interval_overlaps(&everything) {
+                            let &(Included(start), Included(end)) = interval else {
+                                unreachable!("{interval:?}")
+                            };
+                            shorthands.push((start, end));
+                        }
+                        return Ok(Regex::CharacterClass(merge(shorthands), negated));
                     }
                 }
                 c => {
@@ -783,6 +1250,10 @@ pub fn read(regex: &str, mut groups: usize) -> Result<(Regex, usize), RegexError
                 position: pos,
                 message: String::from("Cannot apply kleene star and repetition."),
             }),
+            Regex::Bounded(..) => Err(RegexError {
+                position: pos,
+                message: String::from("Cannot apply kleene star and bounded repetition."),
+            }),
             r => Ok(Regex::KleeneStar(Box::new(r))),
         }
     }
@@ -807,6 +1278,10 @@ pub fn read(regex: &str, mut groups: usize) -> Result<(Regex, usize), RegexError
                 position: pos,
                 message: String::from("Cannot apply repetition and kleene star."),
             }),
+            Regex::Bounded(..) => Err(RegexError {
+                position: pos,
+                message: String::from("Cannot apply repetition and bounded repetition."),
+            }),
             r => Ok(Regex::Repetition(Box::new(r))),
         }
     }
@@ -831,16 +1306,124 @@ pub fn read(regex: &str, mut groups: usize) -> Result<(Regex, usize), RegexError
                 position: pos,
                 message: String::from("Non-greedy repetition is not supported."),
             }),
+            Regex::Bounded(..) => Err(RegexError {
+                position: pos,
+                message: String::from("Non-greedy bounded repetition is not supported."),
+            }),
             r => Ok(Regex::Optional(Box::new(r))),
         }
     }
 
+    fn bounded(
+        exp: Regex,
+        min: usize,
+        max: Option<usize>,
+        pos: usize,
+    ) -> Result<Regex, RegexError> {
+        match exp {
+            Regex::Concat(r1, r2) => Ok(Regex::Concat(
+                r1,
+                Box::new(bounded(*r2, min, max, pos)?),
+            )),
+            Regex::Option(r1, r2) => Ok(Regex::Option(
+                r1,
+                Box::new(bounded(*r2, min, max, pos)?),
+            )),
+            Regex::Empty => Err(RegexError {
+                position: pos,
+                message: String::from("Cannot apply bounded repetition to empty regex."),
+            }),
+            Regex::KleeneStar(..) | Regex::Optional(..) | Regex::Repetition(..) => {
+                Err(RegexError {
+                    position: pos,
+                    message: String::from("Cannot apply bounded repetition to a quantifier."),
+                })
+            }
+            Regex::Bounded(..) => Err(RegexError {
+                position: pos,
+                message: String::from("Cannot apply bounded repetition twice."),
+            }),
+            r => Ok(Regex::Bounded(Box::new(r), min, max)),
+        }
+    }
+
+    /// Parse the bounds of a repetition, `{n}`, `{n,}` or `{n,m}`, the opening
+    /// brace being at `start`.
+    fn read_bounds(
+        input: &mut Enumerate<Chars<'_>>,
+        size: usize,
+        start: usize,
+    ) -> Result<(usize, Option<usize>), RegexError> {
+        // The lower bound, once the comma is read, and the bound being read.
+        let mut lower = None;
+        let mut bound: Option<usize> = None;
+        for (pos, chr) in input.by_ref() {
+            match chr {
+                '0'..='9' => {
+                    let digit = chr.to_digit(10).unwrap() as usize;
+                    let value = bound.unwrap_or(0) * 10 + digit;
+                    if value > MAX_REPETITIONS {
+                        return Err(RegexError {
+                            position: pos,
+                            message: format!(
+                                "Bounds of repetitions cannot exceed {MAX_REPETITIONS}."
+                            ),
+                        });
+                    }
+                    bound = Some(value);
+                }
+                ',' if lower.is_none() && bound.is_some() => lower = bound.take(),
+                '}' => {
+                    let (min, max) = match (lower, bound) {
+                        (Some(min), max) => (min, max),
+                        (None, Some(bound)) => (bound, Some(bound)),
+                        (None, None) => unreachable!("bounds start with a digit"),
+                    };
+                    if let Some(max) = max.filter(|&max| max < min) {
+                        return Err(RegexError {
+                            position: start,
+                            message: format!(
+                                "Upper bound {max} is smaller than lower bound {min}."
+                            ),
+                        });
+                    }
+                    return Ok((min, max));
+                }
+                _ => {
+                    return Err(RegexError {
+                        position: pos,
+                        message: format!(
+                            "Expected a digit, ',' or '}}' in repetition bounds, found {chr}. \
+                             Try replacing /{{/ with /\\{{/ at position {start}."
+                        ),
+                    })
+                }
+            }
+        }
+        Err(RegexError {
+            position: size,
+            message: String::from("Expected end of repetition bounds, but found EOF."),
+        })
+    }
+
     let mut stack = vec![(Regex::Empty, None, 0)];
+    let mut names = Vec::new();
     let mut chrs = regex.chars().enumerate();
     let size = regex.chars().count();
     while let Some((pos, chr)) = chrs.next() {
         match chr {
             '(' => {
+                if matches!(chrs.clone().next(), Some((_, '?'))) {
+                    chrs.next();
+                    let name = read_group_name(&mut chrs, size, pos)?;
+                    if names.iter().any(|(other, _)| *other == name) {
+                        return Err(RegexError {
+                            position: pos,
+                            message: format!("The name {name} is given to several groups."),
+                        });
+                    }
+                    names.push((name, groups));
+                }
                 stack.push((Regex::Empty, None, groups));
 		groups += 1;
             }
@@ -870,19 +1453,20 @@ pub fn read(regex: &str, mut groups: usize) -> Result<(Regex, usize), RegexError
                 let (last, remainder, nb_group) = stack.pop().unwrap();
                 stack.push((repetition(last, pos)?, remainder, nb_group));
             }
+            // A brace that does not start bounds, such as in `LBRACE ::= {`, stands
+            // for itself.
+            '{' if matches!(chrs.clone().next(), Some((_, c)) if c.is_ascii_digit()) => {
+                let (min, max) = read_bounds(&mut chrs, size, pos)?;
+                let (last, remainder, nb_group) = stack.pop().unwrap();
+                stack.push((bounded(last, min, max, pos)?, remainder, nb_group));
+            }
             '|' => {
 		let (l, remainder, group) = stack.pop().unwrap();
                 let last = (l, remainder).into();
                 stack.push((Regex::Empty, Some(last), group));
             }
-	    '$' => return Err(RegexError {
-		position: pos,
-		message: String::from("End-of-line /$/ is not supported. Matches are anchored anyways. Try /\\n/ instead.")
-	    }),
-	    '^' => return Err(RegexError {
-		position: pos,
-		message: String::from("Start-of-line /^/ is not supported. Matches are anchored anyways.")
-	    }),
+            '^' => add(Regex::LineStart, &mut stack),
+            '$' => add(Regex::LineEnd, &mut stack),
 	    ']' => return Err(RegexError {
 		position: pos,
 		message: String::from("Closing bracket doesn't match any previsouly opened."),
@@ -891,7 +1475,7 @@ pub fn read(regex: &str, mut groups: usize) -> Result<(Regex, usize), RegexError
             '\\' => {
                 if let Some((pos, chr)) = chrs.next() {
                     match chr {
-			'\\' | '.' | '(' | ')' | '?' | '+' | '*' | '|' | '$' | '^' | '[' | ']' => add(Regex::Char(chr), &mut stack),
+			'\\' | '.' | '(' | ')' | '?' | '+' | '*' | '|' | '$' | '^' | '[' | ']' | '{' | '}' => add(Regex::Char(chr), &mut stack),
 			'A' => return Err(RegexError {
 			    position: pos,
 			    message: String::from("Start of the string anchor /\\A/ is not supported.")
@@ -903,6 +1487,10 @@ pub fn read(regex: &str, mut groups: usize) -> Result<(Regex, usize), RegexError
 			'Z' => add(Regex::EOF, &mut stack),
 			'b' => add(Regex::WordBoundary, &mut stack),
 			'd' => add(Regex::Digit, &mut stack),
+			'D' | 'S' | 'W' => {
+			    let class = merge(shorthand_intervals(chr.to_ascii_lowercase()));
+			    add(Regex::CharacterClass(class, true), &mut stack)
+			}
 			'h' => return Err(RegexError {
 			    position: pos,
 			    message: String::from("Horizontal whitespace / hexadecimal digit shorthand /\\h/ is not supported. Try [0-9a-f] instead if you wanted hexadecimal digit.")
@@ -972,6 +1560,7 @@ pub fn read(regex: &str, mut groups: usize) -> Result<(Regex, usize), RegexError
                 (last, remainder).into()
             },
             groups,
+            names,
         ))
     }
 }
@@ -0,0 +1,325 @@
+//! # Server
+//!
+//! A small HTTP server, which exposes a lexer and a parser through a JSON API,
+//! so that a shared playground can be set up for a language without writing
+//! any glue. It is only available with the `serve` feature.
+//!
+//! The body of the requests is the source to handle. The endpoints are
+//!  - `POST /lex`, which answers `{"tokens": [...], "diagnostics": [...]}`;
+//!  - `POST /parse`, which answers `{"ast": ..., "diagnostics": [...]}`.
+//!
+//! Each diagnostic is an object with a `message`. Requests are handled one at
+//! a time, so each must be sent within [`REQUEST_TIMEOUT`], with headers of at
+//! most [`MAX_HEADER_SIZE`] bytes in all, and a body of at most
+//! [`MAX_BODY_SIZE`] bytes, which `POST` requests give the `Content-Length` of.
+
+use crate::{
+    lexer::Lexer,
+    parser::{earley::EarleyParser, Parser},
+    regex::Allowed,
+    stream::StringStream,
+};
+use serde_json::{json, Value};
+use std::borrow::Borrow;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// The largest body a request may have, in bytes.
+pub const MAX_BODY_SIZE: usize = 1 << 20;
+
+/// The largest size of the request line and the headers of a request, in bytes.
+pub const MAX_HEADER_SIZE: u64 = 8 << 10;
+
+/// The largest number of headers a request may have.
+pub const MAX_HEADERS: usize = 64;
+
+/// How long a client may take to send its request, and to read the response,
+/// before it is dropped.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A reader of a stream that fails once `deadline` has passed, however steadily
+/// the client sends its request.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self
+            .deadline
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())
+            .ok_or(io::ErrorKind::TimedOut)?;
+        self.stream.set_read_timeout(Some(remaining))?;
+        let mut stream = self.stream;
+        stream.read(buf)
+    }
+}
+
+/// # Summary
+///
+/// [`Server`] serves requests with a pre-loaded lexer and parser, which can be
+/// owned, borrowed, or shared.
+#[derive(Debug)]
+pub struct Server<L = Lexer, P = EarleyParser> {
+    lexer: L,
+    parser: P,
+}
+
+impl<L, P> Server<L, P>
+where
+    L: Borrow<Lexer>,
+    P: Borrow<EarleyParser>,
+{
+    pub fn new(lexer: L, parser: P) -> Self {
+        Self { lexer, parser }
+    }
+
+    /// Listen on `address`, and serve requests forever.
+    pub fn serve(&self, address: impl ToSocketAddrs) -> io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        for stream in listener.incoming() {
+            // A client that misbehaves should not bring the server down.
+            let _ = self.handle(stream?);
+        }
+        Ok(())
+    }
+
+    fn handle(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+        let mut reader = BufReader::new(Deadline {
+            stream: &stream,
+            deadline: Instant::now() + REQUEST_TIMEOUT,
+        });
+        let (status, response) = self.answer(&mut reader)?;
+        let response = response.map(|value| value.to_string()).unwrap_or_default();
+        let mut stream = &stream;
+        write!(
+            stream,
+            "HTTP/1.1 {status}\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: {}\r\n\
+             Access-Control-Allow-Origin: *\r\n\
+             Access-Control-Allow-Methods: POST, OPTIONS\r\n\
+             Access-Control-Allow-Headers: Content-Type\r\n\
+             Connection: close\r\n\r\n{response}",
+            response.len(),
+        )?;
+        stream.flush()
+    }
+
+    /// Read a request from `reader`, and answer it, returning the status line and
+    /// the JSON body.
+    fn answer(&self, reader: &mut impl BufRead) -> io::Result<(&'static str, Option<Value>)> {
+        let too_large = (
+            "431 Request Header Fields Too Large",
+            Some(diagnostics("The headers are too large.")),
+        );
+        let mut head = reader.take(MAX_HEADER_SIZE);
+        let mut request_line = String::new();
+        head.read_line(&mut request_line)?;
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let path = parts.next().unwrap_or_default().to_string();
+        let mut content_length = None;
+        let mut headers = 0;
+        loop {
+            if head.limit() == 0 || headers > MAX_HEADERS {
+                return Ok(too_large);
+            }
+            let mut header = String::new();
+            if head.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+            headers += 1;
+            if let Some((key, value)) = header.split_once(':') {
+                if key.trim().eq_ignore_ascii_case("content-length") {
+                    let Ok(length) = value.trim().parse() else {
+                        return Ok((
+                            "400 Bad Request",
+                            Some(diagnostics("The Content-Length is not a number.")),
+                        ));
+                    };
+                    content_length = Some(length);
+                }
+            }
+        }
+        let content_length = match content_length {
+            Some(length) => length,
+            None if method == "POST" => {
+                return Ok((
+                    "411 Length Required",
+                    Some(diagnostics("POST requests need a Content-Length.")),
+                ))
+            }
+            None => 0,
+        };
+        if content_length > MAX_BODY_SIZE {
+            return Ok((
+                "413 Payload Too Large",
+                Some(diagnostics("The source is too large.")),
+            ));
+        }
+        let mut body = vec![0; content_length];
+        head.into_inner().read_exact(&mut body)?;
+        Ok(self.respond(&method, &path, &body))
+    }
+
+    /// Answer a request, returning the status line and the JSON body.
+    pub fn respond(
+        &self,
+        method: &str,
+        path: &str,
+        body: &[u8],
+    ) -> (&'static str, Option<Value>) {
+        let source = match (method, std::str::from_utf8(body)) {
+            ("OPTIONS", _) => return ("204 No Content", None),
+            ("POST", Ok(source)) => source,
+            ("POST", Err(_)) => {
+                return (
+                    "400 Bad Request",
+                    Some(diagnostics("The source is not valid utf-8.")),
+                )
+            }
+            _ => {
+                return (
+                    "405 Method Not Allowed",
+                    Some(diagnostics("Only POST requests are supported.")),
+                )
+            }
+        };
+        match path {
+            "/lex" => ("200 OK", Some(self.lex(source))),
+            "/parse" => ("200 OK", Some(self.parse(source))),
+            _ => (
+                "404 Not Found",
+                Some(diagnostics("The endpoints are /lex and /parse.")),
+            ),
+        }
+    }
+
+    fn lex(&self, source: &str) -> Value {
+        let mut stream = StringStream::new(Path::new("<playground>"), source);
+        let mut input = self.lexer.borrow().lex(&mut stream);
+        let mut tokens = Vec::new();
+        let mut messages = Vec::new();
+        loop {
+            match input.next(Allowed::All) {
+                Ok(Some(token)) => tokens.push(token.clone()),
+                Ok(None) => break,
+                Err(error) => {
+                    messages.push(json!({ "message": error.to_string().trim_end() }));
+                    break;
+                }
+            }
+        }
+        json!({ "tokens": tokens, "diagnostics": messages })
+    }
+
+    fn parse(&self, source: &str) -> Value {
+        let mut stream = StringStream::new(Path::new("<playground>"), source);
+        let mut input = self.lexer.borrow().lex(&mut stream);
+        match self.parser.borrow().parse(&mut input) {
+            Ok(result) => json!({ "ast": result.tree, "diagnostics": [] }),
+            Err(error) => json!({
+                "ast": null,
+                "diagnostics": [{ "message": error.to_string().trim_end() }],
+            }),
+        }
+    }
+}
+
+fn diagnostics(message: &str) -> Value {
+    json!({ "diagnostics": [{ "message": message }] })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Buildable;
+    use crate::parser::earley::EarleyGrammar;
+
+    fn server() -> Server {
+        let lexer = Lexer::build_from_plain(StringStream::new(
+            Path::new("<lexer input>"),
+            "NUMBER ::= ([0-9]+)\nPLUS ::= \\+",
+        ))
+        .unwrap();
+        let grammar = EarleyGrammar::build_from_plain(
+            StringStream::new(
+                Path::new("<grammar input>"),
+                "@Sum ::= Sum@left PLUS NUMBER.0@right <>\n NUMBER.0@self <>;",
+            ),
+            lexer.grammar(),
+        )
+        .unwrap();
+        Server::new(lexer, EarleyParser::new(grammar))
+    }
+
+    #[test]
+    fn endpoints() {
+        let server = server();
+        let (status, response) = server.respond("POST", "/lex", b"1+2");
+        assert_eq!(status, "200 OK");
+        let response = response.unwrap();
+        assert_eq!(response["tokens"].as_array().unwrap().len(), 3);
+        assert_eq!(response["tokens"][1]["name"], "PLUS");
+
+        let (status, response) = server.respond("POST", "/parse", b"1+2");
+        assert_eq!(status, "200 OK");
+        let response = response.unwrap();
+        assert!(response["ast"].is_object());
+        assert!(response["diagnostics"].as_array().unwrap().is_empty());
+
+        let (_, response) = server.respond("POST", "/parse", b"1+");
+        let response = response.unwrap();
+        assert!(response["ast"].is_null());
+        assert_eq!(response["diagnostics"].as_array().unwrap().len(), 1);
+
+        assert_eq!(server.respond("GET", "/lex", b"").0, "405 Method Not Allowed");
+        assert_eq!(server.respond("POST", "/nothing", b"").0, "404 Not Found");
+    }
+
+    #[test]
+    fn limits() {
+        let server = server();
+        let answer = |request: &str| server.answer(&mut request.as_bytes()).unwrap().0;
+        assert_eq!(
+            answer("POST /lex HTTP/1.1\r\nContent-Length: 3\r\n\r\n1+2"),
+            "200 OK"
+        );
+        assert_eq!(answer("POST /lex HTTP/1.1\r\n\r\n1+2"), "411 Length Required");
+        assert_eq!(
+            answer("POST /lex HTTP/1.1\r\nContent-Length: three\r\n\r\n1+2"),
+            "400 Bad Request"
+        );
+        let length = format!("Content-Length: {}\r\n", MAX_BODY_SIZE + 1);
+        assert_eq!(
+            answer(&format!("POST /lex HTTP/1.1\r\n{length}\r\n")),
+            "413 Payload Too Large"
+        );
+        let long = format!("X: {}\r\n", "x".repeat(MAX_HEADER_SIZE as usize));
+        assert_eq!(
+            answer(&format!("POST /lex HTTP/1.1\r\n{long}\r\n")),
+            "431 Request Header Fields Too Large"
+        );
+        let many = "X: x\r\n".repeat(MAX_HEADERS + 1);
+        assert_eq!(
+            answer(&format!("POST /lex HTTP/1.1\r\n{many}\r\n")),
+            "431 Request Header Fields Too Large"
+        );
+        assert_eq!(answer("OPTIONS /lex HTTP/1.1\r\n\r\n"), "204 No Content");
+    }
+
+    #[test]
+    fn borrowed() {
+        let Server { lexer, parser } = server();
+        let server = Server::new(&lexer, &parser);
+        assert_eq!(server.respond("POST", "/parse", b"1+2").0, "200 OK");
+        let server = Server::new(std::rc::Rc::new(lexer), &parser);
+        assert_eq!(server.respond("POST", "/lex", b"1+2").0, "200 OK");
+    }
+}
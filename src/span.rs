@@ -3,7 +3,7 @@
 //! Data to locate span of text, in files.
 //! The main struct is [`Location`].
 
-use std::{path::Path, rc::Rc};
+use std::{ops::Range, path::Path, rc::Rc};
 
 use fragile::Fragile;
 use serde::{Deserialize, Serialize};
@@ -27,7 +27,12 @@ pub type Location = (usize, usize);
 /// # Summary
 ///
 /// Stores the span of any bit of information that is bound to a file.
-/// Asks a start position (inclusive) and an end position (inclusive).
+/// Asks a start position (inclusive) and an end position (inclusive), which are
+/// the locations of the first and of the last character of the data. Its offsets
+/// in the file, in bytes and in characters, are ranges, that end right after its
+/// last character, so that they slice the text of the file. Empty spans, such as
+/// the one at the end of a file, start and end at the same location, with empty
+/// ranges.
 /// This means that if my chunk of data is one character long,
 /// and starts at the beginning of the file `myfile`, the location
 /// data bound to it is the one defined in example 1.
@@ -44,9 +49,11 @@ pub type Location = (usize, usize);
 ///     (0, 0),
 ///     (0, 0),
 ///     0,
+///     1,
 ///     0,
-/// # //    "a",
-/// # //    vec![0],
+///     1,
+///     "a",
+///     vec![0],
 /// );
 /// ```
 ///
@@ -62,16 +69,18 @@ pub type Location = (usize, usize);
 /// ```rust
 /// # use beans::span::Span;
 /// # use std::path::Path;
-/// Span::new(
+/// let span = Span::new(
 ///   Path::new("afile"),
-///   (0, 4),
-///   (1, 2),
-///   4,
-///   8,
-/// # //  "abcde\nbel",
-/// # //  vec![0, 6],
-/// )
-/// # ;
+///   (0, 2),
+///   (1, 1),
+///   2,
+///   10,
+///   2,
+///   10,
+///   "abc def\nghi",
+///   vec![0, 8],
+/// );
+/// assert_eq!(&"abc def\nghi"[span.byte_range()], "c def\ngh");
 /// ```
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Hash)]
 pub struct Span {
@@ -80,6 +89,8 @@ pub struct Span {
     end: Location,
     start_byte: usize,
     end_byte: usize,
+    start_char: usize,
+    end_char: usize,
     // text: Rc<str>,
     // lines: Rc<[usize]>,
 }
@@ -119,18 +130,25 @@ impl std::fmt::Display for Span {
 
 impl Span {
     /// Create a new `Location` object.
-    /// Require three arguments,
+    /// Require the arguments
     ///  * file: the name of the file where the data is;
-    ///  * start: the location (inclusive) of the beginning of the data;
-    ///  * end: the location (exclusive) of the end of the data.
+    ///  * start: the location (inclusive) of the first character of the data;
+    ///  * end: the location (inclusive) of the last character of the data;
+    ///  * start_byte: the offset of the beginning of the data, in bytes;
+    ///  * end_byte: the offset right after the end of the data, in bytes;
+    ///  * start_char: the offset of the beginning of the data, in characters;
+    ///  * end_char: the offset right after the end of the data, in characters.
     ///
     /// Panic if start > end (lexicographic order)
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         file: impl Into<Rc<Path>>,
         start: Location,
         end: Location,
         start_byte: usize,
         end_byte: usize,
+        start_char: usize,
+        end_char: usize,
         text: impl Into<Rc<str>>,
         lines: impl Into<Rc<[usize]>>,
     ) -> Self {
@@ -144,6 +162,8 @@ impl Span {
             end,
             start_byte,
             end_byte,
+            start_char,
+            end_char,
             // text,
             // lines,
         }
@@ -154,6 +174,8 @@ impl Span {
             file: self.file.clone(),
             start_byte: self.start_byte.min(other.start_byte),
             end_byte: self.end_byte.max(other.end_byte),
+            start_char: self.start_char.min(other.start_char),
+            end_char: self.end_char.max(other.end_char),
             start: self.start.min(other.start),
             end: self.end.max(other.end),
             // text: self.text.clone(),
@@ -161,6 +183,19 @@ impl Span {
         }
     }
 
+    /// Returns the empty span at the beginning of this one.
+    pub fn start_point(&self) -> Self {
+        Self {
+            file: self.file.clone(),
+            start_byte: self.start_byte,
+            end_byte: self.start_byte,
+            start_char: self.start_char,
+            end_char: self.start_char,
+            start: self.start,
+            end: self.start,
+        }
+    }
+
     /// Returns the file from which the data is taken.
     pub fn file(&self) -> Rc<Path> {
         self.file.clone()
@@ -171,19 +206,46 @@ impl Span {
         self.start
     }
 
-    /// Returns the location of the end of the chunk of data in the file
+    /// Returns the location of the last character of the chunk of data in the file.
     pub fn end(&self) -> Location {
         self.end
     }
 
+    /// Returns the offset, in bytes, of the beginning of the chunk of data in the file.
     pub fn start_byte(&self) -> usize {
         self.start_byte
     }
 
+    /// Returns the offset, in bytes, right after the end of the chunk of data in the
+    /// file.
     pub fn end_byte(&self) -> usize {
         self.end_byte
     }
 
+    /// Returns the bytes of the file the chunk of data spans, so that
+    /// `&text[span.byte_range()]` is the data.
+    pub fn byte_range(&self) -> Range<usize> {
+        self.start_byte..self.end_byte
+    }
+
+    /// Returns the offset, in characters, of the beginning of the chunk of data in
+    /// the file.
+    pub fn start_char(&self) -> usize {
+        self.start_char
+    }
+
+    /// Returns the offset, in characters, right after the end of the chunk of data
+    /// in the file.
+    pub fn end_char(&self) -> usize {
+        self.end_char
+    }
+
+    /// Returns the characters of the file the chunk of data spans, as counted by
+    /// `text.chars()`.
+    pub fn char_range(&self) -> Range<usize> {
+        self.start_char..self.end_char
+    }
+
     // /// Returns (start, end), where the line `line_number` is
     // /// `self.text()[start..end]`
     // pub fn line_bytes_of_line(&self, line_number: usize) -> (usize, usize) {
@@ -214,6 +276,8 @@ impl From<&Span> for Fragile<Span> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::stream::StringStream;
+
     #[test]
     fn span() {
         let input: Rc<str> = Rc::from(
@@ -226,7 +290,9 @@ mod tests {
             (0, 3),
             (1, 6),
             3,
-            11,
+            13,
+            3,
+            13,
             input.clone(),
             lines.clone(),
         );
@@ -234,7 +300,9 @@ mod tests {
         assert_eq!(span.start(), (0, 3));
         assert_eq!(span.end(), (1, 6));
         assert_eq!(span.start_byte(), 3);
-        assert_eq!(span.end_byte(), 11);
+        assert_eq!(span.end_byte(), 13);
+        assert_eq!(&input[span.byte_range()], "34\n56789ab");
+        assert_eq!(span.char_range(), 3..13);
         // assert_eq!(span.text(), &*input);
         // assert_eq!(span.lines(), &*lines);
         let span = Span::new(
@@ -243,6 +311,8 @@ mod tests {
             (0, 0),
             0,
             0,
+            0,
+            0,
             input,
             lines,
         );
@@ -251,18 +321,35 @@ mod tests {
         assert_eq!(span.end(), (0, 0));
         assert_eq!(span.start_byte(), 0);
         assert_eq!(span.end_byte(), 0);
+        assert!(span.char_range().is_empty());
         // assert_eq!(span.text(), &*input);
         // assert_eq!(span.lines(), &*lines);
     }
 
+    #[test]
+    fn multibyte() {
+        let text = "Добрый день\n";
+        let stream = StringStream::new(Path::new("<multibyte>"), text);
+        let span = stream.span_between(7, 10);
+        assert_eq!((span.start(), span.end()), ((0, 7), (0, 10)));
+        assert_eq!(span.byte_range(), 13..21);
+        assert_eq!(&text[span.byte_range()], "день");
+        assert_eq!(span.char_range(), 7..11);
+        assert_eq!(span.start_point().byte_range(), 13..13);
+        let end = stream.span_between(12, 12);
+        assert_eq!(end.byte_range(), 22..22);
+        assert_eq!(end.char_range(), 12..12);
+        assert_eq!(span.sup(&end).char_range(), 7..12);
+    }
+
     #[test]
     #[should_panic]
     fn wrong_span() {
-        Span::new(Path::new("some file"), (1, 0), (0, 0), 1, 0, "", Vec::new());
+        Span::new(Path::new("some file"), (1, 0), (0, 0), 1, 0, 1, 0, "", Vec::new());
     }
     #[test]
     #[should_panic]
     fn wrong_span2() {
-        Span::new(Path::new("some file"), (1, 5), (1, 3), 8, 6, "", Vec::new());
+        Span::new(Path::new("some file"), (1, 5), (1, 3), 8, 6, 8, 6, "", Vec::new());
     }
 }
@@ -0,0 +1,68 @@
+//! # Stable
+//!
+//! Hashing that gives the same results from one build of Beans to the other, and
+//! on every platform, unlike the hashers of the standard library. It is meant for
+//! the fingerprints of grammars, and for the keys of caches that outlive a run.
+
+use std::hash::{Hash, Hasher};
+
+/// # Summary
+///
+/// [`StableHasher`] is a FNV-1a hasher. Integers are hashed in little-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StableHasher {
+    hash: u64,
+}
+
+impl StableHasher {
+    pub fn new() -> Self {
+        Self {
+            hash: 0xcbf2_9ce4_8422_2325,
+        }
+    }
+}
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.hash ^= u64::from(byte);
+            self.hash = self.hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write(&(i as u64).to_le_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// The stable hash of `value`.
+pub fn stable_hash(value: &(impl Hash + ?Sized)) -> u64 {
+    let mut hasher = StableHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_hasher() {
+        let mut hasher = StableHasher::new();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(StableHasher::default().finish(), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(stable_hash("grammar"), stable_hash(&String::from("grammar")));
+        assert_ne!(stable_hash(&[1usize, 2]), stable_hash(&[2usize, 1]));
+    }
+}
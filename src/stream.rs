@@ -1,8 +1,9 @@
-use crate::error::{Error, Result};
+use crate::error::{Error, ErrorKind, Result};
 use crate::span::{Location, Span};
+use std::collections::HashSet;
 use std::fs::File;
 use std::io::prelude::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::result::Result as StdResult;
 
@@ -40,12 +41,37 @@ impl TryFrom<RawStream> for StringStream {
     type Error = Error;
 
     fn try_from(value: RawStream) -> StdResult<Self, Self::Error> {
-        let string = String::from_utf8(value.stream)
-            .map_err(|error| Error::with_file(error, &*value.origin))?;
+        let string = decode(&value.origin, value.stream)?;
         Ok(StringStream::new(value.origin, string))
     }
 }
 
+/// The number of bytes at the start of an input that are looked at to tell whether
+/// it is binary.
+const BINARY_PROBE: usize = 8000;
+
+/// Decode `bytes`, the content of `origin`. Inputs that have a NUL byte, or bytes
+/// that are not UTF-8, near their start are rejected early as binary, since they are
+/// more likely compiled grammars or images fed by mistake than text to lex.
+fn decode(origin: &Path, bytes: Vec<u8>) -> Result<String> {
+    let head = &bytes[..bytes.len().min(BINARY_PROBE)];
+    let binary = head.iter().position(|&byte| byte == 0).or_else(|| {
+        std::str::from_utf8(head)
+            .err()
+            // An error without length is a char cut by the end of the probe.
+            .filter(|error| error.error_len().is_some())
+            .map(|error| error.valid_up_to())
+    });
+    if let Some(byte) = binary {
+        return ErrorKind::BinaryInput {
+            path: origin.to_path_buf(),
+            byte,
+        }
+        .err();
+    }
+    String::from_utf8(bytes).map_err(|error| Error::with_file(error, origin))
+}
+
 /// # Summary
 ///
 /// A character, or `EOF`.
@@ -62,6 +88,31 @@ pub enum Char {
     EOF,
 }
 
+/// # Summary
+///
+/// What the columns of the [`Location`]s of a [`StringStream`] count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColumnEncoding {
+    /// Bytes of UTF-8.
+    Utf8,
+    /// Code units of UTF-16, as the positions of the Language Server Protocol.
+    Utf16,
+    /// Unicode scalar values, that is, `char`s.
+    #[default]
+    Scalars,
+}
+
+impl ColumnEncoding {
+    /// The number of columns `c` spans.
+    fn width(self, c: char) -> usize {
+        match self {
+            Self::Utf8 => c.len_utf8(),
+            Self::Utf16 => c.len_utf16(),
+            Self::Scalars => 1,
+        }
+    }
+}
+
 /// # Summary
 ///
 /// A stream based on a string, considered as a file-like object.
@@ -73,8 +124,11 @@ pub enum Char {
 /// `borrow`: borrows (read-only) the stream as a string slice
 /// `len`: the size of the stream
 /// `is_empty`: whether the stream is empty
+#[derive(Clone)]
 pub struct StringStream {
     origin: Rc<Path>,
+    // The parts of the stream, in order, when it chains several files.
+    parts: Vec<Part>,
     // Stores, for each character, its span and its size.
     spans: Vec<CharSpan>,
     stream: Rc<str>,
@@ -82,9 +136,25 @@ pub struct StringStream {
     bytes_pos: usize,
     chars_pos: usize,
     length: usize,
+    // The byte of the file at which the stream starts, when it is a window of it.
+    byte_offset: usize,
+    // The character of the file at which the stream starts.
+    char_offset: usize,
     eof_span: Span,
+    open: bool,
+    column_encoding: ColumnEncoding,
+}
+
+/// A part of a [`StringStream`], that comes from the file `origin` from the
+/// character `first` on, and whose first character is at `location` in that file.
+#[derive(Clone)]
+struct Part {
+    first: usize,
+    origin: Rc<Path>,
+    location: Location,
 }
 
+#[derive(Clone)]
 struct CharSpan {
     location: Location,
     byte_location: usize,
@@ -94,16 +164,148 @@ struct CharSpan {
 impl StringStream {
     /// Build a new `StringStream`, based on its `origin` and on a given `string`.
     pub fn new(origin: impl Into<Rc<Path>>, string: impl Into<Rc<str>>) -> Self {
+        Self::starting_at(origin, string, 0, 0, (0, 0))
+    }
+
+    /// Build a `StringStream` on `string`, a window of the file `origin` that starts
+    /// there at the byte `byte_offset`, the character `char_offset`, and at
+    /// `location`. Spans are relative to the whole file, so that tools can lex a part
+    /// of a large file, or resume lexing from a checkpoint, without reading what
+    /// comes before.
+    pub fn starting_at(
+        origin: impl Into<Rc<Path>>,
+        string: impl Into<Rc<str>>,
+        byte_offset: usize,
+        char_offset: usize,
+        location: Location,
+    ) -> Self {
         let origin = origin.into();
         let string = string.into();
-        let mut current_char = 0;
-        let mut current_line = 0;
-        let mut spans = Vec::new();
-        let mut current_byte = 0;
-        let mut lines = vec![0];
-        for chr in string.chars() {
+        let lines: Rc<[usize]> = Rc::from([0]);
+        let mut stream = Self {
+            origin: origin.clone(),
+            parts: vec![Part {
+                first: 0,
+                origin: origin.clone(),
+                location,
+            }],
+            spans: Vec::new(),
+            stream: string.clone(),
+            lines: lines.clone(),
+            bytes_pos: 0,
+            chars_pos: 0,
+            length: 0,
+            byte_offset,
+            char_offset,
+            eof_span: Span::new(
+                origin,
+                location,
+                location,
+                byte_offset,
+                byte_offset,
+                char_offset,
+                char_offset,
+                string,
+                lines,
+            ),
+            open: false,
+            column_encoding: ColumnEncoding::default(),
+        };
+        stream.index(0);
+        stream
+    }
+
+    /// Build an empty `StringStream`, whose content is given chunk by chunk with
+    /// [`StringStream::feed`], until it is closed.
+    pub fn open(origin: impl Into<Rc<Path>>) -> Self {
+        Self {
+            open: true,
+            ..Self::new(origin, "")
+        }
+    }
+
+    /// Count the columns of the locations of the stream in `column_encoding`, rather
+    /// than in `char`s.
+    pub fn with_column_encoding(mut self, column_encoding: ColumnEncoding) -> Self {
+        self.column_encoding = column_encoding;
+        self.spans.clear();
+        self.lines = Rc::from([0]);
+        self.eof_span = Span::new(
+            self.origin.clone(),
+            (0, 0),
+            (0, 0),
+            0,
+            0,
+            0,
+            0,
+            self.stream.clone(),
+            self.lines.clone(),
+        );
+        self.index(0);
+        self
+    }
+
+    /// Append `chunk` to the content of the stream. What has been fed is kept, so
+    /// that spans can refer to it.
+    pub fn feed(&mut self, chunk: &str) {
+        let start = self.stream.len();
+        let mut text = String::with_capacity(start + chunk.len());
+        text.push_str(&self.stream);
+        text.push_str(chunk);
+        self.stream = Rc::from(text);
+        self.index(start);
+    }
+
+    /// Append `text`, which comes from the file `origin`, and starts there at
+    /// `location`. Spans of that text refer to `origin`, so that several files
+    /// can be read as a single stream.
+    pub fn append(&mut self, origin: impl Into<Rc<Path>>, text: &str, location: Location) {
+        self.parts.push(Part {
+            first: self.length,
+            origin: origin.into(),
+            location,
+        });
+        self.feed(text);
+    }
+
+    /// The file the character at position `pos` comes from.
+    fn origin_at(&self, pos: usize) -> Rc<Path> {
+        let index = self.parts.partition_point(|part| part.first <= pos);
+        self.parts[index - 1].origin.clone()
+    }
+
+    /// Mark that nothing will be fed to the stream anymore.
+    pub fn close(&mut self) {
+        self.open = false;
+    }
+
+    /// Return whether more content might be fed to the stream.
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Compute the spans of the characters of the stream from byte `start` on,
+    /// which follow the ones already computed.
+    fn index(&mut self, start: usize) {
+        let (mut current_line, mut current_char) = self.eof_span.start();
+        let mut current_byte = start;
+        let mut lines = self.lines.to_vec();
+        let mut parts = self
+            .parts
+            .iter()
+            .filter(|part| part.first >= self.spans.len())
+            .map(|part| (part.first, part.location))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .peekable();
+        for chr in self.stream[start..].chars() {
+            while let Some((_, location)) =
+                parts.next_if(|&(first, _)| first == self.spans.len())
+            {
+                (current_line, current_char) = location;
+            }
             let start_pos = (current_line, current_char);
-            spans.push(CharSpan {
+            self.spans.push(CharSpan {
                 location: start_pos,
                 byte_location: current_byte,
                 size: chr.len_utf8(),
@@ -114,40 +316,112 @@ impl StringStream {
                 current_char = 0;
                 lines.push(current_byte);
             } else {
-                current_char += 1;
+                current_char += self.column_encoding.width(chr);
             }
         }
-        let lines: Rc<[usize]> = Rc::from(lines);
-        Self {
-            origin: origin.clone(),
-            length: spans.len(),
-            stream: string.clone(),
-            spans,
-            lines: lines.clone(),
-            bytes_pos: 0,
-            chars_pos: 0,
-            eof_span: Span::new(
-                origin,
-                (current_line, current_char),
-                (current_line, current_char),
-                current_byte,
-                current_byte,
-                string,
-                lines,
-            ),
+        for (_, location) in parts {
+            (current_line, current_char) = location;
         }
+        self.lines = Rc::from(lines);
+        self.length = self.spans.len();
+        self.eof_span = Span::new(
+            self.parts[self.parts.len() - 1].origin.clone(),
+            (current_line, current_char),
+            (current_line, current_char),
+            current_byte + self.byte_offset,
+            current_byte + self.byte_offset,
+            self.spans.len() + self.char_offset,
+            self.spans.len() + self.char_offset,
+            self.stream.clone(),
+            self.lines.clone(),
+        );
     }
 
     /// Create a [`StringStream`] directly from a file. This will try to read the content of the file right away.
     pub fn from_file(file: impl Into<Rc<Path>>) -> Result<Self> {
         let file = file.into();
-        let mut file_stream =
+        let file_stream =
             File::open(file.as_ref()).map_err(|err| Error::with_file(err, &*file))?;
-        let mut stream_buffer = String::new();
-        file_stream
-            .read_to_string(&mut stream_buffer)
-            .map_err(|err| Error::with_file(err, &*file))?;
-        Ok(StringStream::new(file, stream_buffer))
+        Self::from_reader(file_stream, file)
+    }
+
+    /// Create a [`StringStream`] from the whole content of `reader`, such as the
+    /// standard input, whose `origin` is reported as the file it comes from. Inputs
+    /// that look binary are rejected with [`ErrorKind::BinaryInput`].
+    pub fn from_reader(mut reader: impl Read, origin: impl Into<Rc<Path>>) -> Result<Self> {
+        let origin = origin.into();
+        let mut stream_buffer = Vec::new();
+        reader
+            .read_to_end(&mut stream_buffer)
+            .map_err(|err| Error::with_file(err, &*origin))?;
+        let string = decode(&origin, stream_buffer)?;
+        Ok(StringStream::new(origin, string))
+    }
+
+    /// Create a [`StringStream`] from a file, in which every line of the form
+    /// `include "path"` is replaced by the content of the file at `path`, relative
+    /// to the including file. Included files can include files in turn, as long as
+    /// they do not include themselves. A line `import "path"` is replaced the same
+    /// way, unless the file at `path` is already part of the stream, so that files
+    /// shared by several others, and files that import each other, come only once.
+    /// Locations are the ones in the file each character comes from.
+    pub fn from_file_with_includes(file: impl Into<Rc<Path>>) -> Result<Self> {
+        let file = file.into();
+        let mut stream = StringStream::new(file.clone(), "");
+        stream.include(&file, Inclusion::Include, &mut Vec::new(), &mut HashSet::new())?;
+        Ok(stream)
+    }
+
+    /// Append the content of `file`, with its includes resolved. `including` are
+    /// the files that are being included, to detect cycles, and `seen` all the
+    /// files appended so far, to skip the ones imported again.
+    fn include(
+        &mut self,
+        file: &Path,
+        inclusion: Inclusion,
+        including: &mut Vec<PathBuf>,
+        seen: &mut HashSet<PathBuf>,
+    ) -> Result<()> {
+        let canonical = file
+            .canonicalize()
+            .map_err(|err| Error::with_file(err, file))?;
+        if !seen.insert(canonical.clone()) && inclusion == Inclusion::Import {
+            return Ok(());
+        }
+        if including.contains(&canonical) {
+            return ErrorKind::IncludeCycle {
+                path: file.to_path_buf(),
+            }
+            .err();
+        }
+        let bytes = std::fs::read(file).map_err(|err| Error::with_file(err, file))?;
+        let text = decode(file, bytes)?;
+        including.push(canonical);
+        let origin: Rc<Path> = Rc::from(file);
+        // The byte at which the current part of `file` starts, and its line.
+        let (mut start, mut start_line) = (0, 0);
+        let mut offset = 0;
+        for (line, content) in text.split_inclusive('\n').enumerate() {
+            let end = offset + content.len();
+            if let Some((inclusion, included)) = included_path(content) {
+                if start < offset {
+                    self.append(origin.clone(), &text[start..offset], (start_line, 0));
+                }
+                let path = file.parent().unwrap_or(Path::new("")).join(included);
+                self.include(&path, inclusion, including, seen)?;
+                if !self.stream.is_empty() && !self.stream.ends_with('\n') {
+                    self.feed("\n");
+                }
+                start = end;
+                start_line = line + 1;
+            }
+            offset = end;
+        }
+        if start < text.len() {
+            self.append(origin, &text[start..], (start_line, 0));
+        }
+        including.pop();
+        Ok(())
     }
 
     pub fn pos(&self) -> usize {
@@ -177,6 +451,38 @@ impl StringStream {
         self.bytes_pos -= self.spans[self.chars_pos].size;
     }
 
+    /// Move the stream to the character at position `pos`.
+    pub fn seek(&mut self, pos: usize) {
+        self.chars_pos = pos;
+        self.bytes_pos = self
+            .spans
+            .get(pos)
+            .map_or(self.stream.len(), |span| span.byte_location);
+    }
+
+    /// Move the stream to the character that starts at the byte `byte` of the file,
+    /// counted from the start of the file even if the stream is a window of it.
+    pub fn seek_byte(&mut self, byte: usize) -> Result<()> {
+        let position = byte.checked_sub(self.byte_offset).and_then(|byte| {
+            if byte == self.stream.len() {
+                Some(self.length)
+            } else {
+                self.spans
+                    .binary_search_by_key(&byte, |span| span.byte_location)
+                    .ok()
+            }
+        });
+        let Some(position) = position else {
+            return ErrorKind::InvalidOffset {
+                path: self.origin.to_path_buf(),
+                offset: byte,
+            }
+            .err();
+        };
+        self.seek(position);
+        Ok(())
+    }
+
     /// Return a string slice corresponding to the
     /// underlying string, starting at the position of the stream.
     pub fn peek(&self) -> &str {
@@ -199,6 +505,11 @@ impl StringStream {
             .unwrap_or(Char::EOF)
     }
 
+    /// Return the char before the position of the stream, unless it is at the start.
+    pub fn previous(&self) -> Option<char> {
+        self.stream[..self.bytes_pos].chars().next_back()
+    }
+
     /// Return the origin file of the [`StringStream`].
     pub fn origin(&self) -> Rc<Path> {
         self.origin.clone()
@@ -224,19 +535,23 @@ impl StringStream {
                 ..
             } = self.spans[self.chars_pos];
             Span::new(
-                self.origin.clone(),
+                self.origin_at(self.chars_pos),
                 (line, column),
                 (line, column),
-                byte,
-                byte,
+                byte + self.byte_offset,
+                byte + self.byte_offset,
+                self.chars_pos + self.char_offset,
+                self.chars_pos + self.char_offset,
                 self.stream.clone(),
                 self.lines.clone(),
             )
         }
     }
 
+    /// The span from the character `start` to the character `end`, both included, so
+    /// that its offsets end right after the character `end`.
     pub fn span_between(&self, start: usize, end: usize) -> Span {
-        let (start_location, start_byte) = self
+        let (start_location, start_byte, start_char) = self
             .spans
             .get(start)
             .map(
@@ -244,32 +559,80 @@ impl StringStream {
                      location: loc,
                      byte_location: byte_loc,
                      ..
-                 }| (loc, byte_loc),
+                 }| (loc, byte_loc + self.byte_offset, start + self.char_offset),
             )
-            .unwrap_or_else(|| (self.eof_span.start(), self.eof_span.start_byte()));
-        let (end_location, end_byte) = self
+            .unwrap_or_else(|| {
+                let eof = &self.eof_span;
+                (eof.start(), eof.start_byte(), eof.start_char())
+            });
+        let (end_location, end_byte, end_char) = self
             .spans
             .get(end)
             .map(
                 |&CharSpan {
                      location: loc,
                      byte_location: byte_loc,
-                     ..
-                 }| (loc, byte_loc),
+                     size,
+                 }| (loc, byte_loc + size + self.byte_offset, end + 1 + self.char_offset),
             )
-            .unwrap_or_else(|| (self.eof_span.end(), self.eof_span.end_byte()));
+            .unwrap_or_else(|| {
+                let eof = &self.eof_span;
+                (eof.end(), eof.end_byte(), eof.end_char())
+            });
         Span::new(
-            self.origin.clone(),
+            self.origin_at(start),
             start_location,
             end_location,
             start_byte,
             end_byte,
+            start_char,
+            end_char,
             self.stream.clone(),
             self.lines.clone(),
         )
     }
 }
 
+/// The path of the line `line`, if it is of the form `include "path"`.
+/// How a line pulls the content of another file in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Inclusion {
+    /// `include "path"`, every time.
+    Include,
+    /// `import "path"`, the first time only.
+    Import,
+}
+
+fn included_path(line: &str) -> Option<(Inclusion, &str)> {
+    let line = line.trim();
+    let (inclusion, rest) = match line.strip_prefix("include ") {
+        Some(rest) => (Inclusion::Include, rest),
+        None => (Inclusion::Import, line.strip_prefix("import ")?),
+    };
+    let path = rest.trim_start().strip_prefix('"')?.strip_suffix('"')?;
+    Some((inclusion, path))
+}
+
+/// Return `text` with its `include` and `import` lines blanked out, so that the rest
+/// keeps its offsets, along with the offset and the text of each of those lines.
+pub(crate) fn blank_inclusions(text: &str) -> (String, Vec<(usize, &str)>) {
+    let mut blanked = String::with_capacity(text.len());
+    let mut inclusions = Vec::new();
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        if included_path(line).is_some() {
+            inclusions.push((offset, line.trim()));
+            let content = line.trim_end_matches('\n');
+            blanked.push_str(&" ".repeat(content.len()));
+            blanked.push_str(&line[content.len()..]);
+        } else {
+            blanked.push_str(line);
+        }
+        offset += line.len();
+    }
+    (blanked, inclusions)
+}
+
 impl std::fmt::Debug for StringStream {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.peek().fmt(f)
@@ -365,4 +728,156 @@ hij";
         }
         assert!(stream.is_empty());
     }
+
+    #[test]
+    fn span_between() {
+        let string = "Добрый день\ndefg";
+        let stream = StringStream::new(Path::new("<SPANS>"), string);
+        let span = stream.span_between(0, 5);
+        assert_eq!(span.start(), (0, 0));
+        assert_eq!(span.end(), (0, 5));
+        assert_eq!(&string[span.byte_range()], "Добрый");
+        let span = stream.span_between(7, 13);
+        assert_eq!(&string[span.byte_range()], "день\nde");
+    }
+
+    #[test]
+    fn starting_at() {
+        let file = "Добрый день\ndefg";
+        let offset = file.find("день").unwrap();
+        let window = &file[offset..];
+        let mut stream =
+            StringStream::starting_at(Path::new("<WINDOW>"), window, offset, 7, (0, 7));
+        let span = stream.span_between(0, 3);
+        assert_eq!(span.start(), (0, 7));
+        assert_eq!(&file[span.byte_range()], "день");
+        assert_eq!(span.char_range(), 7..11);
+        let d = file.rfind('d').unwrap();
+        stream.seek_byte(d).unwrap();
+        assert_eq!(stream.peek(), "defg");
+        assert_eq!(stream.curr_span().start(), (1, 0));
+        assert_eq!(stream.curr_span().start_byte(), d);
+        assert!(stream.seek_byte(offset + 1).is_err());
+        assert!(stream.seek_byte(0).is_err());
+        stream.seek_byte(file.len()).unwrap();
+        assert!(stream.is_empty());
+        assert_eq!(stream.curr_span().start(), (1, 4));
+    }
+
+    #[test]
+    fn column_encoding() {
+        let string = "é𝄞x\n𝄞y";
+        let columns = |encoding| {
+            let mut stream = StringStream::new(Path::new("<COLUMNS>"), string)
+                .with_column_encoding(encoding);
+            let mut columns = Vec::new();
+            while let Char::Char(_) = stream.get() {
+                columns.push(stream.curr_span().start());
+                stream.incr_pos();
+            }
+            columns
+        };
+        assert_eq!(
+            columns(ColumnEncoding::Scalars),
+            [(0, 0), (0, 1), (0, 2), (0, 3), (1, 0), (1, 1)]
+        );
+        assert_eq!(
+            columns(ColumnEncoding::Utf16),
+            [(0, 0), (0, 1), (0, 3), (0, 4), (1, 0), (1, 2)]
+        );
+        assert_eq!(
+            columns(ColumnEncoding::Utf8),
+            [(0, 0), (0, 2), (0, 6), (0, 7), (1, 0), (1, 4)]
+        );
+    }
+
+    #[test]
+    fn feed() {
+        let string = "Добрый день\ndefg\nhij";
+        let mut stream = StringStream::open(Path::new("<feed>"));
+        assert!(stream.is_empty() && stream.is_open());
+        stream.feed("Добрый де");
+        stream.shift(3);
+        stream.feed("нь\nde");
+        stream.feed("fg\nhij");
+        stream.close();
+        assert!(!stream.is_open());
+        let whole = StringStream::new(Path::new("<feed>"), string);
+        assert_eq!(stream.len(), whole.len());
+        assert_eq!(stream.peek(), &string[6..]);
+        assert_eq!(stream.span_between(3, 16), whole.span_between(3, 16));
+        stream.shift(stream.len() - 3);
+        assert_eq!(stream.curr_span(), whole.span_between(whole.len(), whole.len()));
+    }
+
+    #[test]
+    fn includes() {
+        let dir = std::env::temp_dir().join(format!("beans-includes-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let main = "A ::= a\ninclude \"sub/b.lx\"\nC ::= c\n";
+        std::fs::write(dir.join("main.lx"), main).unwrap();
+        std::fs::write(dir.join("sub/b.lx"), "B ::= b").unwrap();
+        std::fs::write(dir.join("loop.lx"), "include \"sub/../loop.lx\"\n").unwrap();
+        let stream = StringStream::from_file_with_includes(dir.join("main.lx")).unwrap();
+        assert_eq!(stream.peek(), "A ::= a\nB ::= b\nC ::= c\n");
+        let span = stream.span_between(8, 14);
+        assert_eq!(&*span.file(), dir.join("sub/b.lx"));
+        assert_eq!((span.start(), span.end()), ((0, 0), (0, 6)));
+        let span = stream.span_between(16, 16);
+        assert_eq!(&*span.file(), dir.join("main.lx"));
+        assert_eq!(span.start(), (2, 0));
+        assert!(StringStream::from_file_with_includes(dir.join("loop.lx")).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn imports() {
+        let dir = std::env::temp_dir().join(format!("beans-imports-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let main = "import \"sub/a.lx\"\nimport \"b.lx\"\nM ::= m\n";
+        std::fs::write(dir.join("main.lx"), main).unwrap();
+        std::fs::write(dir.join("sub/a.lx"), "import \"../b.lx\"\nA ::= a\n").unwrap();
+        std::fs::write(dir.join("b.lx"), "import \"sub/a.lx\"\nB ::= b\n").unwrap();
+        let stream = StringStream::from_file_with_includes(dir.join("main.lx")).unwrap();
+        assert_eq!(stream.peek(), "B ::= b\nA ::= a\nM ::= m\n");
+        let span = stream.span_between(8, 14);
+        assert_eq!(&*span.file(), dir.join("sub/a.lx"));
+        assert_eq!(span.start(), (1, 0));
+        std::fs::write(dir.join("twice.lx"), "include \"b.lx\"\ninclude \"b.lx\"\n").unwrap();
+        let stream = StringStream::from_file_with_includes(dir.join("twice.lx")).unwrap();
+        assert_eq!(stream.peek(), "A ::= a\nB ::= b\nB ::= b\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn from_reader() {
+        let text = "Добрый\nдень";
+        let stream = StringStream::from_reader(text.as_bytes(), Path::new("<stdin>")).unwrap();
+        assert_eq!(stream.len(), 11);
+        assert_eq!(&*stream.origin(), Path::new("<stdin>"));
+        assert_eq!(stream.span_between(7, 10).start(), (1, 0));
+        assert!(StringStream::from_reader(&[0xff][..], Path::new("<stdin>")).is_err());
+    }
+
+    #[test]
+    fn binary_input() {
+        let byte = |bytes: &[u8]| {
+            match *StringStream::from_reader(bytes, Path::new("<blob>"))
+                .unwrap_err()
+                .kind
+            {
+                ErrorKind::BinaryInput { byte, .. } => Some(byte),
+                _ => None,
+            }
+        };
+        assert_eq!(byte(b"ab\0c"), Some(2));
+        assert_eq!(byte(b"ab\xffc"), Some(2));
+        let mut late = vec![b'a'; BINARY_PROBE];
+        late.push(0xff);
+        assert_eq!(byte(&late), None);
+        // A char cut by the end of the probe is still text.
+        let mut cut = vec![b'a'; BINARY_PROBE - 1];
+        cut.extend("é".as_bytes());
+        assert!(StringStream::from_reader(&cut[..], Path::new("<text>")).is_ok());
+    }
 }